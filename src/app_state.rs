@@ -1,15 +1,23 @@
-use rustix::fs::{MemfdFlags, ftruncate, memfd_create};
+use rustix::fs::ftruncate;
+#[cfg(not(target_os = "openbsd"))]
+use rustix::fs::{MemfdFlags, memfd_create};
 use rustix::mm::{MapFlags, ProtFlags, mmap, munmap};
-use std::os::fd::AsFd;
+use std::fmt::Write as _;
+use std::os::fd::{AsFd, OwnedFd};
 use std::ptr;
-use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use wayland_client::{
-    Connection, Dispatch, QueueHandle,
+    Connection, Dispatch, Proxy, QueueHandle,
     protocol::{
         wl_buffer::WlBuffer,
+        wl_callback::{self, WlCallback},
         wl_compositor::WlCompositor,
+        wl_output::{self, WlOutput},
         wl_registry::{self, WlRegistry},
+        wl_seat::WlSeat,
         wl_shm::{self, WlShm},
         wl_surface::WlSurface,
     },
@@ -19,10 +27,49 @@ use wayland_protocols_wlr::layer_shell::v1::client::{
     zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
 };
 
+use crate::format::{self, Formatted, Part, Symbol};
 use crate::{
-    ACTIVE_WORKSPACE, BATTERY_ESTIMATE_M, BATTERY_PERCENT, BATTERY_STATE, COLOR_BAT, COLOR_DATE,
-    COLOR_TIME, COLOR_WS_FOCUSED, COLOR_WS_OPEN, DATE_DAY, DATE_MONTH, DATE_YEAR, TIME_HOURS,
-    TIME_MINUTES, WORKSPACES, error::LeanbarError, font_renderer,
+    AC_ONLINE, ACTIVE_WORKSPACE, AUDIO_DEVICE, BACKLIGHT_PERCENT, BATTERY_BLINK, BATTERY_CYCLES,
+    BATTERY_ESTIMATE_M, BATTERY_HEALTH, BATTERY_PERCENT, BATTERY_RATE_DW, BATTERY_STATE,
+    BLUETOOTH_BATTERY, BLUETOOTH_CONNECTED, BLUETOOTH_DEVICE, BLUETOOTH_POWERED,
+    COLOR_AUDIO_DEVICE, COLOR_BACKLIGHT, COLOR_BAT, COLOR_BAT_CRITICAL, COLOR_BAT_WARNING,
+    COLOR_BLUETOOTH, COLOR_CAFFEINE, COLOR_CAPSULE_BG, COLOR_CONNECTIVITY, COLOR_CONTAINERS,
+    COLOR_CUSTOM, COLOR_DATE, COLOR_DISK, COLOR_DND, COLOR_ETHERNET, COLOR_FAILED_UNITS,
+    COLOR_FIFO, COLOR_GPU, COLOR_HIGHLIGHT, COLOR_IDLE, COLOR_KBD_BACKLIGHT, COLOR_LAYOUT,
+    COLOR_LEMONBAR, COLOR_MAIL, COLOR_MIC_MUTED, COLOR_MPRIS, COLOR_NET, COLOR_NEXT_EVENT,
+    COLOR_NIGHT_LIGHT, COLOR_POWER, COLOR_POWER_PROFILE, COLOR_PRESSURE, COLOR_PRIVACY_CAMERA,
+    COLOR_PRIVACY_MIC, COLOR_PRIVACY_SCREEN, COLOR_REGION_OUTLINE, COLOR_REMOVABLE, COLOR_RFKILL,
+    COLOR_SINCE, COLOR_STATUS, COLOR_TASKBAR, COLOR_TASKBAR_ACTIVE, COLOR_TEMP_COOL,
+    COLOR_TEMP_HOT, COLOR_TEMP_WARM, COLOR_TEXT_EFFECT, COLOR_TILING, COLOR_TIME, COLOR_TIMER,
+    COLOR_TITLE, COLOR_TODO, COLOR_TRAY, COLOR_UPDATES, COLOR_VOLUME, COLOR_WIFI, COLOR_WS_FOCUSED,
+    COLOR_WS_OPEN, CONTAINERS_RUNNING, CPU_THROTTLED, CUSTOM_COLOR, CUSTOM_TEXT, CUSTOM_TOOLTIP,
+    DATE_DAY, DATE_MONTH, DATE_YEAR, DISK_READ_KBS, DISK_WRITE_KBS, DND, ETHERNET_ADDRESS,
+    ETHERNET_LINK, FAILED_UNITS, FIFO_TEXT, FOCUSED_MONITOR, GPU_BUSY_PERCENT, GPU_VRAM_USED_MB,
+    IDLE_DAEMON, IDLE_INHIBITED, IDLE_REMAINING_S, KBD_BACKLIGHT_LEVEL, KBD_BACKLIGHT_LEVELS,
+    KEYBOARD_LAYOUT, LEMONBAR, LEMONBAR_GENERATION, LemonSegment, MAIL_ACCOUNTS, MAIL_UNREAD,
+    MIC_MUTED, MONITORS, MPRIS_STATUS, MPRIS_TEXT, NET_HISTORY, NET_HISTORY_LEN, NET_SAMPLES,
+    NEXT_EVENT, NEXT_EVENT_SOON, NIGHT_LIGHT, NM_CONNECTION, NM_CONNECTIVITY, POWER_PROFILE,
+    PRESSURE_FULL_MAX, PRESSURE_SOME, PRIVACY, RAPL_POWER_DW, REMOVABLE_MOUNTS, RFKILL,
+    SINCE_ELAPSED_S, STATUS_BLOCKS, STATUS_GENERATION, StatusBlock, TASKBAR, TASKBAR_GENERATION,
+    TEMPERATURE_C, TILING_LAYOUT, TIME_HOURS, TIME_MINUTES, TIMER_S, TIMER_STATE, TIMEZONE,
+    TODO_OPEN, TRAY, TRAY_GENERATION, TaskbarEntry, TrayItem, UPDATES_PENDING, VOLUME_MUTED,
+    VOLUME_PERCENT, WIFI_SIGNAL, WIFI_SSID, WINDOW_CLASS, WINDOW_TITLE, WORKSPACES,
+    calendar::CalendarState,
+    color::{self, ColorState},
+    config::{self, Corner, Module, TextEffect, WorkspaceGroup},
+    diagnostics::EventLog,
+    error::LeanbarError,
+    font_renderer,
+    idle::IdleState,
+    platform::BatteryReading,
+    pointer::{ClickRegion, ClickTarget, PointerState},
+    preview,
+    quality::{self, Quality},
+    taskbar::TaskbarState,
+    threads::{i3bar, lemonbar, networkmanager, power_profile, privacy, rfkill, tray},
+    timer,
+    tray_menu::TrayMenuState,
+    tz,
 };
 
 const BAR_HEIGHT: usize = 28;
@@ -30,19 +77,106 @@ const MARGIN_LEFT: usize = 10;
 const MARGIN_RIGHT: usize = 10;
 const MARGIN_GAP: usize = 24;
 
-const BATTERY_SLOT_MAX_WIDTH: usize = 180;
+/// Line plus the gap after it, the gap before it is the usual workspace gap.
+const GROUP_SEPARATOR_WIDTH: usize = 11;
+const GROUP_LABEL_GAP: usize = 6;
+
+const MINIMAP_BOX_BASE: usize = 6;
+const MINIMAP_BOX_PER_WINDOW: usize = 6;
+
+const SPARKLINE_COLUMN: usize = 2;
+const SPARKLINE_GAP: usize = 4;
+/// Throughput filling the sparkline when everything below it is idle noise.
+const SPARKLINE_MIN_SCALE_KBS: u32 = 64;
+
+const CLOCK_TIMEZONE_GAP: usize = 6;
+
+/// Between the battery icon and the battery text.
+const BATTERY_ICON_GAP: usize = 6;
+/// Between two privacy dots.
+const PRIVACY_DOT_GAP: usize = 4;
+/// Between two tray icons.
+const TRAY_ICON_GAP: usize = 6;
+/// Room for one window of the taskbar, longer names are cut.
+const TASKBAR_ENTRY_WIDTH: usize = 120;
+/// Between two taskbar entries.
+const TASKBAR_ENTRY_GAP: usize = 12;
+/// Between the blocks of the status module.
+const STATUS_BLOCK_GAP: usize = 12;
+
+/// "full" pressure in tenths of a percent that turns the module warm, then hot.
+const PRESSURE_FULL_WARN: u16 = 10;
+const PRESSURE_FULL_SEVERE: u16 = 100;
+
+const WIFI_SSID_GAP: usize = 6;
+/// Between a window's icon and its title.
+const TITLE_ICON_GAP: usize = 6;
+/// Typical connection name the connectivity slot is sized for, longer ones are cut.
+const CONNECTIVITY_SAMPLE_NAME: &str = "home-network";
+/// Between the connection name and the connectivity state.
+const CONNECTIVITY_GAP: usize = 6;
+/// Typical event the next event slot is sized for, longer titles are cut.
+const NEXT_EVENT_SAMPLE: &str = "tue 14:00 team standup";
+/// Sizes the custom module unless `custom_width` is set.
+const CUSTOM_SAMPLE: &str = "custom module text";
+/// Sizes the fifo module unless `fifo_width` is set.
+const FIFO_SAMPLE: &str = "script output";
+/// Blocks of the status module in previews, its width unless `status_width` is set.
+const STATUS_SAMPLE: [&str; 3] = ["W: 192.168.1.20", "87% BAT", "load 0.42"];
+/// Typical SSID length the wifi slot is sized for, longer ones are cut.
+const WIFI_SAMPLE_SSID: &str = "wifi-network";
+/// Calendar popup rows: month, weekday names and six weeks.
+const CALENDAR_ROWS: usize = 8;
+/// Around the calendar grid.
+const CALENDAR_PADDING: usize = 8;
+/// Added to the widest content of a calendar column.
+const CALENDAR_CELL_PADDING: usize = 10;
+/// Between the bar and the calendar popup.
+const CALENDAR_GAP: i32 = 6;
+/// Around the entries of a tray item's menu.
+const MENU_PADDING: usize = 4;
+/// Indent of each submenu level and on both sides of the labels.
+const MENU_INDENT: usize = 12;
+/// Side of the box of checkmark and radio entries.
+const MENU_CHECK_SIZE: usize = 10;
+/// Labels wider than this are cut.
+const MENU_MAX_LABEL_WIDTH: usize = 320;
+/// Device name the Bluetooth module reserves room for, longer ones are cut.
+const BLUETOOTH_SAMPLE_DEVICE: &str = "Headphones";
+/// Between the device name and its battery.
+const BLUETOOTH_BATTERY_GAP: usize = 6;
+/// Between the play or pause indicator and the title.
+const MPRIS_GAP: usize = 6;
+
+/// Capsule width when no font is loaded, nothing is drawn into it anyway.
+const CAPSULE_FALLBACK_WIDTH: usize = 200;
+
+const HIGHLIGHT_HEIGHT: usize = 2;
+const HIGHLIGHT_FADE: Duration = Duration::from_secs(1);
+/// Longest an animation waits for its next frame. Frame callbacks usually come
+/// sooner, the event loop ticks it on a timer where the compositor holds them
+/// back, e.g. for a bar hidden under a fullscreen window.
+const ANIMATION_TICK: Duration = Duration::from_millis(50);
 
 /// A thin wrapper around the raw pixel buffer for drawing operations.
 struct PixelBuffer<'a> {
     pixels: &'a mut [u32],
     width: usize,
     height: usize,
+    text_effect: TextEffect,
 }
 
+/// Outline the click regions on every bar, see `leanbar-ctl regions overlay`.
+static REGION_OVERLAY: AtomicBool = AtomicBool::new(false);
+/// Click regions of every bar as of the last redraw, for `leanbar-ctl regions`.
+static REGION_MAP: Mutex<String> = Mutex::new(String::new());
+/// Modules hidden with `leanbar-ctl toggle`, the others close up over them.
+static HIDDEN_MODULES: Mutex<Vec<Module>> = Mutex::new(Vec::new());
+
 /// Stores the last rendered state to enable efficient partial updates (damage tracking).
 struct DrawCache {
-    active_ws: u8,
-    workspaces: u16, // Bitmask of occupied workspaces
+    active_ws: i32,
+    workspaces: Vec<WorkspaceLabel>,
     ws_render_width: usize,
     minute: u8,
     hour: u8,
@@ -52,13 +186,80 @@ struct DrawCache {
     bat_percent: u8,
     bat_state: u8,
     bat_est_min: u16,
+    bat_rate_dw: u16,
+    ac_online: u8,
+    bat_blink: bool,
+    /// Health and cycles while hovered, (255, 0) while the charge is shown.
+    bat_health: (u8, u16),
+    power_dw: u16,
+    power_profile: u8,
+    temperature: u8,
+    throttled: bool,
+    gpu_busy: u8,
+    gpu_vram_mb: u32,
+    disk_read_kbs: u32,
+    disk_write_kbs: u32,
+    net_samples: u32,
+    wifi_ssid: String,
+    wifi_signal: u8,
+    ethernet_link: u8,
+    ethernet_address: String,
+    nm_connectivity: u8,
+    nm_connection: String,
+    bluetooth_powered: u8,
+    bluetooth_connected: u8,
+    bluetooth_device: String,
+    bluetooth_battery: u8,
+    idle_daemon: u8,
+    idle_remaining_s: u32,
+    idle_inhibited: u8,
+    dnd: u8,
+    night_light: u8,
+    rfkill: u8,
+    removable_mounts: u8,
+    failed_units: u16,
+    next_event_soon: u8,
+    next_event: String,
+    todo_open: u16,
+    mail_unread: u32,
+    updates_pending: u16,
+    containers_running: u16,
+    custom_text: String,
+    custom_color: u32,
+    fifo_text: String,
+    /// Unread mail per account while hovered, empty while the total is shown.
+    mail_accounts: String,
+    tray_generation: u32,
+    taskbar_generation: u32,
+    status_generation: u32,
+    lemonbar_generation: u32,
+    since_elapsed_s: u32,
+    timer_s: u32,
+    timer_state: u8,
+    pressure_some: [u16; 3],
+    pressure_full: u16,
+    backlight_percent: u8,
+    kbd_backlight_level: u8,
+    kbd_backlight_levels: u8,
+    volume_percent: u8,
+    volume_muted: bool,
+    mic_muted: u8,
+    privacy: u8,
+    audio_device: String,
+    mpris_status: u8,
+    mpris_text: String,
+    layout: String,
+    tiling: String,
+    title: String,
+    window_class: String,
+    timezone: String,
 }
 
 impl Default for DrawCache {
     fn default() -> Self {
         Self {
-            active_ws: 255,
-            workspaces: 0,
+            active_ws: i32::MIN,
+            workspaces: Vec::new(),
             ws_render_width: 0,
             minute: 255,
             hour: 255,
@@ -68,27 +269,252 @@ impl Default for DrawCache {
             bat_percent: 255,
             bat_state: 255,
             bat_est_min: 65535,
+            bat_rate_dw: u16::MAX,
+            ac_online: u8::MAX,
+            bat_blink: false,
+            bat_health: (255, 0),
+            power_dw: u16::MAX,
+            power_profile: 255,
+            temperature: 255,
+            throttled: false,
+            gpu_busy: 255,
+            gpu_vram_mb: u32::MAX,
+            disk_read_kbs: u32::MAX,
+            disk_write_kbs: u32::MAX,
+            net_samples: 0,
+            wifi_ssid: String::new(),
+            wifi_signal: 255,
+            ethernet_link: 255,
+            ethernet_address: String::new(),
+            nm_connectivity: 255,
+            nm_connection: String::new(),
+            bluetooth_powered: 255,
+            bluetooth_connected: 0,
+            bluetooth_device: String::new(),
+            bluetooth_battery: 255,
+            idle_daemon: 255,
+            idle_remaining_s: u32::MAX,
+            idle_inhibited: 255,
+            dnd: 255,
+            night_light: 255,
+            rfkill: 255,
+            removable_mounts: 255,
+            failed_units: u16::MAX,
+            next_event_soon: 255,
+            next_event: String::new(),
+            todo_open: u16::MAX,
+            mail_unread: u32::MAX,
+            updates_pending: u16::MAX,
+            containers_running: u16::MAX,
+            custom_text: String::new(),
+            custom_color: 0,
+            fifo_text: String::new(),
+            mail_accounts: String::new(),
+            tray_generation: u32::MAX,
+            taskbar_generation: u32::MAX,
+            status_generation: u32::MAX,
+            lemonbar_generation: u32::MAX,
+            since_elapsed_s: u32::MAX,
+            timer_s: u32::MAX,
+            timer_state: timer::STOPPED,
+            pressure_some: [u16::MAX; 3],
+            pressure_full: u16::MAX,
+            backlight_percent: 255,
+            kbd_backlight_level: 255,
+            kbd_backlight_levels: 0,
+            volume_percent: 255,
+            volume_muted: false,
+            mic_muted: 255,
+            privacy: 0,
+            audio_device: String::new(),
+            mpris_status: 255,
+            mpris_text: String::new(),
+            layout: String::new(),
+            tiling: String::new(),
+            title: String::new(),
+            window_class: String::new(),
+            timezone: String::new(),
         }
     }
 }
 
 impl<'a> PixelBuffer<'a> {
-    fn new(pixels: &'a mut [u32], width: usize, height: usize) -> Self {
+    fn new(pixels: &'a mut [u32], width: usize, height: usize, quality: Quality) -> Self {
         Self {
             pixels,
             width,
             height,
+            text_effect: quality.text_effect(),
         }
     }
 
     fn clear_rect(&mut self, x: usize, width: usize) {
+        let background = color::correct(background());
         if x >= self.width || width == 0 {
             return;
         }
         let actual_w = width.min(self.width - x);
         for y in 0..self.height {
             let start = y * self.width + x;
-            self.pixels[start..start + actual_w].fill(0);
+            self.pixels[start..start + actual_w].fill(background);
+        }
+    }
+
+    /// Fills a rectangle with `color`, replacing what is below it.
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let color = color::correct(color);
+        let width = width.min(self.width - x);
+        for row in y..(y + height).min(self.height) {
+            let start = row * self.width + x;
+            self.pixels[start..start + width].fill(color);
+        }
+    }
+
+    /// Disc `diameter` wide with its left edge at `x`, vertically centered.
+    fn fill_dot(&mut self, x: usize, diameter: usize, color: u32) {
+        let y = (BAR_HEIGHT - diameter) / 2;
+        // Each row spans the chord at its center, measured in half pixels
+        let radius = diameter as isize;
+        for row in 0..diameter {
+            let dy = 2 * row as isize + 1 - radius;
+            let half = ((radius * radius - dy * dy) as f64).sqrt() / 2.0;
+            let start = (diameter as f64 / 2.0 - half).round() as usize;
+            let end = (diameter as f64 / 2.0 + half).round() as usize;
+            self.fill_rect(x + start, y + row, end - start, 1, color);
+        }
+    }
+
+    /// Composites a square premultiplied ARGB icon `size` wide with its left
+    /// edge at `x`, vertically centered.
+    fn blend_icon(&mut self, x: usize, pixels: &[u32], size: usize) {
+        let y = (BAR_HEIGHT.saturating_sub(size)) / 2;
+        for row in 0..size.min(self.height.saturating_sub(y)) {
+            for column in 0..size.min(self.width.saturating_sub(x)) {
+                let src = color::correct(pixels[row * size + column]);
+                let inv = 255 - (src >> 24);
+                if inv == 255 {
+                    continue;
+                }
+                let dst_idx = (y + row) * self.width + x + column;
+                let dst = self.pixels[dst_idx];
+                let over =
+                    |shift: u32| ((src >> shift) & 0xFF) + ((dst >> shift) & 0xFF) * inv / 255;
+                self.pixels[dst_idx] =
+                    (over(24) << 24) | (over(16) << 16) | (over(8) << 8) | over(0);
+            }
+        }
+    }
+
+    /// Outlines a rectangle with lines `thickness` wide, leaving its inside as is.
+    fn stroke_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        thickness: usize,
+        color: u32,
+    ) {
+        self.fill_rect(x, y, width, thickness, color);
+        self.fill_rect(
+            x,
+            y + height.saturating_sub(thickness),
+            width,
+            thickness,
+            color,
+        );
+        self.fill_rect(x, y, thickness, height, color);
+        self.fill_rect(
+            x + width.saturating_sub(thickness),
+            y,
+            thickness,
+            height,
+            color,
+        );
+    }
+
+    /// Battery outline filled up to `percent`, sized to the digits. A lightning
+    /// bolt is cut out of it while charging, in the battery color where the
+    /// fill doesn't reach.
+    fn draw_battery_icon(
+        &mut self,
+        x: usize,
+        glyphs: &font_renderer::GlyphCache,
+        percent: u8,
+        charging: bool,
+        color: u32,
+    ) {
+        let (width, height) = battery_icon_size(glyphs);
+        let y = (BAR_HEIGHT - height) / 2;
+        let thickness = (height / 10).max(1);
+        let nub = (height / 6).max(1);
+        let body = width - nub;
+        self.stroke_rect(x, y, body, height, thickness, color);
+        self.fill_rect(x + body, y + height / 4, nub, height / 2, color);
+
+        // One pixel of air between the outline and the fill
+        let inset = thickness + 1;
+        let (inner_x, inner_y) = (x + inset, y + inset);
+        let inner_width = body.saturating_sub(2 * inset);
+        let inner_height = height.saturating_sub(2 * inset);
+        let fill = match percent {
+            0 => 0,
+            _ => (inner_width * percent.min(100) as usize / 100).max(1),
+        };
+        self.fill_rect(inner_x, inner_y, fill, inner_height, color);
+        if !charging || inner_height < 3 {
+            return;
+        }
+
+        // Rows slant from the top right to the bottom left, the upper half
+        // reaching right of the slant and the lower half left of it
+        let fill_end = inner_x + fill;
+        let segment = (inner_width / 6).max(1);
+        let half = inner_height / 2;
+        for row in 0..inner_height {
+            let slant =
+                (half as isize - row as isize) * inner_width as isize / (3 * inner_height) as isize;
+            let center = (inner_x + inner_width / 2).saturating_add_signed(slant);
+            let start = if row < half {
+                center
+            } else {
+                center.saturating_sub(segment)
+            };
+            let end = if row > half { center } else { center + segment };
+            let (start, end) = (start.max(inner_x), end.min(inner_x + inner_width));
+            let split = fill_end.clamp(start, end);
+            self.fill_rect(start, inner_y + row, split - start, 1, background());
+            self.fill_rect(split, inner_y + row, end - split, 1, color);
+        }
+    }
+
+    /// Bottom aligned columns, one per value, scaled so `max` fills the middle
+    /// half of the bar. Non-zero values always get at least one pixel.
+    fn draw_columns(
+        &mut self,
+        x: usize,
+        column_width: usize,
+        values: &[u32],
+        max: u32,
+        color: u32,
+    ) {
+        let (top, bottom) = (self.height / 4, self.height * 3 / 4);
+        let full = (bottom - top) as u64;
+        for (i, &value) in values.iter().enumerate() {
+            if value == 0 {
+                continue;
+            }
+            let height = (value.min(max) as u64 * full / max.max(1) as u64).max(1) as usize;
+            self.fill_rect(
+                x + i * column_width,
+                bottom - height,
+                column_width,
+                height,
+                color,
+            );
         }
     }
 
@@ -102,6 +528,62 @@ impl<'a> PixelBuffer<'a> {
         if glyph.coverage.is_empty() {
             return;
         }
+        let (x, y) = (x as i32, y as i32);
+        // Extra coverage passes under the glyph keep it readable on bright
+        // wallpapers, the glyph is then blended over them
+        match self.text_effect {
+            TextEffect::None => {
+                self.write_coverage(x, y, glyph, color);
+                return;
+            }
+            TextEffect::Shadow => self.blend_coverage(x + 1, y + 1, glyph, COLOR_TEXT_EFFECT),
+            TextEffect::Outline => {
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    self.blend_coverage(x + dx, y + dy, glyph, COLOR_TEXT_EFFECT);
+                }
+            }
+        }
+        self.blend_coverage(x, y, glyph, color);
+    }
+
+    /// Writes the glyph coverage in `color`, replacing what is drawn under it.
+    fn write_coverage(
+        &mut self,
+        x: i32,
+        y: i32,
+        glyph: &font_renderer::RasterizedGlyph,
+        color: u32,
+    ) {
+        self.apply_coverage(x, y, glyph, color, |src, _| src);
+    }
+
+    /// Composites the glyph coverage in `color` over what is already drawn.
+    fn blend_coverage(
+        &mut self,
+        x: i32,
+        y: i32,
+        glyph: &font_renderer::RasterizedGlyph,
+        color: u32,
+    ) {
+        // Premultiplied "over": src + dst * (1 - src_alpha)
+        self.apply_coverage(x, y, glyph, color, |src, dst| {
+            let inv = 255 - (src >> 24);
+            let over = |shift: u32| ((src >> shift) & 0xFF) + ((dst >> shift) & 0xFF) * inv / 255;
+            (over(24) << 24) | (over(16) << 16) | (over(8) << 8) | over(0)
+        });
+    }
+
+    /// Calls `combine` with `color` scaled by the coverage and the pixel under
+    /// it for every covered pixel, storing what it returns.
+    fn apply_coverage(
+        &mut self,
+        x: i32,
+        y: i32,
+        glyph: &font_renderer::RasterizedGlyph,
+        color: u32,
+        combine: impl Fn(u32, u32) -> u32,
+    ) {
+        let color = color::correct(color);
         let color_a = (color >> 24) & 0xFF;
         let color_r = (color >> 16) & 0xFF;
         let color_g = (color >> 8) & 0xFF;
@@ -109,13 +591,16 @@ impl<'a> PixelBuffer<'a> {
 
         let mask = &glyph.coverage;
         for gy in 0..glyph.height {
-            let py = y + gy;
-            if py >= self.height {
+            let py = y + gy as i32;
+            if py < 0 {
+                continue;
+            }
+            if py as usize >= self.height {
                 break;
             }
             for gx in 0..glyph.width {
-                let px = x + gx;
-                if px >= self.width {
+                let px = x + gx as i32;
+                if px < 0 || px as usize >= self.width {
                     continue;
                 }
                 let mask_idx = gy * glyph.width + gx;
@@ -129,8 +614,9 @@ impl<'a> PixelBuffer<'a> {
                 let b = (color_b * alpha) / 255;
                 let a = (color_a * alpha) / 255;
 
-                let dst_idx = py * self.width + px;
-                self.pixels[dst_idx] = (a << 24) | (r << 16) | (g << 8) | b;
+                let dst_idx = py as usize * self.width + px as usize;
+                self.pixels[dst_idx] =
+                    combine((a << 24) | (r << 16) | (g << 8) | b, self.pixels[dst_idx]);
             }
         }
     }
@@ -204,34 +690,225 @@ impl<'a> PixelBuffer<'a> {
             self.draw_centered(x, g, color, if i > 0 { spacing } else { 0 });
         }
     }
+
+    /// Baseline for free-form text, placed so digits end up vertically centered.
+    fn text_baseline(glyphs: &font_renderer::GlyphCache) -> i32 {
+        ((BAR_HEIGHT + glyphs.numbers[0].height) / 2) as i32
+    }
+
+    fn measure_text(glyphs: &font_renderer::GlyphCache, text: &str) -> usize {
+        text.chars().map(|c| glyphs.glyph(c).advance).sum()
+    }
+
+    fn draw_text(
+        &mut self,
+        x: &mut usize,
+        glyphs: &font_renderer::GlyphCache,
+        text: &str,
+        color: u32,
+    ) {
+        let baseline = Self::text_baseline(glyphs);
+        for c in text.chars() {
+            let g = glyphs.glyph(c);
+            let gx = (*x as i32 + g.xmin).max(0) as usize;
+            let gy = (baseline - g.ymin - g.height as i32).max(0) as usize;
+            self.draw_glyph(gx, gy, g, color);
+            *x += g.advance;
+        }
+    }
+
+    /// Draws as much of `text` as fits in `max_width`, ending in "..." when cut.
+    fn draw_text_fitted(
+        &mut self,
+        x: &mut usize,
+        glyphs: &font_renderer::GlyphCache,
+        text: &str,
+        max_width: usize,
+        color: u32,
+    ) {
+        if Self::measure_text(glyphs, text) <= max_width {
+            self.draw_text(x, glyphs, text, color);
+            return;
+        }
+        let ellipsis = "...";
+        let budget = max_width.saturating_sub(Self::measure_text(glyphs, ellipsis));
+        let mut width = 0;
+        let fitting: String = text
+            .chars()
+            .take_while(|&c| {
+                width += glyphs.glyph(c).advance;
+                width <= budget
+            })
+            .collect();
+        self.draw_text(x, glyphs, &fitting, color);
+        self.draw_text(x, glyphs, ellipsis, color);
+    }
+
+    /// Width of formatted content, ignoring its minimum width.
+    fn natural_width(glyphs: &font_renderer::GlyphCache, formatted: &Formatted) -> usize {
+        let mut width = 0;
+        for (i, part) in formatted.parts.iter().enumerate() {
+            if i > 0 {
+                width += 1;
+            }
+            width += match *part {
+                Part::Num { value, pad } => Self::measure_num(glyphs, value, pad, 1),
+                Part::Symbol(symbol) => symbol_glyph(glyphs, symbol).width,
+                Part::Unit(c) => glyphs.glyph(c).advance,
+                Part::Text(text) => Self::measure_text(glyphs, text),
+                Part::Gap(px) => px,
+            };
+        }
+        width
+    }
+
+    /// Width of the formatted value with every digit and symbol at its widest,
+    /// i.e. the most any value of the same format can take.
+    fn measure_widest(glyphs: &font_renderer::GlyphCache, formatted: &Formatted) -> usize {
+        let mut width = 0;
+        for (i, part) in formatted.parts.iter().enumerate() {
+            if i > 0 {
+                width += 1;
+            }
+            width += match *part {
+                Part::Num { value, pad } => {
+                    let (_, len) = Self::get_digits(value, pad);
+                    len * (glyphs.max_digit_width + 1) - 1
+                }
+                Part::Symbol(Symbol::Am | Symbol::Pm) => glyphs.max_ampm_width,
+                Part::Symbol(Symbol::Up | Symbol::Down) => glyphs.up.width.max(glyphs.down.width),
+                Part::Symbol(symbol) => symbol_glyph(glyphs, symbol).width,
+                Part::Unit(c) => glyphs.glyph(c).advance,
+                Part::Text(text) => Self::measure_text(glyphs, text),
+                Part::Gap(px) => px,
+            };
+        }
+        width.max(formatted.min_cells * glyphs.max_digit_width)
+    }
+
+    fn measure_formatted(glyphs: &font_renderer::GlyphCache, formatted: &Formatted) -> usize {
+        Self::natural_width(glyphs, formatted).max(formatted.min_cells * glyphs.max_digit_width)
+    }
+
+    fn draw_formatted(
+        &mut self,
+        x: &mut usize,
+        glyphs: &font_renderer::GlyphCache,
+        formatted: &Formatted,
+        color: u32,
+    ) {
+        // Right align content narrower than its minimum width
+        *x += Self::measure_formatted(glyphs, formatted) - Self::natural_width(glyphs, formatted);
+        let baseline = Self::text_baseline(glyphs);
+        for (i, part) in formatted.parts.iter().enumerate() {
+            if i > 0 {
+                *x += 1;
+            }
+            match *part {
+                Part::Num { value, pad } => self.draw_num(x, glyphs, value, color, pad, 1),
+                // Sit the dot on the digit baseline rather than centering it
+                Part::Symbol(Symbol::Dot) => {
+                    self.draw_glyph(
+                        *x,
+                        (baseline as usize).saturating_sub(glyphs.dot.height),
+                        &glyphs.dot,
+                        color,
+                    );
+                    *x += glyphs.dot.width;
+                }
+                Part::Symbol(symbol) => {
+                    self.draw_centered(x, symbol_glyph(glyphs, symbol), color, 0)
+                }
+                Part::Unit(c) => {
+                    let g = glyphs.glyph(c);
+                    let gx = (*x as i32 + g.xmin).max(0) as usize;
+                    let gy = (baseline - g.ymin - g.height as i32).max(0) as usize;
+                    self.draw_glyph(gx, gy, g, color);
+                    *x += g.advance;
+                }
+                Part::Text(text) => self.draw_text(x, glyphs, text, color),
+                Part::Gap(px) => *x += px,
+            }
+        }
+    }
 }
 
-pub struct AppState {
-    pub compositor: Option<WlCompositor>,
-    pub shm: Option<WlShm>,
-    pub layer_shell: Option<ZwlrLayerShellV1>,
+fn symbol_glyph(
+    glyphs: &font_renderer::GlyphCache,
+    symbol: Symbol,
+) -> &font_renderer::RasterizedGlyph {
+    match symbol {
+        Symbol::Colon => &glyphs.colon,
+        Symbol::Slash => &glyphs.slash,
+        Symbol::Percent => &glyphs.percent,
+        Symbol::Dot => &glyphs.dot,
+        Symbol::Watt => &glyphs.watt,
+        Symbol::Space => &glyphs.space,
+        Symbol::Am => &glyphs.am,
+        Symbol::Pm => &glyphs.pm,
+        Symbol::Up => &glyphs.up,
+        Symbol::Down => &glyphs.down,
+        Symbol::MicMuted => &glyphs.mic_muted,
+    }
+}
 
-    pub layer_surface: Option<ZwlrLayerSurfaceV1>,
-    pub wl_surface: Option<WlSurface>,
-    pub buffer: Option<WlBuffer>,
-    pub pixels: *mut u32,
-    pub pixels_len: usize,
-    pub width: u32,
-    pub height: u32,
-    pub configured: bool,
+/// Underline under the most recently updated module.
+struct Highlight {
+    x: usize,
+    width: usize,
+    started: Instant,
+    /// Last drawn at, see `ANIMATION_TICK`.
+    drawn: Instant,
+}
+
+/// One bar surface, bound to a single wl_output.
+struct Bar {
+    id: u32,
+    output: Option<WlOutput>,
+    output_name: Option<String>,
+
+    layer_surface: Option<ZwlrLayerSurfaceV1>,
+    wl_surface: Option<WlSurface>,
+    buffer: Option<WlBuffer>,
+    pixels: *mut u32,
+    pixels_len: usize,
+    width: u32,
+    height: u32,
+    configured: bool,
 
-    pub force_full_redraw: bool,
+    force_full_redraw: bool,
+    /// Quality the current contents were drawn with.
+    quality: Quality,
     cache: DrawCache,
+    regions: Vec<ClickRegion>,
 
-    pub glyphs: Option<font_renderer::GlyphCache>,
+    layout: Layout,
+
+    highlight: Option<Highlight>,
+    /// A frame callback is outstanding, animations advance when it fires.
+    frame_pending: bool,
+    /// Moved to the overlay layer without an exclusive zone for a fullscreen window.
+    overlaying: bool,
+    /// Click regions are outlined.
+    region_overlay: bool,
+    /// Modules hidden when the layout was computed.
+    hidden_modules: Vec<Module>,
+    /// A frame has been committed since the surface was configured.
+    committed: bool,
+    /// The battery is hovered, it shows its health instead.
+    battery_health: bool,
+    /// The mail module is hovered, it shows the unread mail per account.
+    mail_accounts: bool,
+    /// The custom module is hovered, it shows its tooltip.
+    custom_tooltip: bool,
 }
 
-impl AppState {
-    pub fn new(glyphs: Option<font_renderer::GlyphCache>) -> Self {
+impl Bar {
+    fn new(id: u32, output: Option<WlOutput>) -> Self {
         Self {
-            compositor: None,
-            shm: None,
-            layer_shell: None,
+            id,
+            output,
+            output_name: None,
             layer_surface: None,
             wl_surface: None,
             buffer: None,
@@ -241,67 +918,221 @@ impl AppState {
             height: 0,
             configured: false,
             force_full_redraw: true,
+            quality: Quality::Full,
             cache: DrawCache::default(),
-            glyphs,
+            regions: Vec::new(),
+            layout: Layout::default(),
+            highlight: None,
+            frame_pending: false,
+            overlaying: false,
+            region_overlay: false,
+            hidden_modules: Vec::new(),
+            committed: false,
+            battery_health: false,
+            mail_accounts: false,
+            custom_tooltip: false,
         }
     }
 
-    pub fn has_required_globals(&self) -> bool {
-        self.compositor.is_some() && self.shm.is_some() && self.layer_shell.is_some()
-    }
-
-    pub fn initialize_layer_surface(&mut self, qh: &QueueHandle<Self>) -> Result<(), LeanbarError> {
-        let compositor = self
-            .compositor
-            .as_ref()
-            .ok_or_else(|| LeanbarError::Wayland("missing wl_compositor".into()))?;
-        let layer_shell = self
-            .layer_shell
-            .as_ref()
-            .ok_or_else(|| LeanbarError::Wayland("missing zwlr_layer_shell_v1".into()))?;
-
+    fn create_layer_surface(
+        &mut self,
+        compositor: &WlCompositor,
+        layer_shell: &ZwlrLayerShellV1,
+        glyphs: Option<&font_renderer::GlyphCache>,
+        qh: &QueueHandle<AppState>,
+    ) {
         let wl_surface = compositor.create_surface(qh, ());
         let layer_surface = layer_shell.get_layer_surface(
             &wl_surface,
-            None,
+            self.output.as_ref(),
             zwlr_layer_shell_v1::Layer::Top,
-            "leanbar".to_string(),
+            config::get().namespace.clone(),
             qh,
-            (),
+            self.id,
         );
 
-        layer_surface.set_anchor(
-            zwlr_layer_surface_v1::Anchor::Bottom
-                | zwlr_layer_surface_v1::Anchor::Left
-                | zwlr_layer_surface_v1::Anchor::Right,
-        );
-        layer_surface.set_size(0, BAR_HEIGHT as u32);
-        layer_surface.set_exclusive_zone(BAR_HEIGHT as i32);
+        let geometry = SurfaceGeometry::from_config(glyphs);
+        let [top, right, bottom, left] = geometry.margins;
+        layer_surface.set_anchor(geometry.anchor);
+        layer_surface.set_size(geometry.width, BAR_HEIGHT as u32);
+        layer_surface.set_margin(top, right, bottom, left);
+        layer_surface.set_exclusive_zone(geometry.exclusive_zone);
 
         wl_surface.commit();
 
         self.wl_surface = Some(wl_surface);
         self.layer_surface = Some(layer_surface);
+    }
 
-        Ok(())
+    fn resize(&mut self, shm: &WlShm, w: u32, h: u32, qh: &QueueHandle<AppState>) {
+        self.release_buffer();
+
+        self.width = w;
+        self.height = h;
+
+        let (buffer, pixels, pixels_len) = create_buffer(shm, w, h, qh);
+        self.pixels = pixels;
+        self.pixels_len = pixels_len;
+        self.buffer = Some(buffer);
     }
 
-    pub fn redraw_and_commit(&mut self) {
-        if self.configured
-            && self.draw_and_damage()
+    fn release_buffer(&mut self) {
+        self.highlight = None;
+        if let Some(buffer) = self.buffer.take() {
+            destroy_buffer(buffer, self.pixels, self.pixels_len);
+            self.pixels = ptr::null_mut();
+            self.pixels_len = 0;
+        }
+    }
+
+    /// Active workspace of the monitor this bar sits on, falling back to the
+    /// globally focused one when the output name is unknown.
+    fn active_workspace(&self) -> i32 {
+        self.output_name
+            .as_deref()
+            .and_then(monitor_active_workspace)
+            .unwrap_or_else(|| ACTIVE_WORKSPACE.load(Ordering::Acquire))
+    }
+
+    /// Switches between reserving space and overlaying when the workspace under
+    /// the bar gains or loses a fullscreen window. Returns true if the surface
+    /// state changed and needs a commit.
+    fn follow_fullscreen(&mut self) -> bool {
+        let config = config::get();
+        if !config.fullscreen_overlay || config.capsule.is_some() {
+            return false;
+        }
+        let fullscreen = workspace_fullscreen(self.active_workspace());
+        let Some(layer_surface) = &self.layer_surface else {
+            return false;
+        };
+        if fullscreen == self.overlaying {
+            return false;
+        }
+        let (layer, exclusive_zone) = if fullscreen {
+            (zwlr_layer_shell_v1::Layer::Overlay, 0)
+        } else {
+            (zwlr_layer_shell_v1::Layer::Top, BAR_HEIGHT as i32)
+        };
+        layer_surface.set_layer(layer);
+        layer_surface.set_exclusive_zone(exclusive_zone);
+        self.overlaying = fullscreen;
+        true
+    }
+
+    fn redraw_and_commit(
+        &mut self,
+        glyphs: Option<&font_renderer::GlyphCache>,
+        qh: Option<&QueueHandle<AppState>>,
+    ) {
+        if !self.configured {
+            return;
+        }
+        let Some(glyphs) = glyphs else {
+            return;
+        };
+        let relayered = self.follow_fullscreen();
+        let drawn = self.draw_and_damage(glyphs);
+        let animated = self.draw_highlight();
+        if (drawn || animated || relayered)
             && let (Some(surface), Some(buffer)) = (&self.wl_surface, &self.buffer)
         {
+            // Keep the animation ticking on frame callbacks while something fades
+            if self.highlight.is_some()
+                && !self.frame_pending
+                && let Some(qh) = qh
+            {
+                surface.frame(qh, self.id);
+                self.frame_pending = true;
+            }
             surface.attach(Some(buffer), 0, 0);
             surface.commit();
+            self.committed = true;
+        }
+    }
+
+    /// Moves the underline to a freshly redrawn slot.
+    fn start_highlight(&mut self, x: usize, width: usize) {
+        if let Some(old) = self.highlight.take() {
+            self.fill_highlight(old.x, old.width, background());
+        }
+        self.highlight = Some(Highlight {
+            x,
+            width,
+            started: Instant::now(),
+            drawn: Instant::now(),
+        });
+    }
+
+    /// Draws the underline at its current opacity. Returns true if pixels changed.
+    fn draw_highlight(&mut self) -> bool {
+        let Some(highlight) = &mut self.highlight else {
+            return false;
+        };
+        highlight.drawn = Instant::now();
+        let (x, width) = (highlight.x, highlight.width);
+        let elapsed = highlight.started.elapsed();
+        let color = if elapsed >= HIGHLIGHT_FADE {
+            self.highlight = None;
+            background()
+        } else {
+            let remaining = HIGHLIGHT_FADE - elapsed;
+            let alpha = (remaining.as_millis() * 255 / HIGHLIGHT_FADE.as_millis()) as u32;
+            fade(COLOR_HIGHLIGHT, alpha)
+        };
+        self.fill_highlight(x, width, color);
+        true
+    }
+
+    fn fill_highlight(&mut self, x: usize, width: usize, color: u32) {
+        if self.pixels.is_null() || x >= self.width as usize {
+            return;
+        }
+        let (w, h) = (self.width as usize, self.height as usize);
+        let width = width.min(w - x);
+        let color = color::correct(color);
+        let slice = unsafe { std::slice::from_raw_parts_mut(self.pixels, w * h) };
+        for y in h.saturating_sub(HIGHLIGHT_HEIGHT)..h {
+            slice[y * w + x..y * w + x + width].fill(color);
+        }
+        if let Some(surface) = &self.wl_surface {
+            surface.damage_buffer(
+                x as i32,
+                h.saturating_sub(HIGHLIGHT_HEIGHT) as i32,
+                width as i32,
+                HIGHLIGHT_HEIGHT as i32,
+            );
         }
     }
 
-    fn draw_and_damage(&mut self) -> bool {
-        if self.pixels.is_null() || self.width == 0 || self.glyphs.is_none() {
+    fn draw_and_damage(&mut self, glyphs: &font_renderer::GlyphCache) -> bool {
+        if self.pixels.is_null() || self.width == 0 {
             return false;
         }
 
-        let active_ws = ACTIVE_WORKSPACE.load(Ordering::Acquire);
+        // Text effects change every glyph, redraw everything when quality changes
+        let quality = quality::current();
+        if quality != self.quality {
+            self.quality = quality;
+            self.force_full_redraw = true;
+        }
+        // Outlines are drawn over the modules, turning them off needs a clean bar
+        let region_overlay = REGION_OVERLAY.load(Ordering::Acquire);
+        if region_overlay != self.region_overlay {
+            self.region_overlay = region_overlay;
+            self.force_full_redraw = true;
+        }
+        let hidden = hidden_modules();
+        if hidden != self.hidden_modules {
+            self.hidden_modules = hidden;
+            self.force_full_redraw = true;
+        }
+
+        if lemonbar::ENABLED.load(Ordering::Acquire) {
+            return self.draw_lemonbar(glyphs, quality, region_overlay);
+        }
+
+        let active_ws = self.active_workspace();
         let hour = TIME_HOURS.load(Ordering::Acquire);
         let minute = TIME_MINUTES.load(Ordering::Acquire);
         let day = DATE_DAY.load(Ordering::Acquire);
@@ -310,266 +1141,3638 @@ impl AppState {
         let battery_percent = BATTERY_PERCENT.load(Ordering::Acquire);
         let battery_state = BATTERY_STATE.load(Ordering::Acquire);
         let battery_estimate = BATTERY_ESTIMATE_M.load(Ordering::Acquire);
+        let battery_rate_dw = BATTERY_RATE_DW.load(Ordering::Acquire);
+        let ac_online = AC_ONLINE.load(Ordering::Acquire);
+        let battery_blink = BATTERY_BLINK.load(Ordering::Acquire);
+        let battery_health = match BATTERY_HEALTH.load(Ordering::Acquire) {
+            255 => (255, 0),
+            _ if !self.battery_health => (255, 0),
+            health => (health, BATTERY_CYCLES.load(Ordering::Acquire)),
+        };
+        let power_dw = RAPL_POWER_DW.load(Ordering::Acquire);
+        let power_profile = POWER_PROFILE.load(Ordering::Acquire);
+        let temperature = TEMPERATURE_C.load(Ordering::Acquire);
+        let throttled = CPU_THROTTLED.load(Ordering::Acquire);
+        let gpu_busy = GPU_BUSY_PERCENT.load(Ordering::Acquire);
+        let gpu_vram_mb = if config::get().gpu_vram {
+            GPU_VRAM_USED_MB.load(Ordering::Acquire)
+        } else {
+            u32::MAX
+        };
+        let disk_read_kbs = DISK_READ_KBS.load(Ordering::Acquire);
+        let disk_write_kbs = DISK_WRITE_KBS.load(Ordering::Acquire);
+        let net_samples = NET_SAMPLES.load(Ordering::Acquire);
+        let wifi_signal = WIFI_SIGNAL.load(Ordering::Acquire);
+        let wifi_ssid = WIFI_SSID.lock().map(|s| s.clone()).unwrap_or_default();
+        let ethernet_link = ETHERNET_LINK.load(Ordering::Acquire);
+        let ethernet_address = ETHERNET_ADDRESS
+            .lock()
+            .map(|a| a.clone())
+            .unwrap_or_default();
+        let nm_connectivity = NM_CONNECTIVITY.load(Ordering::Acquire);
+        let nm_connection = NM_CONNECTION.lock().map(|c| c.clone()).unwrap_or_default();
+        let bluetooth_powered = BLUETOOTH_POWERED.load(Ordering::Acquire);
+        let bluetooth_connected = BLUETOOTH_CONNECTED.load(Ordering::Acquire);
+        let bluetooth_battery = BLUETOOTH_BATTERY.load(Ordering::Acquire);
+        let bluetooth_device = BLUETOOTH_DEVICE
+            .lock()
+            .map(|d| d.clone())
+            .unwrap_or_default();
+        let idle_daemon = IDLE_DAEMON.load(Ordering::Acquire);
+        let idle_remaining_s = IDLE_REMAINING_S.load(Ordering::Acquire);
+        let idle_inhibited = IDLE_INHIBITED.load(Ordering::Acquire);
+        let dnd = DND.load(Ordering::Acquire);
+        let night_light = NIGHT_LIGHT.load(Ordering::Acquire);
+        let rfkill = RFKILL.load(Ordering::Acquire);
+        let removable_mounts = REMOVABLE_MOUNTS.load(Ordering::Acquire);
+        let failed_units = FAILED_UNITS.load(Ordering::Acquire);
+        let next_event_soon = NEXT_EVENT_SOON.load(Ordering::Acquire);
+        let next_event = NEXT_EVENT.lock().map(|e| e.clone()).unwrap_or_default();
+        let todo_open = TODO_OPEN.load(Ordering::Acquire);
+        let mail_unread = MAIL_UNREAD.load(Ordering::Acquire);
+        let updates_pending = UPDATES_PENDING.load(Ordering::Acquire);
+        let containers_running = CONTAINERS_RUNNING.load(Ordering::Acquire);
+        let custom_tooltip = if self.custom_tooltip {
+            CUSTOM_TOOLTIP.lock().map(|t| t.clone()).unwrap_or_default()
+        } else {
+            String::new()
+        };
+        // The tooltip replaces the text while there is one
+        let custom_text = if custom_tooltip.is_empty() {
+            CUSTOM_TEXT.lock().map(|t| t.clone()).unwrap_or_default()
+        } else {
+            custom_tooltip
+        };
+        let fifo_text = FIFO_TEXT.lock().map(|t| t.clone()).unwrap_or_default();
+        let custom_color = match CUSTOM_COLOR.load(Ordering::Acquire) {
+            0 => COLOR_CUSTOM,
+            color => color,
+        };
+        let mail_accounts = if self.mail_accounts {
+            MAIL_ACCOUNTS
+                .lock()
+                .map(|accounts| mail_accounts_text(&accounts))
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        let tray_generation = TRAY_GENERATION.load(Ordering::Acquire);
+        let taskbar_generation = TASKBAR_GENERATION.load(Ordering::Acquire);
+        let status_generation = STATUS_GENERATION.load(Ordering::Acquire);
+        let since_elapsed_s = SINCE_ELAPSED_S.load(Ordering::Acquire);
+        let timer_s = TIMER_S.load(Ordering::Acquire);
+        let timer_state = TIMER_STATE.load(Ordering::Acquire);
+        let pressure_some = PRESSURE_SOME.each_ref().map(|p| p.load(Ordering::Acquire));
+        let pressure_full = PRESSURE_FULL_MAX.load(Ordering::Acquire);
+        let backlight_percent = BACKLIGHT_PERCENT.load(Ordering::Acquire);
+        let kbd_backlight_level = KBD_BACKLIGHT_LEVEL.load(Ordering::Acquire);
+        let kbd_backlight_levels = KBD_BACKLIGHT_LEVELS.load(Ordering::Acquire);
+        let volume_percent = VOLUME_PERCENT.load(Ordering::Acquire);
+        let volume_muted = VOLUME_MUTED.load(Ordering::Acquire);
+        let mic_muted = MIC_MUTED.load(Ordering::Acquire);
+        let privacy = PRIVACY.load(Ordering::Acquire);
+        let audio_device = AUDIO_DEVICE.lock().map(|d| d.clone()).unwrap_or_default();
+        let mpris_status = MPRIS_STATUS.load(Ordering::Acquire);
+        let mpris_text = MPRIS_TEXT.lock().map(|t| t.clone()).unwrap_or_default();
+        let timezone = TIMEZONE.lock().map(|t| t.clone()).unwrap_or_default();
+        let layout = KEYBOARD_LAYOUT
+            .lock()
+            .map(|l| {
+                if l.differs_from_default {
+                    format!("{}*", l.code)
+                } else {
+                    l.code.clone()
+                }
+            })
+            .unwrap_or_default();
 
-        let mut current_ws_mask: u16 = 0;
-        for (i, ws) in WORKSPACES.iter().enumerate() {
-            if ws.load(Ordering::Acquire) {
-                current_ws_mask |= 1 << i;
-            }
-        }
+        let tiling = TILING_LAYOUT
+            .lock()
+            .map(|t| match t.name.as_str() {
+                "master" => format!("master {}", t.masters),
+                _ => t.name.clone(),
+            })
+            .unwrap_or_default();
+
+        let title = WINDOW_TITLE.lock().map(|t| t.clone()).unwrap_or_default();
+        let window_class = WINDOW_CLASS.lock().map(|c| c.clone()).unwrap_or_default();
+
+        let workspaces = visible_workspaces(active_ws);
 
-        let ws_changed = self.force_full_redraw
-            || current_ws_mask != self.cache.workspaces
-            || active_ws != self.cache.active_ws;
-        let clock_changed =
-            self.force_full_redraw || hour != self.cache.hour || minute != self.cache.minute;
-        let date_changed = self.force_full_redraw
-            || day != self.cache.day
-            || month != self.cache.month
-            || year != self.cache.year;
-        let bat_changed = self.force_full_redraw
-            || battery_percent != self.cache.bat_percent
-            || battery_state != self.cache.bat_state
-            || battery_estimate != self.cache.bat_est_min;
+        // The capsule only has room for the clock and the battery
+        let full_bar = config::get().capsule.is_none();
+        let shown = |module| !self.hidden_modules.contains(&module);
+        let ws_changed = full_bar
+            && (self.force_full_redraw
+                || workspaces != self.cache.workspaces
+                || active_ws != self.cache.active_ws);
+        let clock_changed = shown(Module::Clock)
+            && (self.force_full_redraw
+                || hour != self.cache.hour
+                || minute != self.cache.minute
+                || timezone != self.cache.timezone);
+        let date_changed = full_bar
+            && shown(Module::Date)
+            && (self.force_full_redraw
+                || day != self.cache.day
+                || month != self.cache.month
+                || year != self.cache.year);
+        let bat_changed = shown(Module::Battery)
+            && (self.force_full_redraw
+                || battery_percent != self.cache.bat_percent
+                || battery_state != self.cache.bat_state
+                || battery_estimate != self.cache.bat_est_min
+                || battery_rate_dw != self.cache.bat_rate_dw
+                || ac_online != self.cache.ac_online
+                || battery_blink != self.cache.bat_blink
+                || battery_health != self.cache.bat_health);
+        let power_changed = full_bar
+            && shown(Module::Power)
+            && (self.force_full_redraw || power_dw != self.cache.power_dw);
+        let power_profile_changed = full_bar
+            && shown(Module::PowerProfile)
+            && (self.force_full_redraw || power_profile != self.cache.power_profile);
+        let temperature_changed = full_bar
+            && shown(Module::Temperature)
+            && (self.force_full_redraw
+                || temperature != self.cache.temperature
+                || throttled != self.cache.throttled);
+        let gpu_changed = full_bar
+            && shown(Module::Gpu)
+            && (self.force_full_redraw
+                || gpu_busy != self.cache.gpu_busy
+                || gpu_vram_mb != self.cache.gpu_vram_mb);
+        let disk_changed = full_bar
+            && shown(Module::Disk)
+            && (self.force_full_redraw
+                || disk_read_kbs != self.cache.disk_read_kbs
+                || disk_write_kbs != self.cache.disk_write_kbs);
+        let net_changed = full_bar
+            && shown(Module::Network)
+            && (self.force_full_redraw
+                || net_samples.wrapping_sub(self.cache.net_samples)
+                    >= quality.sparkline_interval());
+        let wifi_changed = full_bar
+            && shown(Module::Wifi)
+            && (self.force_full_redraw
+                || wifi_signal != self.cache.wifi_signal
+                || wifi_ssid != self.cache.wifi_ssid);
+        let ethernet_changed = full_bar
+            && shown(Module::Ethernet)
+            && (self.force_full_redraw
+                || ethernet_link != self.cache.ethernet_link
+                || ethernet_address != self.cache.ethernet_address);
+        let connectivity_changed = full_bar
+            && shown(Module::Connectivity)
+            && (self.force_full_redraw
+                || nm_connectivity != self.cache.nm_connectivity
+                || nm_connection != self.cache.nm_connection);
+        let bluetooth_changed = full_bar
+            && shown(Module::Bluetooth)
+            && (self.force_full_redraw
+                || bluetooth_powered != self.cache.bluetooth_powered
+                || bluetooth_connected != self.cache.bluetooth_connected
+                || bluetooth_device != self.cache.bluetooth_device
+                || bluetooth_battery != self.cache.bluetooth_battery);
+        let idle_changed = full_bar
+            && shown(Module::Idle)
+            && (self.force_full_redraw
+                || idle_daemon != self.cache.idle_daemon
+                || idle_remaining_s != self.cache.idle_remaining_s);
+        let caffeine_changed = full_bar
+            && shown(Module::Caffeine)
+            && (self.force_full_redraw || idle_inhibited != self.cache.idle_inhibited);
+        let dnd_changed =
+            full_bar && shown(Module::Dnd) && (self.force_full_redraw || dnd != self.cache.dnd);
+        let night_light_changed = full_bar
+            && shown(Module::NightLight)
+            && (self.force_full_redraw || night_light != self.cache.night_light);
+        let rfkill_changed = full_bar
+            && shown(Module::Rfkill)
+            && (self.force_full_redraw || rfkill != self.cache.rfkill);
+        let removable_changed = full_bar
+            && shown(Module::Removable)
+            && (self.force_full_redraw || removable_mounts != self.cache.removable_mounts);
+        let failed_units_changed = full_bar
+            && shown(Module::FailedUnits)
+            && (self.force_full_redraw || failed_units != self.cache.failed_units);
+        let next_event_changed = full_bar
+            && shown(Module::NextEvent)
+            && (self.force_full_redraw
+                || next_event_soon != self.cache.next_event_soon
+                || next_event != self.cache.next_event);
+        let updates_changed = full_bar
+            && shown(Module::Updates)
+            && (self.force_full_redraw || updates_pending != self.cache.updates_pending);
+        let containers_changed = full_bar
+            && shown(Module::Containers)
+            && (self.force_full_redraw || containers_running != self.cache.containers_running);
+        let custom_changed = full_bar
+            && shown(Module::Custom)
+            && (self.force_full_redraw
+                || custom_text != self.cache.custom_text
+                || custom_color != self.cache.custom_color);
+        let mail_changed = full_bar
+            && shown(Module::Mail)
+            && (self.force_full_redraw
+                || mail_unread != self.cache.mail_unread
+                || mail_accounts != self.cache.mail_accounts);
+        let todo_changed = full_bar
+            && shown(Module::Todo)
+            && (self.force_full_redraw || todo_open != self.cache.todo_open);
+        let tray_changed = full_bar
+            && shown(Module::Tray)
+            && (self.force_full_redraw || tray_generation != self.cache.tray_generation);
+        let taskbar_changed = full_bar
+            && shown(Module::Taskbar)
+            && (self.force_full_redraw || taskbar_generation != self.cache.taskbar_generation);
+        let fifo_changed = full_bar
+            && shown(Module::Fifo)
+            && (self.force_full_redraw || fifo_text != self.cache.fifo_text);
+        let status_changed = full_bar
+            && shown(Module::Status)
+            && (self.force_full_redraw || status_generation != self.cache.status_generation);
+        let since_changed = full_bar
+            && shown(Module::Since)
+            && (self.force_full_redraw || since_elapsed_s != self.cache.since_elapsed_s);
+        let timer_changed = full_bar
+            && shown(Module::Timer)
+            && (self.force_full_redraw
+                || timer_s != self.cache.timer_s
+                || timer_state != self.cache.timer_state);
+        let pressure_changed = full_bar
+            && shown(Module::Pressure)
+            && (self.force_full_redraw
+                || pressure_some != self.cache.pressure_some
+                || pressure_full != self.cache.pressure_full);
+        let backlight_changed = full_bar
+            && shown(Module::Backlight)
+            && (self.force_full_redraw || backlight_percent != self.cache.backlight_percent);
+        let kbd_backlight_changed = full_bar
+            && shown(Module::KeyboardBacklight)
+            && (self.force_full_redraw
+                || kbd_backlight_level != self.cache.kbd_backlight_level
+                || kbd_backlight_levels != self.cache.kbd_backlight_levels);
+        let volume_changed = full_bar
+            && shown(Module::Volume)
+            && (self.force_full_redraw
+                || volume_percent != self.cache.volume_percent
+                || volume_muted != self.cache.volume_muted);
+        let mic_changed = full_bar
+            && shown(Module::Microphone)
+            && (self.force_full_redraw || mic_muted != self.cache.mic_muted);
+        let privacy_changed = full_bar
+            && shown(Module::Privacy)
+            && (self.force_full_redraw || privacy != self.cache.privacy);
+        let audio_device_changed = full_bar
+            && shown(Module::AudioDevice)
+            && (self.force_full_redraw || audio_device != self.cache.audio_device);
+        let mpris_changed = full_bar
+            && shown(Module::Mpris)
+            && (self.force_full_redraw
+                || mpris_status != self.cache.mpris_status
+                || mpris_text != self.cache.mpris_text);
+        // The title starts after the workspace strip and moves with it
+        let title_changed = full_bar
+            && (ws_changed || title != self.cache.title || window_class != self.cache.window_class);
+        let layout_changed = full_bar
+            && shown(Module::KeyboardLayout)
+            && (self.force_full_redraw || layout != self.cache.layout);
+        let tiling_changed = full_bar
+            && shown(Module::Tiling)
+            && (self.force_full_redraw || tiling != self.cache.tiling);
 
-        if !ws_changed && !clock_changed && !date_changed && !bat_changed {
+        if !ws_changed
+            && !title_changed
+            && !clock_changed
+            && !date_changed
+            && !bat_changed
+            && !power_changed
+            && !power_profile_changed
+            && !temperature_changed
+            && !gpu_changed
+            && !disk_changed
+            && !net_changed
+            && !wifi_changed
+            && !ethernet_changed
+            && !connectivity_changed
+            && !bluetooth_changed
+            && !idle_changed
+            && !caffeine_changed
+            && !dnd_changed
+            && !night_light_changed
+            && !rfkill_changed
+            && !removable_changed
+            && !failed_units_changed
+            && !next_event_changed
+            && !todo_changed
+            && !mail_changed
+            && !updates_changed
+            && !containers_changed
+            && !custom_changed
+            && !tray_changed
+            && !taskbar_changed
+            && !status_changed
+            && !fifo_changed
+            && !since_changed
+            && !timer_changed
+            && !pressure_changed
+            && !backlight_changed
+            && !kbd_backlight_changed
+            && !volume_changed
+            && !mic_changed
+            && !privacy_changed
+            && !audio_device_changed
+            && !mpris_changed
+            && !layout_changed
+            && !tiling_changed
+        {
             return false;
         }
 
+        if self.force_full_redraw {
+            self.layout = Layout::compute(self.width as usize, glyphs);
+        }
+
         let slice = unsafe {
             std::slice::from_raw_parts_mut(self.pixels, (self.width * self.height) as usize)
         };
-        let mut pb = PixelBuffer::new(slice, self.width as usize, self.height as usize);
-        let glyphs = self.glyphs.as_ref().unwrap();
+        let mut pb = PixelBuffer::new(slice, self.width as usize, self.height as usize, quality);
 
         let mut renderer = Renderer {
             pb: &mut pb,
             glyphs,
             cache: &mut self.cache,
+            regions: &mut self.regions,
             surface: self.wl_surface.as_ref(),
             height: self.height,
+            layout: &self.layout,
+            last_slot: None,
         };
 
+        // Start from a clean buffer so nothing outside the slots survives a resize,
+        // every module shown puts its click region back
+        if self.force_full_redraw {
+            renderer.clear_and_damage_slot(0, self.width as usize);
+            renderer.regions.clear();
+        }
+
         if ws_changed {
-            renderer.draw_workspaces(active_ws, current_ws_mask);
+            renderer.draw_workspaces(active_ws, workspaces);
+        }
+
+        if title_changed {
+            renderer.draw_title_module(title, window_class);
         }
 
-        let center = renderer.pb.width / 2;
         if date_changed {
-            renderer.draw_date_module(center, day, month, year);
+            renderer.draw_date_module(day, month, year);
         }
 
         if clock_changed {
-            renderer.draw_clock_module(center, hour, minute);
+            renderer.draw_clock_module(hour, minute, timezone);
         }
 
         if bat_changed && battery_state != 255 {
-            renderer.draw_battery_module(battery_percent, battery_state, battery_estimate);
+            renderer.draw_battery_module(
+                BatteryReading {
+                    percent: battery_percent,
+                    state: battery_state,
+                    estimate_m: battery_estimate,
+                    rate_dw: battery_rate_dw,
+                },
+                ac_online,
+                battery_blink,
+                battery_health,
+            );
         }
 
-        self.force_full_redraw = false;
-        true
-    }
-}
+        if power_changed && power_dw != u16::MAX {
+            renderer.draw_power_module(power_dw);
+        }
+
+        if power_profile_changed && power_profile != 255 {
+            renderer.draw_power_profile_module(power_profile);
+        }
+
+        if temperature_changed && temperature != 255 {
+            renderer.draw_temperature_module(temperature, throttled);
+        }
+
+        if gpu_changed && gpu_busy != 255 {
+            renderer.draw_gpu_module(gpu_busy, gpu_vram_mb);
+        }
+
+        if disk_changed && disk_read_kbs != u32::MAX {
+            renderer.draw_disk_module(disk_read_kbs, disk_write_kbs);
+        }
+
+        if net_changed && net_samples != 0 {
+            let history: Vec<u32> = NET_HISTORY
+                .lock()
+                .map(|h| h.iter().copied().collect())
+                .unwrap_or_default();
+            renderer.draw_network_module(net_samples, &history);
+        }
+
+        if wifi_changed {
+            renderer.draw_wifi_module(wifi_ssid, wifi_signal);
+        }
+
+        if ethernet_changed && ethernet_link != 255 {
+            renderer.draw_ethernet_module(ethernet_link, ethernet_address);
+        }
+
+        if connectivity_changed && nm_connectivity != 255 {
+            renderer.draw_connectivity_module(nm_connectivity, nm_connection);
+        }
+
+        if bluetooth_changed {
+            renderer.draw_bluetooth_module(
+                bluetooth_powered,
+                bluetooth_connected,
+                bluetooth_device,
+                bluetooth_battery,
+            );
+        }
+
+        if idle_changed && idle_daemon != 255 {
+            renderer.draw_idle_module(idle_daemon, idle_remaining_s);
+        }
+
+        if caffeine_changed && idle_inhibited != 255 {
+            renderer.draw_caffeine_module(idle_inhibited);
+        }
+
+        if dnd_changed && dnd != 255 {
+            renderer.draw_dnd_module(dnd);
+        }
+
+        if night_light_changed {
+            renderer.draw_night_light_module(night_light);
+        }
+
+        if rfkill_changed && rfkill != 255 {
+            renderer.draw_rfkill_module(rfkill);
+        }
+
+        if removable_changed {
+            renderer.draw_removable_module(removable_mounts);
+        }
+
+        if failed_units_changed && failed_units != u16::MAX {
+            renderer.draw_failed_units_module(failed_units);
+        }
+
+        if next_event_changed {
+            renderer.draw_next_event_module(next_event_soon, next_event);
+        }
+
+        if todo_changed {
+            renderer.draw_todo_module(todo_open);
+        }
+
+        if mail_changed && mail_unread != u32::MAX {
+            renderer.draw_mail_module(mail_unread, mail_accounts);
+        }
+
+        if updates_changed && updates_pending != u16::MAX {
+            renderer.draw_updates_module(updates_pending);
+        }
+
+        if containers_changed {
+            renderer.draw_containers_module(containers_running);
+        }
+
+        if custom_changed {
+            renderer.draw_custom_module(custom_text, custom_color);
+        }
+
+        if fifo_changed {
+            renderer.draw_fifo_module(fifo_text);
+        }
+
+        if status_changed {
+            let blocks = STATUS_BLOCKS.lock().map(|b| b.clone()).unwrap_or_default();
+            renderer.draw_status_module(&blocks, status_generation);
+        }
+
+        if tray_changed {
+            let items = TRAY.lock().map(|t| t.clone()).unwrap_or_default();
+            renderer.draw_tray_module(&items, tray_generation);
+        }
+
+        if taskbar_changed {
+            let entries = TASKBAR.lock().map(|t| t.clone()).unwrap_or_default();
+            renderer.draw_taskbar_module(&entries, taskbar_generation);
+        }
+
+        if since_changed {
+            renderer.draw_since_module(since_elapsed_s);
+        }
+
+        if timer_changed {
+            renderer.draw_timer_module(timer_s, timer_state);
+        }
+
+        if pressure_changed && pressure_some[0] != u16::MAX {
+            renderer.draw_pressure_module(pressure_some, pressure_full);
+        }
+
+        if backlight_changed && backlight_percent != 255 {
+            renderer.draw_backlight_module(backlight_percent);
+        }
+
+        if kbd_backlight_changed && kbd_backlight_level != 255 {
+            renderer.draw_keyboard_backlight_module(kbd_backlight_level, kbd_backlight_levels);
+        }
+
+        if volume_changed && volume_percent != 255 {
+            renderer.draw_volume_module(volume_percent, volume_muted);
+        }
+
+        if mic_changed {
+            renderer.draw_microphone_module(mic_muted);
+        }
+
+        if privacy_changed {
+            renderer.draw_privacy_module(privacy);
+        }
+
+        if audio_device_changed {
+            renderer.draw_audio_device_module(audio_device);
+        }
+
+        if mpris_changed {
+            renderer.draw_mpris_module(mpris_status, mpris_text);
+        }
+
+        if layout_changed {
+            renderer.draw_layout_module(layout);
+        }
+
+        if tiling_changed {
+            renderer.draw_tiling_module(tiling);
+        }
+
+        if region_overlay {
+            renderer.outline_regions();
+        }
+
+        let last_slot = renderer.last_slot;
+        if !self.force_full_redraw
+            && quality.animations()
+            && let Some((x, width)) = last_slot
+        {
+            self.start_highlight(x, width);
+        }
+
+        self.force_full_redraw = false;
+        true
+    }
+
+    /// `--lemonbar` replaces every module with the text read from stdin.
+    fn draw_lemonbar(
+        &mut self,
+        glyphs: &font_renderer::GlyphCache,
+        quality: Quality,
+        region_overlay: bool,
+    ) -> bool {
+        let generation = LEMONBAR_GENERATION.load(Ordering::Acquire);
+        if !self.force_full_redraw && generation == self.cache.lemonbar_generation {
+            return false;
+        }
+        let segments = LEMONBAR.lock().map(|s| s.clone()).unwrap_or_default();
+
+        let slice = unsafe {
+            std::slice::from_raw_parts_mut(self.pixels, (self.width * self.height) as usize)
+        };
+        let mut pb = PixelBuffer::new(slice, self.width as usize, self.height as usize, quality);
+        let mut renderer = Renderer {
+            pb: &mut pb,
+            glyphs,
+            cache: &mut self.cache,
+            regions: &mut self.regions,
+            surface: self.wl_surface.as_ref(),
+            height: self.height,
+            layout: &self.layout,
+            last_slot: None,
+        };
+        renderer.draw_lemonbar(&segments, self.width as usize, generation);
+        if region_overlay {
+            renderer.outline_regions();
+        }
+
+        self.force_full_redraw = false;
+        true
+    }
+
+    fn destroy(mut self) {
+        self.release_buffer();
+        if let Some(layer_surface) = self.layer_surface.take() {
+            layer_surface.destroy();
+        }
+        if let Some(surface) = self.wl_surface.take() {
+            surface.destroy();
+        }
+        if let Some(output) = self.output.take()
+            && output.version() >= 3
+        {
+            output.release();
+        }
+    }
+}
+
+pub struct AppState {
+    pub compositor: Option<WlCompositor>,
+    pub shm: Option<WlShm>,
+    pub layer_shell: Option<ZwlrLayerShellV1>,
+    pub seat: Option<WlSeat>,
+
+    bars: Vec<Bar>,
+    pub(crate) pointer: PointerState,
+    pub(crate) idle: IdleState,
+    pub(crate) color: ColorState,
+    pub(crate) calendar: CalendarState,
+    pub(crate) tray_menu: TrayMenuState,
+    pub(crate) taskbar: TaskbarState,
+
+    pub glyphs: Option<font_renderer::GlyphCache>,
+    pub event_log: EventLog,
+    pub(crate) qh: Option<QueueHandle<AppState>>,
+}
+
+impl AppState {
+    pub fn new(glyphs: Option<font_renderer::GlyphCache>) -> Self {
+        Self {
+            compositor: None,
+            shm: None,
+            layer_shell: None,
+            seat: None,
+            bars: Vec::new(),
+            pointer: PointerState::default(),
+            idle: IdleState::default(),
+            color: ColorState::default(),
+            calendar: CalendarState::default(),
+            tray_menu: TrayMenuState::default(),
+            taskbar: TaskbarState::default(),
+            glyphs,
+            event_log: EventLog::new(config::get().event_log),
+            qh: None,
+        }
+    }
+
+    pub fn has_required_globals(&self) -> bool {
+        self.compositor.is_some() && self.shm.is_some() && self.layer_shell.is_some()
+    }
+
+    /// Creates a layer surface for every known output that does not have one yet.
+    /// If the compositor advertised no outputs, a single surface is created and
+    /// the compositor picks where it goes.
+    pub fn initialize_layer_surfaces(
+        &mut self,
+        qh: &QueueHandle<Self>,
+    ) -> Result<(), LeanbarError> {
+        let compositor = self
+            .compositor
+            .as_ref()
+            .ok_or_else(|| LeanbarError::Wayland("missing wl_compositor".into()))?;
+        let layer_shell = self
+            .layer_shell
+            .as_ref()
+            .ok_or_else(|| LeanbarError::Wayland("missing zwlr_layer_shell_v1".into()))?;
+
+        self.qh = Some(qh.clone());
+        if self.bars.is_empty() {
+            self.bars.push(Bar::new(0, None));
+        }
+
+        for bar in self.bars.iter_mut() {
+            if bar.layer_surface.is_none() {
+                bar.create_layer_surface(compositor, layer_shell, self.glyphs.as_ref(), qh);
+            }
+        }
+        self.tag_surfaces();
+
+        Ok(())
+    }
+
+    /// Whether any bar has put a frame on screen yet.
+    pub fn has_committed_frame(&self) -> bool {
+        self.bars.iter().any(|bar| bar.committed)
+    }
+
+    pub fn redraw_and_commit(&mut self) {
+        for bar in self.bars.iter_mut() {
+            bar.redraw_and_commit(self.glyphs.as_ref(), self.qh.as_ref());
+        }
+        self.publish_regions();
+    }
+
+    /// How long the event loop may wait before an animation is due a frame,
+    /// None while nothing animates.
+    pub fn animation_timeout(&self) -> Option<Duration> {
+        self.bars
+            .iter()
+            .filter_map(|bar| bar.highlight.as_ref())
+            .map(|highlight| ANIMATION_TICK.saturating_sub(highlight.drawn.elapsed()))
+            .min()
+    }
+
+    /// Draws the next frame of animations whose frame callback is overdue.
+    pub fn tick_animations(&mut self) {
+        for bar in self.bars.iter_mut() {
+            let Some(highlight) = &mut bar.highlight else {
+                continue;
+            };
+            if highlight.drawn.elapsed() < ANIMATION_TICK {
+                continue;
+            }
+            // Redrawing is up to the bar, the timer must not fire again right away
+            highlight.drawn = Instant::now();
+            bar.redraw_and_commit(self.glyphs.as_ref(), self.qh.as_ref());
+        }
+    }
+
+    /// Writes the click regions of every bar where the control thread can read them.
+    fn publish_regions(&self) {
+        let mut map = format!(
+            "{:<10} {:<16} {:>5} {:>5} {:>6}  {}\n",
+            "OUTPUT", "MODULE", "X", "WIDTH", "HEIGHT", "ACTION"
+        );
+        for bar in &self.bars {
+            for region in &bar.regions {
+                let _ = writeln!(
+                    map,
+                    "{:<10} {:<16} {:>5} {:>5} {:>6}  {}",
+                    bar.output_name.as_deref().unwrap_or("-"),
+                    region.target.name(),
+                    region.start,
+                    region.end - region.start,
+                    bar.height,
+                    region.target.action()
+                );
+            }
+        }
+        if let Ok(mut published) = REGION_MAP.lock() {
+            *published = map;
+        }
+    }
+
+    fn add_output(&mut self, id: u32, output: WlOutput, qh: &QueueHandle<Self>) {
+        // Drop the output-less fallback bar once a real output shows up
+        if let Some(idx) = self.bars.iter().position(|b| b.output.is_none()) {
+            self.bars.remove(idx).destroy();
+        }
+
+        let mut bar = Bar::new(id, Some(output));
+        if let (Some(compositor), Some(layer_shell)) = (&self.compositor, &self.layer_shell) {
+            bar.create_layer_surface(compositor, layer_shell, self.glyphs.as_ref(), qh);
+        }
+        self.bars.push(bar);
+        self.tag_surfaces();
+    }
+
+    fn remove_bar(&mut self, id: u32) {
+        self.rehome_idle_inhibitor(id);
+        if let Some(idx) = self.bars.iter().position(|b| b.id == id) {
+            self.bars.remove(idx).destroy();
+        }
+    }
+
+    pub(crate) fn bar_ids(&self) -> Vec<u32> {
+        self.bars.iter().map(|b| b.id).collect()
+    }
+
+    pub(crate) fn bar_surface(&self, id: u32) -> Option<WlSurface> {
+        self.bars
+            .iter()
+            .find(|b| b.id == id)
+            .and_then(|b| b.wl_surface.clone())
+    }
+
+    pub(crate) fn bar_surfaces(&self) -> Vec<WlSurface> {
+        self.bars
+            .iter()
+            .filter_map(|b| b.wl_surface.clone())
+            .collect()
+    }
+
+    /// Output of a bar, None for the fallback bar the compositor placed.
+    pub(crate) fn bar_output(&self, id: u32) -> Option<WlOutput> {
+        self.bars.iter().find(|b| b.id == id)?.output.clone()
+    }
+
+    /// Swaps the battery module of the given bar for the battery's health,
+    /// None puts every bar back.
+    pub(crate) fn show_battery_health(&mut self, bar: Option<u32>) {
+        for b in self.bars.iter_mut() {
+            b.battery_health = Some(b.id) == bar;
+        }
+        self.redraw_and_commit();
+    }
+
+    pub(crate) fn show_mail_accounts(&mut self, bar: Option<u32>) {
+        for b in self.bars.iter_mut() {
+            b.mail_accounts = Some(b.id) == bar;
+        }
+        self.redraw_and_commit();
+    }
+
+    pub(crate) fn show_custom_tooltip(&mut self, bar: Option<u32>) {
+        for b in self.bars.iter_mut() {
+            b.custom_tooltip = Some(b.id) == bar;
+        }
+        self.redraw_and_commit();
+    }
+
+    pub(crate) fn force_full_redraw(&mut self) {
+        for bar in self.bars.iter_mut() {
+            bar.force_full_redraw = true;
+        }
+    }
+
+    pub(crate) fn bar_for_surface(&self, surface: &WlSurface) -> Option<u32> {
+        self.bars
+            .iter()
+            .find(|b| b.wl_surface.as_ref() == Some(surface))
+            .map(|b| b.id)
+    }
+
+    pub(crate) fn bar_active_workspace(&self, id: u32) -> i32 {
+        self.bars.iter().find(|b| b.id == id).map_or_else(
+            || ACTIVE_WORKSPACE.load(Ordering::Acquire),
+            Bar::active_workspace,
+        )
+    }
+
+    /// Forwards a click on a status block to `status_command`, `button` numbered
+    /// like X11's.
+    pub(crate) fn click_status_block(&self, id: u32, index: u8, button: u8, x: f64, y: f64) {
+        let Some(bar) = self.bars.iter().find(|b| b.id == id) else {
+            return;
+        };
+        let Some(region) = bar
+            .regions
+            .iter()
+            .find(|r| r.target == ClickTarget::Status(index))
+        else {
+            return;
+        };
+        let x = x as usize;
+        i3bar::click(
+            index,
+            i3bar::ClickEvent {
+                button,
+                x,
+                y: y as usize,
+                relative_x: x.saturating_sub(region.start),
+                width: region.end - region.start,
+                height: bar.height as usize,
+            },
+        );
+    }
+
+    pub(crate) fn target_at(&self, id: u32, x: f64) -> Option<ClickTarget> {
+        let x = x as usize;
+        self.bars
+            .iter()
+            .find(|b| b.id == id)?
+            .regions
+            .iter()
+            .find(|r| x >= r.start && x < r.end)
+            .map(|r| r.target)
+    }
+}
+
+#[cfg(not(target_os = "openbsd"))]
+fn shm_fd() -> rustix::io::Result<OwnedFd> {
+    memfd_create("leanbar-shm", MemfdFlags::CLOEXEC)
+}
+
+/// A POSIX shared memory object unlinked right away, there is no memfd here.
+#[cfg(target_os = "openbsd")]
+fn shm_fd() -> rustix::io::Result<OwnedFd> {
+    use rustix::shm;
+    use std::sync::atomic::AtomicU32;
+
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    let name = format!(
+        "/leanbar-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    );
+    let fd = shm::open(
+        &name,
+        shm::OFlags::CREATE | shm::OFlags::EXCL | shm::OFlags::RDWR,
+        shm::Mode::RUSR | shm::Mode::WUSR,
+    )?;
+    shm::unlink(&name)?;
+    Ok(fd)
+}
+
+/// A `w` x `h` ARGB shm buffer with its pixels mapped, returns the buffer, the
+/// pixels and their length in bytes.
+pub(crate) fn create_buffer(
+    shm: &WlShm,
+    w: u32,
+    h: u32,
+    qh: &QueueHandle<AppState>,
+) -> (WlBuffer, *mut u32, usize) {
+    let stride = w * 4;
+    let size = stride * h;
+
+    let memfd = shm_fd().unwrap();
+    ftruncate(&memfd, size as u64).unwrap();
+
+    let ptr = unsafe {
+        mmap(
+            ptr::null_mut(),
+            size as usize,
+            ProtFlags::READ | ProtFlags::WRITE,
+            MapFlags::SHARED,
+            &memfd,
+            0,
+        )
+        .unwrap()
+    };
+
+    let pool = shm.create_pool(memfd.as_fd(), size as i32, qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        w as i32,
+        h as i32,
+        stride as i32,
+        wl_shm::Format::Argb8888,
+        qh,
+        (),
+    );
+    (buffer, ptr.cast(), size as usize)
+}
+
+/// Destroys a buffer from `create_buffer` and unmaps its pixels.
+pub(crate) fn destroy_buffer(buffer: WlBuffer, pixels: *mut u32, len: usize) {
+    buffer.destroy();
+    if !pixels.is_null() && len > 0 {
+        let _ = unsafe { munmap(pixels.cast(), len) };
+    }
+}
+
+fn monitor_active_workspace(name: &str) -> Option<i32> {
+    MONITORS
+        .lock()
+        .ok()?
+        .iter()
+        .find(|m| m.name == name)
+        .map(|m| m.active_ws)
+}
+
+fn workspace_fullscreen(id: i32) -> bool {
+    WORKSPACES
+        .lock()
+        .is_ok_and(|w| w.iter().any(|ws| ws.id == id && ws.fullscreen))
+}
+
+/// A workspace as drawn in the strip.
+#[derive(Clone, PartialEq, Eq)]
+struct WorkspaceLabel {
+    id: i32,
+    name: String,
+    /// The workspace lives on a monitor without keyboard focus.
+    dimmed: bool,
+    windows: u16,
+}
+
+/// Workspaces shown in the strip: numbered ones in order followed by named ones,
+/// special workspaces are left out. The active workspace is always included.
+fn visible_workspaces(active_ws: i32) -> Vec<WorkspaceLabel> {
+    let focused_monitor = FOCUSED_MONITOR
+        .lock()
+        .map(|m| m.clone())
+        .unwrap_or_default();
+    let mut workspaces: Vec<WorkspaceLabel> = WORKSPACES
+        .lock()
+        .map(|g| {
+            g.iter()
+                .filter(|w| !w.is_special())
+                .map(|w| WorkspaceLabel {
+                    id: w.id,
+                    name: w.name.clone(),
+                    dimmed: !focused_monitor.is_empty()
+                        && !w.monitor.is_empty()
+                        && w.monitor != focused_monitor,
+                    windows: w.windows,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if active_ws > 0 && !workspaces.iter().any(|w| w.id == active_ws) {
+        workspaces.push(WorkspaceLabel {
+            id: active_ws,
+            name: active_ws.to_string(),
+            dimmed: false,
+            windows: 0,
+        });
+    }
+    workspaces.sort_by_key(|w| (w.id <= 0, w.id.unsigned_abs()));
+    workspaces
+}
+
+/// Minimap boxes grow with the window count, up to eight windows.
+fn minimap_box_width(windows: u16) -> usize {
+    MINIMAP_BOX_BASE + windows.min(8) as usize * MINIMAP_BOX_PER_WINDOW
+}
+
+/// For every workspace, the configured group that begins with it. Named
+/// workspaces are never grouped.
+fn group_starts(workspaces: &[WorkspaceLabel]) -> Vec<Option<&'static WorkspaceGroup>> {
+    let groups = &config::get().workspace_groups;
+    let mut previous = None;
+    workspaces
+        .iter()
+        .map(|w| {
+            let group = groups
+                .iter()
+                .find(|g| is_numbered(w.id, &w.name) && g.contains(w.id));
+            let starts = group.filter(|g| previous != Some(*g));
+            previous = group;
+            starts
+        })
+        .collect()
+}
+
+// helper to coordinate drawing a single frame.
+struct Renderer<'a> {
+    pb: &'a mut PixelBuffer<'a>,
+    glyphs: &'a font_renderer::GlyphCache,
+    cache: &'a mut DrawCache,
+    regions: &'a mut Vec<ClickRegion>,
+    surface: Option<&'a WlSurface>,
+    height: u32,
+    layout: &'a Layout,
+    /// Slot cleared most recently, i.e. the last module drawn this frame.
+    last_slot: Option<(usize, usize)>,
+}
+
+impl<'a> Renderer<'a> {
+    fn clear_and_damage_slot(&mut self, x: usize, width: usize) {
+        self.last_slot = Some((x, width));
+        self.pb.clear_rect(x, width);
+        if let Some(surface) = self.surface {
+            surface.damage_buffer(x as i32, 0, width as i32, self.height as i32);
+        }
+    }
+
+    /// Debug outlines around the click regions, drawn over the modules.
+    fn outline_regions(&mut self) {
+        let height = self.height as usize;
+        for region in self.regions.iter() {
+            let (x, width) = (region.start, region.end - region.start);
+            self.pb.fill_rect(x, 0, width, 1, COLOR_REGION_OUTLINE);
+            self.pb
+                .fill_rect(x, height - 1, width, 1, COLOR_REGION_OUTLINE);
+            self.pb.fill_rect(x, 0, 1, height, COLOR_REGION_OUTLINE);
+            self.pb
+                .fill_rect(x + width - 1, 0, 1, height, COLOR_REGION_OUTLINE);
+            if let Some(surface) = self.surface {
+                surface.damage_buffer(x as i32, 0, width as i32, height as i32);
+            }
+        }
+    }
+
+    /// Wide bars can show workspaces as a minimap, see `workspace_minimap_min_width`.
+    fn minimap(&self) -> bool {
+        let min_width = config::get().workspace_minimap_min_width;
+        min_width > 0 && self.pb.width >= min_width
+    }
+
+    fn measure_workspace(&self, ws: &WorkspaceLabel) -> usize {
+        if self.minimap() {
+            minimap_box_width(ws.windows)
+        } else if is_numbered(ws.id, &ws.name) {
+            PixelBuffer::measure_num(self.glyphs, ws.id as u32, 1, 1)
+        } else {
+            PixelBuffer::measure_text(self.glyphs, &ws.name)
+        }
+    }
+
+    /// A filled box for a workspace with windows, an outline for an empty one.
+    fn draw_minimap_box(&mut self, x: &mut usize, windows: u16, color: u32) {
+        let width = minimap_box_width(windows);
+        let height = self.height as usize;
+        let (top, box_height) = (height / 4, height / 2);
+        if windows > 0 {
+            self.pb.fill_rect(*x, top, width, box_height, color);
+        } else {
+            self.pb.fill_rect(*x, top, width, 1, color);
+            self.pb.fill_rect(*x, top + box_height - 1, width, 1, color);
+            self.pb.fill_rect(*x, top, 1, box_height, color);
+            self.pb.fill_rect(*x + width - 1, top, 1, box_height, color);
+        }
+        *x += width;
+    }
+
+    /// Space taken by the separator and label in front of a group's first workspace.
+    fn measure_group_start(&self, group: &WorkspaceGroup, first: bool) -> usize {
+        let line = if first { 0 } else { GROUP_SEPARATOR_WIDTH };
+        let label = if group.label.is_empty() {
+            0
+        } else {
+            PixelBuffer::measure_text(self.glyphs, &group.label) + GROUP_LABEL_GAP
+        };
+        line + label
+    }
+
+    fn draw_workspaces(&mut self, active_ws: i32, workspaces: Vec<WorkspaceLabel>) {
+        let group_starts = group_starts(&workspaces);
+        let total_width: usize = workspaces
+            .iter()
+            .zip(&group_starts)
+            .enumerate()
+            .map(|(i, (w, group))| {
+                let group_width = group.map_or(0, |g| self.measure_group_start(g, i == 0));
+                group_width + self.measure_workspace(w) + 10
+            })
+            .sum();
+
+        let old_width = self.cache.ws_render_width;
+        self.cache.active_ws = active_ws;
+        self.cache.ws_render_width = total_width;
+
+        self.clear_and_damage_slot(0, old_width.max(total_width));
+
+        self.regions
+            .retain(|r| !matches!(r.target, ClickTarget::Workspace(_)));
+
+        let mut cursor_x = MARGIN_LEFT;
+        for (ws, group) in workspaces.iter().zip(&group_starts) {
+            if let Some(group) = group {
+                let color = dim(COLOR_WS_OPEN);
+                if cursor_x != MARGIN_LEFT {
+                    let height = self.height as usize;
+                    self.pb
+                        .fill_rect(cursor_x, height / 4, 1, height / 2, color);
+                    cursor_x += GROUP_SEPARATOR_WIDTH;
+                }
+                if !group.label.is_empty() {
+                    self.pb
+                        .draw_text(&mut cursor_x, self.glyphs, &group.label, color);
+                    cursor_x += GROUP_LABEL_GAP;
+                }
+            }
+            let (id, name) = (ws.id, ws.name.as_str());
+            let mut color = if active_ws == id {
+                COLOR_WS_FOCUSED
+            } else {
+                occupancy_color(ws.windows)
+            };
+            if ws.dimmed {
+                color = dim(color);
+            }
+            // Regions include the gap so hovering across numbers never leaves the strip
+            let start = cursor_x
+                - if cursor_x == MARGIN_LEFT {
+                    MARGIN_LEFT
+                } else {
+                    5
+                };
+            if self.minimap() {
+                self.draw_minimap_box(&mut cursor_x, ws.windows, color);
+            } else if is_numbered(id, name) {
+                self.pb
+                    .draw_num(&mut cursor_x, self.glyphs, id as u32, color, 1, 1);
+            } else {
+                self.pb.draw_text(&mut cursor_x, self.glyphs, name, color);
+            }
+            cursor_x += 10;
+            self.regions.push(ClickRegion {
+                start,
+                end: cursor_x - 5,
+                target: ClickTarget::Workspace(id),
+            });
+        }
+
+        self.cache.workspaces = workspaces;
+    }
+
+    /// Focused window title between the workspace strip and the date, cut short
+    /// with "..." when it does not fit. Preceded by the icon `window_icons` has
+    /// for the window's class.
+    fn draw_title_module(&mut self, title: String, class: String) {
+        let start = self.cache.ws_render_width;
+        let end = self.layout.date.x.saturating_sub(MARGIN_GAP / 2);
+        self.clear_and_damage_slot(start, end.saturating_sub(start));
+
+        let mut cursor_x = start + MARGIN_GAP;
+        let icon = config::get().window_icon(&class).unwrap_or("");
+        // Nothing to draw when the font lacks the icon's glyphs
+        if !title.is_empty() && PixelBuffer::measure_text(self.glyphs, icon) > 0 {
+            let max_width = end.saturating_sub(cursor_x + MARGIN_GAP / 2);
+            self.pb
+                .draw_text_fitted(&mut cursor_x, self.glyphs, icon, max_width, COLOR_TITLE);
+            cursor_x += TITLE_ICON_GAP;
+        }
+        let max_width = end.saturating_sub(cursor_x + MARGIN_GAP / 2);
+        self.pb
+            .draw_text_fitted(&mut cursor_x, self.glyphs, &title, max_width, COLOR_TITLE);
+
+        self.cache.title = title;
+        self.cache.window_class = class;
+    }
+
+    /// Clears a module slot together with half the gap on either side.
+    fn clear_slot(&mut self, slot: Slot) {
+        let x = slot.x.saturating_sub(MARGIN_GAP / 2);
+        self.clear_and_damage_slot(x, slot.end() + MARGIN_GAP / 2 - x);
+    }
+
+    fn draw_date_module(&mut self, day: u8, month: u8, year: u8) {
+        let slot = self.layout.date;
+        self.clear_slot(slot);
+
+        let date = format::date(day, month, year);
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &date);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &date, COLOR_DATE);
+
+        self.cache.day = day;
+        self.cache.month = month;
+        self.cache.year = year;
+    }
+
+    /// Time followed by the zone abbreviation when there is one.
+    fn draw_clock_module(&mut self, hour: u8, minute: u8, timezone: String) {
+        let slot = self.layout.clock;
+        self.clear_slot(slot);
+
+        let mut cursor_x = slot.x;
+        self.pb.draw_formatted(
+            &mut cursor_x,
+            self.glyphs,
+            &format::clock_12h(hour, minute),
+            COLOR_TIME,
+        );
+        if !timezone.is_empty() {
+            cursor_x += CLOCK_TIMEZONE_GAP;
+            self.pb
+                .draw_text(&mut cursor_x, self.glyphs, &timezone, dim(COLOR_TIME));
+        }
+
+        // Date and clock together open the calendar
+        let start = if self.layout.date.width > 0 {
+            self.layout.date.x
+        } else {
+            slot.x
+        };
+        self.regions.retain(|r| r.target != ClickTarget::Calendar);
+        self.regions.push(ClickRegion {
+            start,
+            end: slot.end(),
+            target: ClickTarget::Calendar,
+        });
+
+        self.cache.hour = hour;
+        self.cache.minute = minute;
+        self.cache.timezone = timezone;
+    }
+
+    fn draw_battery_module(
+        &mut self,
+        reading: BatteryReading,
+        ac_online: u8,
+        blink: bool,
+        health: (u8, u16),
+    ) {
+        let BatteryReading {
+            percent,
+            state,
+            estimate_m: estimate,
+            rate_dw,
+        } = reading;
+        let slot = self.layout.battery;
+        self.clear_slot(slot);
+        self.regions.retain(|r| r.target != ClickTarget::Battery);
+
+        // Hovering swaps the charge for the wear
+        let content = match health {
+            (255, _) => battery_content(percent, state, estimate, rate_dw, ac_online),
+            (health, cycles) => battery_health_content(health, cycles),
+        };
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        // The off phase of a blinking battery
+        let color = if blink {
+            dim(battery_color(percent))
+        } else {
+            battery_color(percent)
+        };
+        if config::get().battery_icon {
+            let (icon_width, _) = battery_icon_size(self.glyphs);
+            let icon_x = cursor_x.saturating_sub(BATTERY_ICON_GAP + icon_width);
+            self.pb
+                .draw_battery_icon(icon_x, self.glyphs, percent, state == 2, color);
+        }
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, color);
+        self.regions.push(ClickRegion {
+            start: slot.x.saturating_sub(MARGIN_GAP / 2),
+            end: slot.end() + MARGIN_GAP / 2,
+            target: ClickTarget::Battery,
+        });
+        self.cache.bat_percent = percent;
+        self.cache.bat_state = state;
+        self.cache.bat_est_min = estimate;
+        self.cache.bat_rate_dw = rate_dw;
+        self.cache.ac_online = ac_online;
+        self.cache.bat_blink = blink;
+        self.cache.bat_health = health;
+    }
+
+    fn draw_power_module(&mut self, deciwatts: u16) {
+        let slot = self.layout.power;
+        self.clear_slot(slot);
+
+        let content = format::watts(deciwatts as u32);
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_POWER);
+
+        self.cache.power_dw = deciwatts;
+    }
+
+    /// The active power-profiles-daemon profile, clicking switches to the next.
+    fn draw_power_profile_module(&mut self, profile: u8) {
+        let slot = self.layout.power_profile;
+        self.clear_slot(slot);
+        self.regions
+            .retain(|r| r.target != ClickTarget::PowerProfile);
+
+        let name = power_profile::PROFILES
+            .get(profile as usize)
+            .copied()
+            .unwrap_or("");
+        let content_width = PixelBuffer::measure_text(self.glyphs, name);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_text(&mut cursor_x, self.glyphs, name, COLOR_POWER_PROFILE);
+        self.regions.push(ClickRegion {
+            start: slot.x.saturating_sub(MARGIN_GAP / 2),
+            end: slot.end() + MARGIN_GAP / 2,
+            target: ClickTarget::PowerProfile,
+        });
+
+        self.cache.power_profile = profile;
+    }
+
+    /// Degrees, followed by the throttle badge in the hot color while the CPU
+    /// slows itself down.
+    fn draw_temperature_module(&mut self, degrees: u8, throttled: bool) {
+        let slot = self.layout.temperature;
+        self.clear_slot(slot);
+
+        let content = format::celsius(degrees);
+        let badge = throttle_badge(throttled);
+        let content_width =
+            PixelBuffer::measure_formatted(self.glyphs, &content.clone().then(badge.clone()));
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb.draw_formatted(
+            &mut cursor_x,
+            self.glyphs,
+            &content,
+            temperature_color(degrees),
+        );
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &badge, COLOR_TEMP_HOT);
+
+        self.cache.temperature = degrees;
+        self.cache.throttled = throttled;
+    }
+
+    fn draw_gpu_module(&mut self, busy: u8, vram_mb: u32) {
+        let slot = self.layout.gpu;
+        self.clear_slot(slot);
+
+        let content = gpu_content(busy, vram_mb);
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_GPU);
+
+        self.cache.gpu_busy = busy;
+        self.cache.gpu_vram_mb = vram_mb;
+    }
+
+    fn draw_disk_module(&mut self, read_kbs: u32, write_kbs: u32) {
+        let slot = self.layout.disk;
+        self.clear_slot(slot);
+
+        let content = disk_content(read_kbs, write_kbs);
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_DISK);
+
+        self.cache.disk_read_kbs = read_kbs;
+        self.cache.disk_write_kbs = write_kbs;
+    }
+
+    /// Sparkline of the recent throughput followed by the latest sample.
+    fn draw_network_module(&mut self, samples: u32, history: &[u32]) {
+        let slot = self.layout.network;
+        self.clear_slot(slot);
+
+        let latest = history.last().copied().unwrap_or(0);
+        let content = format::bytes(latest as u64 * 1024);
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_NET);
+
+        // Newest sample next to the value, older ones drift left
+        let graph_end = slot.end().saturating_sub(content_width + SPARKLINE_GAP);
+        let graph_x = graph_end.saturating_sub(history.len() * SPARKLINE_COLUMN);
+        let max = history
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(SPARKLINE_MIN_SCALE_KBS);
+        self.pb
+            .draw_columns(graph_x, SPARKLINE_COLUMN, history, max, dim(COLOR_NET));
+
+        self.cache.net_samples = samples;
+    }
+
+    /// SSID followed by the signal strength, the SSID is cut to fit the slot.
+    /// Disconnected clears the slot.
+    fn draw_wifi_module(&mut self, ssid: String, signal: u8) {
+        let slot = self.layout.wifi;
+        self.clear_slot(slot);
+
+        if signal != 255 {
+            let content = format::percent(signal);
+            let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+            let mut cursor_x = slot.end().saturating_sub(content_width);
+            self.pb
+                .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_WIFI);
+
+            let ssid_width = slot.width.saturating_sub(content_width + WIFI_SSID_GAP);
+            let mut cursor_x = slot.end().saturating_sub(
+                content_width
+                    + WIFI_SSID_GAP
+                    + PixelBuffer::measure_text(self.glyphs, &ssid).min(ssid_width),
+            );
+            self.pb
+                .draw_text_fitted(&mut cursor_x, self.glyphs, &ssid, ssid_width, COLOR_WIFI);
+        }
+
+        self.cache.wifi_ssid = ssid;
+        self.cache.wifi_signal = signal;
+    }
+
+    /// IPv4 address while the cable is plugged in, "down" dimmed without carrier.
+    fn draw_ethernet_module(&mut self, link: u8, address: String) {
+        let slot = self.layout.ethernet;
+        self.clear_slot(slot);
+
+        let (text, color) = match (link, address.is_empty()) {
+            (0, _) => ("down", dim(COLOR_ETHERNET)),
+            // Carrier but no lease yet
+            (_, true) => ("up", COLOR_ETHERNET),
+            (_, false) => (address.as_str(), COLOR_ETHERNET),
+        };
+        let content_width = PixelBuffer::measure_text(self.glyphs, text);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb.draw_text(&mut cursor_x, self.glyphs, text, color);
+
+        self.cache.ethernet_link = link;
+        self.cache.ethernet_address = address;
+    }
+
+    /// NetworkManager's primary connection followed by its connectivity, dimmed
+    /// while offline and warning colored behind a captive portal or with only
+    /// local access.
+    fn draw_connectivity_module(&mut self, state: u8, name: String) {
+        let slot = self.layout.connectivity;
+        self.clear_slot(slot);
+
+        let (text, color) = match state {
+            networkmanager::FULL => ("full", COLOR_CONNECTIVITY),
+            networkmanager::LIMITED => ("limited", COLOR_TEMP_WARM),
+            networkmanager::PORTAL => ("portal", COLOR_TEMP_WARM),
+            networkmanager::NONE => ("none", dim(COLOR_CONNECTIVITY)),
+            _ => ("?", dim(COLOR_CONNECTIVITY)),
+        };
+        let text_width = PixelBuffer::measure_text(self.glyphs, text);
+        let mut cursor_x = slot.end().saturating_sub(text_width);
+        self.pb.draw_text(&mut cursor_x, self.glyphs, text, color);
+
+        if !name.is_empty() {
+            let name_width = slot.width.saturating_sub(text_width + CONNECTIVITY_GAP);
+            let mut cursor_x = slot.end().saturating_sub(
+                text_width
+                    + CONNECTIVITY_GAP
+                    + PixelBuffer::measure_text(self.glyphs, &name).min(name_width),
+            );
+            self.pb
+                .draw_text_fitted(&mut cursor_x, self.glyphs, &name, name_width, color);
+        }
+
+        self.cache.nm_connectivity = state;
+        self.cache.nm_connection = name;
+    }
+
+    /// "BT" followed by the first connected device, its battery when it reports
+    /// one and how many more devices there are, dimmed "BT off" while the adapter
+    /// is powered off.
+    fn draw_bluetooth_module(&mut self, powered: u8, connected: u8, device: String, battery: u8) {
+        let slot = self.layout.bluetooth;
+        self.clear_slot(slot);
+
+        if powered != 255 {
+            let color = if powered == 0 {
+                dim(COLOR_BLUETOOTH)
+            } else {
+                COLOR_BLUETOOTH
+            };
+            let (prefix, suffix) = match (powered, connected) {
+                (0, _) => ("BT off", String::new()),
+                (_, 0) => ("BT", String::new()),
+                (_, 1) => ("BT ", String::new()),
+                (_, n) => ("BT ", format!(" +{}", n - 1)),
+            };
+            let (name, battery) = if powered == 1 && connected > 0 {
+                (
+                    device.as_str(),
+                    (battery != 255).then(|| format::percent(battery)),
+                )
+            } else {
+                ("", None)
+            };
+            let prefix_width = PixelBuffer::measure_text(self.glyphs, prefix);
+            let battery_width = battery.as_ref().map_or(0, |b| {
+                BLUETOOTH_BATTERY_GAP + PixelBuffer::measure_formatted(self.glyphs, b)
+            });
+            let suffix_width = PixelBuffer::measure_text(self.glyphs, &suffix);
+            let name_width = slot
+                .width
+                .saturating_sub(prefix_width + battery_width + suffix_width);
+            let mut cursor_x = slot.end().saturating_sub(
+                prefix_width
+                    + PixelBuffer::measure_text(self.glyphs, name).min(name_width)
+                    + battery_width
+                    + suffix_width,
+            );
+            self.pb.draw_text(&mut cursor_x, self.glyphs, prefix, color);
+            self.pb
+                .draw_text_fitted(&mut cursor_x, self.glyphs, name, name_width, color);
+            if let Some(battery) = &battery {
+                cursor_x += BLUETOOTH_BATTERY_GAP;
+                self.pb
+                    .draw_formatted(&mut cursor_x, self.glyphs, battery, color);
+            }
+            self.pb
+                .draw_text(&mut cursor_x, self.glyphs, &suffix, color);
+        }
+
+        self.cache.bluetooth_powered = powered;
+        self.cache.bluetooth_connected = connected;
+        self.cache.bluetooth_device = device;
+        self.cache.bluetooth_battery = battery;
+    }
+
+    /// Time until the idle daemon acts, dimmed "off" when no daemon is running.
+    fn draw_idle_module(&mut self, daemon: u8, remaining_s: u32) {
+        let slot = self.layout.idle;
+        self.clear_slot(slot);
+
+        let content = idle_content(daemon, remaining_s);
+        let color = if daemon == 1 {
+            COLOR_IDLE
+        } else {
+            dim(COLOR_IDLE)
+        };
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, color);
+
+        self.cache.idle_daemon = daemon;
+        self.cache.idle_remaining_s = remaining_s;
+    }
+
+    /// Whether the bar keeps the seat from going idle, dimmed while it doesn't.
+    fn draw_caffeine_module(&mut self, inhibited: u8) {
+        let color = if inhibited == 1 {
+            COLOR_CAFFEINE
+        } else {
+            dim(COLOR_CAFFEINE)
+        };
+        self.draw_text_module(
+            self.layout.caffeine,
+            caffeine_text(inhibited),
+            color,
+            ClickTarget::Caffeine,
+        );
+        self.cache.idle_inhibited = inhibited;
+    }
+
+    /// Whether the notification daemon holds notifications back, dimmed while
+    /// it shows them.
+    fn draw_dnd_module(&mut self, enabled: u8) {
+        let color = if enabled == 1 {
+            COLOR_DND
+        } else {
+            dim(COLOR_DND)
+        };
+        self.draw_text_module(self.layout.dnd, dnd_text(enabled), color, ClickTarget::Dnd);
+        self.cache.dnd = enabled;
+    }
+
+    /// Whether wlsunset or gammastep shifts the display warm right now, dimmed
+    /// during the day and while neither runs. Clickable with a
+    /// `night_light_command` only.
+    fn draw_night_light_module(&mut self, state: u8) {
+        let color = if state == 2 {
+            COLOR_NIGHT_LIGHT
+        } else {
+            dim(COLOR_NIGHT_LIGHT)
+        };
+        self.draw_text_module(
+            self.layout.night_light,
+            night_light_text(state),
+            color,
+            ClickTarget::NightLight,
+        );
+        if config::get().night_light_command.is_empty() {
+            self.regions.retain(|r| r.target != ClickTarget::NightLight);
+        }
+        self.cache.night_light = state;
+    }
+
+    /// Which radios are blocked, dimmed while all of them are on.
+    fn draw_rfkill_module(&mut self, state: u8) {
+        let color = if state & (rfkill::WIFI | rfkill::BLUETOOTH | rfkill::AIRPLANE) != 0 {
+            COLOR_RFKILL
+        } else {
+            dim(COLOR_RFKILL)
+        };
+        self.draw_text_module(
+            self.layout.rfkill,
+            &rfkill_text(state),
+            color,
+            ClickTarget::Rfkill,
+        );
+        self.cache.rfkill = state;
+    }
+
+    /// How many removable drives are mounted, empty without any.
+    fn draw_removable_module(&mut self, count: u8) {
+        self.draw_text_module(
+            self.layout.removable,
+            &removable_text(count),
+            COLOR_REMOVABLE,
+            ClickTarget::Removable,
+        );
+        self.cache.removable_mounts = count;
+    }
+
+    /// How many systemd units have failed, empty while none have.
+    fn draw_failed_units_module(&mut self, count: u16) {
+        let slot = self.layout.failed_units;
+        self.clear_slot(slot);
+
+        let text = failed_units_text(count);
+        let content_width = PixelBuffer::measure_text(self.glyphs, &text).min(slot.width);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb.draw_text_fitted(
+            &mut cursor_x,
+            self.glyphs,
+            &text,
+            slot.width,
+            COLOR_FAILED_UNITS,
+        );
+
+        self.cache.failed_units = count;
+    }
+
+    /// The next calendar event, warm colored shortly before and while it runs.
+    fn draw_next_event_module(&mut self, soon: u8, text: String) {
+        let slot = self.layout.next_event;
+        self.clear_slot(slot);
+
+        let color = if soon == 1 {
+            COLOR_TEMP_WARM
+        } else {
+            COLOR_NEXT_EVENT
+        };
+        let content_width = PixelBuffer::measure_text(self.glyphs, &text).min(slot.width);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_text_fitted(&mut cursor_x, self.glyphs, &text, slot.width, color);
+
+        self.cache.next_event_soon = soon;
+        self.cache.next_event = text;
+    }
+
+    /// Open todo items, empty once everything is done.
+    fn draw_todo_module(&mut self, count: u16) {
+        let slot = self.layout.todo;
+        self.clear_slot(slot);
+
+        let text = todo_text(count);
+        let content_width = PixelBuffer::measure_text(self.glyphs, &text).min(slot.width);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_text_fitted(&mut cursor_x, self.glyphs, &text, slot.width, COLOR_TODO);
+
+        self.cache.todo_open = count;
+    }
+
+    /// Unread mail, or each account's while hovered. Hidden without any.
+    fn draw_mail_module(&mut self, unread: u32, accounts: String) {
+        let text = if accounts.is_empty() {
+            mail_text(unread)
+        } else {
+            accounts.clone()
+        };
+        self.draw_text_module(self.layout.mail, &text, COLOR_MAIL, ClickTarget::Mail);
+        self.cache.mail_unread = unread;
+        self.cache.mail_accounts = accounts;
+    }
+
+    /// Pending package updates, empty while the system is up to date.
+    fn draw_updates_module(&mut self, pending: u16) {
+        let slot = self.layout.updates;
+        self.clear_slot(slot);
+
+        let text = updates_text(pending);
+        let content_width = PixelBuffer::measure_text(self.glyphs, &text).min(slot.width);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_text_fitted(&mut cursor_x, self.glyphs, &text, slot.width, COLOR_UPDATES);
+
+        self.cache.updates_pending = pending;
+    }
+
+    /// Running containers, empty with none or without an engine.
+    fn draw_containers_module(&mut self, running: u16) {
+        let slot = self.layout.containers;
+        self.clear_slot(slot);
+
+        let text = containers_text(running);
+        let content_width = PixelBuffer::measure_text(self.glyphs, &text).min(slot.width);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb.draw_text_fitted(
+            &mut cursor_x,
+            self.glyphs,
+            &text,
+            slot.width,
+            COLOR_CONTAINERS,
+        );
+
+        self.cache.containers_running = running;
+    }
+
+    /// Last line of `custom_command`, or its tooltip while hovered, cut off at
+    /// the slot's edge.
+    fn draw_custom_module(&mut self, text: String, color: u32) {
+        self.draw_text_module(self.layout.custom, &text, color, ClickTarget::Custom);
+        self.cache.custom_text = text;
+        self.cache.custom_color = color;
+    }
+
+    /// Last line written to the fifo, cut off at the slot's edge.
+    fn draw_fifo_module(&mut self, text: String) {
+        let slot = self.layout.fifo;
+        self.clear_slot(slot);
+
+        let content_width = PixelBuffer::measure_text(self.glyphs, &text).min(slot.width);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_text_fitted(&mut cursor_x, self.glyphs, &text, slot.width, COLOR_FIFO);
+
+        self.cache.fifo_text = text;
+    }
+
+    /// The segments of each alignment one after another, left ones from the
+    /// left margin, centered ones around the middle and right ones up to the
+    /// right margin.
+    fn draw_lemonbar(&mut self, segments: &[LemonSegment], width: usize, generation: u32) {
+        self.clear_and_damage_slot(0, width);
+        self.regions.clear();
+
+        for align in [lemonbar::LEFT, lemonbar::CENTER, lemonbar::RIGHT] {
+            let segments: Vec<_> = segments.iter().filter(|s| s.align == align).collect();
+            let content_width: usize = segments
+                .iter()
+                .map(|s| PixelBuffer::measure_text(self.glyphs, &s.text))
+                .sum();
+            let mut cursor_x = match align {
+                lemonbar::LEFT => MARGIN_LEFT,
+                lemonbar::CENTER => width.saturating_sub(content_width) / 2,
+                _ => width.saturating_sub(MARGIN_RIGHT + content_width),
+            };
+            for segment in segments {
+                let color = match segment.color {
+                    0 => COLOR_LEMONBAR,
+                    color => color,
+                };
+                self.pb
+                    .draw_text(&mut cursor_x, self.glyphs, &segment.text, color);
+            }
+        }
+
+        self.cache.lemonbar_generation = generation;
+    }
+
+    /// Blocks of `status_command` right aligned, each one clickable. Their short
+    /// texts are used when the full ones don't fit, the leftmost blocks are
+    /// left out when those don't either.
+    fn draw_status_module(&mut self, blocks: &[StatusBlock], generation: u32) {
+        let slot = self.layout.status;
+        self.clear_slot(slot);
+        self.regions
+            .retain(|r| !matches!(r.target, ClickTarget::Status(_)));
+
+        let texts_of = |short: bool| -> Vec<&str> {
+            blocks
+                .iter()
+                .map(|b| {
+                    if short && !b.short_text.is_empty() {
+                        b.short_text.as_str()
+                    } else {
+                        b.full_text.as_str()
+                    }
+                })
+                .collect()
+        };
+        let total = |texts: &[&str]| {
+            texts
+                .iter()
+                .map(|t| PixelBuffer::measure_text(self.glyphs, t) + STATUS_BLOCK_GAP)
+                .sum::<usize>()
+                .saturating_sub(STATUS_BLOCK_GAP)
+        };
+        let mut texts = texts_of(false);
+        if total(&texts) > slot.width {
+            texts = texts_of(true);
+        }
+        let mut skipped = 0;
+        while skipped < texts.len() && total(&texts[skipped..]) > slot.width {
+            skipped += 1;
+        }
+
+        let mut x = slot.end().saturating_sub(total(&texts[skipped..]));
+        for (index, (block, text)) in blocks.iter().zip(&texts).enumerate().skip(skipped) {
+            let color = match block.color {
+                _ if block.urgent => COLOR_TEMP_HOT,
+                0 => COLOR_STATUS,
+                color => color,
+            };
+            let start = x;
+            self.pb.draw_text(&mut x, self.glyphs, text, color);
+            self.regions.push(ClickRegion {
+                start: start.saturating_sub(STATUS_BLOCK_GAP / 2),
+                end: x + STATUS_BLOCK_GAP / 2,
+                target: ClickTarget::Status(index.min(u8::MAX as usize) as u8),
+            });
+            x += STATUS_BLOCK_GAP;
+        }
+
+        self.cache.status_generation = generation;
+    }
+
+    /// StatusNotifierItem icons right aligned, each one clickable. Items
+    /// without a pixmap show their first letter instead.
+    fn draw_tray_module(&mut self, items: &[TrayItem], generation: u32) {
+        let slot = self.layout.tray;
+        self.clear_slot(slot);
+        self.regions
+            .retain(|r| !matches!(r.target, ClickTarget::Tray(_)));
+
+        let fitting = (slot.width + TRAY_ICON_GAP) / (tray::ICON_SIZE + TRAY_ICON_GAP);
+        let skipped = items.len().saturating_sub(fitting);
+        let shown = items.len() - skipped;
+        let width = (shown * (tray::ICON_SIZE + TRAY_ICON_GAP)).saturating_sub(TRAY_ICON_GAP);
+        let mut x = slot.end().saturating_sub(width);
+        for (index, item) in items.iter().enumerate().skip(skipped) {
+            if item.pixels.is_empty() {
+                let label = item.label.to_string();
+                let label_width = PixelBuffer::measure_text(self.glyphs, &label);
+                let mut cursor_x = x + tray::ICON_SIZE.saturating_sub(label_width) / 2;
+                self.pb
+                    .draw_text(&mut cursor_x, self.glyphs, &label, COLOR_TRAY);
+            } else {
+                self.pb.blend_icon(x, &item.pixels, tray::ICON_SIZE);
+            }
+            self.regions.push(ClickRegion {
+                start: x.saturating_sub(TRAY_ICON_GAP / 2),
+                end: x + tray::ICON_SIZE + TRAY_ICON_GAP / 2,
+                target: ClickTarget::Tray(index.min(u8::MAX as usize) as u8),
+            });
+            x += tray::ICON_SIZE + TRAY_ICON_GAP;
+        }
+
+        self.cache.tray_generation = generation;
+    }
+
+    /// Window names right aligned in cells of equal width, the focused one
+    /// brighter. Each entry can be clicked.
+    fn draw_taskbar_module(&mut self, entries: &[TaskbarEntry], generation: u32) {
+        let slot = self.layout.taskbar;
+        self.clear_slot(slot);
+        self.regions
+            .retain(|r| !matches!(r.target, ClickTarget::Taskbar(_)));
+
+        let fitting = (slot.width + TASKBAR_ENTRY_GAP) / (TASKBAR_ENTRY_WIDTH + TASKBAR_ENTRY_GAP);
+        let skipped = entries.len().saturating_sub(fitting);
+        let shown = entries.len() - skipped;
+        let width =
+            (shown * (TASKBAR_ENTRY_WIDTH + TASKBAR_ENTRY_GAP)).saturating_sub(TASKBAR_ENTRY_GAP);
+        let mut x = slot.end().saturating_sub(width);
+        for (index, entry) in entries.iter().enumerate().skip(skipped) {
+            let color = if entry.activated && !entry.minimized {
+                COLOR_TASKBAR_ACTIVE
+            } else {
+                COLOR_TASKBAR
+            };
+            let mut cursor_x = x;
+            self.pb.draw_text_fitted(
+                &mut cursor_x,
+                self.glyphs,
+                &entry.label,
+                TASKBAR_ENTRY_WIDTH,
+                color,
+            );
+            self.regions.push(ClickRegion {
+                start: x.saturating_sub(TASKBAR_ENTRY_GAP / 2),
+                end: x + TASKBAR_ENTRY_WIDTH + TASKBAR_ENTRY_GAP / 2,
+                target: ClickTarget::Taskbar(index.min(u8::MAX as usize) as u8),
+            });
+            x += TASKBAR_ENTRY_WIDTH + TASKBAR_ENTRY_GAP;
+        }
+
+        self.cache.taskbar_generation = generation;
+    }
+
+    /// Elapsed time since the configured event, stopping clears the slot.
+    fn draw_since_module(&mut self, elapsed_s: u32) {
+        let slot = self.layout.since;
+        self.clear_slot(slot);
+
+        if elapsed_s != u32::MAX {
+            let content = since_content(elapsed_s);
+            let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+            let mut cursor_x = slot.end().saturating_sub(content_width);
+            self.pb
+                .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_SINCE);
+        }
+
+        self.cache.since_elapsed_s = elapsed_s;
+    }
+
+    /// Stopwatch or countdown from the control socket, dimmed while stopped and
+    /// warm once a countdown is over.
+    fn draw_timer_module(&mut self, seconds: u32, state: u8) {
+        let slot = self.layout.timer;
+        self.clear_slot(slot);
+
+        let text = timer_text(seconds);
+        let color = match state {
+            timer::RUNNING => COLOR_TIMER,
+            timer::FINISHED => COLOR_TEMP_WARM,
+            _ => dim(COLOR_TIMER),
+        };
+        let content_width = PixelBuffer::measure_text(self.glyphs, &text).min(slot.width);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_text_fitted(&mut cursor_x, self.glyphs, &text, slot.width, color);
+
+        self.cache.timer_s = seconds;
+        self.cache.timer_state = state;
+    }
+
+    /// "some" stall shares of cpu, memory and io, colored by the worst "full"
+    /// stall where every task was waiting.
+    fn draw_pressure_module(&mut self, some: [u16; 3], full: u16) {
+        let slot = self.layout.pressure;
+        self.clear_slot(slot);
+
+        let content = pressure_content(some);
+        let color = match full {
+            0..PRESSURE_FULL_WARN => COLOR_PRESSURE,
+            PRESSURE_FULL_WARN..PRESSURE_FULL_SEVERE => COLOR_TEMP_WARM,
+            _ => COLOR_TEMP_HOT,
+        };
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, color);
+
+        self.cache.pressure_some = some;
+        self.cache.pressure_full = full;
+    }
+
+    /// "45%", dimmed and followed by "mute" while muted.
+    fn draw_volume_module(&mut self, percent: u8, muted: bool) {
+        let slot = self.layout.volume;
+        self.clear_slot(slot);
+
+        let content = volume_content(percent, muted);
+        let color = if muted {
+            dim(COLOR_VOLUME)
+        } else {
+            COLOR_VOLUME
+        };
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, color);
+
+        self.cache.volume_percent = percent;
+        self.cache.volume_muted = muted;
+    }
+
+    /// Scrolling it changes the brightness.
+    fn draw_backlight_module(&mut self, percent: u8) {
+        let slot = self.layout.backlight;
+        self.clear_slot(slot);
+        self.regions.retain(|r| r.target != ClickTarget::Backlight);
+
+        let content = backlight_content(percent);
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_BACKLIGHT);
+        self.regions.push(ClickRegion {
+            start: slot.x.saturating_sub(MARGIN_GAP / 2),
+            end: slot.end() + MARGIN_GAP / 2,
+            target: ClickTarget::Backlight,
+        });
+
+        self.cache.backlight_percent = percent;
+    }
+
+    /// Clicking steps through the levels, scrolling goes up and down. Dimmed
+    /// while off.
+    fn draw_keyboard_backlight_module(&mut self, level: u8, levels: u8) {
+        let slot = self.layout.keyboard_backlight;
+        self.clear_slot(slot);
+        self.regions
+            .retain(|r| r.target != ClickTarget::KeyboardBacklight);
+
+        let content = keyboard_backlight_content(level, levels);
+        let color = if level == 0 {
+            dim(COLOR_KBD_BACKLIGHT)
+        } else {
+            COLOR_KBD_BACKLIGHT
+        };
+        let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+        let mut cursor_x = slot.end().saturating_sub(content_width);
+        self.pb
+            .draw_formatted(&mut cursor_x, self.glyphs, &content, color);
+        self.regions.push(ClickRegion {
+            start: slot.x.saturating_sub(MARGIN_GAP / 2),
+            end: slot.end() + MARGIN_GAP / 2,
+            target: ClickTarget::KeyboardBacklight,
+        });
+
+        self.cache.kbd_backlight_level = level;
+        self.cache.kbd_backlight_levels = levels;
+    }
+
+    /// Only shows anything while the microphone is muted, then loudly.
+    fn draw_microphone_module(&mut self, muted: u8) {
+        let slot = self.layout.microphone;
+        self.clear_slot(slot);
+
+        if muted == 1 {
+            let content = Formatted::default().symbol(Symbol::MicMuted);
+            let content_width = PixelBuffer::measure_formatted(self.glyphs, &content);
+            let mut cursor_x = slot.end().saturating_sub(content_width);
+            self.pb
+                .draw_formatted(&mut cursor_x, self.glyphs, &content, COLOR_MIC_MUTED);
+        }
+
+        self.cache.mic_muted = muted;
+    }
+
+    /// A dot for each of the microphone, camera and screen sharing while in use,
+    /// nothing otherwise.
+    fn draw_privacy_module(&mut self, in_use: u8) {
+        let slot = self.layout.privacy;
+        self.clear_slot(slot);
+
+        let diameter = privacy_dot_diameter(self.glyphs);
+        let dots: Vec<u32> = [
+            (privacy::MICROPHONE, COLOR_PRIVACY_MIC),
+            (privacy::CAMERA, COLOR_PRIVACY_CAMERA),
+            (privacy::SCREEN, COLOR_PRIVACY_SCREEN),
+        ]
+        .into_iter()
+        .filter(|(bit, _)| in_use & bit != 0)
+        .map(|(_, color)| color)
+        .collect();
+        let width = dots.len() * (diameter + PRIVACY_DOT_GAP);
+        let mut x = slot
+            .end()
+            .saturating_sub(width.saturating_sub(PRIVACY_DOT_GAP));
+        for color in dots {
+            self.pb.fill_dot(x, diameter, color);
+            x += diameter + PRIVACY_DOT_GAP;
+        }
+
+        self.cache.privacy = in_use;
+    }
+
+    /// Right aligned text module that can be clicked, cut short to fit its slot.
+    fn draw_text_module(&mut self, slot: Slot, text: &str, color: u32, target: ClickTarget) {
+        self.clear_slot(slot);
+        self.regions.retain(|r| r.target != target);
+
+        if !text.is_empty() {
+            let content_width = PixelBuffer::measure_text(self.glyphs, text).min(slot.width);
+            let mut cursor_x = slot.end().saturating_sub(content_width);
+            self.pb
+                .draw_text_fitted(&mut cursor_x, self.glyphs, text, slot.width, color);
+            self.regions.push(ClickRegion {
+                start: slot.x.saturating_sub(MARGIN_GAP / 2),
+                end: slot.end() + MARGIN_GAP / 2,
+                target,
+            });
+        }
+    }
+
+    fn draw_layout_module(&mut self, code: String) {
+        self.draw_text_module(
+            self.layout.keyboard_layout,
+            &code,
+            COLOR_LAYOUT,
+            ClickTarget::KeyboardLayout,
+        );
+        self.cache.layout = code;
+    }
+
+    fn draw_audio_device_module(&mut self, label: String) {
+        self.draw_text_module(
+            self.layout.audio_device,
+            &label,
+            COLOR_AUDIO_DEVICE,
+            ClickTarget::AudioDevice,
+        );
+        self.cache.audio_device = label;
+    }
+
+    /// The play or pause indicator followed by "artist - title", nothing
+    /// without a player.
+    fn draw_mpris_module(&mut self, status: u8, text: String) {
+        let slot = self.layout.mpris;
+        self.clear_slot(slot);
+
+        if matches!(status, 1 | 2) && !text.is_empty() {
+            let config = config::get();
+            let indicator = if status == 1 {
+                &config.mpris_playing
+            } else {
+                &config.mpris_paused
+            };
+            let indicator_width = PixelBuffer::measure_text(self.glyphs, indicator) + MPRIS_GAP;
+            let text_width = PixelBuffer::measure_text(self.glyphs, &text)
+                .min(slot.width.saturating_sub(indicator_width));
+            let mut cursor_x = slot.end().saturating_sub(indicator_width + text_width);
+            self.pb
+                .draw_text(&mut cursor_x, self.glyphs, indicator, COLOR_MPRIS);
+            cursor_x += MPRIS_GAP;
+            self.pb
+                .draw_text_fitted(&mut cursor_x, self.glyphs, &text, text_width, COLOR_MPRIS);
+        }
+
+        self.cache.mpris_status = status;
+        self.cache.mpris_text = text;
+    }
+
+    fn draw_tiling_module(&mut self, label: String) {
+        self.draw_text_module(
+            self.layout.tiling,
+            &label,
+            COLOR_TILING,
+            ClickTarget::TilingLayout,
+        );
+        self.cache.tiling = label;
+    }
+}
+
+/// "G 37%", followed by the used VRAM when known.
+fn gpu_content(busy: u8, vram_mb: u32) -> Formatted {
+    let content = Formatted::default()
+        .unit('G')
+        .gap(2)
+        .then(format::percent(busy));
+    if vram_mb == u32::MAX {
+        content
+    } else {
+        content
+            .gap(4)
+            .then(format::bytes(vram_mb as u64 * 1024 * 1024))
+    }
+}
+
+/// Read rate after a down arrow, write rate after an up arrow.
+fn disk_content(read_kbs: u32, write_kbs: u32) -> Formatted {
+    Formatted::default()
+        .symbol(Symbol::Down)
+        .then(format::bytes(read_kbs as u64 * 1024))
+        .gap(4)
+        .symbol(Symbol::Up)
+        .then(format::bytes(write_kbs as u64 * 1024))
+}
+
+/// "idle 04:12" counting down, or "idle off" without a daemon to act on it.
+fn idle_content(daemon: u8, remaining_s: u32) -> Formatted {
+    let content = Formatted::default().text("idle").gap(4);
+    if daemon == 1 {
+        content.then(format::minutes_seconds(remaining_s))
+    } else {
+        content.text("off")
+    }
+}
+
+/// "caf on" while inhibiting idle, "caf off" otherwise.
+fn caffeine_text(inhibited: u8) -> &'static str {
+    if inhibited == 1 { "caf on" } else { "caf off" }
+}
+
+/// "dnd on" while notifications are held back, "dnd off" otherwise.
+fn dnd_text(enabled: u8) -> &'static str {
+    if enabled == 1 { "dnd on" } else { "dnd off" }
+}
+
+/// "night warm" while shifted, "night day" while the daemon waits for the
+/// evening, "night off" without one.
+fn night_light_text(state: u8) -> &'static str {
+    match state {
+        2 => "night warm",
+        1 => "night day",
+        _ => "night off",
+    }
+}
+
+/// "airplane" while every radio is blocked, otherwise the blocked ones as in
+/// "wifi off", or "radios on". A hardware switch adds "hw".
+fn rfkill_text(state: u8) -> String {
+    let wifi = state & rfkill::WIFI != 0;
+    let bluetooth = state & rfkill::BLUETOOTH != 0;
+    let text = match (state & rfkill::AIRPLANE != 0, wifi, bluetooth) {
+        (true, _, _) => "airplane",
+        (false, true, true) => "wifi bt off",
+        (false, true, false) => "wifi off",
+        (false, false, true) => "bt off",
+        (false, false, false) => "radios on",
+    };
+    if state & rfkill::HARD != 0 {
+        format!("{} hw", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// "4:05" or "1:02:03", nothing without a timer.
+fn timer_text(seconds: u32) -> String {
+    match seconds {
+        u32::MAX => String::new(),
+        0..3600 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        _ => format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        ),
+    }
+}
+
+/// "ctr 4", nothing while none run or the engine is gone.
+fn containers_text(running: u16) -> String {
+    if running == 0 || running == u16::MAX {
+        String::new()
+    } else {
+        format!("ctr {}", running)
+    }
+}
+
+/// "upd 12", nothing with no updates pending.
+fn updates_text(pending: u16) -> String {
+    if pending == 0 {
+        String::new()
+    } else {
+        format!("upd {}", pending)
+    }
+}
+
+/// "mail 3", nothing without unread mail.
+fn mail_text(unread: u32) -> String {
+    if unread == 0 {
+        String::new()
+    } else {
+        format!("mail {}", unread)
+    }
+}
+
+/// "work 2 home 1", the accounts with unread mail.
+fn mail_accounts_text(accounts: &[(String, u32)]) -> String {
+    accounts
+        .iter()
+        .filter(|(_, unread)| *unread > 0)
+        .map(|(label, unread)| format!("{} {}", label, unread))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// "todo 5", nothing with no open items or once the file is gone.
+fn todo_text(count: u16) -> String {
+    if count == 0 || count == u16::MAX {
+        String::new()
+    } else {
+        format!("todo {}", count)
+    }
+}
+
+/// "failed 2", nothing while every unit is fine.
+fn failed_units_text(count: u16) -> String {
+    if count == 0 {
+        String::new()
+    } else {
+        format!("failed {}", count)
+    }
+}
+
+/// "usb 2", nothing while no removable drive is mounted.
+fn removable_text(count: u8) -> String {
+    if count == 0 {
+        String::new()
+    } else {
+        format!("usb {}", count)
+    }
+}
+
+/// "bri 60%"
+fn backlight_content(percent: u8) -> Formatted {
+    Formatted::default()
+        .text("bri")
+        .gap(4)
+        .then(format::percent(percent))
+}
+
+/// "kbd 2/3"
+fn keyboard_backlight_content(level: u8, levels: u8) -> Formatted {
+    Formatted::default()
+        .text("kbd")
+        .gap(4)
+        .num(level as u32, 1)
+        .symbol(Symbol::Slash)
+        .num(levels as u32, 1)
+}
+
+fn volume_content(percent: u8, muted: bool) -> Formatted {
+    let content = format::percent(percent);
+    if muted {
+        content.gap(4).text("mute")
+    } else {
+        content
+    }
+}
+
+/// "cpu 3.7 mem 0.0 io 0.3"
+fn pressure_content(some: [u16; 3]) -> Formatted {
+    let [cpu, memory, io] = some.map(|p| format::tenths(p as u32));
+    Formatted::default()
+        .text("cpu")
+        .gap(2)
+        .then(cpu)
+        .gap(4)
+        .text("mem")
+        .gap(2)
+        .then(memory)
+        .gap(4)
+        .text("io")
+        .gap(2)
+        .then(io)
+}
+
+/// "deploy 3d 4h" with the configured label.
+fn since_content(elapsed_s: u32) -> Formatted {
+    let label = config::get().since_label.as_str();
+    let content = if label.is_empty() {
+        Formatted::default()
+    } else {
+        Formatted::default().text(label).gap(4)
+    };
+    content.then(format::elapsed(elapsed_s))
+}
+
+/// "87% + 1:23" with the configured indicator, or only the full indicator.
+fn battery_content(
+    percent: u8,
+    state: u8,
+    estimate: u16,
+    rate_dw: u16,
+    ac_online: u8,
+) -> Formatted {
+    let config = config::get();
+    if state == 3 {
+        return Formatted::default().text(&config.battery_full);
+    }
+    // Plugged in without charging, e.g. held at a charge threshold
+    if state == 0 && ac_online == 1 {
+        return format::percent(percent)
+            .gap(1)
+            .text(&config.battery_plugged);
+    }
+    let indicator = if state == 2 {
+        &config.battery_charging
+    } else {
+        &config.battery_discharging
+    };
+    let content = format::percent(percent)
+        .gap(1)
+        .text(indicator)
+        .gap(1)
+        .then(format::hours_minutes(estimate as u32));
+    // Wattage in or out follows the estimate
+    if rate_dw > 0 {
+        content.gap(4).then(format::watts(rate_dw as u32))
+    } else {
+        content
+    }
+}
+
+/// Full charge against the design capacity and, when the battery counts them,
+/// the charge cycles.
+fn battery_health_content(percent: u8, cycles: u16) -> Formatted {
+    let content = Formatted::default()
+        .text("health")
+        .symbol(Symbol::Space)
+        .then(format::percent(percent));
+    if cycles == u16::MAX {
+        content
+    } else {
+        content
+            .gap(4)
+            .num(cycles as u32, 1)
+            .symbol(Symbol::Space)
+            .text("cycles")
+    }
+}
+
+fn sample_status_blocks() -> Vec<StatusBlock> {
+    STATUS_SAMPLE
+        .iter()
+        .map(|text| StatusBlock {
+            full_text: text.to_string(),
+            ..StatusBlock::default()
+        })
+        .collect()
+}
+
+/// Tray items for previews, lettered since there are no pixmaps to show.
+fn sample_tray_items(count: usize) -> Vec<TrayItem> {
+    ('A'..='Z')
+        .take(count)
+        .map(|label| TrayItem {
+            label,
+            ..TrayItem::default()
+        })
+        .collect()
+}
+
+/// Windows for previews, the first one focused.
+fn sample_taskbar_entries(count: usize) -> Vec<TaskbarEntry> {
+    ["foot", "firefox", "thunderbird", "mpv", "gimp"]
+        .into_iter()
+        .cycle()
+        .take(count)
+        .enumerate()
+        .map(|(index, label)| TaskbarEntry {
+            label: label.to_string(),
+            activated: index == 0,
+            minimized: false,
+        })
+        .collect()
+}
+
+/// Privacy dots are two thirds as high as the digits.
+fn privacy_dot_diameter(glyphs: &font_renderer::GlyphCache) -> usize {
+    (glyphs.numbers[0].height * 2 / 3).max(4)
+}
+
+/// Width including the nub and height of the battery icon, twice as wide as
+/// the digits are high.
+fn battery_icon_size(glyphs: &font_renderer::GlyphCache) -> (usize, usize) {
+    let height = glyphs.numbers[0].height.max(6);
+    (height * 2, height)
+}
+
+/// Horizontal span reserved for a module's content.
+#[derive(Clone, Copy, Default)]
+struct Slot {
+    x: usize,
+    width: usize,
+}
+
+impl Slot {
+    fn end(&self) -> usize {
+        self.x + self.width
+    }
+}
 
-// helper to coordinate drawing a single frame.
-struct Renderer<'a> {
-    pb: &'a mut PixelBuffer<'a>,
-    glyphs: &'a font_renderer::GlyphCache,
-    cache: &'a mut DrawCache,
-    surface: Option<&'a WlSurface>,
-    height: u32,
+/// Where every fixed width module goes on a bar of a given width. Each module
+/// reserves the width of its widest possible content so that content changes
+/// never move its neighbours.
+#[derive(Default)]
+struct Layout {
+    date: Slot,
+    clock: Slot,
+    battery: Slot,
+    power: Slot,
+    power_profile: Slot,
+    temperature: Slot,
+    gpu: Slot,
+    disk: Slot,
+    network: Slot,
+    wifi: Slot,
+    ethernet: Slot,
+    connectivity: Slot,
+    bluetooth: Slot,
+    idle: Slot,
+    caffeine: Slot,
+    dnd: Slot,
+    night_light: Slot,
+    rfkill: Slot,
+    removable: Slot,
+    failed_units: Slot,
+    next_event: Slot,
+    todo: Slot,
+    mail: Slot,
+    updates: Slot,
+    containers: Slot,
+    custom: Slot,
+    status: Slot,
+    fifo: Slot,
+    tray: Slot,
+    taskbar: Slot,
+    since: Slot,
+    timer: Slot,
+    pressure: Slot,
+    backlight: Slot,
+    keyboard_backlight: Slot,
+    volume: Slot,
+    microphone: Slot,
+    privacy: Slot,
+    audio_device: Slot,
+    keyboard_layout: Slot,
+    tiling: Slot,
+    mpris: Slot,
 }
 
-impl<'a> Renderer<'a> {
-    fn clear_and_damage_slot(&mut self, x: usize, width: usize) {
-        self.pb.clear_rect(x, width);
-        if let Some(surface) = self.surface {
-            surface.damage_buffer(x as i32, 0, width as i32, self.height as i32);
+impl Layout {
+    fn compute(width: usize, glyphs: &font_renderer::GlyphCache) -> Self {
+        if config::get().capsule.is_some() {
+            return Self::capsule(glyphs);
         }
-    }
+        let center = width / 2;
+        let date_width = reserved_width(Module::Date, glyphs);
+        let clock_width = reserved_width(Module::Clock, glyphs);
 
-    fn draw_workspaces(&mut self, active_ws: u8, mask: u16) {
-        let mut total_width = 0;
-        for i in 0..10 {
-            let num = (i + 1) as u8;
-            if (mask & (1 << i)) != 0 || active_ws == num {
-                total_width += PixelBuffer::measure_num(self.glyphs, num as u32, 1, 1) + 10;
+        // Right side modules are packed from the screen edge inward
+        let mut right_edge = width.saturating_sub(MARGIN_RIGHT);
+        let mut next_slot = |module| {
+            let width = reserved_width(module, glyphs).min(right_edge);
+            let slot = Slot {
+                x: right_edge - width,
+                width,
+            };
+            // Hidden and zero width modules leave no gap behind
+            if width > 0 {
+                right_edge = slot.x.saturating_sub(MARGIN_GAP);
             }
-        }
+            slot
+        };
 
-        let old_width = self.cache.ws_render_width;
-        self.cache.workspaces = mask;
-        self.cache.active_ws = active_ws;
-        self.cache.ws_render_width = total_width;
+        Self {
+            date: Slot {
+                x: center.saturating_sub(MARGIN_GAP / 2 + date_width),
+                width: date_width,
+            },
+            clock: Slot {
+                x: center + MARGIN_GAP / 2,
+                width: clock_width,
+            },
+            battery: next_slot(Module::Battery),
+            power: next_slot(Module::Power),
+            power_profile: next_slot(Module::PowerProfile),
+            temperature: next_slot(Module::Temperature),
+            gpu: next_slot(Module::Gpu),
+            disk: next_slot(Module::Disk),
+            network: next_slot(Module::Network),
+            wifi: next_slot(Module::Wifi),
+            ethernet: next_slot(Module::Ethernet),
+            connectivity: next_slot(Module::Connectivity),
+            bluetooth: next_slot(Module::Bluetooth),
+            idle: next_slot(Module::Idle),
+            caffeine: next_slot(Module::Caffeine),
+            dnd: next_slot(Module::Dnd),
+            night_light: next_slot(Module::NightLight),
+            rfkill: next_slot(Module::Rfkill),
+            removable: next_slot(Module::Removable),
+            failed_units: next_slot(Module::FailedUnits),
+            next_event: next_slot(Module::NextEvent),
+            todo: next_slot(Module::Todo),
+            mail: next_slot(Module::Mail),
+            updates: next_slot(Module::Updates),
+            containers: next_slot(Module::Containers),
+            custom: next_slot(Module::Custom),
+            status: next_slot(Module::Status),
+            fifo: next_slot(Module::Fifo),
+            tray: next_slot(Module::Tray),
+            taskbar: next_slot(Module::Taskbar),
+            since: next_slot(Module::Since),
+            timer: next_slot(Module::Timer),
+            pressure: next_slot(Module::Pressure),
+            backlight: next_slot(Module::Backlight),
+            keyboard_backlight: next_slot(Module::KeyboardBacklight),
+            volume: next_slot(Module::Volume),
+            microphone: next_slot(Module::Microphone),
+            privacy: next_slot(Module::Privacy),
+            audio_device: next_slot(Module::AudioDevice),
+            keyboard_layout: next_slot(Module::KeyboardLayout),
+            tiling: next_slot(Module::Tiling),
+            mpris: next_slot(Module::Mpris),
+        }
+    }
 
-        self.clear_and_damage_slot(0, old_width.max(total_width));
+    fn slot(&self, module: Module) -> Slot {
+        match module {
+            Module::Date => self.date,
+            Module::Clock => self.clock,
+            Module::Battery => self.battery,
+            Module::Power => self.power,
+            Module::PowerProfile => self.power_profile,
+            Module::Temperature => self.temperature,
+            Module::Gpu => self.gpu,
+            Module::Disk => self.disk,
+            Module::Network => self.network,
+            Module::Wifi => self.wifi,
+            Module::Ethernet => self.ethernet,
+            Module::Connectivity => self.connectivity,
+            Module::Bluetooth => self.bluetooth,
+            Module::Idle => self.idle,
+            Module::Caffeine => self.caffeine,
+            Module::Dnd => self.dnd,
+            Module::NightLight => self.night_light,
+            Module::Rfkill => self.rfkill,
+            Module::Removable => self.removable,
+            Module::FailedUnits => self.failed_units,
+            Module::NextEvent => self.next_event,
+            Module::Todo => self.todo,
+            Module::Mail => self.mail,
+            Module::Updates => self.updates,
+            Module::Containers => self.containers,
+            Module::Custom => self.custom,
+            Module::Status => self.status,
+            Module::Fifo => self.fifo,
+            Module::Tray => self.tray,
+            Module::Taskbar => self.taskbar,
+            Module::Since => self.since,
+            Module::Timer => self.timer,
+            Module::Pressure => self.pressure,
+            Module::Backlight => self.backlight,
+            Module::KeyboardBacklight => self.keyboard_backlight,
+            Module::Volume => self.volume,
+            Module::Microphone => self.microphone,
+            Module::Privacy => self.privacy,
+            Module::AudioDevice => self.audio_device,
+            Module::KeyboardLayout => self.keyboard_layout,
+            Module::Tiling => self.tiling,
+            Module::Mpris => self.mpris,
+        }
+    }
 
-        let mut cursor_x = MARGIN_LEFT;
-        for i in 0..10 {
-            let num = (i + 1) as u8;
-            if (mask & (1 << i)) != 0 || active_ws == num {
-                let color = if active_ws == num {
-                    COLOR_WS_FOCUSED
-                } else {
-                    COLOR_WS_OPEN
-                };
-                self.pb
-                    .draw_num(&mut cursor_x, self.glyphs, num as u32, color, 1, 1);
-                cursor_x += 10;
-            }
+    /// Clock followed by the battery, the battery keeps its space on machines
+    /// without one unless `battery_width = 0`.
+    fn capsule(glyphs: &font_renderer::GlyphCache) -> Self {
+        let clock = Slot {
+            x: MARGIN_LEFT,
+            width: reserved_width(Module::Clock, glyphs),
+        };
+        let battery = Slot {
+            x: clock.end() + MARGIN_GAP,
+            width: reserved_width(Module::Battery, glyphs),
+        };
+        Self {
+            clock,
+            battery,
+            ..Self::default()
         }
     }
+}
 
-    fn draw_date_module(&mut self, center: usize, day: u8, month: u8, year: u8) {
-        let max_width = (self.glyphs.max_digit_width * 6) + (self.glyphs.slash.width * 2) + 10;
-        let slot_x = center
-            .saturating_sub(MARGIN_GAP / 2)
-            .saturating_sub(max_width);
-        self.clear_and_damage_slot(slot_x, max_width);
+/// Size and placement of a bar's layer surface.
+struct SurfaceGeometry {
+    anchor: zwlr_layer_surface_v1::Anchor,
+    /// 0 stretches the surface between its left and right anchors.
+    width: u32,
+    exclusive_zone: i32,
+    /// Top, right, bottom, left.
+    margins: [i32; 4],
+}
 
-        let content_width = PixelBuffer::measure_num(self.glyphs, day as u32, 2, 1)
-            + 1
-            + self.glyphs.slash.width
-            + 1
-            + PixelBuffer::measure_num(self.glyphs, month as u32, 2, 1)
-            + 1
-            + self.glyphs.slash.width
-            + 1
-            + PixelBuffer::measure_num(self.glyphs, year as u32, 2, 0);
-        let mut cursor_x = center
-            .saturating_sub(MARGIN_GAP / 2)
-            .saturating_sub(content_width);
+/// Table of the click regions of every bar for `leanbar-ctl regions`.
+pub fn regions_report() -> String {
+    REGION_MAP.lock().map(|m| m.clone()).unwrap_or_default()
+}
 
-        let color = COLOR_DATE;
-        self.pb
-            .draw_num(&mut cursor_x, self.glyphs, day as u32, color, 2, 1);
-        cursor_x += 1;
-        self.pb
-            .draw_centered(&mut cursor_x, &self.glyphs.slash, color, 1);
-        self.pb
-            .draw_num(&mut cursor_x, self.glyphs, month as u32, color, 2, 1);
-        cursor_x += 1;
-        self.pb
-            .draw_centered(&mut cursor_x, &self.glyphs.slash, color, 1);
-        self.pb
-            .draw_num(&mut cursor_x, self.glyphs, year as u32, color, 2, 0);
+/// Outlines the click regions on every bar from the next redraw on.
+pub fn set_region_overlay(on: bool) {
+    REGION_OVERLAY.store(on, Ordering::Release);
+}
 
-        self.cache.day = day;
-        self.cache.month = month;
-        self.cache.year = year;
+/// Hides a shown module or shows a hidden one on every bar from the next redraw
+/// on, returns whether it is shown now.
+pub fn toggle_module(module: Module) -> bool {
+    let Ok(mut hidden) = HIDDEN_MODULES.lock() else {
+        return true;
+    };
+    if let Some(index) = hidden.iter().position(|m| *m == module) {
+        hidden.remove(index);
+        true
+    } else {
+        hidden.push(module);
+        false
     }
+}
 
-    fn draw_clock_module(&mut self, center: usize, hour: u8, minute: u8) {
-        let max_width = (self.glyphs.max_digit_width * 4)
-            + self.glyphs.colon.width
-            + self.glyphs.space.width
-            + self.glyphs.max_ampm_width
-            + 10;
-        let slot_x = center + (MARGIN_GAP / 2);
-        self.clear_and_damage_slot(slot_x, max_width);
-
-        let mut cursor_x = slot_x;
-        let color = COLOR_TIME;
-        let hour_12 = if hour == 0 {
-            12
-        } else if hour > 12 {
-            hour - 12
-        } else {
-            hour
+/// Modules hidden with `toggle_module`.
+pub fn hidden_modules() -> Vec<Module> {
+    HIDDEN_MODULES.lock().map(|h| h.clone()).unwrap_or_default()
+}
+
+/// The layer surface settings that layer rules and other tooling care about, as
+/// `key value` lines for the control socket.
+pub fn surface_report() -> String {
+    let config = config::get();
+    let geometry = SurfaceGeometry::from_config(None);
+    let [top, right, bottom, left] = geometry.margins;
+    format!(
+        "namespace {}\nlayer top\nmargins {} {} {} {}\nexclusive_zone {}\n",
+        config.namespace, top, right, bottom, left, geometry.exclusive_zone
+    )
+}
+
+impl SurfaceGeometry {
+    /// A full width bar along the bottom edge, or the capsule sized to its modules
+    /// in a corner. The capsule floats over windows instead of reserving space.
+    fn from_config(glyphs: Option<&font_renderer::GlyphCache>) -> Self {
+        use zwlr_layer_surface_v1::Anchor;
+
+        let config = config::get();
+        let Some(corner) = config.capsule else {
+            return Self {
+                anchor: Anchor::Bottom | Anchor::Left | Anchor::Right,
+                width: 0,
+                exclusive_zone: BAR_HEIGHT as i32,
+                margins: config.margins,
+            };
         };
-        self.pb
-            .draw_num(&mut cursor_x, self.glyphs, hour_12 as u32, color, 2, 1);
-        cursor_x += 1;
-        self.pb
-            .draw_centered(&mut cursor_x, &self.glyphs.colon, color, 1);
-        self.pb
-            .draw_num(&mut cursor_x, self.glyphs, minute as u32, color, 2, 1);
-        cursor_x += 1;
-        cursor_x += self.glyphs.space.width + 1;
-        let ampm_glyph = if hour >= 12 {
-            &self.glyphs.pm
-        } else {
-            &self.glyphs.am
+        let (top, left) = match corner {
+            Corner::TopLeft => (true, true),
+            Corner::TopRight => (true, false),
+            Corner::BottomLeft => (false, true),
+            Corner::BottomRight => (false, false),
         };
-        self.pb.draw_centered(&mut cursor_x, ampm_glyph, color, 0);
+        let vertical = if top { Anchor::Top } else { Anchor::Bottom };
+        let horizontal = if left { Anchor::Left } else { Anchor::Right };
+        let m = config.capsule_margin;
+        let width = glyphs.map_or(CAPSULE_FALLBACK_WIDTH, |g| {
+            Layout::capsule(g).battery.end() + MARGIN_RIGHT
+        });
+        Self {
+            anchor: vertical | horizontal,
+            width: width as u32,
+            exclusive_zone: 0,
+            margins: [
+                if top { m } else { 0 },
+                if left { 0 } else { m },
+                if top { 0 } else { m },
+                if left { m } else { 0 },
+            ],
+        }
+    }
+}
 
-        self.cache.hour = hour;
-        self.cache.minute = minute;
+/// Anchor and margins (top, right, bottom, left) of the calendar popup: centered
+/// above the full width bar, which its exclusive zone already keeps clear, or
+/// above or below the capsule in its corner.
+pub(crate) fn calendar_placement() -> (zwlr_layer_surface_v1::Anchor, [i32; 4]) {
+    use zwlr_layer_surface_v1::Anchor;
+
+    let geometry = SurfaceGeometry::from_config(None);
+    if config::get().capsule.is_none() {
+        return (Anchor::Bottom, [0, 0, CALENDAR_GAP, 0]);
     }
+    let mut margins = geometry.margins;
+    let edge = if geometry.anchor.contains(Anchor::Top) {
+        0
+    } else {
+        2
+    };
+    margins[edge] += BAR_HEIGHT as i32 + CALENDAR_GAP;
+    (geometry.anchor, margins)
+}
 
-    fn draw_battery_module(&mut self, percent: u8, state: u8, estimate: u16) {
-        let slot_x = self.pb.width.saturating_sub(BATTERY_SLOT_MAX_WIDTH);
-        self.clear_and_damage_slot(slot_x, BATTERY_SLOT_MAX_WIDTH);
-        let color = COLOR_BAT;
+impl AppState {
+    /// Anchor and margins of a tray item's menu: above the bar like the calendar,
+    /// starting `x` from the bar's left edge, or ending there for a capsule in a
+    /// right corner.
+    pub(crate) fn tray_menu_placement(
+        &self,
+        bar: u32,
+        x: f64,
+    ) -> (zwlr_layer_surface_v1::Anchor, [i32; 4]) {
+        use zwlr_layer_surface_v1::Anchor;
 
-        if state == 3 {
-            let mut cursor_x = self
-                .pb
-                .width
-                .saturating_sub(MARGIN_RIGHT + self.glyphs.full.width);
-            self.pb
-                .draw_centered(&mut cursor_x, &self.glyphs.full, color, 0);
+        let (mut anchor, mut margins) = calendar_placement();
+        let x = x as i32;
+        if config::get().capsule.is_none() {
+            anchor |= Anchor::Left;
+            margins[3] = config::get().margins[3] + x;
+        } else if anchor.contains(Anchor::Right) {
+            let width = self
+                .bars
+                .iter()
+                .find(|b| b.id == bar)
+                .map_or(0, |b| b.width);
+            margins[1] += (width as i32 - x).max(0);
         } else {
-            let content_width = PixelBuffer::measure_num(self.glyphs, percent as u32, 1, 1)
-                + 1
-                + self.glyphs.percent.width
-                + 3
-                + self.glyphs.plus.width
-                + 3
-                + PixelBuffer::measure_num(self.glyphs, (estimate / 60) as u32, 2, 1)
-                + 1
-                + self.glyphs.colon.width
-                + 1
-                + PixelBuffer::measure_num(self.glyphs, (estimate % 60) as u32, 2, 0);
-            let mut cursor_x = self.pb.width.saturating_sub(MARGIN_RIGHT + content_width);
-            self.pb
-                .draw_num(&mut cursor_x, self.glyphs, percent as u32, color, 1, 1);
-            cursor_x += 1;
-            self.pb
-                .draw_centered(&mut cursor_x, &self.glyphs.percent, color, 3);
-            let status_glyph = if state == 2 {
-                &self.glyphs.plus
+            margins[3] += x;
+        }
+        (anchor, margins)
+    }
+}
+
+/// A month of the calendar popup.
+pub(crate) struct CalendarMonth {
+    /// "October 2026"
+    pub title: String,
+    /// Weekday of the 1st, 0 is Monday.
+    pub first_weekday: u8,
+    pub days: u8,
+    /// Today's day of the month when it is this month.
+    pub today: Option<u8>,
+}
+
+/// Month header, weekday names and six weeks, each row as tall as the bar so
+/// text sits in it just like on the bar.
+pub(crate) struct CalendarGrid {
+    cell_width: usize,
+}
+
+impl CalendarGrid {
+    pub(crate) fn new(glyphs: &font_renderer::GlyphCache) -> Self {
+        let widest =
+            PixelBuffer::measure_text(glyphs, "Mo").max(PixelBuffer::measure_num(glyphs, 28, 2, 1));
+        Self {
+            cell_width: widest + CALENDAR_CELL_PADDING,
+        }
+    }
+
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (
+            (7 * self.cell_width + 2 * CALENDAR_PADDING) as u32,
+            (CALENDAR_ROWS * BAR_HEIGHT + 2 * CALENDAR_PADDING) as u32,
+        )
+    }
+
+    /// Day of the month at a point on the popup.
+    pub(crate) fn day_at(&self, x: f64, y: f64, month: &CalendarMonth) -> Option<u8> {
+        let column = (x as usize).checked_sub(CALENDAR_PADDING)? / self.cell_width;
+        let row = (y as usize).checked_sub(CALENDAR_PADDING + 2 * BAR_HEIGHT)? / BAR_HEIGHT;
+        if column >= 7 || row >= CALENDAR_ROWS - 2 {
+            return None;
+        }
+        let day = (row * 7 + column + 1).checked_sub(month.first_weekday as usize)?;
+        (1..=month.days as usize)
+            .contains(&day)
+            .then_some(day as u8)
+    }
+
+    /// Draws the month over the whole popup buffer.
+    pub(crate) fn draw(
+        &self,
+        pixels: &mut [u32],
+        glyphs: &font_renderer::GlyphCache,
+        month: &CalendarMonth,
+    ) {
+        const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+
+        let width = self.size().0 as usize;
+        pixels.fill(color::correct(COLOR_CAPSULE_BG));
+        let rows = pixels[CALENDAR_PADDING * width..].chunks_mut(width * BAR_HEIGHT);
+        for (row, band) in rows.take(CALENDAR_ROWS).enumerate() {
+            let mut pb = PixelBuffer::new(band, width, BAR_HEIGHT, Quality::Full);
+            // Left edge of a column with content of the given width centered in it
+            let cell_x = |column: usize, content: usize| {
+                CALENDAR_PADDING + column * self.cell_width + (self.cell_width - content) / 2
+            };
+            match row {
+                0 => {
+                    let title_width = PixelBuffer::measure_text(glyphs, &month.title);
+                    let mut x = (width.saturating_sub(title_width)) / 2;
+                    pb.draw_text(&mut x, glyphs, &month.title, COLOR_DATE);
+                }
+                1 => {
+                    for (column, name) in WEEKDAYS.iter().enumerate() {
+                        let mut x = cell_x(column, PixelBuffer::measure_text(glyphs, name));
+                        pb.draw_text(&mut x, glyphs, name, dim(COLOR_TITLE));
+                    }
+                }
+                _ => {
+                    for column in 0..7 {
+                        let Some(day) = ((row - 2) * 7 + column + 1)
+                            .checked_sub(month.first_weekday as usize)
+                            .filter(|day| (1..=month.days as usize).contains(day))
+                        else {
+                            continue;
+                        };
+                        let color = if month.today == Some(day as u8) {
+                            pb.fill_rect(
+                                CALENDAR_PADDING + column * self.cell_width,
+                                2,
+                                self.cell_width,
+                                BAR_HEIGHT - 4,
+                                dim(COLOR_TIME),
+                            );
+                            COLOR_WS_FOCUSED
+                        } else {
+                            COLOR_TITLE
+                        };
+                        let mut x =
+                            cell_x(column, PixelBuffer::measure_num(glyphs, day as u32, 1, 1));
+                        pb.draw_num(&mut x, glyphs, day as u32, color, 1, 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Entries of a tray item's menu, one row as tall as the bar each and submenus
+/// indented below their parent.
+pub(crate) struct MenuList {
+    width: usize,
+    rows: usize,
+    /// Room for checkmarks when any entry has one.
+    check_width: usize,
+}
+
+impl MenuList {
+    pub(crate) fn new(glyphs: &font_renderer::GlyphCache, entries: &[tray::MenuEntry]) -> Self {
+        let check_width = if entries.iter().any(|e| e.toggled.is_some()) {
+            MENU_CHECK_SIZE + MENU_INDENT / 2
+        } else {
+            0
+        };
+        let widest = entries
+            .iter()
+            .map(|e| {
+                e.depth as usize * MENU_INDENT
+                    + PixelBuffer::measure_text(glyphs, &e.label).min(MENU_MAX_LABEL_WIDTH)
+            })
+            .max()
+            .unwrap_or(0);
+        Self {
+            width: widest + check_width + 2 * MENU_INDENT + 2 * MENU_PADDING,
+            rows: entries.len().max(1),
+            check_width,
+        }
+    }
+
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (
+            self.width as u32,
+            (self.rows * BAR_HEIGHT + 2 * MENU_PADDING) as u32,
+        )
+    }
+
+    /// Index of the entry at a height on the popup.
+    pub(crate) fn row_at(&self, y: f64) -> Option<usize> {
+        let row = (y as usize).checked_sub(MENU_PADDING)? / BAR_HEIGHT;
+        (row < self.rows).then_some(row)
+    }
+
+    /// Draws the entries over the whole popup buffer.
+    pub(crate) fn draw(
+        &self,
+        pixels: &mut [u32],
+        glyphs: &font_renderer::GlyphCache,
+        entries: &[tray::MenuEntry],
+        hovered: Option<usize>,
+    ) {
+        pixels.fill(color::correct(COLOR_CAPSULE_BG));
+        let rows = pixels[MENU_PADDING * self.width..].chunks_mut(self.width * BAR_HEIGHT);
+        for ((row, band), entry) in rows.enumerate().zip(entries) {
+            let mut pb = PixelBuffer::new(band, self.width, BAR_HEIGHT, Quality::Full);
+            let inner = self.width - 2 * MENU_PADDING;
+            if entry.separator {
+                pb.fill_rect(
+                    MENU_PADDING + MENU_INDENT / 2,
+                    BAR_HEIGHT / 2,
+                    inner - MENU_INDENT,
+                    1,
+                    dim(COLOR_TITLE),
+                );
+                continue;
+            }
+            if hovered == Some(row) {
+                pb.fill_rect(MENU_PADDING, 2, inner, BAR_HEIGHT - 4, dim(COLOR_TIME));
+            }
+            let mut x = MENU_PADDING + MENU_INDENT + entry.depth as usize * MENU_INDENT;
+            if let Some(toggled) = entry.toggled {
+                let y = (BAR_HEIGHT - MENU_CHECK_SIZE) / 2;
+                if toggled {
+                    pb.fill_rect(x, y, MENU_CHECK_SIZE, MENU_CHECK_SIZE, COLOR_TITLE);
+                } else {
+                    pb.stroke_rect(x, y, MENU_CHECK_SIZE, MENU_CHECK_SIZE, 1, COLOR_TITLE);
+                }
+            }
+            x += self.check_width;
+            let color = if entry.enabled {
+                COLOR_TITLE
             } else {
-                &self.glyphs.minus
+                dim(COLOR_TITLE)
             };
-            self.pb.draw_centered(&mut cursor_x, status_glyph, color, 3);
-            self.pb.draw_num(
-                &mut cursor_x,
-                self.glyphs,
-                (estimate / 60) as u32,
-                color,
-                2,
-                1,
-            );
-            cursor_x += 1;
-            self.pb
-                .draw_centered(&mut cursor_x, &self.glyphs.colon, color, 1);
-            self.pb.draw_num(
-                &mut cursor_x,
-                self.glyphs,
-                (estimate % 60) as u32,
-                color,
-                2,
+            pb.draw_text_fitted(&mut x, glyphs, &entry.label, MENU_MAX_LABEL_WIDTH, color);
+        }
+    }
+}
+
+/// Clears to the capsule's backdrop, the full width bar stays transparent.
+fn background() -> u32 {
+    if config::get().capsule.is_some() {
+        COLOR_CAPSULE_BG
+    } else {
+        0
+    }
+}
+
+/// Width reserved for a module, either set in the config or measured from its
+/// widest possible content.
+fn reserved_width(module: Module, glyphs: &font_renderer::GlyphCache) -> usize {
+    if hidden_modules().contains(&module) {
+        return 0;
+    }
+    if let Some(width) = config::get().module_widths.get(&module) {
+        return *width;
+    }
+    match module {
+        Module::Date => PixelBuffer::measure_widest(glyphs, &format::date(0, 0, 0)),
+        Module::Clock => {
+            // Room for the "?" of the fallback clock either way
+            let widest = if config::get().clock_timezone {
+                tz::abbreviations()
+                    .iter()
+                    .map(|name| PixelBuffer::measure_text(glyphs, &format!("{}?", name)))
+                    .max()
+                    .unwrap_or(0)
+            } else {
+                PixelBuffer::measure_text(glyphs, "?")
+            };
+            PixelBuffer::measure_widest(glyphs, &format::clock_12h(0, 0))
+                + CLOCK_TIMEZONE_GAP
+                + widest
+        }
+        // Indicators are configurable, any state may be the widest
+        Module::Battery => {
+            let text = [0, 1, 2, 3]
+                .into_iter()
+                .map(|state| {
+                    PixelBuffer::measure_widest(
+                        glyphs,
+                        &battery_content(100, state, 99 * 60 + 59, 999, 1),
+                    )
+                })
+                .chain([PixelBuffer::measure_widest(
+                    glyphs,
+                    &battery_health_content(100, 9999),
+                )])
+                .max()
+                .unwrap_or(0);
+            if config::get().battery_icon {
+                text + BATTERY_ICON_GAP + battery_icon_size(glyphs).0
+            } else {
+                text
+            }
+        }
+        Module::Power => PixelBuffer::measure_widest(glyphs, &format::watts(999)),
+        Module::PowerProfile => power_profile::PROFILES
+            .iter()
+            .map(|name| PixelBuffer::measure_text(glyphs, name))
+            .max()
+            .unwrap_or(0),
+        Module::Gpu => {
+            let vram = if config::get().gpu_vram {
+                999
+            } else {
+                u32::MAX
+            };
+            PixelBuffer::measure_widest(glyphs, &gpu_content(100, vram))
+        }
+        Module::Temperature => {
+            PixelBuffer::measure_widest(glyphs, &format::celsius(199).then(throttle_badge(true)))
+        }
+        Module::Disk => PixelBuffer::measure_widest(glyphs, &disk_content(999, 999)),
+        Module::Network => {
+            NET_HISTORY_LEN * SPARKLINE_COLUMN
+                + SPARKLINE_GAP
+                + PixelBuffer::measure_widest(glyphs, &format::bytes(999))
+        }
+        Module::Wifi => {
+            PixelBuffer::measure_text(glyphs, WIFI_SAMPLE_SSID)
+                + WIFI_SSID_GAP
+                + PixelBuffer::measure_widest(glyphs, &format::percent(100))
+        }
+        Module::Ethernet => PixelBuffer::measure_text(glyphs, "255.255.255.255"),
+        Module::Connectivity => {
+            PixelBuffer::measure_text(glyphs, CONNECTIVITY_SAMPLE_NAME)
+                + CONNECTIVITY_GAP
+                + ["full", "limited", "portal", "none"]
+                    .iter()
+                    .map(|text| PixelBuffer::measure_text(glyphs, text))
+                    .max()
+                    .unwrap_or(0)
+        }
+        Module::Bluetooth => {
+            PixelBuffer::measure_text(glyphs, &format!("BT {} +9", BLUETOOTH_SAMPLE_DEVICE))
+                + BLUETOOTH_BATTERY_GAP
+                + PixelBuffer::measure_widest(glyphs, &format::percent(100))
+        }
+        Module::Idle => [0, 1]
+            .into_iter()
+            .map(|daemon| PixelBuffer::measure_widest(glyphs, &idle_content(daemon, 99 * 60 + 59)))
+            .max()
+            .unwrap_or(0),
+        Module::Caffeine => [0, 1]
+            .into_iter()
+            .map(|inhibited| PixelBuffer::measure_text(glyphs, caffeine_text(inhibited)))
+            .max()
+            .unwrap_or(0),
+        Module::Dnd => [0, 1]
+            .into_iter()
+            .map(|enabled| PixelBuffer::measure_text(glyphs, dnd_text(enabled)))
+            .max()
+            .unwrap_or(0),
+        Module::NightLight => [0, 1, 2]
+            .into_iter()
+            .map(|state| PixelBuffer::measure_text(glyphs, night_light_text(state)))
+            .max()
+            .unwrap_or(0),
+        Module::Rfkill => (0..16)
+            .map(|state| PixelBuffer::measure_text(glyphs, &rfkill_text(state)))
+            .max()
+            .unwrap_or(0),
+        Module::Removable => PixelBuffer::measure_text(glyphs, &removable_text(99)),
+        Module::FailedUnits => PixelBuffer::measure_text(glyphs, &failed_units_text(999)),
+        Module::NextEvent => PixelBuffer::measure_text(glyphs, NEXT_EVENT_SAMPLE),
+        Module::Todo => PixelBuffer::measure_text(glyphs, &todo_text(999)),
+        Module::Mail => PixelBuffer::measure_text(glyphs, &mail_text(9999)),
+        Module::Updates => PixelBuffer::measure_text(glyphs, &updates_text(999)),
+        Module::Containers => PixelBuffer::measure_text(glyphs, &containers_text(999)),
+        Module::Custom => PixelBuffer::measure_text(glyphs, CUSTOM_SAMPLE),
+        Module::Fifo => PixelBuffer::measure_text(glyphs, FIFO_SAMPLE),
+        Module::Status => STATUS_SAMPLE
+            .iter()
+            .map(|text| PixelBuffer::measure_text(glyphs, text) + STATUS_BLOCK_GAP)
+            .sum::<usize>()
+            .saturating_sub(STATUS_BLOCK_GAP),
+        Module::Tray => (config::get().tray_icons * (tray::ICON_SIZE + TRAY_ICON_GAP))
+            .saturating_sub(TRAY_ICON_GAP),
+        Module::Taskbar => (config::get().taskbar_entries
+            * (TASKBAR_ENTRY_WIDTH + TASKBAR_ENTRY_GAP))
+            .saturating_sub(TASKBAR_ENTRY_GAP),
+        Module::Since => [23 * 3600 + 59 * 60, 99 * 86400 + 23 * 3600]
+            .into_iter()
+            .map(|elapsed| PixelBuffer::measure_widest(glyphs, &since_content(elapsed)))
+            .max()
+            .unwrap_or(0),
+        Module::Timer => PixelBuffer::measure_text(glyphs, &timer_text(99 * 3600 + 59 * 60 + 59)),
+        Module::Pressure => PixelBuffer::measure_widest(glyphs, &pressure_content([999; 3])),
+        Module::Backlight => PixelBuffer::measure_widest(glyphs, &backlight_content(100)),
+        Module::KeyboardBacklight => {
+            PixelBuffer::measure_widest(glyphs, &keyboard_backlight_content(10, 10))
+        }
+        Module::Volume => PixelBuffer::measure_widest(glyphs, &volume_content(150, true)),
+        Module::Microphone => glyphs.mic_muted.width,
+        Module::Privacy => 3 * privacy_dot_diameter(glyphs) + 2 * PRIVACY_DOT_GAP,
+        Module::AudioDevice => PixelBuffer::measure_text(glyphs, "Headphones"),
+        Module::KeyboardLayout => PixelBuffer::measure_text(glyphs, "MM*"),
+        Module::Tiling => PixelBuffer::measure_text(glyphs, "master 9"),
+        Module::Mpris => {
+            let config = config::get();
+            let indicator = PixelBuffer::measure_text(glyphs, &config.mpris_playing)
+                .max(PixelBuffer::measure_text(glyphs, &config.mpris_paused));
+            // Sized for average text rather than the widest letters
+            indicator + MPRIS_GAP + PixelBuffer::measure_text(glyphs, "n") * config.mpris_max_chars
+        }
+    }
+}
+
+/// Draws with a renderer over a fresh transparent buffer of `width` x
+/// `BAR_HEIGHT`, without a Wayland connection.
+fn draw_offscreen(
+    glyphs: &font_renderer::GlyphCache,
+    width: usize,
+    draw: impl FnOnce(&mut Renderer),
+) -> (Vec<u32>, Layout) {
+    let mut pixels = vec![0u32; width * BAR_HEIGHT];
+    let mut pb = PixelBuffer::new(&mut pixels, width, BAR_HEIGHT, Quality::Full);
+    let mut cache = DrawCache::default();
+    let mut regions = Vec::new();
+    let layout = Layout::compute(width, glyphs);
+    draw(&mut Renderer {
+        pb: &mut pb,
+        glyphs,
+        cache: &mut cache,
+        regions: &mut regions,
+        surface: None,
+        height: BAR_HEIGHT as u32,
+        layout: &layout,
+        last_slot: None,
+    });
+    (pixels, layout)
+}
+
+/// Draws a sample bar with every module filled in, without a Wayland connection.
+/// Returns premultiplied ARGB pixels of `width` x `BAR_HEIGHT`.
+pub fn render_preview(glyphs: &font_renderer::GlyphCache, width: usize) -> (Vec<u32>, usize) {
+    let (pixels, _) = draw_offscreen(glyphs, width, |renderer| {
+        let workspaces = [(1, "1"), (2, "2"), (3, "3"), (-98, "web")]
+            .into_iter()
+            .map(|(id, name)| WorkspaceLabel {
+                id,
+                name: name.to_string(),
+                dimmed: false,
+                windows: 1,
+            })
+            .collect();
+        renderer.draw_workspaces(2, workspaces);
+        renderer.draw_title_module("Sample window title".to_string(), "kitty".to_string());
+        renderer.draw_date_module(16, 10, 26);
+        let timezone = if config::get().clock_timezone {
+            "CEST"
+        } else {
+            ""
+        };
+        renderer.draw_clock_module(21, 47, timezone.to_string());
+        let reading = BatteryReading {
+            percent: 87,
+            state: 2,
+            estimate_m: 83,
+            rate_dw: 452,
+        };
+        renderer.draw_battery_module(reading, 1, false, (255, 0));
+        renderer.draw_power_module(123);
+        renderer.draw_power_profile_module(1);
+        renderer.draw_disk_module(12 * 1024, 340);
+        let history: Vec<u32> = (0..NET_HISTORY_LEN as u32)
+            .map(|i| (i * 37 % 23) * 40)
+            .collect();
+        renderer.draw_network_module(1, &history);
+        renderer.draw_wifi_module("home".to_string(), 72);
+        renderer.draw_ethernet_module(1, "192.168.1.20".to_string());
+        renderer.draw_connectivity_module(networkmanager::FULL, "home".to_string());
+        renderer.draw_bluetooth_module(1, 1, "WH-1000XM4".to_string(), 80);
+        renderer.draw_idle_module(1, 4 * 60 + 12);
+        renderer.draw_caffeine_module(1);
+        renderer.draw_dnd_module(0);
+        renderer.draw_night_light_module(2);
+        renderer.draw_rfkill_module(rfkill::BLUETOOTH);
+        renderer.draw_removable_module(1);
+        renderer.draw_failed_units_module(2);
+        renderer.draw_next_event_module(0, NEXT_EVENT_SAMPLE.to_string());
+        renderer.draw_todo_module(5);
+        renderer.draw_mail_module(3, String::new());
+        renderer.draw_updates_module(12);
+        renderer.draw_containers_module(4);
+        renderer.draw_custom_module(CUSTOM_SAMPLE.to_string(), COLOR_CUSTOM);
+        renderer.draw_status_module(&sample_status_blocks(), 0);
+        renderer.draw_fifo_module(FIFO_SAMPLE.to_string());
+        renderer.draw_tray_module(&sample_tray_items(2), 0);
+        renderer.draw_taskbar_module(&sample_taskbar_entries(3), 0);
+        renderer.draw_since_module(3 * 86400 + 4 * 3600);
+        renderer.draw_timer_module(4 * 60 + 5, timer::RUNNING);
+        renderer.draw_pressure_module([37, 0, 3], 0);
+        renderer.draw_backlight_module(60);
+        renderer.draw_keyboard_backlight_module(1, 2);
+        renderer.draw_volume_module(45, false);
+        renderer.draw_microphone_module(1);
+        renderer.draw_privacy_module(privacy::MICROPHONE | privacy::SCREEN);
+        renderer.draw_audio_device_module("HDMI".to_string());
+        renderer.draw_layout_module("us".to_string());
+        renderer.draw_tiling_module("dwindle".to_string());
+        renderer.draw_mpris_module(1, "Boards of Canada - Roygbiv".to_string());
+    });
+    (pixels, BAR_HEIGHT)
+}
+
+/// Draws one module from `--render-module` state, values missing from it fall
+/// back to those of `render_preview`. Returns premultiplied ARGB pixels cropped
+/// to the module's slot, with their width and height.
+pub fn render_module(
+    glyphs: &font_renderer::GlyphCache,
+    module: Module,
+    state: &preview::State,
+) -> (Vec<u32>, usize, usize) {
+    // Wide enough that every right side module gets its full slot
+    const WIDTH: usize = 8192;
+    let (pixels, layout) = draw_offscreen(glyphs, WIDTH, |renderer| match module {
+        Module::Date => renderer.draw_date_module(
+            state.num("day", 16) as u8,
+            state.num("month", 10) as u8,
+            state.num("year", 26) as u8,
+        ),
+        Module::Clock => renderer.draw_clock_module(
+            state.num("hour", 21) as u8,
+            state.num("minute", 47) as u8,
+            state.text("timezone", ""),
+        ),
+        Module::Battery => renderer.draw_battery_module(
+            BatteryReading {
+                percent: state.num("percent", 87) as u8,
+                state: state.choice("state", &["unknown", "discharging", "charging", "full"], 2)
+                    as u8,
+                estimate_m: state.num("estimate_min", 83) as u16,
+                rate_dw: state.num("rate_dw", 452) as u16,
+            },
+            state.choice("ac", &["unplugged", "plugged"], 1) as u8,
+            state.flag("blink", false),
+            match state.num("health", 255) as u8 {
+                255 => (255, 0),
+                health => (health, state.num("cycles", u16::MAX as u32) as u16),
+            },
+        ),
+        Module::Power => renderer.draw_power_module(state.num("deciwatts", 123) as u16),
+        Module::PowerProfile => {
+            renderer.draw_power_profile_module(
+                state.choice("profile", &power_profile::PROFILES, 1) as u8
+            )
+        }
+        Module::Temperature => renderer.draw_temperature_module(
+            state.num("celsius", 54) as u8,
+            state.flag("throttled", false),
+        ),
+        Module::Gpu => {
+            renderer.draw_gpu_module(state.num("busy", 37) as u8, state.num("vram_mb", u32::MAX))
+        }
+        Module::Disk => renderer.draw_disk_module(
+            state.num("read_kbs", 12 * 1024),
+            state.num("write_kbs", 340),
+        ),
+        Module::Network => {
+            // A ramp up to the given rate so the sparkline has a shape
+            let kbs = state.num("kbs", 880);
+            let history: Vec<u32> = (1..=NET_HISTORY_LEN as u32)
+                .map(|i| kbs * i / NET_HISTORY_LEN as u32)
+                .collect();
+            renderer.draw_network_module(1, &history)
+        }
+        Module::Wifi => {
+            renderer.draw_wifi_module(state.text("ssid", "home"), state.num("signal", 72) as u8)
+        }
+        Module::Ethernet => renderer.draw_ethernet_module(
+            state.choice("link", &["down", "up"], 1) as u8,
+            state.text("address", "192.168.1.20"),
+        ),
+        Module::Connectivity => renderer.draw_connectivity_module(
+            state.choice(
+                "state",
+                &["unknown", "none", "portal", "limited", "full"],
+                4,
+            ) as u8,
+            state.text("connection", "home"),
+        ),
+        Module::Bluetooth => renderer.draw_bluetooth_module(
+            state.choice("powered", &["off", "on"], 1) as u8,
+            state.num("connected", 1) as u8,
+            state.text("device", "WH-1000XM4"),
+            state.num("battery", 80).min(255) as u8,
+        ),
+        Module::Idle => renderer.draw_idle_module(
+            state.flag("daemon", true) as u8,
+            state.num("remaining_s", 4 * 60 + 12),
+        ),
+        Module::Caffeine => renderer.draw_caffeine_module(state.flag("inhibited", true) as u8),
+        Module::Dnd => renderer.draw_dnd_module(state.flag("enabled", true) as u8),
+        Module::Tray => {
+            renderer.draw_tray_module(&sample_tray_items(state.num("items", 2) as usize), 0)
+        }
+        Module::Taskbar => renderer
+            .draw_taskbar_module(&sample_taskbar_entries(state.num("entries", 3) as usize), 0),
+        Module::Fifo => renderer.draw_fifo_module(state.text("text", FIFO_SAMPLE)),
+        Module::Status => {
+            let mut blocks = sample_status_blocks();
+            blocks[0].urgent = state.flag("urgent", false);
+            renderer.draw_status_module(&blocks, 0);
+        }
+        Module::NightLight => {
+            renderer
+                .draw_night_light_module(state.choice("state", &["off", "day", "warm"], 2) as u8)
+        }
+        Module::Rfkill => {
+            let blocked = [
                 0,
-            );
+                rfkill::WIFI,
+                rfkill::BLUETOOTH,
+                rfkill::WIFI | rfkill::BLUETOOTH | rfkill::AIRPLANE,
+            ][state.choice("blocked", &["none", "wifi", "bluetooth", "all"], 2) as usize];
+            let hard = if state.flag("hard", false) {
+                rfkill::HARD
+            } else {
+                0
+            };
+            renderer.draw_rfkill_module(blocked | hard)
         }
-        self.cache.bat_percent = percent;
-        self.cache.bat_state = state;
-        self.cache.bat_est_min = estimate;
+        Module::Removable => renderer.draw_removable_module(state.num("count", 1).min(254) as u8),
+        Module::FailedUnits => {
+            renderer.draw_failed_units_module(state.num("count", 2).min(999) as u16)
+        }
+        Module::NextEvent => renderer.draw_next_event_module(
+            state.flag("soon", false) as u8,
+            state.text("event", NEXT_EVENT_SAMPLE),
+        ),
+        Module::Todo => renderer.draw_todo_module(state.num("count", 5).min(999) as u16),
+        Module::Mail => renderer.draw_mail_module(
+            state.num("unread", 3).min(9999),
+            if state.flag("hovered", false) {
+                mail_accounts_text(&[("work".to_string(), 2), ("home".to_string(), 1)])
+            } else {
+                String::new()
+            },
+        ),
+        Module::Updates => renderer.draw_updates_module(state.num("pending", 12).min(999) as u16),
+        Module::Containers => {
+            renderer.draw_containers_module(state.num("running", 4).min(999) as u16)
+        }
+        Module::Custom => renderer.draw_custom_module(
+            state.text("text", CUSTOM_SAMPLE),
+            if state.flag("critical", false) {
+                COLOR_TEMP_HOT
+            } else {
+                COLOR_CUSTOM
+            },
+        ),
+        Module::Since => renderer.draw_since_module(state.num("elapsed_s", 3 * 86400 + 4 * 3600)),
+        Module::Timer => renderer.draw_timer_module(
+            state.num("seconds", 4 * 60 + 5),
+            state.choice("state", &["stopped", "running", "finished"], 1) as u8,
+        ),
+        Module::Pressure => renderer.draw_pressure_module(
+            [("cpu", 37), ("memory", 0), ("io", 3)]
+                .map(|(key, value)| state.num(key, value) as u16),
+            state.num("full", 0) as u16,
+        ),
+        Module::Backlight => renderer.draw_backlight_module(state.num("percent", 60) as u8),
+        Module::KeyboardBacklight => renderer.draw_keyboard_backlight_module(
+            state.num("level", 1) as u8,
+            state.num("levels", 2) as u8,
+        ),
+        Module::Volume => {
+            renderer.draw_volume_module(state.num("percent", 45) as u8, state.flag("muted", false))
+        }
+        Module::Microphone => renderer.draw_microphone_module(state.flag("muted", true) as u8),
+        Module::Privacy => {
+            let in_use = [
+                ("microphone", privacy::MICROPHONE),
+                ("camera", privacy::CAMERA),
+                ("screen", privacy::SCREEN),
+            ]
+            .into_iter()
+            .filter(|(key, _)| state.flag(key, true))
+            .fold(0, |bits, (_, bit)| bits | bit);
+            renderer.draw_privacy_module(in_use)
+        }
+        Module::AudioDevice => renderer.draw_audio_device_module(state.text("label", "HDMI")),
+        Module::KeyboardLayout => renderer.draw_layout_module(state.text("code", "us")),
+        Module::Tiling => renderer.draw_tiling_module(state.text("layout", "dwindle")),
+        Module::Mpris => renderer.draw_mpris_module(
+            state.choice("status", &["stopped", "playing", "paused"], 1) as u8,
+            state.text("text", "Boards of Canada - Roygbiv"),
+        ),
+    });
+
+    let slot = layout.slot(module);
+    let cropped = pixels
+        .chunks(WIDTH)
+        .flat_map(|row| &row[slot.x..slot.end()])
+        .copied()
+        .collect();
+    (cropped, slot.width, BAR_HEIGHT)
+}
+
+/// Color of the first configured band the charge falls into.
+fn battery_color(percent: u8) -> u32 {
+    let config = config::get();
+    if !config.battery_colors.is_empty() {
+        return config
+            .battery_colors
+            .iter()
+            .find(|(threshold, _)| percent <= *threshold)
+            .map_or(COLOR_BAT, |(_, color)| *color);
+    }
+    // Warning levels are highest first
+    match (
+        config.battery_warnings.first(),
+        config.battery_warnings.last(),
+    ) {
+        (_, Some(critical)) if percent <= *critical => COLOR_BAT_CRITICAL,
+        (Some(warning), _) if percent <= *warning => COLOR_BAT_WARNING,
+        _ => COLOR_BAT,
+    }
+}
+
+/// Green up to 50C, through yellow at 70C, red from 90C.
+/// Gap and `temperature_throttled` while throttled, nothing otherwise.
+fn throttle_badge(throttled: bool) -> Formatted {
+    let badge = &config::get().temperature_throttled;
+    if throttled && !badge.is_empty() {
+        Formatted::default().gap(4).text(badge)
+    } else {
+        Formatted::default()
+    }
+}
+
+fn temperature_color(degrees: u8) -> u32 {
+    match degrees {
+        ..=50 => COLOR_TEMP_COOL,
+        51..=70 => lerp_color(
+            COLOR_TEMP_COOL,
+            COLOR_TEMP_WARM,
+            (degrees - 50) as u32 * 255 / 20,
+        ),
+        71..=89 => lerp_color(
+            COLOR_TEMP_WARM,
+            COLOR_TEMP_HOT,
+            (degrees - 70) as u32 * 255 / 20,
+        ),
+        _ => COLOR_TEMP_HOT,
+    }
+}
+
+/// Mixes two colors, `t` runs from 0 (all `from`) to 255 (all `to`).
+fn lerp_color(from: u32, to: u32, t: u32) -> u32 {
+    let channel = |shift: u32| {
+        let (a, b) = ((from >> shift) & 0xff, (to >> shift) & 0xff);
+        ((a * (255 - t) + b * t) / 255) << shift
+    };
+    channel(24) | channel(16) | channel(8) | channel(0)
+}
+
+/// Scales a premultiplied ARGB color to the given opacity out of 255.
+fn fade(color: u32, alpha: u32) -> u32 {
+    let channel = |shift: u32| (((color >> shift) & 0xff) * alpha / 255) << shift;
+    channel(24) | channel(16) | channel(8) | channel(0)
+}
+
+/// Workspace color on the `workspace_ramp_windows` ramp, from dim with a single
+/// window to full with the configured count.
+fn occupancy_color(windows: u16) -> u32 {
+    let full_at = config::get().workspace_ramp_windows;
+    if full_at == 0 {
+        return COLOR_WS_OPEN;
     }
+    let t = match full_at {
+        1 => 255,
+        _ => windows.saturating_sub(1).min(full_at - 1) as u32 * 255 / (full_at - 1) as u32,
+    };
+    lerp_color(dim(COLOR_WS_OPEN), COLOR_WS_OPEN, t)
+}
+
+/// Halves the opacity of a premultiplied ARGB color.
+fn dim(color: u32) -> u32 {
+    (color >> 1) & 0x7f7f7f7f
+}
+
+/// Workspaces whose name is just their ID are drawn with the digit glyphs.
+fn is_numbered(id: i32, name: &str) -> bool {
+    id > 0 && name.parse::<i32>() == Ok(id)
 }
 
 impl Drop for AppState {
     fn drop(&mut self) {
-        if let Some(buffer) = self.buffer.take() {
-            buffer.destroy();
-        }
-
-        if !self.pixels.is_null() && self.pixels_len > 0 {
-            let _ = unsafe { munmap(self.pixels.cast(), self.pixels_len) };
-            self.pixels = ptr::null_mut();
-            self.pixels_len = 0;
+        self.close_calendar();
+        self.close_tray_menu();
+        for bar in self.bars.drain(..) {
+            bar.destroy();
         }
     }
 }
@@ -583,11 +4786,13 @@ impl Dispatch<WlRegistry, ()> for AppState {
         _: &Connection,
         qhandle: &QueueHandle<Self>,
     ) {
-        if let wl_registry::Event::Global {
-            name, interface, ..
-        } = event
-        {
-            match interface.as_str() {
+        state.event_log.record(registry, event.opcode(), &event);
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } => match interface.as_str() {
                 "wl_compositor" => {
                     state.compositor = Some(registry.bind(name, 4, qhandle, ()));
                 }
@@ -597,91 +4802,123 @@ impl Dispatch<WlRegistry, ()> for AppState {
                 "zwlr_layer_shell_v1" => {
                     state.layer_shell = Some(registry.bind(name, 4, qhandle, ()));
                 }
+                "wp_color_manager_v1" if config::get().color_management => {
+                    state.color.manager = Some(registry.bind(name, 1, qhandle, ()));
+                }
+                "ext_idle_notifier_v1" => {
+                    state.idle.notifier = Some(registry.bind(name, 1, qhandle, ()));
+                }
+                "zwp_idle_inhibit_manager_v1" => {
+                    state.idle.inhibit_manager = Some(registry.bind(name, 1, qhandle, ()));
+                }
+                "zwlr_foreign_toplevel_manager_v1" if config::get().taskbar_entries > 0 => {
+                    state.taskbar.manager = Some(registry.bind(name, version.min(3), qhandle, ()));
+                }
+                "zwlr_data_control_manager_v1" => {
+                    state.calendar.data_control = Some(registry.bind(name, 1, qhandle, ()));
+                }
+                "wl_seat" => {
+                    state.seat = Some(registry.bind(name, version.min(5), qhandle, ()));
+                }
+                "wl_output" => {
+                    let output = registry.bind(name, version.min(4), qhandle, name);
+                    state.add_output(name, output, qhandle);
+                }
                 _ => {}
+            },
+            wl_registry::Event::GlobalRemove { name } => {
+                state.remove_bar(name);
             }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, u32> for AppState {
+    fn event(
+        state: &mut Self,
+        output: &WlOutput,
+        event: wl_output::Event,
+        id: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(output, event.opcode(), &event);
+        if let wl_output::Event::Name { name } = event
+            && let Some(bar) = state.bars.iter_mut().find(|b| b.id == *id)
+        {
+            bar.output_name = Some(name);
+            bar.force_full_redraw = true;
         }
     }
 }
 
-impl Dispatch<ZwlrLayerSurfaceV1, ()> for AppState {
+impl Dispatch<ZwlrLayerSurfaceV1, u32> for AppState {
     fn event(
         state: &mut Self,
         layer_surface: &ZwlrLayerSurfaceV1,
         event: <ZwlrLayerSurfaceV1 as wayland_client::Proxy>::Event,
-        _: &(),
+        id: &u32,
         _: &Connection,
         qhandle: &QueueHandle<Self>,
     ) {
-        if let zwlr_layer_surface_v1::Event::Configure {
-            serial,
-            width,
-            height,
-        } = event
-        {
-            layer_surface.ack_configure(serial);
+        state
+            .event_log
+            .record(layer_surface, event.opcode(), &event);
+        match event {
+            zwlr_layer_surface_v1::Event::Configure {
+                serial,
+                width,
+                height,
+            } => {
+                layer_surface.ack_configure(serial);
 
-            let w = if width == 0 { 1920 } else { width };
-            let h = if height == 0 {
-                BAR_HEIGHT as u32
-            } else {
-                height
-            };
+                let Some(bar) = state.bars.iter_mut().find(|b| b.id == *id) else {
+                    return;
+                };
 
-            if state.width != w || state.height != h {
-                if let Some(old_buffer) = state.buffer.take() {
-                    old_buffer.destroy();
-                }
+                let w = if width == 0 { 1920 } else { width };
+                let h = if height == 0 {
+                    BAR_HEIGHT as u32
+                } else {
+                    height
+                };
 
-                if !state.pixels.is_null() && state.pixels_len > 0 {
-                    let _ = unsafe { munmap(state.pixels.cast(), state.pixels_len) };
-                    state.pixels = ptr::null_mut();
-                    state.pixels_len = 0;
+                if bar.width != w || bar.height != h {
+                    let shm = state
+                        .shm
+                        .as_ref()
+                        .expect("wl_shm must exist after globals discovery");
+                    bar.resize(shm, w, h, qhandle);
                 }
 
-                state.width = w;
-                state.height = h;
-
-                let stride = w * 4;
-                let size = stride * h;
-
-                let memfd = memfd_create("leanbar-shm", MemfdFlags::CLOEXEC).unwrap();
-                ftruncate(&memfd, size as u64).unwrap();
-
-                let ptr = unsafe {
-                    mmap(
-                        ptr::null_mut(),
-                        size as usize,
-                        ProtFlags::READ | ProtFlags::WRITE,
-                        MapFlags::SHARED,
-                        &memfd,
-                        0,
-                    )
-                    .unwrap()
-                };
-
-                state.pixels = ptr.cast();
-                state.pixels_len = size as usize;
-
-                let pool = state
-                    .shm
-                    .as_ref()
-                    .expect("wl_shm must exist after globals discovery")
-                    .create_pool(memfd.as_fd(), size as i32, qhandle, ());
-                let buffer = pool.create_buffer(
-                    0,
-                    w as i32,
-                    h as i32,
-                    stride as i32,
-                    wl_shm::Format::Argb8888,
-                    qhandle,
-                    (),
-                );
-                state.buffer = Some(buffer);
+                bar.configured = true;
+                bar.force_full_redraw = true;
+                bar.redraw_and_commit(state.glyphs.as_ref(), Some(qhandle));
             }
+            zwlr_layer_surface_v1::Event::Closed => {
+                state.remove_bar(*id);
+            }
+            _ => {}
+        }
+    }
+}
 
-            state.configured = true;
-            state.force_full_redraw = true;
-            state.redraw_and_commit();
+impl Dispatch<WlCallback, u32> for AppState {
+    fn event(
+        state: &mut Self,
+        callback: &WlCallback,
+        event: wl_callback::Event,
+        id: &u32,
+        _: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(callback, event.opcode(), &event);
+        if let wl_callback::Event::Done { .. } = event
+            && let Some(bar) = state.bars.iter_mut().find(|b| b.id == *id)
+        {
+            bar.frame_pending = false;
+            bar.redraw_and_commit(state.glyphs.as_ref(), Some(qhandle));
         }
     }
 }
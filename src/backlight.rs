@@ -0,0 +1,249 @@
+//! Screen and keyboard brightness for the backlight modules. Levels are read from
+//! sysfs like every other sensor, changes go through logind's `SetBrightness`,
+//! which lets the user of the active session set them without udev rules or a
+//! setuid helper. A thread of its own makes those calls, scroll steps that
+//! arrive while it waits for logind are added up into one change.
+
+use std::fs;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use crate::{BACKLIGHT_PERCENT, KBD_BACKLIGHT_LEVEL, KBD_BACKLIGHT_LEVELS, dbus, ping_main_thread};
+
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+const LEDS_DIR: &str = "/sys/class/leds";
+/// Brightness change per scroll step, in percent of the maximum.
+const STEP_PERCENT: u32 = 5;
+/// Keyboards mostly have two or three levels, the few with a fine grained
+/// brightness are stepped through this many.
+const MAX_KEYBOARD_LEVELS: u32 = 10;
+
+/// Steps asked for and not yet set.
+struct Pending {
+    screen: i32,
+    /// Keyboard steps and whether they wrap, runs of the same kind summed up.
+    keyboard: Vec<(i32, bool)>,
+}
+
+static PENDING: Mutex<Pending> = Mutex::new(Pending {
+    screen: 0,
+    keyboard: Vec::new(),
+});
+static ADJUSTED: Condvar = Condvar::new();
+
+/// A brightness control in sysfs, by the subsystem and name logind knows it as.
+pub struct Device {
+    subsystem: &'static str,
+    name: String,
+    path: PathBuf,
+}
+
+impl Device {
+    /// Backlight of the built-in panel. Firmware interfaces drive it the way the
+    /// vendor intended, raw GPU registers come last.
+    pub fn find_backlight() -> Option<Self> {
+        let mut devices: Vec<(u8, PathBuf)> = fs::read_dir(BACKLIGHT_DIR)
+            .ok()?
+            .flatten()
+            .map(|e| e.path())
+            .filter_map(|path| {
+                let rank = match fs::read_to_string(path.join("type")).ok()?.trim() {
+                    "firmware" => 0,
+                    "platform" => 1,
+                    _ => 2,
+                };
+                Some((rank, path))
+            })
+            .collect();
+        devices.sort();
+        let (_, path) = devices.into_iter().next()?;
+        Self::at("backlight", path)
+    }
+
+    /// The LED lighting the keyboard, e.g. `tpacpi::kbd_backlight`.
+    pub fn find_keyboard() -> Option<Self> {
+        let mut leds: Vec<PathBuf> = fs::read_dir(LEDS_DIR)
+            .ok()?
+            .flatten()
+            .map(|e| e.path())
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().contains("kbd_backlight"))
+            })
+            .collect();
+        leds.sort();
+        Self::at("leds", leds.into_iter().next()?)
+    }
+
+    fn at(subsystem: &'static str, path: PathBuf) -> Option<Self> {
+        Some(Self {
+            subsystem,
+            name: path.file_name()?.to_string_lossy().into_owned(),
+            path,
+        })
+    }
+
+    /// Current and maximum raw brightness.
+    fn read(&self) -> Option<(u32, u32)> {
+        let read = |file: &str| -> Option<u32> {
+            fs::read_to_string(self.path.join(file))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        };
+        Some((read("brightness")?, read("max_brightness")?.max(1)))
+    }
+
+    pub fn percent(&self) -> Option<u8> {
+        let (brightness, max) = self.read()?;
+        Some(percent_of(brightness, max))
+    }
+
+    /// Current level and the number of levels above off.
+    pub fn levels(&self) -> Option<(u8, u8)> {
+        let (brightness, max) = self.read()?;
+        let levels = max.min(MAX_KEYBOARD_LEVELS);
+        Some((
+            ((brightness.min(max) * levels + max / 2) / max) as u8,
+            levels as u8,
+        ))
+    }
+
+    /// Through the worker's system bus connection, opened on first use and
+    /// again after a failure.
+    fn set(&self, connection: &mut Option<dbus::Connection>, brightness: u32) -> io::Result<()> {
+        let bus = match connection {
+            Some(bus) => bus,
+            None => connection.insert(dbus::Connection::system()?),
+        };
+        let result = bus.call(
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1/session/auto",
+            "org.freedesktop.login1.Session",
+            "SetBrightness",
+            &[
+                dbus::Value::Str(self.subsystem.to_string()),
+                dbus::Value::Str(self.name.clone()),
+                dbus::Value::Uint32(brightness),
+            ],
+        );
+        if result.is_err() {
+            *connection = None;
+        }
+        result.map(|_| ())
+    }
+}
+
+fn percent_of(brightness: u32, max: u32) -> u8 {
+    ((brightness.min(max) as u64 * 100 + max as u64 / 2) / max as u64) as u8
+}
+
+/// Sets the brightness as scrolls and clicks ask for, the poll thread would
+/// only notice the new level on its next tick.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+        println!("[Backlight Thread] Started");
+        let mut connection = None;
+        loop {
+            let Ok(mut pending) = PENDING.lock() else {
+                return;
+            };
+            while pending.screen == 0 && pending.keyboard.is_empty() {
+                pending = match ADJUSTED.wait(pending) {
+                    Ok(pending) => pending,
+                    Err(_) => return,
+                };
+            }
+            let screen = std::mem::take(&mut pending.screen);
+            let keyboard = std::mem::take(&mut pending.keyboard);
+            drop(pending);
+
+            let mut changed = screen != 0 && step_screen(&mut connection, screen);
+            for (steps, wrap) in keyboard {
+                changed |= step_keyboard(&mut connection, steps, wrap);
+            }
+            if changed {
+                ping_main_thread(&wake_fd);
+            }
+        }
+    });
+}
+
+/// Makes the screen `steps` times `STEP_PERCENT` brighter, or darker for negative
+/// steps, stopping one step short of black. Called from the main thread when the
+/// backlight module is scrolled.
+pub fn adjust(steps: i32) {
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.screen = pending.screen.saturating_add(steps);
+        ADJUSTED.notify_one();
+    }
+}
+
+/// Moves the keyboard backlight `steps` levels up, or down for negative steps.
+/// With `wrap` going past the brightest level turns it off. Called from the main
+/// thread when the keyboard backlight module is clicked or scrolled.
+pub fn adjust_keyboard(steps: i32, wrap: bool) {
+    if let Ok(mut pending) = PENDING.lock() {
+        match pending.keyboard.last_mut() {
+            Some((pending_steps, pending_wrap)) if *pending_wrap == wrap => {
+                *pending_steps = pending_steps.saturating_add(steps);
+            }
+            _ => pending.keyboard.push((steps, wrap)),
+        }
+        ADJUSTED.notify_one();
+    }
+}
+
+/// Returns whether the level changed.
+fn step_screen(connection: &mut Option<dbus::Connection>, steps: i32) -> bool {
+    let Some(device) = Device::find_backlight() else {
+        return false;
+    };
+    let Some((brightness, max)) = device.read() else {
+        return false;
+    };
+    let step = (max * STEP_PERCENT / 100).max(1);
+    let target = (brightness as i64 + steps as i64 * step as i64)
+        .clamp(step.min(max) as i64, max as i64) as u32;
+    if target == brightness {
+        return false;
+    }
+    if let Err(e) = device.set(connection, target) {
+        eprintln!("[Backlight] Setting the brightness failed: {}", e);
+        return false;
+    }
+    BACKLIGHT_PERCENT.store(percent_of(target, max), Ordering::Release);
+    true
+}
+
+/// Returns whether the level changed.
+fn step_keyboard(connection: &mut Option<dbus::Connection>, steps: i32, wrap: bool) -> bool {
+    let Some(device) = Device::find_keyboard() else {
+        return false;
+    };
+    let (Some((brightness, max)), Some((level, levels))) = (device.read(), device.levels()) else {
+        return false;
+    };
+    let (level, levels) = (level as i32, levels as i32);
+    let target = if wrap {
+        level.saturating_add(steps).rem_euclid(levels + 1)
+    } else {
+        level.saturating_add(steps).clamp(0, levels)
+    };
+    let raw = (target as u32 * max) / levels as u32;
+    if target == level || raw == brightness {
+        return false;
+    }
+    if let Err(e) = device.set(connection, raw) {
+        eprintln!("[Backlight] Setting the keyboard brightness failed: {}", e);
+        return false;
+    }
+    KBD_BACKLIGHT_LEVEL.store(target as u8, Ordering::Release);
+    KBD_BACKLIGHT_LEVELS.store(levels as u8, Ordering::Release);
+    true
+}
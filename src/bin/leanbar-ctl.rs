@@ -0,0 +1,46 @@
+//! Client for the leanbar control socket: `leanbar-ctl modules`, `leanbar-ctl surface`,
+//! `leanbar-ctl regions`, `leanbar-ctl regions overlay on`, `leanbar-ctl since start`,
+//! `leanbar-ctl timer start 10m`, `leanbar-ctl toggle mpris`,
+//! `leanbar-ctl set custom.text "on call"`, `leanbar-ctl reload`, `leanbar-ctl quit`.
+//! `leanbar ctl ...` does the same.
+
+use std::env;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let command = env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") else {
+        eprintln!("XDG_RUNTIME_DIR not set");
+        return ExitCode::FAILURE;
+    };
+    let path = PathBuf::from(runtime_dir)
+        .join("leanbar")
+        .join("control.sock");
+
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!(
+                "Failed to connect to {}: {}. Is leanbar running?",
+                path.display(),
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut reply = String::new();
+    let sent = stream
+        .write_all(format!("{}\n", command).as_bytes())
+        .and_then(|_| stream.shutdown(Shutdown::Write))
+        .and_then(|_| stream.read_to_string(&mut reply));
+    if let Err(e) = sent {
+        eprintln!("Control socket error: {}", e);
+        return ExitCode::FAILURE;
+    }
+    print!("{}", reply);
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,292 @@
+//! Calendar popup of the clock. Clicking the date or clock opens a month grid in
+//! its own layer surface above the bar, scrolling changes the month and clicking
+//! a day copies it as an ISO 8601 date to the clipboard through wlr-data-control,
+//! which works without the bar ever taking keyboard focus.
+
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::Ordering;
+
+use time::{Date, Month};
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, event_created_child,
+    protocol::{wl_buffer::WlBuffer, wl_surface::WlSurface},
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1,
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+
+use crate::{
+    DATE_DAY, DATE_MONTH, DATE_YEAR,
+    app_state::{self, AppState, CalendarGrid, CalendarMonth},
+    config,
+};
+
+const TEXT_MIME_TYPES: [&str; 3] = ["text/plain;charset=utf-8", "text/plain", "UTF8_STRING"];
+
+/// User data of the popup's layer surface, bars use their output's id.
+pub struct CalendarSurface;
+
+#[derive(Default)]
+pub struct CalendarState {
+    pub data_control: Option<ZwlrDataControlManagerV1>,
+    /// Created on the first copy.
+    device: Option<ZwlrDataControlDeviceV1>,
+    popup: Option<Popup>,
+}
+
+struct Popup {
+    surface: WlSurface,
+    layer_surface: ZwlrLayerSurfaceV1,
+    /// Allocated on the first configure, the popup never changes size.
+    buffer: Option<(WlBuffer, *mut u32, usize)>,
+    grid: CalendarGrid,
+    /// Months since January of year 0, stepping across years is plain arithmetic.
+    month: i32,
+}
+
+impl Popup {
+    fn destroy(self) {
+        if let Some((buffer, pixels, len)) = self.buffer {
+            app_state::destroy_buffer(buffer, pixels, len);
+        }
+        self.layer_surface.destroy();
+        self.surface.destroy();
+    }
+}
+
+impl AppState {
+    /// Opens the calendar at the current month above the given bar, or closes
+    /// it when it is already open.
+    pub(crate) fn toggle_calendar(&mut self, bar: u32) {
+        if self.close_calendar() {
+            return;
+        }
+        let (Some(compositor), Some(layer_shell), Some(glyphs), Some(qh)) =
+            (&self.compositor, &self.layer_shell, &self.glyphs, &self.qh)
+        else {
+            return;
+        };
+        let surface = compositor.create_surface(qh, ());
+        let layer_surface = layer_shell.get_layer_surface(
+            &surface,
+            self.bar_output(bar).as_ref(),
+            zwlr_layer_shell_v1::Layer::Top,
+            format!("{}-calendar", config::get().namespace),
+            qh,
+            CalendarSurface,
+        );
+        let grid = CalendarGrid::new(glyphs);
+        let (width, height) = grid.size();
+        let (anchor, [top, right, bottom, left]) = app_state::calendar_placement();
+        layer_surface.set_anchor(anchor);
+        layer_surface.set_size(width, height);
+        layer_surface.set_margin(top, right, bottom, left);
+        surface.commit();
+
+        self.calendar.popup = Some(Popup {
+            surface,
+            layer_surface,
+            buffer: None,
+            grid,
+            month: current_month(),
+        });
+    }
+
+    /// Returns true if the calendar was open.
+    pub(crate) fn close_calendar(&mut self) -> bool {
+        match self.calendar.popup.take() {
+            Some(popup) => {
+                popup.destroy();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_calendar_surface(&self, surface: &WlSurface) -> bool {
+        self.calendar
+            .popup
+            .as_ref()
+            .is_some_and(|p| p.surface == *surface)
+    }
+
+    /// Moves the calendar by some months, later ones for positive steps.
+    pub(crate) fn scroll_calendar(&mut self, months: i32) {
+        let Some(popup) = &mut self.calendar.popup else {
+            return;
+        };
+        popup.month += months;
+        self.draw_calendar();
+    }
+
+    /// Copies the clicked day and closes the calendar.
+    pub(crate) fn click_calendar(&mut self, x: f64, y: f64) {
+        let Some(popup) = &self.calendar.popup else {
+            return;
+        };
+        let Some(day) = popup.grid.day_at(x, y, &month_at(popup.month)) else {
+            return;
+        };
+        let date = format!(
+            "{:04}-{:02}-{:02}",
+            popup.month.div_euclid(12),
+            popup.month.rem_euclid(12) + 1,
+            day
+        );
+        self.copy_to_clipboard(date);
+        self.close_calendar();
+    }
+
+    fn copy_to_clipboard(&mut self, text: String) {
+        let (Some(manager), Some(seat), Some(qh)) =
+            (&self.calendar.data_control, &self.seat, &self.qh)
+        else {
+            eprintln!(
+                "[Calendar] Compositor lacks wlr-data-control, can't copy {}",
+                text
+            );
+            return;
+        };
+        let device = self
+            .calendar
+            .device
+            .get_or_insert_with(|| manager.get_data_device(seat, qh, ()));
+        let source = manager.create_data_source(qh, text);
+        for mime_type in TEXT_MIME_TYPES {
+            source.offer(mime_type.to_string());
+        }
+        device.set_selection(Some(&source));
+    }
+
+    fn draw_calendar(&mut self) {
+        let (Some(popup), Some(glyphs)) = (&self.calendar.popup, &self.glyphs) else {
+            return;
+        };
+        let Some((buffer, pixels, len)) = &popup.buffer else {
+            return;
+        };
+        let slice = unsafe { std::slice::from_raw_parts_mut(*pixels, len / 4) };
+        popup.grid.draw(slice, glyphs, &month_at(popup.month));
+        let (width, height) = popup.grid.size();
+        popup
+            .surface
+            .damage_buffer(0, 0, width as i32, height as i32);
+        popup.surface.attach(Some(buffer), 0, 0);
+        popup.surface.commit();
+    }
+}
+
+/// The month the clock is in, as months since January of year 0.
+fn current_month() -> i32 {
+    let year = 2000 + DATE_YEAR.load(Ordering::Acquire) as i32;
+    year * 12 + DATE_MONTH.load(Ordering::Acquire) as i32 - 1
+}
+
+fn month_at(index: i32) -> CalendarMonth {
+    let year = index.div_euclid(12);
+    let month = Month::try_from(index.rem_euclid(12) as u8 + 1).unwrap_or(Month::January);
+    let first_weekday = Date::from_calendar_date(year, month, 1)
+        .map_or(0, |date| date.weekday().number_days_from_monday());
+    CalendarMonth {
+        title: format!("{} {}", month, year),
+        first_weekday,
+        days: month.length(year),
+        today: (index == current_month()).then(|| DATE_DAY.load(Ordering::Acquire)),
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, CalendarSurface> for AppState {
+    fn event(
+        state: &mut Self,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        _: &CalendarSurface,
+        _: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        state
+            .event_log
+            .record(layer_surface, event.opcode(), &event);
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => {
+                layer_surface.ack_configure(serial);
+                let (Some(popup), Some(shm)) = (&mut state.calendar.popup, &state.shm) else {
+                    return;
+                };
+                if popup.buffer.is_none() {
+                    let (width, height) = popup.grid.size();
+                    popup.buffer = Some(app_state::create_buffer(shm, width, height, qhandle));
+                }
+                state.draw_calendar();
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                state.close_calendar();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, String> for AppState {
+    fn event(
+        state: &mut Self,
+        source: &ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        text: &String,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(source, event.opcode(), &event);
+        match event {
+            zwlr_data_control_source_v1::Event::Send { fd, .. } => {
+                // Every offered type is plain UTF-8 text
+                if let Err(e) = File::from(fd).write_all(text.as_bytes()) {
+                    eprintln!("[Calendar] Failed to send the copied date: {}", e);
+                }
+            }
+            // Something else was copied since
+            zwlr_data_control_source_v1::Event::Cancelled => source.destroy(),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        device: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(device, event.opcode(), &event);
+        match event {
+            // Other clients' selections are never read
+            zwlr_data_control_device_v1::Event::Selection { id: Some(offer) }
+            | zwlr_data_control_device_v1::Event::PrimarySelection { id: Some(offer) } => {
+                offer.destroy();
+            }
+            zwlr_data_control_device_v1::Event::Finished => {
+                device.destroy();
+                state.calendar.device = None;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(AppState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+wayland_client::delegate_noop!(AppState: ignore ZwlrDataControlManagerV1);
+wayland_client::delegate_noop!(AppState: ignore ZwlrDataControlOfferV1);
@@ -0,0 +1,197 @@
+//! Color management for wide gamut outputs. The theme colors are sRGB, when the
+//! compositor speaks the color-management protocol the bar surfaces are tagged
+//! as sRGB and the compositor maps them to the output. Otherwise an optional
+//! `color_matrix` from the config is applied to every color that gets drawn.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, WEnum, backend::ObjectId};
+use wayland_protocols::wp::color_management::v1::client::{
+    wp_color_management_surface_v1::WpColorManagementSurfaceV1,
+    wp_color_manager_v1::{
+        self, Feature, Primaries, RenderIntent, TransferFunction, WpColorManagerV1,
+    },
+    wp_image_description_creator_params_v1::WpImageDescriptionCreatorParamsV1,
+    wp_image_description_v1::{self, WpImageDescriptionV1},
+};
+
+use crate::{app_state::AppState, config};
+
+/// The compositor converts from sRGB itself, the correction matrix must not be
+/// applied on top.
+static MANAGED: AtomicBool = AtomicBool::new(false);
+
+/// Applies the configured correction matrix to a premultiplied ARGB color.
+/// The matrix works on the encoded values, which is coarse but keeps drawing
+/// free of any per-pixel math.
+pub fn correct(color: u32) -> u32 {
+    let Some(m) = &config::get().color_matrix else {
+        return color;
+    };
+    if MANAGED.load(Ordering::Relaxed) {
+        return color;
+    }
+    let alpha = color >> 24;
+    let [r, g, b] = [16, 8, 0].map(|shift| ((color >> shift) & 0xff) as f32);
+    let channel = |row: usize| {
+        let value = m[row * 3] * r + m[row * 3 + 1] * g + m[row * 3 + 2] * b;
+        // Premultiplied channels never exceed alpha
+        (value.round().max(0.0) as u32).min(alpha)
+    };
+    (alpha << 24) | (channel(0) << 16) | (channel(1) << 8) | channel(2)
+}
+
+/// WCAG AA contrast ratio for normal sized text, bar text is never large.
+const MIN_CONTRAST: f32 = 4.5;
+
+/// The color, or black or white, whichever reads better, when its WCAG contrast
+/// ratio against the background is too low. Both are premultiplied ARGB, the
+/// background is taken as opaque.
+pub fn readable_on(color: u32, background: u32) -> u32 {
+    let background = luminance(background);
+    if contrast(luminance(color), background) >= MIN_CONTRAST {
+        return color;
+    }
+    let alpha = color >> 24;
+    if contrast(1.0, background) >= contrast(0.0, background) {
+        (alpha << 24) | (alpha << 16) | (alpha << 8) | alpha
+    } else {
+        alpha << 24
+    }
+}
+
+/// WCAG relative luminance of a premultiplied ARGB color.
+fn luminance(color: u32) -> f32 {
+    let alpha = (color >> 24).max(1) as f32;
+    let [r, g, b] = [16, 8, 0].map(|shift| {
+        let c = (((color >> shift) & 0xff) as f32 / alpha).min(1.0);
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+fn contrast(a: f32, b: f32) -> f32 {
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+#[derive(Default)]
+pub struct ColorState {
+    pub manager: Option<WpColorManagerV1>,
+    parametric: bool,
+    srgb_primaries: bool,
+    perceptual: bool,
+    transfer: Option<TransferFunction>,
+    description: Option<WpImageDescriptionV1>,
+    ready: bool,
+    /// Surfaces already tagged, each may only get one color management object.
+    tagged: Vec<ObjectId>,
+}
+
+impl AppState {
+    /// Tags every bar surface that isn't yet with the sRGB description, once
+    /// the compositor has it ready.
+    pub(crate) fn tag_surfaces(&mut self) {
+        let (Some(manager), Some(description), Some(qh)) = (
+            &self.color.manager,
+            &self.color.description,
+            self.qh.clone(),
+        ) else {
+            return;
+        };
+        if !self.color.ready {
+            return;
+        }
+        let mut tagged_any = false;
+        for surface in self.bar_surfaces() {
+            if self.color.tagged.contains(&surface.id()) {
+                continue;
+            }
+            let color_surface: WpColorManagementSurfaceV1 = manager.get_surface(&surface, &qh, ());
+            color_surface.set_image_description(description, RenderIntent::Perceptual);
+            self.color.tagged.push(surface.id());
+            tagged_any = true;
+        }
+        if tagged_any {
+            self.force_full_redraw();
+            self.redraw_and_commit();
+        }
+    }
+}
+
+impl Dispatch<WpColorManagerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        manager: &WpColorManagerV1,
+        event: wp_color_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(manager, event.opcode(), &event);
+        let color = &mut state.color;
+        match event {
+            wp_color_manager_v1::Event::SupportedIntent {
+                render_intent: WEnum::Value(RenderIntent::Perceptual),
+            } => color.perceptual = true,
+            wp_color_manager_v1::Event::SupportedFeature {
+                feature: WEnum::Value(Feature::Parametric),
+            } => color.parametric = true,
+            wp_color_manager_v1::Event::SupportedPrimariesNamed {
+                primaries: WEnum::Value(Primaries::Srgb),
+            } => color.srgb_primaries = true,
+            // Gamma 2.2 is what sRGB displays actually do, the piecewise sRGB
+            // curve is only the fallback
+            wp_color_manager_v1::Event::SupportedTfNamed {
+                tf: WEnum::Value(tf @ (TransferFunction::Gamma22 | TransferFunction::Srgb)),
+            } if color.transfer != Some(TransferFunction::Gamma22) => color.transfer = Some(tf),
+            wp_color_manager_v1::Event::Done => {
+                let Some(transfer) = color.transfer else {
+                    println!("[Color] no sRGB transfer function, leaving colors unmanaged");
+                    return;
+                };
+                if !(color.parametric && color.srgb_primaries && color.perceptual) {
+                    println!("[Color] no parametric sRGB support, leaving colors unmanaged");
+                    return;
+                }
+                let creator = manager.create_parametric_creator(qhandle, ());
+                creator.set_primaries_named(Primaries::Srgb);
+                creator.set_tf_named(transfer);
+                color.description = Some(creator.create(qhandle, ()));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WpImageDescriptionV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        description: &WpImageDescriptionV1,
+        event: wp_image_description_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(description, event.opcode(), &event);
+        match event {
+            wp_image_description_v1::Event::Ready { .. } => {
+                println!("[Color] surfaces tagged as sRGB");
+                MANAGED.store(true, Ordering::Relaxed);
+                state.color.ready = true;
+                state.tag_surfaces();
+            }
+            wp_image_description_v1::Event::Failed { msg, .. } => {
+                eprintln!("[Color] sRGB image description failed: {}", msg);
+                state.color.description = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+wayland_client::delegate_noop!(AppState: ignore WpImageDescriptionCreatorParamsV1);
+wayland_client::delegate_noop!(AppState: ignore WpColorManagementSurfaceV1);
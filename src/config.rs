@@ -0,0 +1,1127 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::{COLOR_CAPSULE_BG, color};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Nerd Font icons of common applications by window class. Fonts without them
+/// leave the title as it is, see `GlyphCache::from_font`.
+const DEFAULT_WINDOW_ICONS: [(&str, &str); 20] = [
+    ("firefox", "\u{f269}"),
+    ("librewolf", "\u{f269}"),
+    ("chromium", "\u{f268}"),
+    ("google-chrome", "\u{f268}"),
+    ("kitty", "\u{f011b}"),
+    ("Alacritty", "\u{f489}"),
+    ("foot", "\u{f489}"),
+    ("org.wezfurlong.wezterm", "\u{f489}"),
+    ("com.mitchellh.ghostty", "\u{f489}"),
+    ("code", "\u{f0a1e}"),
+    ("code-oss", "\u{f0a1e}"),
+    ("discord", "\u{f066f}"),
+    ("vesktop", "\u{f066f}"),
+    ("spotify", "\u{f1bc}"),
+    ("thunderbird", "\u{f0e0}"),
+    ("org.gnome.Nautilus", "\u{f07b}"),
+    ("thunar", "\u{f07b}"),
+    ("mpv", "\u{f144}"),
+    ("steam", "\u{f1b6}"),
+    ("obsidian", "\u{f0219}"),
+];
+
+/// Keyboard modifier that can be required for pointer gestures.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Modifier {
+    None,
+    Super,
+    Alt,
+    Ctrl,
+    Shift,
+}
+
+impl Modifier {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Some(Modifier::None),
+            "super" | "mod4" | "logo" => Some(Modifier::Super),
+            "alt" | "mod1" => Some(Modifier::Alt),
+            "ctrl" | "control" => Some(Modifier::Ctrl),
+            "shift" => Some(Modifier::Shift),
+            _ => None,
+        }
+    }
+}
+
+/// Extra rendering under text so it stays readable on a transparent bar.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextEffect {
+    None,
+    Outline,
+    Shadow,
+}
+
+impl TextEffect {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Some(TextEffect::None),
+            "outline" => Some(TextEffect::Outline),
+            "shadow" => Some(TextEffect::Shadow),
+            _ => None,
+        }
+    }
+}
+
+/// Where audio volumes come from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AudioBackend {
+    /// `wpctl` if it works, otherwise the PulseAudio protocol.
+    Auto,
+    /// PipeWire through its `wpctl` tool, a few processes per change rather
+    /// than a connection of leanbar's own.
+    Wpctl,
+    Pulse,
+}
+
+impl AudioBackend {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => Some(AudioBackend::Auto),
+            "wpctl" | "pipewire" => Some(AudioBackend::Wpctl),
+            "pulse" | "pulseaudio" => Some(AudioBackend::Pulse),
+            _ => None,
+        }
+    }
+}
+
+/// Where the battery module's readings come from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BatteryBackend {
+    /// UPower while it runs, otherwise sysfs.
+    Auto,
+    UPower,
+    Sysfs,
+}
+
+impl BatteryBackend {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => Some(BatteryBackend::Auto),
+            "upower" => Some(BatteryBackend::UPower),
+            "sysfs" => Some(BatteryBackend::Sysfs),
+            _ => None,
+        }
+    }
+}
+
+/// Screen corner for the floating capsule.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    fn parse(value: &str) -> Option<Option<Self>> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" | "off" => Some(None),
+            "top-left" => Some(Some(Corner::TopLeft)),
+            "top-right" => Some(Some(Corner::TopRight)),
+            "bottom-left" => Some(Some(Corner::BottomLeft)),
+            "bottom-right" => Some(Some(Corner::BottomRight)),
+            _ => None,
+        }
+    }
+}
+
+/// A labelled range of numbered workspaces. Groups are set as
+/// `workspace_groups = work: 1-5 | personal: 6-10`, the label is optional.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WorkspaceGroup {
+    pub label: String,
+    pub first: i32,
+    pub last: i32,
+}
+
+impl WorkspaceGroup {
+    fn parse_list(value: &str) -> Option<Vec<Self>> {
+        if value.is_empty() {
+            return Some(Vec::new());
+        }
+        value.split('|').map(Self::parse).collect()
+    }
+
+    fn parse(entry: &str) -> Option<Self> {
+        let (label, range) = entry.rsplit_once(':').unwrap_or(("", entry));
+        let range = range.trim();
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let (first, last) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+        (0 < first && first <= last).then(|| Self {
+            label: label.trim().to_string(),
+            first,
+            last,
+        })
+    }
+
+    pub fn contains(&self, id: i32) -> bool {
+        (self.first..=self.last).contains(&id)
+    }
+}
+
+/// A mailbox the mail module watches over IMAP, set as
+/// `imap_accounts = work: alice@example.com@imap.example.com | home: bob@mail.example.org:143`.
+/// The port defaults to 993, it is always spoken to over TLS.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ImapAccount {
+    pub label: String,
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl ImapAccount {
+    fn parse_list(value: &str) -> Option<Vec<Self>> {
+        if value.is_empty() {
+            return Some(Vec::new());
+        }
+        value.split('|').map(Self::parse).collect()
+    }
+
+    fn parse(entry: &str) -> Option<Self> {
+        let (label, address) = entry.split_once(':')?;
+        // User names are often addresses themselves, the host follows the last @
+        let (user, server) = address.trim().rsplit_once('@')?;
+        let (host, port) = match server.split_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (server, 993),
+        };
+        let label = label.trim();
+        (!label.is_empty() && !user.is_empty() && !host.is_empty()).then(|| Self {
+            label: label.to_string(),
+            user: user.to_string(),
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+/// Modules with a fixed width that can be overridden with `<module>_width = PIXELS`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Module {
+    Date,
+    Clock,
+    Battery,
+    Power,
+    PowerProfile,
+    Temperature,
+    Gpu,
+    Disk,
+    Network,
+    Wifi,
+    Ethernet,
+    Connectivity,
+    Bluetooth,
+    Idle,
+    Caffeine,
+    Dnd,
+    NightLight,
+    Rfkill,
+    Removable,
+    FailedUnits,
+    NextEvent,
+    Todo,
+    Mail,
+    Updates,
+    Containers,
+    Custom,
+    Status,
+    Fifo,
+    Tray,
+    Taskbar,
+    Since,
+    Timer,
+    Pressure,
+    Backlight,
+    KeyboardBacklight,
+    Volume,
+    Microphone,
+    Privacy,
+    AudioDevice,
+    KeyboardLayout,
+    Tiling,
+    Mpris,
+}
+
+impl Module {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "date" => Some(Module::Date),
+            "clock" => Some(Module::Clock),
+            "battery" => Some(Module::Battery),
+            "power" => Some(Module::Power),
+            "power_profile" => Some(Module::PowerProfile),
+            "temperature" => Some(Module::Temperature),
+            "gpu" => Some(Module::Gpu),
+            "disk" => Some(Module::Disk),
+            "network" => Some(Module::Network),
+            "wifi" => Some(Module::Wifi),
+            "ethernet" => Some(Module::Ethernet),
+            "connectivity" => Some(Module::Connectivity),
+            "bluetooth" => Some(Module::Bluetooth),
+            "idle" => Some(Module::Idle),
+            "caffeine" => Some(Module::Caffeine),
+            "dnd" => Some(Module::Dnd),
+            "night_light" => Some(Module::NightLight),
+            "rfkill" => Some(Module::Rfkill),
+            "removable" => Some(Module::Removable),
+            "failed_units" => Some(Module::FailedUnits),
+            "next_event" => Some(Module::NextEvent),
+            "todo" => Some(Module::Todo),
+            "mail" => Some(Module::Mail),
+            "updates" => Some(Module::Updates),
+            "containers" => Some(Module::Containers),
+            "custom" => Some(Module::Custom),
+            "status" => Some(Module::Status),
+            "fifo" => Some(Module::Fifo),
+            "tray" => Some(Module::Tray),
+            "taskbar" => Some(Module::Taskbar),
+            "since" => Some(Module::Since),
+            "timer" => Some(Module::Timer),
+            "pressure" => Some(Module::Pressure),
+            "backlight" => Some(Module::Backlight),
+            "keyboard_backlight" => Some(Module::KeyboardBacklight),
+            "volume" => Some(Module::Volume),
+            "microphone" => Some(Module::Microphone),
+            "privacy" => Some(Module::Privacy),
+            "audio_device" => Some(Module::AudioDevice),
+            "keyboard_layout" => Some(Module::KeyboardLayout),
+            "tiling" => Some(Module::Tiling),
+            "mpris" => Some(Module::Mpris),
+            _ => None,
+        }
+    }
+}
+
+/// User settings read from `$XDG_CONFIG_HOME/leanbar/config`.
+///
+/// The file is a list of `key = value` lines, a `#` at the start of a line or
+/// after whitespace starts a comment. Missing keys
+/// keep their defaults, unknown keys and bad values are reported and ignored.
+pub struct Config {
+    /// Modifier that must be held while hovering a workspace to peek at it.
+    pub peek_modifier: Modifier,
+    /// Keyboard layouts are remembered per window, mark windows not using the default.
+    pub per_window_layout: bool,
+    /// Underline the most recently updated module, fading out over a second.
+    pub highlight_changes: bool,
+    pub text_effect: TextEffect,
+    /// hwmon sensor for the temperature module, "name" or "name:label" such as
+    /// "k10temp:Tctl". Empty picks the first CPU sensor found.
+    pub temperature_sensor: String,
+    /// Shown after the temperature while the CPU is thermally throttled.
+    pub temperature_throttled: String,
+    /// Show used VRAM next to the GPU utilization.
+    pub gpu_vram: bool,
+    /// Block device for the disk I/O module such as "nvme0n1". Empty picks the
+    /// first disk in /proc/diskstats.
+    pub disk_device: String,
+    /// Interface for the network module such as "wlan0". Empty follows the
+    /// interface of the default route.
+    pub network_interface: String,
+    /// Wireless interface for the wifi module, empty picks the connected one.
+    pub wifi_interface: String,
+    /// Wired interface for the ethernet module, empty picks the first physical one.
+    pub ethernet_interface: String,
+    /// Backend for the volume module.
+    pub audio_backend: AudioBackend,
+    /// Labels for audio sinks whose description contains the pattern, written as
+    /// `audio_device_names = Buds: WH-1000XM4 | Desk: USB Audio`.
+    pub audio_device_names: Vec<(String, String)>,
+    /// Icons drawn before the window title for windows of a class (app ID),
+    /// written `window_icons = firefox: ff | mpv: >` with any text, usually a
+    /// Nerd Font glyph. Matched ignoring case, configured ones take precedence
+    /// over `DEFAULT_WINDOW_ICONS`. Empty turns icons off.
+    pub window_icons: Vec<(String, String)>,
+    /// Longest now playing text shown, longer titles scroll while playing.
+    pub mpris_max_chars: usize,
+    /// Shown before the title while the player plays or is paused.
+    pub mpris_playing: String,
+    pub mpris_paused: String,
+    /// Battery percentage below which rendering gets cheaper while discharging,
+    /// 0 keeps full quality.
+    pub reduce_quality_below: u8,
+    /// Seconds of inactivity before the idle daemon locks or suspends, the idle
+    /// module counts down to it. 0 hides the module.
+    pub idle_timeout: u32,
+    /// While the workspace under the bar shows a fullscreen window, release the
+    /// reserved space and draw over the window instead.
+    pub fullscreen_overlay: bool,
+    /// Show the time zone abbreviation after the clock.
+    pub clock_timezone: bool,
+    /// Offset from UTC in seconds (written "+05:30") the clock falls back to
+    /// when the local offset can't be determined.
+    pub clock_fallback_offset: i32,
+    /// Unix time the "time since" module counts up from, 0 hides it until it is
+    /// started over the control socket.
+    pub since: u64,
+    /// Shown before the elapsed time, e.g. "deploy".
+    pub since_label: String,
+    /// Calendars the next event module shows, .ics files or http(s) and webcal
+    /// URLs separated by `|`. Times given in a named zone (`TZID=`) are read
+    /// as local time, events from calendars in another zone show at the wrong
+    /// hour.
+    pub next_event_sources: Vec<String>,
+    /// Minutes between downloads of remote calendars.
+    pub next_event_refresh: u32,
+    /// Minutes before its start an event is highlighted.
+    pub next_event_soon: u32,
+    /// todo.txt the todo module counts open items of, the file name may contain
+    /// `*` and `?` to count several files of one directory.
+    pub todo_file: String,
+    /// (label, path) of the Maildirs the mail module counts new messages of.
+    /// Set as `maildirs = work:~/mail/work|~/mail/home`, without a label the
+    /// directory name is used.
+    pub maildirs: Vec<(String, String)>,
+    /// Mailboxes the mail module counts unread mail of over IMAP.
+    pub imap_accounts: Vec<ImapAccount>,
+    /// Prints an IMAP account's password, run through `sh -c` with the
+    /// account's label in `LEANBAR_IMAP_ACCOUNT`, e.g. `pass mail/$LEANBAR_IMAP_ACCOUNT`.
+    pub imap_password_command: String,
+    /// Prints one line per pending package update, e.g. `checkupdates`,
+    /// `apt list --upgradable` or `dnf check-update`. Empty hides the module.
+    pub updates_command: String,
+    /// Minutes between runs of `updates_command`.
+    pub updates_interval: u32,
+    /// Docker or Podman API socket, empty looks for the usual ones.
+    pub containers_socket: String,
+    /// Only containers with this label count, `key` or `key=value`.
+    pub containers_label: String,
+    /// Run through `sh -c` for the custom module, which shows the last line it
+    /// printed. Empty hides the module.
+    pub custom_command: String,
+    /// Seconds between runs of `custom_command`, 0 starts it once and shows
+    /// every line it prints as it comes.
+    pub custom_interval: u32,
+    /// Read `custom_command`'s lines as Waybar's JSON, `text`, `tooltip`,
+    /// `class` and `percentage`.
+    pub custom_json: bool,
+    /// (class, color) pairs, the custom module takes the color of the first
+    /// class its JSON names. Set as `custom_colors = warning:#f9e2af, critical:#f38ba8`.
+    pub custom_colors: Vec<(String, u32)>,
+    /// Prints the i3bar protocol, e.g. `i3status` or `i3blocks`, for the status
+    /// module. Empty hides the module.
+    pub status_command: String,
+    /// Bars at least this wide draw workspaces as boxes sized by their window
+    /// count instead of numbers, 0 never does.
+    pub workspace_minimap_min_width: usize,
+    /// Unfocused workspaces brighten with their window count, from dim at one
+    /// window to full color at this many. 0 draws them all alike.
+    pub workspace_ramp_windows: u16,
+    /// Backend for the battery module.
+    pub battery_backend: BatteryBackend,
+    /// Battery indicators, any text including nerd font glyphs (the font has to
+    /// contain them).
+    pub battery_charging: String,
+    pub battery_discharging: String,
+    pub battery_full: String,
+    /// Shown instead of the estimate while plugged in but not charging, e.g.
+    /// held at a charge threshold.
+    pub battery_plugged: String,
+    /// (percent, color) pairs sorted by percent, the battery takes the color of
+    /// the first band its charge is at or below. Set as
+    /// `battery_colors = 15:#f38ba8, 30:#f9e2af`.
+    pub battery_colors: Vec<(u8, u32)>,
+    /// Draw a battery icon filled to the charge before the battery text.
+    pub battery_icon: bool,
+    /// Warning levels in percent, highest first. Without `battery_colors` the
+    /// battery turns the warning color at the first and critical at the last.
+    pub battery_warnings: Vec<u8>,
+    /// Blink the battery below the last warning level while discharging.
+    pub battery_blink: bool,
+    /// Run through `sh -c` whenever a warning level is crossed downwards while
+    /// discharging, with the percentage in `LEANBAR_BATTERY`.
+    pub battery_warning_command: String,
+    /// Minutes after midnight the display turns warm and neutral again, written
+    /// `night_light_hours = 20:00-07:00`. None takes the schedule of the running
+    /// wlsunset or gammastep.
+    pub night_light_hours: Option<(u16, u16)>,
+    /// Run through `sh -c` when the night light module is clicked, e.g.
+    /// `pkill -USR1 wlsunset`. Empty makes the module not clickable.
+    pub night_light_command: String,
+    /// Tray icons room is reserved for, more are cut off on the left.
+    pub tray_icons: usize,
+    /// Windows room is reserved for in the taskbar module, more are cut off on
+    /// the left. 0 leaves the taskbar out.
+    pub taskbar_entries: usize,
+    /// Separators drawn in the workspace strip where a group begins.
+    pub workspace_groups: Vec<WorkspaceGroup>,
+    /// Show only a small clock and battery capsule floating in this corner
+    /// instead of the full width bar.
+    pub capsule: Option<Corner>,
+    /// Distance between the capsule and the screen edges.
+    pub capsule_margin: i32,
+    /// Layer surface namespace, what Hyprland `layerrule`s match on.
+    pub namespace: String,
+    /// Margins of the full width bar as top, right, bottom, left, written CSS
+    /// style as one, two or four values.
+    pub margins: [i32; 4],
+    /// Tag the bar as sRGB for compositors that do color management.
+    pub color_management: bool,
+    /// Row major 3x3 matrix applied to every drawn color when the compositor
+    /// doesn't manage colors, e.g. to tame oversaturated wide gamut panels.
+    pub color_matrix: Option<[f32; 9]>,
+    /// Widths replacing the reservation measured from a module's widest content.
+    pub module_widths: HashMap<Module, usize>,
+    /// `<module>_signal = N` refreshes the module on SIGRTMIN+N, for the
+    /// modules that run a command or poll: updates, custom and failed_units.
+    pub module_signals: HashMap<Module, u8>,
+    /// Add the arguments of the last Wayland events to the crash report written on
+    /// a protocol error, which always names their objects and opcodes. Off by
+    /// default, every event is formatted while it is on.
+    pub event_log: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            peek_modifier: Modifier::Super,
+            per_window_layout: false,
+            highlight_changes: false,
+            text_effect: TextEffect::None,
+            temperature_sensor: String::new(),
+            temperature_throttled: "!".to_string(),
+            gpu_vram: false,
+            disk_device: String::new(),
+            network_interface: String::new(),
+            wifi_interface: String::new(),
+            ethernet_interface: String::new(),
+            audio_backend: AudioBackend::Auto,
+            audio_device_names: Vec::new(),
+            window_icons: DEFAULT_WINDOW_ICONS
+                .iter()
+                .map(|(class, icon)| (class.to_string(), icon.to_string()))
+                .collect(),
+            mpris_max_chars: 32,
+            mpris_playing: ">".to_string(),
+            mpris_paused: "||".to_string(),
+            reduce_quality_below: 0,
+            idle_timeout: 0,
+            fullscreen_overlay: false,
+            clock_timezone: false,
+            clock_fallback_offset: 0,
+            since: 0,
+            since_label: String::new(),
+            next_event_sources: Vec::new(),
+            next_event_refresh: 15,
+            next_event_soon: 10,
+            todo_file: "~/todo.txt".to_string(),
+            maildirs: Vec::new(),
+            imap_accounts: Vec::new(),
+            imap_password_command: String::new(),
+            updates_command: String::new(),
+            updates_interval: 60,
+            containers_socket: String::new(),
+            containers_label: String::new(),
+            custom_command: String::new(),
+            custom_interval: 0,
+            custom_json: false,
+            custom_colors: Vec::new(),
+            status_command: String::new(),
+            workspace_minimap_min_width: 0,
+            workspace_ramp_windows: 0,
+            battery_backend: BatteryBackend::Auto,
+            battery_charging: "+".to_string(),
+            battery_discharging: "-".to_string(),
+            battery_full: "Full".to_string(),
+            battery_plugged: "=".to_string(),
+            battery_colors: Vec::new(),
+            battery_icon: false,
+            battery_warnings: vec![20, 10, 5],
+            battery_blink: false,
+            battery_warning_command: String::new(),
+            night_light_hours: None,
+            night_light_command: String::new(),
+            tray_icons: 6,
+            taskbar_entries: 0,
+            workspace_groups: Vec::new(),
+            capsule: None,
+            capsule_margin: 12,
+            namespace: "leanbar".to_string(),
+            margins: [0; 4],
+            color_management: false,
+            color_matrix: None,
+            module_widths: HashMap::new(),
+            module_signals: HashMap::new(),
+            event_log: false,
+        }
+    }
+}
+
+impl Config {
+    fn load() -> Self {
+        let Some(path) = config_path() else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                println!("[Config] loaded {}", path.display());
+                Self::parse(&text)
+            }
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut config = Self::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                eprintln!("[Config] line {}: expected `key = value`", line_no + 1);
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if !config.set(key, value) {
+                eprintln!(
+                    "[Config] line {}: invalid setting `{} = {}`",
+                    line_no + 1,
+                    key,
+                    value
+                );
+            }
+        }
+        config.resolve_colors();
+        config
+    }
+
+    /// Swaps configured text colors that would be unreadable on the capsule's
+    /// backdrop for black or white. The full width bar is transparent, what is
+    /// behind it is unknown.
+    fn resolve_colors(&mut self) {
+        if self.capsule.is_none() {
+            return;
+        }
+        for (percent, color) in &mut self.battery_colors {
+            make_readable(color, &format!("battery color for {}%", percent));
+        }
+        for (class, color) in &mut self.custom_colors {
+            make_readable(color, &format!("custom color for {}", class));
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "peek_modifier" => Modifier::parse(value).map(|m| self.peek_modifier = m),
+            "per_window_layout" => parse_bool(value).map(|b| self.per_window_layout = b),
+            "highlight_changes" => parse_bool(value).map(|b| self.highlight_changes = b),
+            "text_effect" => TextEffect::parse(value).map(|e| self.text_effect = e),
+            "event_log" => parse_bool(value).map(|b| self.event_log = b),
+            "gpu_vram" => parse_bool(value).map(|b| self.gpu_vram = b),
+            "fullscreen_overlay" => parse_bool(value).map(|b| self.fullscreen_overlay = b),
+            "clock_timezone" => parse_bool(value).map(|b| self.clock_timezone = b),
+            "clock_fallback_offset" => {
+                parse_utc_offset(value).map(|o| self.clock_fallback_offset = o)
+            }
+            "since" => parse_timestamp(value).map(|t| self.since = t),
+            "since_label" => {
+                self.since_label = value.to_string();
+                Some(())
+            }
+            "next_event_sources" => {
+                self.next_event_sources = value
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|source| !source.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                Some(())
+            }
+            "next_event_refresh" => value
+                .parse()
+                .ok()
+                .filter(|m: &u32| *m > 0)
+                .map(|m| self.next_event_refresh = m),
+            "next_event_soon" => value.parse().ok().map(|m| self.next_event_soon = m),
+            "todo_file" => Some(value)
+                .filter(|f| !f.is_empty())
+                .map(|f| self.todo_file = f.to_string()),
+            "imap_accounts" => ImapAccount::parse_list(value).map(|a| self.imap_accounts = a),
+            "updates_command" => {
+                self.updates_command = value.to_string();
+                Some(())
+            }
+            "updates_interval" => value
+                .parse()
+                .ok()
+                .filter(|m: &u32| *m > 0)
+                .map(|m| self.updates_interval = m),
+            "containers_socket" => {
+                self.containers_socket = value.to_string();
+                Some(())
+            }
+            "containers_label" => {
+                self.containers_label = value.to_string();
+                Some(())
+            }
+            "custom_command" => {
+                self.custom_command = value.to_string();
+                Some(())
+            }
+            "custom_interval" => value.parse().ok().map(|s| self.custom_interval = s),
+            "custom_json" => parse_bool(value).map(|b| self.custom_json = b),
+            "status_command" => {
+                self.status_command = value.to_string();
+                Some(())
+            }
+            "custom_colors" => value
+                .split(',')
+                .filter(|entry| !entry.trim().is_empty())
+                .map(|entry| {
+                    let (class, color) = entry.split_once(':')?;
+                    Some((class.trim().to_string(), parse_color(color.trim())?))
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|c| self.custom_colors = c),
+            "imap_password_command" => {
+                self.imap_password_command = value.to_string();
+                Some(())
+            }
+            "maildirs" => {
+                self.maildirs = value
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|entry| !entry.is_empty())
+                    .map(|entry| match entry.split_once(':') {
+                        Some((label, path)) if !label.contains('/') => {
+                            (label.trim().to_string(), path.trim().to_string())
+                        }
+                        _ => {
+                            let label = entry.trim_end_matches('/').rsplit('/').next();
+                            (label.unwrap_or(entry).to_string(), entry.to_string())
+                        }
+                    })
+                    .collect();
+                Some(())
+            }
+            "audio_backend" => AudioBackend::parse(value).map(|b| self.audio_backend = b),
+            "audio_device_names" => value
+                .split('|')
+                .map(|entry| {
+                    let (label, pattern) = entry.split_once(':')?;
+                    let (label, pattern) = (label.trim(), pattern.trim());
+                    (!label.is_empty() && !pattern.is_empty())
+                        .then(|| (label.to_string(), pattern.to_string()))
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|names| self.audio_device_names = names),
+            "window_icons" if value.is_empty() => {
+                self.window_icons.clear();
+                Some(())
+            }
+            "window_icons" => value
+                .split('|')
+                .map(|entry| {
+                    let (class, icon) = entry.split_once(':')?;
+                    let (class, icon) = (class.trim(), icon.trim());
+                    (!class.is_empty() && !icon.is_empty())
+                        .then(|| (class.to_string(), icon.to_string()))
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|icons| {
+                    self.window_icons.splice(0..0, icons);
+                }),
+            "mpris_max_chars" => value.parse().ok().map(|n| self.mpris_max_chars = n),
+            "mpris_playing" => {
+                self.mpris_playing = value.to_string();
+                Some(())
+            }
+            "mpris_paused" => {
+                self.mpris_paused = value.to_string();
+                Some(())
+            }
+            "reduce_quality_below" => value
+                .parse()
+                .ok()
+                .filter(|p: &u8| *p <= 100)
+                .map(|p| self.reduce_quality_below = p),
+            "idle_timeout" => value.parse().ok().map(|t| self.idle_timeout = t),
+            "workspace_minimap_min_width" => value
+                .parse()
+                .ok()
+                .map(|w| self.workspace_minimap_min_width = w),
+            "workspace_ramp_windows" => value.parse().ok().map(|w| self.workspace_ramp_windows = w),
+            "battery_backend" => BatteryBackend::parse(value).map(|b| self.battery_backend = b),
+            "battery_charging" => {
+                self.battery_charging = value.to_string();
+                Some(())
+            }
+            "battery_discharging" => {
+                self.battery_discharging = value.to_string();
+                Some(())
+            }
+            "battery_full" => {
+                self.battery_full = value.to_string();
+                Some(())
+            }
+            "battery_plugged" => {
+                self.battery_plugged = value.to_string();
+                Some(())
+            }
+            "battery_colors" => parse_bands(value).map(|b| self.battery_colors = b),
+            "battery_icon" => parse_bool(value).map(|b| self.battery_icon = b),
+            "battery_warnings" => parse_levels(value).map(|l| self.battery_warnings = l),
+            "battery_blink" => parse_bool(value).map(|b| self.battery_blink = b),
+            "battery_warning_command" => {
+                self.battery_warning_command = value.to_string();
+                Some(())
+            }
+            "night_light_hours" => value.split_once('-').and_then(|(from, until)| {
+                let hours = (
+                    parse_time_of_day(from.trim())?,
+                    parse_time_of_day(until.trim())?,
+                );
+                self.night_light_hours = Some(hours);
+                Some(())
+            }),
+            "night_light_command" => {
+                self.night_light_command = value.to_string();
+                Some(())
+            }
+            "tray_icons" => value.parse().ok().map(|n| self.tray_icons = n),
+            "taskbar_entries" => value.parse().ok().map(|n| self.taskbar_entries = n),
+            "workspace_groups" => {
+                WorkspaceGroup::parse_list(value).map(|g| self.workspace_groups = g)
+            }
+            "capsule" => Corner::parse(value).map(|c| self.capsule = c),
+            "capsule_margin" => value
+                .parse()
+                .ok()
+                .filter(|m: &i32| *m >= 0)
+                .map(|m| self.capsule_margin = m),
+            "namespace" => Some(value)
+                .filter(|n| !n.is_empty())
+                .map(|n| self.namespace = n.to_string()),
+            "margins" => parse_margins(value).map(|m| self.margins = m),
+            "color_management" => parse_bool(value).map(|b| self.color_management = b),
+            "color_matrix" => value
+                .split_whitespace()
+                .map(|v| v.parse().ok())
+                .collect::<Option<Vec<f32>>>()
+                .and_then(|m| <[f32; 9]>::try_from(m).ok())
+                .map(|m| self.color_matrix = Some(m)),
+            "disk_device" => {
+                self.disk_device = value.to_string();
+                Some(())
+            }
+            "network_interface" => {
+                self.network_interface = value.to_string();
+                Some(())
+            }
+            "wifi_interface" => {
+                self.wifi_interface = value.to_string();
+                Some(())
+            }
+            "ethernet_interface" => {
+                self.ethernet_interface = value.to_string();
+                Some(())
+            }
+            "temperature_sensor" => {
+                self.temperature_sensor = value.to_string();
+                Some(())
+            }
+            "temperature_throttled" => {
+                self.temperature_throttled = value.to_string();
+                Some(())
+            }
+            _ if key.ends_with("_signal") => key
+                .strip_suffix("_signal")
+                .and_then(Module::parse)
+                .filter(|m| matches!(m, Module::Updates | Module::Custom | Module::FailedUnits))
+                .zip(value.parse().ok())
+                .map(|(module, offset)| {
+                    self.module_signals.insert(module, offset);
+                }),
+            _ => key
+                .strip_suffix("_width")
+                .and_then(Module::parse)
+                .zip(value.parse().ok())
+                .map(|(module, width)| {
+                    self.module_widths.insert(module, width);
+                }),
+        }
+        .is_some()
+    }
+}
+
+impl Config {
+    /// Icon for windows of `class`, None when no entry matches.
+    pub fn window_icon(&self, class: &str) -> Option<&str> {
+        self.window_icons
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(class))
+            .map(|(_, icon)| icon.as_str())
+    }
+
+    /// Characters outside printable ASCII used in configured strings, they get
+    /// their own glyphs in the font atlas.
+    pub fn extra_chars(&self) -> Vec<char> {
+        let mut chars: Vec<char> = [
+            &self.battery_charging,
+            &self.battery_discharging,
+            &self.battery_full,
+            &self.battery_plugged,
+            &self.temperature_throttled,
+            &self.mpris_playing,
+            &self.mpris_paused,
+        ]
+        .into_iter()
+        .chain(self.window_icons.iter().map(|(_, icon)| icon))
+        .flat_map(|s| s.chars())
+        .filter(|c| !(' '..='~').contains(c))
+        .collect();
+        chars.sort_unstable();
+        chars.dedup();
+        chars
+    }
+}
+
+/// Swaps `color` for black or white when it is unreadable on the capsule.
+fn make_readable(color: &mut u32, what: &str) {
+    let readable = color::readable_on(*color, COLOR_CAPSULE_BG);
+    if readable != *color {
+        eprintln!(
+            "[Config] {} is unreadable on the capsule, using {}",
+            what,
+            if readable & 0xffffff == 0 {
+                "black"
+            } else {
+                "white"
+            }
+        );
+        *color = readable;
+    }
+}
+
+fn parse_bands(value: &str) -> Option<Vec<(u8, u32)>> {
+    let mut bands = value
+        .split(',')
+        .filter(|band| !band.trim().is_empty())
+        .map(|band| {
+            let (percent, color) = band.split_once(':')?;
+            let percent = percent.trim().parse().ok().filter(|p: &u8| *p <= 100)?;
+            Some((percent, parse_color(color.trim())?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    bands.sort_by_key(|(percent, _)| *percent);
+    // Two colors for the same threshold can't both apply
+    bands.dedup_by_key(|(percent, _)| *percent);
+    Some(bands)
+}
+
+/// "20, 10, 5", percentages returned highest first.
+fn parse_levels(value: &str) -> Option<Vec<u8>> {
+    let mut levels = value
+        .split(',')
+        .filter(|level| !level.trim().is_empty())
+        .map(|level| level.trim().parse().ok().filter(|p: &u8| *p <= 100))
+        .collect::<Option<Vec<_>>>()?;
+    levels.sort_unstable_by(|a, b| b.cmp(a));
+    levels.dedup();
+    Some(levels)
+}
+
+/// "#rrggbb" or "#aarrggbb", returned as premultiplied 0xAARRGGBB.
+pub fn parse_color(value: &str) -> Option<u32> {
+    let hex = value.strip_prefix('#')?;
+    let color = u32::from_str_radix(hex, 16).ok()?;
+    let color = match hex.len() {
+        6 => 0xff00_0000 | color,
+        8 => color,
+        _ => return None,
+    };
+    let alpha = color >> 24;
+    let channel = |shift: u32| (((color >> shift) & 0xff) * alpha / 255) << shift;
+    Some((alpha << 24) | channel(16) | channel(8) | channel(0))
+}
+
+/// "4" for every side, "0 8" for vertical and horizontal, or "0 8 4 8".
+fn parse_margins(value: &str) -> Option<[i32; 4]> {
+    let values = value
+        .split_whitespace()
+        .map(|v| v.parse().ok().filter(|m: &i32| *m >= 0))
+        .collect::<Option<Vec<i32>>>()?;
+    match values[..] {
+        [all] => Some([all; 4]),
+        [vertical, horizontal] => Some([vertical, horizontal, vertical, horizontal]),
+        [top, right, bottom, left] => Some([top, right, bottom, left]),
+        _ => None,
+    }
+}
+
+/// Unix seconds, or a local "YYYY-MM-DD" with an optional " HH:MM[:SS]".
+fn parse_timestamp(value: &str) -> Option<u64> {
+    if let Ok(seconds) = value.parse() {
+        return Some(seconds);
+    }
+    let (date, time) = value.split_once([' ', 'T']).unwrap_or((value, "00:00"));
+    let mut date_parts = date.splitn(3, '-').map(str::parse::<i32>);
+    let (year, month, day) = (
+        date_parts.next()?.ok()?,
+        date_parts.next()?.ok()?,
+        date_parts.next()?.ok()?,
+    );
+    let mut time_parts = time.splitn(3, ':').map(str::parse::<u8>);
+    let hour = time_parts.next()?.ok()?;
+    let minute = time_parts.next()?.ok()?;
+    let second = time_parts.next().unwrap_or(Ok(0)).ok()?;
+    let date = time::Date::from_calendar_date(
+        year,
+        u8::try_from(month).ok()?.try_into().ok()?,
+        u8::try_from(day).ok()?,
+    )
+    .ok()?;
+    let offset = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
+    let timestamp = date
+        .with_hms(hour, minute, second)
+        .ok()?
+        .assume_offset(offset)
+        .unix_timestamp();
+    u64::try_from(timestamp).ok()
+}
+
+/// "+05:30", "-8" or "0", in seconds.
+fn parse_utc_offset(value: &str) -> Option<i32> {
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    let hours: i32 = hours.parse().ok().filter(|h| (0..=14).contains(h))?;
+    let minutes: i32 = minutes.parse().ok().filter(|m| (0..60).contains(m))?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// "20:30" as minutes after midnight.
+pub fn parse_time_of_day(value: &str) -> Option<u16> {
+    let (hours, minutes) = value.split_once(':')?;
+    let hours: u16 = hours.parse().ok().filter(|h| *h < 24)?;
+    let minutes: u16 = minutes.parse().ok().filter(|m| *m < 60)?;
+    Some(hours * 60 + minutes)
+}
+
+/// A path with a leading "~/" resolved against `$HOME`.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/").zip(env::var_os("HOME")) {
+        Some((rest, home)) => PathBuf::from(home).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// The line up to a `#` that starts it or follows whitespace, so colors such as
+/// `15:#f38ba8` are kept.
+fn strip_comment(line: &str) -> &str {
+    let mut after_space = true;
+    for (i, c) in line.char_indices() {
+        if c == '#' && after_space {
+            return &line[..i];
+        }
+        after_space = c.is_whitespace();
+    }
+    line
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::load)
+}
+
+fn config_path() -> Option<PathBuf> {
+    let config_root = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .ok()?;
+    Some(config_root.join("leanbar").join("config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_are_stripped() {
+        let config = Config::parse(
+            "# leanbar config\n\
+             highlight_changes = true # underline updates\n\
+             \t# indented comment\n\
+             gpu_vram = yes#not a comment\n",
+        );
+        assert!(config.highlight_changes);
+        // `#` inside a value is kept, so `yes#not a comment` is rejected
+        assert!(!config.gpu_vram);
+    }
+
+    #[test]
+    fn color_values_survive_comment_stripping() {
+        assert_eq!(strip_comment("a = 15:#f38ba8 # low"), "a = 15:#f38ba8 ");
+        assert_eq!(strip_comment("#f38ba8"), "");
+        assert_eq!(parse_color("#f38ba8"), Some(0xfff3_8ba8));
+        assert_eq!(parse_color("#80ffffff"), Some(0x8080_8080));
+        assert_eq!(parse_color("f38ba8"), None);
+        assert_eq!(parse_color("#f38ba"), None);
+    }
+
+    #[test]
+    fn battery_colors_line() {
+        let config = Config::parse("battery_colors = 15:#f38ba8, 30:#f9e2af # low bands\n");
+        assert_eq!(
+            config.battery_colors,
+            [(15, 0xfff3_8ba8), (30, 0xfff9_e2af)]
+        );
+        assert_eq!(
+            parse_bands("30:#f9e2af, 15:#f38ba8, 15:#000000"),
+            Some(vec![(15, 0xfff3_8ba8), (30, 0xfff9_e2af)])
+        );
+        assert_eq!(parse_bands("15:"), None);
+        assert_eq!(parse_bands("101:#f38ba8"), None);
+    }
+
+    #[test]
+    fn custom_colors_line() {
+        let config = Config::parse("custom_colors = warning:#f9e2af, critical:#f38ba8\n");
+        assert_eq!(
+            config.custom_colors,
+            [
+                ("warning".to_string(), 0xfff9_e2af),
+                ("critical".to_string(), 0xfff3_8ba8)
+            ]
+        );
+        let config = Config::parse("custom_colors = warning:f9e2af\n");
+        assert!(config.custom_colors.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_and_bad_values_keep_defaults() {
+        let config = Config::parse(
+            "no_such_key = 1\n\
+             per_window_layout = maybe\n\
+             not a setting\n\
+             highlight_changes = on\n",
+        );
+        assert!(!config.per_window_layout);
+        assert!(config.highlight_changes);
+    }
+}
@@ -0,0 +1,1263 @@
+//! Minimal D-Bus client for the session and system buses: method calls,
+//! properties and signals, enough for the modules that talk to desktop services.
+//! Answering calls and emitting signals is only what the tray needs to act as
+//! the StatusNotifierWatcher.
+//!
+//! Messages are written little endian and read in either byte order. A reply is
+//! waited for synchronously up to a timeout, signals and calls that arrive in
+//! the meantime are queued for `next_signal` and `next_message`.
+//!
+//! Other threads never call on a connection themselves, they post requests to
+//! a `Mailbox` the thread owning it takes them from. Threads keeping a
+//! connection open for good run on `reconnecting`.
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+
+pub const METHOD_CALL: u8 = 1;
+pub const METHOD_RETURN: u8 = 2;
+pub const ERROR: u8 = 3;
+pub const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+const FIXED_HEADER_LEN: usize = 16;
+const MAX_MESSAGE_LEN: usize = 1 << 27;
+/// Longest a reply or the bus's half of authenticating is waited for, libdbus'
+/// default.
+const CALL_TIMEOUT: Duration = Duration::from_secs(25);
+/// Between attempts to reconnect to a bus that went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Containers nested deeper than this are rejected rather than recursed into.
+const MAX_DEPTH: usize = 32;
+
+/// `RequestName` flag, fail rather than wait in line for the name.
+const DO_NOT_QUEUE: u32 = 4;
+/// `RequestName` replies for owning the name.
+const PRIMARY_OWNER: u32 = 1;
+const ALREADY_OWNER: u32 = 4;
+
+const BUS_NAME: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    Uint16(u16),
+    Int32(i32),
+    Uint32(u32),
+    Int64(i64),
+    Uint64(u64),
+    Double(f64),
+    Str(String),
+    ObjectPath(String),
+    Signature(String),
+    /// Element signature and elements, the signature is needed to write empty arrays.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    pub fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".into(),
+            Value::Bool(_) => "b".into(),
+            Value::Int16(_) => "n".into(),
+            Value::Uint16(_) => "q".into(),
+            Value::Int32(_) => "i".into(),
+            Value::Uint32(_) => "u".into(),
+            Value::Int64(_) => "x".into(),
+            Value::Uint64(_) => "t".into(),
+            Value::Double(_) => "d".into(),
+            Value::Str(_) => "s".into(),
+            Value::ObjectPath(_) => "o".into(),
+            Value::Signature(_) => "g".into(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => {
+                format!(
+                    "({})",
+                    fields.iter().map(Value::signature).collect::<String>()
+                )
+            }
+            Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+            Value::Variant(_) => "v".into(),
+        }
+    }
+
+    /// Looks through variants, properties arrive wrapped in them.
+    fn inner(&self) -> &Value {
+        match self {
+            Value::Variant(inner) => inner.inner(),
+            other => other,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self.inner() {
+            Value::Str(s) | Value::ObjectPath(s) | Value::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.inner() {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Any integer type.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self.inner() {
+            Value::Byte(v) => Some(v as i64),
+            Value::Int16(v) => Some(v as i64),
+            Value::Uint16(v) => Some(v as i64),
+            Value::Int32(v) => Some(v as i64),
+            Value::Uint32(v) => Some(v as i64),
+            Value::Int64(v) => Some(v),
+            Value::Uint64(v) => i64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.inner() {
+            Value::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self.inner() {
+            Value::Array(_, items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_struct(&self) -> Option<&[Value]> {
+        match self.inner() {
+            Value::Struct(fields) => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// Key and value pairs of a dictionary, empty for anything else.
+    pub fn entries(&self) -> impl Iterator<Item = (&Value, &Value)> {
+        self.as_array()
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|entry| match entry {
+                Value::DictEntry(key, value) => Some((key.as_ref(), value.inner())),
+                _ => None,
+            })
+    }
+
+    /// Value for a string key of a dictionary (`a{sv}` and the like).
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries()
+            .find_map(|(k, v)| (k.as_str() == Some(key)).then_some(v))
+    }
+}
+
+pub struct Message {
+    pub kind: u8,
+    pub serial: u32,
+    pub reply_serial: Option<u32>,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    /// Whether this is the signal `interface.member`.
+    pub fn is_signal(&self, interface: &str, member: &str) -> bool {
+        self.kind == SIGNAL
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+}
+
+/// Requests other threads leave for the thread owning a connection, such as a
+/// click from the main thread. Posting one makes the `next_message` or
+/// `next_signal` of a connection that `wake_on` it return None, its owner then
+/// `take`s them. Threads waiting on something else poll `wake_fd`.
+pub struct Mailbox<T> {
+    requests: Mutex<Vec<T>>,
+    /// Read and write end of the wakeup, created on first use.
+    wake: OnceLock<Option<(OwnedFd, OwnedFd)>>,
+}
+
+impl<T> Mailbox<T> {
+    pub const fn new() -> Self {
+        Self {
+            requests: Mutex::new(Vec::new()),
+            wake: OnceLock::new(),
+        }
+    }
+
+    fn wake_fds(&self) -> Option<&(OwnedFd, OwnedFd)> {
+        self.wake
+            .get_or_init(|| match crate::wake_fds() {
+                Ok(fds) => Some(fds),
+                Err(e) => {
+                    eprintln!("[DBus] Creating a mailbox wakeup failed: {}", e);
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    /// Readable while there are requests to `take`.
+    pub fn wake_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.wake_fds().map(|(read_end, _)| read_end.as_fd())
+    }
+
+    pub fn post(&self, request: T) {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(request);
+        }
+        if let Some((_, write_end)) = self.wake_fds() {
+            let _ = rustix::io::write(write_end, &1u64.to_ne_bytes());
+        }
+    }
+
+    /// Everything posted since the last call, oldest first.
+    pub fn take(&self) -> Vec<T> {
+        if let Some((read_end, _)) = self.wake_fds() {
+            let mut buf = [0u8; 8];
+            while rustix::io::read(read_end, &mut buf).is_ok_and(|n| n > 0) {}
+        }
+        self.requests
+            .lock()
+            .map(|mut requests| std::mem::take(&mut *requests))
+            .unwrap_or_default()
+    }
+}
+
+/// Runs `serve` on a connection from `connect`, e.g. `Connection::session`,
+/// and connects again whenever it returns, which it does once the connection
+/// failed. Only returns if the bus cannot be reached in the first place.
+pub fn reconnecting(
+    connect: fn() -> io::Result<Connection>,
+    mut serve: impl FnMut(&mut Connection),
+) -> io::Result<Infallible> {
+    let mut connection = connect()?;
+    loop {
+        serve(&mut connection);
+        connection = loop {
+            thread::sleep(RECONNECT_DELAY);
+            if let Ok(connection) = connect() {
+                break connection;
+            }
+        };
+    }
+}
+
+pub struct Connection {
+    stream: UnixStream,
+    serial: u32,
+    /// Signals and method calls that arrived while waiting for a reply.
+    incoming: VecDeque<Message>,
+    /// Read end of the `Mailbox` that interrupts waiting for a message.
+    wake: Option<BorrowedFd<'static>>,
+    /// Assigned by the bus, e.g. ":1.42".
+    pub unique_name: String,
+}
+
+impl Connection {
+    /// The session bus from `$DBUS_SESSION_BUS_ADDRESS`, or `$XDG_RUNTIME_DIR/bus`.
+    pub fn session() -> io::Result<Self> {
+        let address = env::var("DBUS_SESSION_BUS_ADDRESS").ok().or_else(|| {
+            env::var("XDG_RUNTIME_DIR")
+                .ok()
+                .map(|dir| format!("unix:path={}/bus", dir))
+        });
+        Self::open(
+            address
+                .as_deref()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no session bus address"))?,
+        )
+    }
+
+    /// The system bus from `$DBUS_SYSTEM_BUS_ADDRESS`, or the well known socket.
+    pub fn system() -> io::Result<Self> {
+        let address = env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .unwrap_or_else(|_| "unix:path=/run/dbus/system_bus_socket".to_string());
+        Self::open(&address)
+    }
+
+    fn open(address: &str) -> io::Result<Self> {
+        let mut connection = Self {
+            stream: connect_address(address)?,
+            serial: 0,
+            incoming: VecDeque::new(),
+            wake: None,
+            unique_name: String::new(),
+        };
+        connection.authenticate()?;
+        let reply = connection.call(BUS_NAME, BUS_PATH, BUS_NAME, "Hello", &[])?;
+        if let Some(name) = reply.first().and_then(Value::as_str) {
+            connection.unique_name = name.to_string();
+        }
+        Ok(connection)
+    }
+
+    /// SASL EXTERNAL, the bus checks the uid against the socket credentials.
+    fn authenticate(&mut self) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+        self.sasl_external()?;
+        self.stream.set_read_timeout(None)
+    }
+
+    fn sasl_external(&mut self) -> io::Result<()> {
+        let uid = rustix::process::getuid().as_raw();
+        let hex: String = uid
+            .to_string()
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.stream
+            .write_all(format!("\0AUTH EXTERNAL {}\r\n", hex).as_bytes())?;
+
+        // Read byte by byte, nothing after the line may be consumed
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while !line.ends_with(b"\r\n") && line.len() < 512 {
+            self.stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+        if !line.starts_with(b"OK ") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "D-Bus authentication failed: {}",
+                    String::from_utf8_lossy(&line).trim()
+                ),
+            ));
+        }
+        self.stream.write_all(b"BEGIN\r\n")
+    }
+
+    /// Calls a method and waits for its reply, an error reply becomes an `Err`.
+    pub fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Value],
+    ) -> io::Result<Vec<Value>> {
+        self.call_within(CALL_TIMEOUT, destination, path, interface, member, args)
+    }
+
+    /// `call` giving up with `ErrorKind::TimedOut` once `timeout` passed, for
+    /// calls someone is waiting on. A late reply is skipped.
+    pub fn call_within(
+        &mut self,
+        timeout: Duration,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Value],
+    ) -> io::Result<Vec<Value>> {
+        let serial = self.send(destination, path, interface, member, args)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = timespec(deadline.saturating_duration_since(Instant::now()));
+            if poll(&mut [PollFd::new(&self.stream, PollFlags::IN)], Some(&left))? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{}.{}: no reply within {:?}", interface, member, timeout),
+                ));
+            }
+            let message = self.read_message()?;
+            match message.kind {
+                SIGNAL | METHOD_CALL => self.incoming.push_back(message),
+                METHOD_RETURN if message.reply_serial == Some(serial) => return Ok(message.body),
+                ERROR if message.reply_serial == Some(serial) => {
+                    let detail = message.body.first().and_then(Value::as_str).unwrap_or("");
+                    return Err(io::Error::other(format!(
+                        "{}.{}: {} {}",
+                        interface,
+                        member,
+                        message.error_name.as_deref().unwrap_or("error"),
+                        detail
+                    )));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends a method call without waiting for the reply, returns its serial.
+    pub fn send(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Value],
+    ) -> io::Result<u32> {
+        let fields = vec![
+            (FIELD_PATH, Value::ObjectPath(path.to_string())),
+            (FIELD_INTERFACE, Value::Str(interface.to_string())),
+            (FIELD_MEMBER, Value::Str(member.to_string())),
+            (FIELD_DESTINATION, Value::Str(destination.to_string())),
+        ];
+        self.write(METHOD_CALL, fields, args)
+    }
+
+    /// Answers a method call received through `next_message`.
+    pub fn reply(&mut self, call: &Message, args: &[Value]) -> io::Result<()> {
+        let fields = vec![
+            (FIELD_REPLY_SERIAL, Value::Uint32(call.serial)),
+            (
+                FIELD_DESTINATION,
+                Value::Str(call.sender.clone().unwrap_or_default()),
+            ),
+        ];
+        self.write(METHOD_RETURN, fields, args).map(|_| ())
+    }
+
+    /// Answers a method call with the error `name`, e.g.
+    /// `org.freedesktop.DBus.Error.UnknownMethod`.
+    pub fn reply_error(&mut self, call: &Message, name: &str, detail: &str) -> io::Result<()> {
+        let fields = vec![
+            (FIELD_REPLY_SERIAL, Value::Uint32(call.serial)),
+            (
+                FIELD_DESTINATION,
+                Value::Str(call.sender.clone().unwrap_or_default()),
+            ),
+            (FIELD_ERROR_NAME, Value::Str(name.to_string())),
+        ];
+        self.write(ERROR, fields, &[Value::Str(detail.to_string())])
+            .map(|_| ())
+    }
+
+    /// Broadcasts a signal.
+    pub fn emit(
+        &mut self,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Value],
+    ) -> io::Result<()> {
+        let fields = vec![
+            (FIELD_PATH, Value::ObjectPath(path.to_string())),
+            (FIELD_INTERFACE, Value::Str(interface.to_string())),
+            (FIELD_MEMBER, Value::Str(member.to_string())),
+        ];
+        self.write(SIGNAL, fields, args).map(|_| ())
+    }
+
+    /// Writes a message with the header `fields` and the signature of `args`
+    /// added, returns its serial.
+    fn write(&mut self, kind: u8, mut fields: Vec<(u8, Value)>, args: &[Value]) -> io::Result<u32> {
+        self.serial = self.serial.wrapping_add(1).max(1);
+        let mut body = Writer::default();
+        for arg in args {
+            body.value(arg);
+        }
+        let signature: String = args.iter().map(Value::signature).collect();
+        if !signature.is_empty() {
+            fields.push((FIELD_SIGNATURE, Value::Signature(signature)));
+        }
+
+        let mut message = Writer::default();
+        message.buf.extend_from_slice(&[b'l', kind, 0, 1]);
+        message.u32(body.buf.len() as u32);
+        message.u32(self.serial);
+        message.value(&Value::Array(
+            "(yv)".into(),
+            fields
+                .into_iter()
+                .map(|(code, value)| {
+                    Value::Struct(vec![Value::Byte(code), Value::Variant(Box::new(value))])
+                })
+                .collect(),
+        ));
+        message.pad(8);
+        message.buf.extend_from_slice(&body.buf);
+        self.stream.write_all(&message.buf)?;
+        Ok(self.serial)
+    }
+
+    /// Subscribes to the signals matching a match rule, e.g.
+    /// `type='signal',interface='org.freedesktop.DBus.Properties'`.
+    pub fn add_match(&mut self, rule: &str) -> io::Result<()> {
+        self.call(
+            BUS_NAME,
+            BUS_PATH,
+            BUS_NAME,
+            "AddMatch",
+            &[Value::Str(rule.to_string())],
+        )
+        .map(|_| ())
+    }
+
+    /// Takes a well known name, false when another connection owns it.
+    pub fn request_name(&mut self, name: &str) -> io::Result<bool> {
+        let args = [Value::Str(name.to_string()), Value::Uint32(DO_NOT_QUEUE)];
+        let reply = self.call(BUS_NAME, BUS_PATH, BUS_NAME, "RequestName", &args)?;
+        let result = reply.first().and_then(Value::as_i64).unwrap_or(0) as u32;
+        Ok(matches!(result, PRIMARY_OWNER | ALREADY_OWNER))
+    }
+
+    /// Unique name of the connection owning `name`.
+    pub fn name_owner(&mut self, name: &str) -> io::Result<String> {
+        let args = [Value::Str(name.to_string())];
+        let reply = self.call(BUS_NAME, BUS_PATH, BUS_NAME, "GetNameOwner", &args)?;
+        reply
+            .first()
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| invalid("empty GetNameOwner reply"))
+    }
+
+    /// Names currently on the bus.
+    pub fn list_names(&mut self) -> io::Result<Vec<String>> {
+        let reply = self.call(BUS_NAME, BUS_PATH, BUS_NAME, "ListNames", &[])?;
+        Ok(reply
+            .first()
+            .and_then(Value::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|name| name.as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// Every property of an interface as an `a{sv}` dictionary, see `Value::get`.
+    pub fn get_all(&mut self, destination: &str, path: &str, interface: &str) -> io::Result<Value> {
+        let args = [Value::Str(interface.to_string())];
+        let reply = self.call(destination, path, PROPERTIES, "GetAll", &args)?;
+        reply
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty properties reply"))
+    }
+
+    /// Sets a property, `value` is wrapped in the variant the call takes.
+    pub fn set(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        property: &str,
+        value: Value,
+    ) -> io::Result<()> {
+        let args = [
+            Value::Str(interface.to_string()),
+            Value::Str(property.to_string()),
+            Value::Variant(Box::new(value)),
+        ];
+        self.call(destination, path, PROPERTIES, "Set", &args)
+            .map(|_| ())
+    }
+
+    /// Makes `next_message` and `next_signal` return None as soon as something
+    /// is posted to `mailbox`.
+    pub fn wake_on<T>(&mut self, mailbox: &'static Mailbox<T>) {
+        self.wake = mailbox.wake_fds().map(|(read_end, _)| read_end.as_fd());
+    }
+
+    /// The next signal, waiting at most `timeout` for one when given. Returns
+    /// None when the timeout passed first or the mailbox was posted to.
+    pub fn next_signal(&mut self, timeout: Option<Duration>) -> io::Result<Option<Message>> {
+        self.next_of(&[SIGNAL], timeout)
+    }
+
+    /// The next signal or method call, waiting like `next_signal`. Calls have
+    /// to be answered with `reply` or `reply_error`.
+    pub fn next_message(&mut self, timeout: Option<Duration>) -> io::Result<Option<Message>> {
+        self.next_of(&[SIGNAL, METHOD_CALL], timeout)
+    }
+
+    fn next_of(&mut self, kinds: &[u8], timeout: Option<Duration>) -> io::Result<Option<Message>> {
+        while let Some(message) = self.incoming.pop_front() {
+            if kinds.contains(&message.kind) {
+                return Ok(Some(message));
+            }
+        }
+        let timeout = timeout.map(timespec);
+        loop {
+            if timeout.is_some() || self.wake.is_some() {
+                let mut fds = vec![PollFd::new(&self.stream, PollFlags::IN)];
+                if let Some(wake) = &self.wake {
+                    fds.push(PollFd::new(wake, PollFlags::IN));
+                }
+                if poll(&mut fds, timeout.as_ref())? == 0
+                    || fds
+                        .get(1)
+                        .is_some_and(|fd| fd.revents().contains(PollFlags::IN))
+                {
+                    return Ok(None);
+                }
+            }
+            let message = self.read_message()?;
+            if kinds.contains(&message.kind) {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    fn read_message(&mut self) -> io::Result<Message> {
+        let mut fixed = [0u8; FIXED_HEADER_LEN];
+        self.stream.read_exact(&mut fixed)?;
+        let big_endian = match fixed[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(invalid("bad endianness marker")),
+        };
+        let u32_at = |i: usize| {
+            let bytes = fixed[i..i + 4].try_into().unwrap();
+            if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        let body_len = u32_at(4) as usize;
+        let fields_len = u32_at(12) as usize;
+        let header_len = (FIXED_HEADER_LEN + fields_len).next_multiple_of(8);
+        if header_len + body_len > MAX_MESSAGE_LEN {
+            return Err(invalid("message too large"));
+        }
+        let mut data = fixed.to_vec();
+        data.resize(header_len + body_len, 0);
+        self.stream.read_exact(&mut data[FIXED_HEADER_LEN..])?;
+
+        let mut message = Message {
+            kind: fixed[1],
+            serial: u32_at(8),
+            reply_serial: None,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            sender: None,
+            body: Vec::new(),
+        };
+        let mut header = Reader {
+            data: &data[..header_len],
+            pos: 12,
+            big_endian,
+            depth: 0,
+        };
+        let mut signature = String::new();
+        let fields = header.value(b"a(yv)")?;
+        for field in fields.as_array().unwrap_or(&[]) {
+            let Value::Struct(parts) = field else {
+                continue;
+            };
+            let (Some(Value::Byte(code)), Some(value)) = (parts.first(), parts.get(1)) else {
+                continue;
+            };
+            let text = value.as_str().map(str::to_string);
+            match *code {
+                FIELD_PATH => message.path = text,
+                FIELD_INTERFACE => message.interface = text,
+                FIELD_MEMBER => message.member = text,
+                FIELD_ERROR_NAME => message.error_name = text,
+                FIELD_SENDER => message.sender = text,
+                FIELD_SIGNATURE => signature = text.unwrap_or_default(),
+                FIELD_REPLY_SERIAL => message.reply_serial = value.as_i64().map(|s| s as u32),
+                _ => {}
+            }
+        }
+
+        let mut body = Reader {
+            data: &data[header_len..],
+            pos: 0,
+            big_endian,
+            depth: 0,
+        };
+        let signature = signature.as_bytes();
+        let mut start = 0;
+        while start < signature.len() {
+            let len = single_type_len(&signature[start..])?;
+            message
+                .body
+                .push(body.value(&signature[start..start + len])?);
+            start += len;
+        }
+        Ok(message)
+    }
+}
+
+/// Connects to the first `unix:` address of a bus address list.
+fn connect_address(address: &str) -> io::Result<UnixStream> {
+    for entry in address.split(';') {
+        let Some(params) = entry.strip_prefix("unix:") else {
+            continue;
+        };
+        for param in params.split(',') {
+            if let Some(path) = param.strip_prefix("path=") {
+                return UnixStream::connect(unescape(path));
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(name) = param.strip_prefix("abstract=") {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(unescape(name))?;
+                return UnixStream::connect_addr(&addr);
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no usable D-Bus address in {}", address),
+    ))
+}
+
+/// Undoes the %XX escaping of address values.
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn timespec(duration: Duration) -> Timespec {
+    Timespec {
+        tv_sec: duration.as_secs() as i64,
+        tv_nsec: duration.subsec_nanos() as i64,
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Length of the first complete type in a signature.
+fn single_type_len(signature: &[u8]) -> io::Result<usize> {
+    match signature.first() {
+        Some(b'a') => Ok(1 + single_type_len(&signature[1..])?),
+        Some(&open @ (b'(' | b'{')) => {
+            let close = if open == b'(' { b')' } else { b'}' };
+            let mut depth = 0;
+            for (i, &c) in signature.iter().enumerate() {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(i + 1);
+                    }
+                }
+            }
+            Err(invalid("unbalanced signature"))
+        }
+        Some(_) => Ok(1),
+        None => Err(invalid("empty signature")),
+    }
+}
+
+fn alignment(code: u8) -> usize {
+    match code {
+        b'y' | b'g' | b'v' => 1,
+        b'n' | b'q' => 2,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 4,
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn pad(&mut self, align: usize) {
+        let len = self.buf.len().next_multiple_of(align);
+        self.buf.resize(len, 0);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.pad(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.buf.push(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Byte(v) => self.buf.push(*v),
+            Value::Bool(v) => self.u32(*v as u32),
+            Value::Int16(v) => {
+                self.pad(2);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::Uint16(v) => {
+                self.pad(2);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::Int32(v) => self.u32(*v as u32),
+            Value::Uint32(v) => self.u32(*v),
+            Value::Int64(v) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::Uint64(v) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::Double(v) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Value::Str(v) | Value::ObjectPath(v) => self.string(v),
+            Value::Signature(v) => self.signature(v),
+            Value::Array(element, items) => {
+                self.u32(0);
+                let len_at = self.buf.len() - 4;
+                self.pad(alignment(
+                    element.as_bytes().first().copied().unwrap_or(b'y'),
+                ));
+                let start = self.buf.len();
+                for item in items {
+                    self.value(item);
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.pad(8);
+                for field in fields {
+                    self.value(field);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                self.pad(8);
+                self.value(key);
+                self.value(value);
+            }
+            Value::Variant(inner) => {
+                self.signature(&inner.signature());
+                self.value(inner);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn align(&mut self, align: usize) {
+        self.pos = self.pos.next_multiple_of(align);
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        self.align(N);
+        let mut bytes: [u8; N] = self.take(N)?.try_into().unwrap();
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.fixed().map(u32::from_le_bytes)
+    }
+
+    fn text(&mut self, len: usize) -> io::Result<String> {
+        let bytes = self.take(len + 1)?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+
+    /// Reads one value of a single complete type.
+    fn value(&mut self, signature: &[u8]) -> io::Result<Value> {
+        if self.depth > MAX_DEPTH {
+            return Err(invalid("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = self.value_inner(signature);
+        self.depth -= 1;
+        value
+    }
+
+    fn value_inner(&mut self, signature: &[u8]) -> io::Result<Value> {
+        let code = *signature
+            .first()
+            .ok_or_else(|| invalid("empty signature"))?;
+        Ok(match code {
+            b'y' => Value::Byte(self.take(1)?[0]),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::Int16(i16::from_le_bytes(self.fixed()?)),
+            b'q' => Value::Uint16(u16::from_le_bytes(self.fixed()?)),
+            b'i' => Value::Int32(i32::from_le_bytes(self.fixed()?)),
+            b'u' | b'h' => Value::Uint32(self.u32()?),
+            b'x' => Value::Int64(i64::from_le_bytes(self.fixed()?)),
+            b't' => Value::Uint64(u64::from_le_bytes(self.fixed()?)),
+            b'd' => Value::Double(f64::from_le_bytes(self.fixed()?)),
+            b's' | b'o' => {
+                let len = self.u32()? as usize;
+                let text = self.text(len)?;
+                if code == b's' {
+                    Value::Str(text)
+                } else {
+                    Value::ObjectPath(text)
+                }
+            }
+            b'g' => {
+                let len = self.take(1)?[0] as usize;
+                Value::Signature(self.text(len)?)
+            }
+            b'v' => {
+                let len = self.take(1)?[0] as usize;
+                let inner = self.text(len)?;
+                let inner = inner.as_bytes();
+                if single_type_len(inner)? != inner.len() {
+                    return Err(invalid("variant holds more than one type"));
+                }
+                Value::Variant(Box::new(self.value(inner)?))
+            }
+            b'a' => {
+                let element = &signature[1..1 + single_type_len(&signature[1..])?];
+                let len = self.u32()? as usize;
+                self.align(alignment(element[0]));
+                let end = self.pos + len;
+                if end > self.data.len() {
+                    return Err(invalid("array runs past the message"));
+                }
+                let mut items = Vec::new();
+                while self.pos < end {
+                    items.push(self.value(element)?);
+                }
+                Value::Array(String::from_utf8_lossy(element).into_owned(), items)
+            }
+            b'(' | b'{' => {
+                self.align(8);
+                let len = single_type_len(signature)?;
+                let inner = &signature[1..len - 1];
+                let mut fields = Vec::new();
+                let mut start = 0;
+                while start < inner.len() {
+                    let field_len = single_type_len(&inner[start..])?;
+                    fields.push(self.value(&inner[start..start + field_len])?);
+                    start += field_len;
+                }
+                if code == b'(' {
+                    Value::Struct(fields)
+                } else {
+                    let mut fields = fields.into_iter();
+                    match (fields.next(), fields.next(), fields.next()) {
+                        (Some(key), Some(value), None) => {
+                            Value::DictEntry(Box::new(key), Box::new(value))
+                        }
+                        _ => return Err(invalid("dict entry without a key and value")),
+                    }
+                }
+            }
+            _ => return Err(invalid("unknown type code")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn pair() -> (Connection, Connection) {
+        let (a, b) = UnixStream::pair().unwrap();
+        let connection = |stream| Connection {
+            stream,
+            serial: 0,
+            incoming: VecDeque::new(),
+            wake: None,
+            unique_name: String::new(),
+        };
+        (connection(a), connection(b))
+    }
+
+    fn round_trip(value: Value) {
+        let mut writer = Writer::default();
+        // Misaligned on purpose, padding is relative to the message start
+        writer.buf.push(0);
+        writer.value(&value);
+        let mut reader = Reader {
+            data: &writer.buf,
+            pos: 1,
+            big_endian: false,
+            depth: 0,
+        };
+        assert_eq!(reader.value(value.signature().as_bytes()).unwrap(), value);
+        assert_eq!(reader.pos, writer.buf.len());
+    }
+
+    #[test]
+    fn marshals_every_type() {
+        for value in [
+            Value::Byte(7),
+            Value::Bool(true),
+            Value::Int16(-2),
+            Value::Uint16(65535),
+            Value::Int32(-70000),
+            Value::Uint32(70000),
+            Value::Int64(-1 << 40),
+            Value::Uint64(1 << 60),
+            Value::Double(2.5),
+            Value::Str("héllo".into()),
+            Value::ObjectPath("/org/leanbar".into()),
+            Value::Signature("a{sv}".into()),
+            Value::Array("s".into(), Vec::new()),
+            Value::Array("x".into(), vec![Value::Int64(1), Value::Int64(2)]),
+            Value::Array(
+                "(iiay)".into(),
+                vec![Value::Struct(vec![
+                    Value::Int32(1),
+                    Value::Int32(1),
+                    Value::Array("y".into(), vec![Value::Byte(0xff); 4]),
+                ])],
+            ),
+            Value::Array(
+                "{sv}".into(),
+                vec![Value::DictEntry(
+                    Box::new(Value::Str("Percentage".into())),
+                    Box::new(Value::Variant(Box::new(Value::Double(42.0)))),
+                )],
+            ),
+            Value::Variant(Box::new(Value::Struct(vec![
+                Value::Byte(1),
+                Value::Str("a".into()),
+            ]))),
+        ] {
+            round_trip(value);
+        }
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let signature = format!("{}i", "a".repeat(MAX_DEPTH + 2));
+        let mut data = vec![0u8; 4 * (MAX_DEPTH + 3)];
+        // Every array holds just the next one's length
+        for (i, chunk) in data.chunks_mut(4).enumerate() {
+            let left = ((MAX_DEPTH + 2 - i.min(MAX_DEPTH + 2)) * 4) as u32;
+            chunk.copy_from_slice(&left.to_le_bytes());
+        }
+        let mut reader = Reader {
+            data: &data,
+            pos: 0,
+            big_endian: false,
+            depth: 0,
+        };
+        let error = reader.value(signature.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "nested too deeply");
+    }
+
+    #[test]
+    fn sends_and_reads_a_message() {
+        let (mut a, mut b) = pair();
+        let args = [
+            Value::Str("hi".into()),
+            Value::Array("s".into(), vec![Value::Str("x".into())]),
+            Value::Variant(Box::new(Value::Uint64(9))),
+        ];
+        a.emit("/a", "b.c", "D", &args).unwrap();
+        let message = b.read_message().unwrap();
+        assert!(message.is_signal("b.c", "D"));
+        assert_eq!(message.path.as_deref(), Some("/a"));
+        assert_eq!(message.serial, 1);
+        assert_eq!(message.body, args);
+    }
+
+    #[test]
+    fn reads_big_endian_messages() {
+        fn pad(m: &mut Vec<u8>, align: usize) {
+            m.resize(m.len().next_multiple_of(align), 0);
+        }
+        fn string(m: &mut Vec<u8>, s: &str) {
+            pad(m, 4);
+            m.extend((s.len() as u32).to_be_bytes());
+            m.extend(s.as_bytes());
+            m.push(0);
+        }
+        fn field(m: &mut Vec<u8>, code: u8, kind: u8, value: &str) {
+            pad(m, 8);
+            m.extend([code, 1, kind, 0]);
+            match kind {
+                b'g' => m.extend(
+                    [value.len() as u8]
+                        .iter()
+                        .chain(value.as_bytes())
+                        .chain(&[0]),
+                ),
+                _ => string(m, value),
+            }
+        }
+
+        let mut body = Vec::new();
+        string(&mut body, "hi");
+        pad(&mut body, 4);
+        body.extend(7u32.to_be_bytes());
+
+        let mut message = vec![b'B', SIGNAL, 0, 1];
+        message.extend((body.len() as u32).to_be_bytes());
+        message.extend(9u32.to_be_bytes());
+        message.extend([0; 4]);
+        field(&mut message, FIELD_PATH, b'o', "/a");
+        field(&mut message, FIELD_INTERFACE, b's', "b.c");
+        field(&mut message, FIELD_MEMBER, b's', "D");
+        field(&mut message, FIELD_SIGNATURE, b'g', "su");
+        let fields_len = (message.len() - FIXED_HEADER_LEN) as u32;
+        message[12..16].copy_from_slice(&fields_len.to_be_bytes());
+        pad(&mut message, 8);
+        message.extend(body);
+
+        let (a, mut b) = pair();
+        (&a.stream).write_all(&message).unwrap();
+        let read = b.read_message().unwrap();
+        assert!(read.is_signal("b.c", "D"));
+        assert_eq!(read.serial, 9);
+        assert_eq!(read.path.as_deref(), Some("/a"));
+        assert_eq!(read.body, [Value::Str("hi".into()), Value::Uint32(7)]);
+    }
+
+    #[test]
+    fn call_waits_for_its_reply() {
+        let (mut a, mut b) = pair();
+        let peer = thread::spawn(move || {
+            let call = b.next_message(None).unwrap().unwrap();
+            assert_eq!(call.member.as_deref(), Some("Get"));
+            b.emit("/a", "b.c", "Changed", &[]).unwrap();
+            b.reply(&call, &[Value::Int32(5)]).unwrap();
+            let call = b.next_message(None).unwrap().unwrap();
+            b.reply_error(&call, "b.c.Error.Failed", "no").unwrap();
+        });
+        assert_eq!(
+            a.call("b.c", "/a", "b.c", "Get", &[]).unwrap(),
+            [Value::Int32(5)]
+        );
+        // The signal from before the reply waits for next_signal
+        let signal = a.next_signal(Some(Duration::ZERO)).unwrap().unwrap();
+        assert!(signal.is_signal("b.c", "Changed"));
+        let error = a.call("b.c", "/a", "b.c", "Fail", &[]).unwrap_err();
+        assert!(error.to_string().contains("b.c.Error.Failed"));
+        peer.join().unwrap();
+    }
+
+    #[test]
+    fn call_times_out() {
+        let (mut a, _b) = pair();
+        let timeout = Duration::from_millis(20);
+        let error = a
+            .call_within(timeout, "b.c", "/a", "b.c", "Get", &[])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn mailbox_interrupts_waiting() {
+        static MAILBOX: Mailbox<u8> = Mailbox::new();
+        let (mut a, _b) = pair();
+        a.wake_on(&MAILBOX);
+        MAILBOX.post(1);
+        MAILBOX.post(2);
+        assert!(a.next_message(None).unwrap().is_none());
+        assert_eq!(MAILBOX.take(), [1, 2]);
+        assert!(a.next_message(Some(Duration::ZERO)).unwrap().is_none());
+    }
+
+    /// Plays the bus's half of SASL EXTERNAL, answering with `reply`.
+    fn authenticate_against(reply: &'static [u8]) -> io::Result<()> {
+        let (mut a, b) = pair();
+        let bus = thread::spawn(move || {
+            let mut stream = b.stream;
+            let mut line = Vec::new();
+            let mut byte = [0u8; 1];
+            while !line.ends_with(b"\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                line.push(byte[0]);
+            }
+            stream.write_all(reply).unwrap();
+            let mut begin = [0u8; 7];
+            let _ = stream.read_exact(&mut begin);
+            (line, begin)
+        });
+        let result = a.authenticate();
+        drop(a);
+        let (line, begin) = bus.join().unwrap();
+        let uid: String = rustix::process::getuid()
+            .as_raw()
+            .to_string()
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(line, format!("\0AUTH EXTERNAL {}\r\n", uid).as_bytes());
+        if result.is_ok() {
+            assert_eq!(&begin, b"BEGIN\r\n");
+        }
+        result
+    }
+
+    #[test]
+    fn authenticates() {
+        authenticate_against(b"OK 1234deadbeef\r\n").unwrap();
+        let error = authenticate_against(b"REJECTED EXTERNAL\r\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
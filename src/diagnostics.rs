@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::{Debug, Write as _};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use wayland_client::{Proxy, backend::protocol::ProtocolError};
+
+use crate::error::LeanbarError;
+
+const EVENT_LOG_LEN: usize = 64;
+
+/// Ring buffer of the most recently dispatched Wayland events, dumped into the
+/// crash report when the compositor kills the connection.
+pub struct EventLog {
+    entries: VecDeque<Entry>,
+    /// `event_log` in the config, events are only formatted while it is on.
+    enabled: bool,
+}
+
+/// The object and opcode are always kept, they cost no formatting.
+struct Entry {
+    interface: &'static str,
+    id: u32,
+    opcode: u16,
+    /// The event with its arguments, empty unless `event_log` is on.
+    detail: String,
+}
+
+impl EventLog {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(EVENT_LOG_LEN),
+            enabled,
+        }
+    }
+
+    pub fn record<P: Proxy>(&mut self, proxy: &P, opcode: u16, event: &impl Debug) {
+        // Reuse the oldest entry's allocation once the ring is full
+        let mut detail = if self.entries.len() == EVENT_LOG_LEN {
+            self.entries
+                .pop_front()
+                .map(|e| e.detail)
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        detail.clear();
+        if self.enabled {
+            let _ = write!(detail, "{:?}", event);
+        }
+        let id = proxy.id();
+        self.entries.push_back(Entry {
+            interface: id.interface().name,
+            id: id.protocol_id(),
+            opcode,
+            detail,
+        });
+    }
+}
+
+/// Logs a fatal protocol error and writes a crash report with the recent events.
+pub fn report_protocol_error(err: &ProtocolError, log: &EventLog) {
+    eprintln!(
+        "Wayland protocol error on {}@{} (code {}): {}",
+        err.object_interface, err.object_id, err.code, err.message
+    );
+    match write_crash_report(err, log) {
+        Ok(path) => eprintln!("Crash report written to {}", path.display()),
+        Err(e) => eprintln!("Failed to write crash report: {}", e),
+    }
+}
+
+fn write_crash_report(err: &ProtocolError, log: &EventLog) -> Result<PathBuf, LeanbarError> {
+    let state_root = env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|h| PathBuf::from(h).join(".local/state")))
+        .map_err(|_| LeanbarError::NoHome)?;
+    let dir = state_root.join("leanbar");
+    fs::create_dir_all(&dir)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = dir.join(format!("crash-{}.txt", timestamp));
+
+    let mut file = fs::File::create(&path)?;
+    writeln!(file, "leanbar {} crash report", env!("CARGO_PKG_VERSION"))?;
+    writeln!(file, "unix time: {}", timestamp)?;
+    writeln!(
+        file,
+        "desktop: {}",
+        env::var("XDG_CURRENT_DESKTOP").unwrap_or_default()
+    )?;
+    writeln!(file)?;
+    writeln!(file, "protocol error")?;
+    writeln!(file, "  object: {}@{}", err.object_interface, err.object_id)?;
+    writeln!(file, "  code: {}", err.code)?;
+    writeln!(file, "  message: {}", err.message)?;
+    writeln!(file)?;
+    writeln!(
+        file,
+        "last {} dispatched events (oldest first{}):",
+        log.entries.len(),
+        if log.enabled {
+            ""
+        } else {
+            ", set event_log = true for their arguments"
+        }
+    )?;
+    for entry in &log.entries {
+        if entry.detail.is_empty() {
+            writeln!(
+                file,
+                "  {}@{} opcode {}",
+                entry.interface, entry.id, entry.opcode
+            )?;
+        } else {
+            writeln!(file, "  {}@{} {}", entry.interface, entry.id, entry.detail)?;
+        }
+    }
+    Ok(path)
+}
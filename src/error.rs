@@ -11,6 +11,9 @@ pub enum LeanbarError {
     #[error("Atlas error: {0}")]
     Atlas(String),
 
+    #[error("Preview error: {0}")]
+    Preview(String),
+
     #[error("XDG_CACHE_HOME or HOME not set")]
     NoHome,
 
@@ -0,0 +1,69 @@
+use rustix::fs::{Mode, OFlags, open};
+use rustix::ioctl::{Getter, ioctl, opcode};
+use std::fs;
+use std::os::fd::OwnedFd;
+
+use crate::config::Modifier;
+
+// Key state bitmap size for KEY_MAX (0x2ff)
+const KEY_BYTES: usize = 96;
+const EVIOCGKEY: rustix::ioctl::Opcode = opcode::read::<[u8; KEY_BYTES]>(b'E', 0x18);
+
+const KEY_LEFTCTRL: usize = 29;
+const KEY_LEFTSHIFT: usize = 42;
+const KEY_RIGHTSHIFT: usize = 54;
+const KEY_LEFTALT: usize = 56;
+const KEY_RIGHTCTRL: usize = 97;
+const KEY_RIGHTALT: usize = 100;
+const KEY_LEFTMETA: usize = 125;
+const KEY_RIGHTMETA: usize = 126;
+
+/// Keyboard event devices used to query held modifiers.
+///
+/// The bar surface never has keyboard focus, so Wayland does not tell us about
+/// modifiers; the key state is read straight from evdev instead. This needs read
+/// access to `/dev/input` (usually the `input` group) and silently reports no
+/// modifiers otherwise.
+#[derive(Default)]
+pub struct Keyboards {
+    devices: Option<Vec<OwnedFd>>,
+}
+
+impl Keyboards {
+    pub fn modifier_held(&mut self, modifier: Modifier) -> bool {
+        let keys: &[usize] = match modifier {
+            Modifier::None => return true,
+            Modifier::Super => &[KEY_LEFTMETA, KEY_RIGHTMETA],
+            Modifier::Alt => &[KEY_LEFTALT, KEY_RIGHTALT],
+            Modifier::Ctrl => &[KEY_LEFTCTRL, KEY_RIGHTCTRL],
+            Modifier::Shift => &[KEY_LEFTSHIFT, KEY_RIGHTSHIFT],
+        };
+
+        let devices = self.devices.get_or_insert_with(open_keyboards);
+        devices.iter().any(|fd| {
+            let Ok(state) = (unsafe { ioctl(fd, Getter::<EVIOCGKEY, [u8; KEY_BYTES]>::new()) })
+            else {
+                return false;
+            };
+            keys.iter().any(|&k| state[k / 8] & (1 << (k % 8)) != 0)
+        })
+    }
+}
+
+fn open_keyboards() -> Vec<OwnedFd> {
+    let Ok(entries) = fs::read_dir("/dev/input/by-path") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().ends_with("-event-kbd"))
+        .filter_map(|e| {
+            open(
+                e.path(),
+                OFlags::RDONLY | OFlags::NONBLOCK | OFlags::CLOEXEC,
+                Mode::empty(),
+            )
+            .ok()
+        })
+        .collect()
+}
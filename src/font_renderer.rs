@@ -3,20 +3,33 @@ use std::collections::hash_map::DefaultHasher;
 use std::env;
 use std::fs;
 use std::hash::{Hash, Hasher};
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::UNIX_EPOCH;
 
-use crate::error::LeanbarError;
+use crate::{config, error::LeanbarError};
 
-const ATLAS_MAGIC: &[u8; 5] = b"LBAT1"; // leanbar atlas v1
-const GLYPH_COUNT: usize = 19;
+/// The font the bar is drawn with.
+pub const FONT_PATH: &str = "/usr/share/fonts/noto/NotoSans-Regular.ttf";
+pub const FONT_SIZE: f32 = 15.0;
+
+const ATLAS_MAGIC: &[u8; 5] = b"LBAT5"; // leanbar atlas v5
+const GLYPH_COUNT: usize = 20;
+// Printable ASCII, ' ' through '~'
+const ASCII_FIRST: u8 = 0x20;
+const ASCII_COUNT: usize = 95;
 
 #[derive(Default)]
 pub struct RasterizedGlyph {
     pub width: usize,
     pub height: usize,
+    /// Horizontal offset from the pen position to the left edge of the bitmap.
+    pub xmin: i32,
+    /// Offset from the baseline to the bottom edge of the bitmap, positive is up.
+    pub ymin: i32,
+    /// Distance to move the pen after drawing this glyph.
+    pub advance: usize,
     pub coverage: Vec<u8>,
 }
 
@@ -28,26 +41,54 @@ pub struct GlyphCache {
     pub colon: RasterizedGlyph,
     pub space: RasterizedGlyph,
     pub percent: RasterizedGlyph,
-    pub plus: RasterizedGlyph,
-    pub minus: RasterizedGlyph,
-    pub full: RasterizedGlyph,
+    pub dot: RasterizedGlyph,
+    pub watt: RasterizedGlyph,
+    pub up: RasterizedGlyph,
+    pub down: RasterizedGlyph,
+    /// Not from the font, see `mic_muted_glyph`.
+    pub mic_muted: RasterizedGlyph,
+    /// Glyphs for free-form text, indexed from `ASCII_FIRST`.
+    pub ascii: Vec<RasterizedGlyph>,
+    /// Non-ASCII characters used in configured strings, see `Config::extra_chars`.
+    pub extra: Vec<(char, RasterizedGlyph)>,
     pub max_digit_width: usize,
     pub max_ampm_width: usize,
 }
 
 impl GlyphCache {
+    /// Glyph used to draw `c` as text, characters missing from the atlas fall back to '?'.
+    pub fn glyph(&self, c: char) -> &RasterizedGlyph {
+        if let Some((_, g)) = self.extra.iter().find(|(e, _)| *e == c) {
+            return g;
+        }
+        let idx = (c as u32)
+            .checked_sub(ASCII_FIRST as u32)
+            .filter(|i| (*i as usize) < ASCII_COUNT)
+            .unwrap_or((b'?' - ASCII_FIRST) as u32);
+        &self.ascii[idx as usize]
+    }
+
     pub fn load_or_build(font_path: &str, size: f32) -> Result<Self, LeanbarError> {
         let atlas_path = atlas_cache_path(font_path, size)?;
-        if let Ok(cache) = Self::load_from_atlas(font_path, size, &atlas_path) {
-            println!("[FontAtlas] cache hit: {}", atlas_path.display());
-            return Ok(cache);
+        match Self::load_from_atlas(font_path, size, &atlas_path) {
+            Ok(cache) => {
+                println!("[FontAtlas] cache hit: {}", atlas_path.display());
+                return Ok(cache);
+            }
+            Err(LeanbarError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                println!("[FontAtlas] cache miss: rebuilding");
+            }
+            Err(e) => {
+                // Stale or unreadable, either way it must not survive a failed rebuild
+                println!("[FontAtlas] discarding cache ({}): rebuilding", e);
+                let _ = fs::remove_file(&atlas_path);
+            }
         }
-        println!("[FontAtlas] cache miss: rebuilding");
         build_atlas_with_helper(font_path, size, &atlas_path)?;
         Self::load_from_atlas(font_path, size, &atlas_path)
     }
 
-    fn from_font(font_path: &str, size: f32) -> Result<Self, LeanbarError> {
+    pub fn from_font(font_path: &str, size: f32) -> Result<Self, LeanbarError> {
         let font = Font::from_bytes(fs::read(font_path)?, FontSettings::default())
             .map_err(|e| LeanbarError::Font(e.to_string()))?;
         let numbers: [RasterizedGlyph; 10] =
@@ -57,6 +98,7 @@ impl GlyphCache {
         let pm = rasterize_string(&font, "PM", size);
         let max_digit_width = numbers.iter().map(|g| g.width).max().unwrap_or(0);
         let max_ampm_width = am.width.max(pm.width);
+        let mic_muted = mic_muted_glyph(numbers[0].height);
 
         Ok(GlyphCache {
             numbers,
@@ -66,19 +108,37 @@ impl GlyphCache {
             colon: rasterize_char(&font, ':', size),
             space: rasterize_char(&font, ' ', size),
             percent: rasterize_char(&font, '%', size),
-            plus: rasterize_char(&font, '+', size),
-            minus: rasterize_char(&font, '-', size),
-            full: rasterize_string(&font, "Full", size),
+            dot: rasterize_char(&font, '.', size),
+            watt: rasterize_char(&font, 'W', size),
+            up: rasterize_char(&font, '\u{2191}', size),
+            down: rasterize_char(&font, '\u{2193}', size),
+            mic_muted,
+            ascii: (0..ASCII_COUNT as u8)
+                .map(|i| rasterize_char(&font, (ASCII_FIRST + i) as char, size))
+                .collect(),
+            // Characters the font lacks are drawn as nothing rather than its
+            // missing glyph box, the default window icons need a Nerd Font
+            extra: config::get()
+                .extra_chars()
+                .into_iter()
+                .map(|c| match font.lookup_glyph_index(c) {
+                    0 => (c, RasterizedGlyph::default()),
+                    _ => (c, rasterize_char(&font, c, size)),
+                })
+                .collect(),
             max_digit_width,
             max_ampm_width,
         })
     }
 
-    fn from_vec(all: Vec<RasterizedGlyph>) -> Result<Self, LeanbarError> {
-        if all.len() != GLYPH_COUNT {
+    fn from_vec(
+        all: Vec<RasterizedGlyph>,
+        extra: Vec<(char, RasterizedGlyph)>,
+    ) -> Result<Self, LeanbarError> {
+        if all.len() != GLYPH_COUNT + ASCII_COUNT {
             return Err(LeanbarError::Atlas(format!(
                 "expected {} glyphs, got {}",
-                GLYPH_COUNT,
+                GLYPH_COUNT + ASCII_COUNT,
                 all.len()
             )));
         }
@@ -89,6 +149,7 @@ impl GlyphCache {
         let pm = it.next().unwrap();
         let max_digit_width = numbers.iter().map(|g| g.width).max().unwrap_or(0);
         let max_ampm_width = am.width.max(pm.width);
+        let mic_muted = mic_muted_glyph(numbers[0].height);
 
         Ok(GlyphCache {
             numbers,
@@ -98,24 +159,50 @@ impl GlyphCache {
             colon: it.next().unwrap(),
             space: it.next().unwrap(),
             percent: it.next().unwrap(),
-            plus: it.next().unwrap(),
-            minus: it.next().unwrap(),
-            full: it.next().unwrap(),
+            dot: it.next().unwrap(),
+            watt: it.next().unwrap(),
+            up: it.next().unwrap(),
+            down: it.next().unwrap(),
+            mic_muted,
+            ascii: it.collect(),
+            extra,
             max_digit_width,
             max_ampm_width,
         })
     }
 
+    /// Writes to a temporary file next to the target and renames it into place,
+    /// so a crash mid-write never leaves a truncated atlas behind.
     fn write_atlas(
         &self,
         font_path: &str,
         size: f32,
         target_path: &Path,
     ) -> Result<(), LeanbarError> {
-        if let Some(parent) = target_path.parent() {
-            fs::create_dir_all(parent)?;
+        let parent = target_path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)?;
+        let temp_path = target_path.with_extension(format!("tmp{}", std::process::id()));
+        let result = self
+            .write_atlas_contents(font_path, size, &temp_path)
+            .and_then(|()| Ok(fs::rename(&temp_path, target_path)?));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+            return result;
+        }
+        // Persist the rename itself, best effort
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
         }
-        let mut writer = BufWriter::new(fs::File::create(target_path)?);
+        Ok(())
+    }
+
+    fn write_atlas_contents(
+        &self,
+        font_path: &str,
+        size: f32,
+        path: &Path,
+    ) -> Result<(), LeanbarError> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
         writer.write_all(ATLAS_MAGIC)?;
         writer.write_all(&(font_path.len() as u32).to_le_bytes())?;
         writer.write_all(font_path.as_bytes())?;
@@ -123,13 +210,18 @@ impl GlyphCache {
         writer.write_all(&secs.to_le_bytes())?;
         writer.write_all(&nanos.to_le_bytes())?;
         writer.write_all(&size.to_bits().to_le_bytes())?;
-        for glyph in self.as_slice_ordered() {
-            writer.write_all(&(glyph.width as u16).to_le_bytes())?;
-            writer.write_all(&(glyph.height as u16).to_le_bytes())?;
-            writer.write_all(&(glyph.coverage.len() as u32).to_le_bytes())?;
-            writer.write_all(&glyph.coverage)?;
+        for glyph in self.as_slice_ordered().into_iter().chain(&self.ascii) {
+            write_glyph(&mut writer, glyph)?;
         }
-        writer.flush()?;
+        writer.write_all(&(self.extra.len() as u32).to_le_bytes())?;
+        for (c, glyph) in &self.extra {
+            writer.write_all(&(*c as u32).to_le_bytes())?;
+            write_glyph(&mut writer, glyph)?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
         Ok(())
     }
 
@@ -160,18 +252,26 @@ impl GlyphCache {
             return Err(LeanbarError::Atlas("size mismatch".into()));
         }
 
-        let mut glyphs = Vec::with_capacity(GLYPH_COUNT);
-        for _ in 0..GLYPH_COUNT {
-            let width = u16::from_le_bytes(take(&mut cursor, 2)?.try_into()?) as usize;
-            let height = u16::from_le_bytes(take(&mut cursor, 2)?.try_into()?) as usize;
-            let cov_len = u32::from_le_bytes(take(&mut cursor, 4)?.try_into()?) as usize;
-            glyphs.push(RasterizedGlyph {
-                width,
-                height,
-                coverage: take(&mut cursor, cov_len)?.to_vec(),
-            });
+        let mut glyphs = Vec::with_capacity(GLYPH_COUNT + ASCII_COUNT);
+        for _ in 0..GLYPH_COUNT + ASCII_COUNT {
+            glyphs.push(read_glyph(&mut cursor)?);
         }
-        GlyphCache::from_vec(glyphs)
+
+        // Configured strings changed since the atlas was built
+        let expected_extra = config::get().extra_chars();
+        let extra_len = u32::from_le_bytes(take(&mut cursor, 4)?.try_into()?) as usize;
+        if extra_len != expected_extra.len() {
+            return Err(LeanbarError::Atlas("extra glyphs mismatch".into()));
+        }
+        let mut extra = Vec::with_capacity(extra_len);
+        for expected in expected_extra {
+            let c = u32::from_le_bytes(take(&mut cursor, 4)?.try_into()?);
+            if c != expected as u32 {
+                return Err(LeanbarError::Atlas("extra glyphs mismatch".into()));
+            }
+            extra.push((expected, read_glyph(&mut cursor)?));
+        }
+        GlyphCache::from_vec(glyphs, extra)
     }
 
     fn as_slice_ordered(&self) -> [&RasterizedGlyph; GLYPH_COUNT] {
@@ -192,9 +292,10 @@ impl GlyphCache {
             &self.colon,
             &self.space,
             &self.percent,
-            &self.plus,
-            &self.minus,
-            &self.full,
+            &self.dot,
+            &self.watt,
+            &self.up,
+            &self.down,
         ]
     }
 }
@@ -281,11 +382,42 @@ fn take<'a>(cursor: &mut &'a [u8], n: usize) -> Result<&'a [u8], LeanbarError> {
     Ok(head)
 }
 
+fn write_glyph(writer: &mut impl Write, glyph: &RasterizedGlyph) -> Result<(), LeanbarError> {
+    writer.write_all(&(glyph.width as u16).to_le_bytes())?;
+    writer.write_all(&(glyph.height as u16).to_le_bytes())?;
+    writer.write_all(&(glyph.xmin as i16).to_le_bytes())?;
+    writer.write_all(&(glyph.ymin as i16).to_le_bytes())?;
+    writer.write_all(&(glyph.advance as u16).to_le_bytes())?;
+    writer.write_all(&(glyph.coverage.len() as u32).to_le_bytes())?;
+    writer.write_all(&glyph.coverage)?;
+    Ok(())
+}
+
+fn read_glyph(cursor: &mut &[u8]) -> Result<RasterizedGlyph, LeanbarError> {
+    let width = u16::from_le_bytes(take(cursor, 2)?.try_into()?) as usize;
+    let height = u16::from_le_bytes(take(cursor, 2)?.try_into()?) as usize;
+    let xmin = i16::from_le_bytes(take(cursor, 2)?.try_into()?) as i32;
+    let ymin = i16::from_le_bytes(take(cursor, 2)?.try_into()?) as i32;
+    let advance = u16::from_le_bytes(take(cursor, 2)?.try_into()?) as usize;
+    let cov_len = u32::from_le_bytes(take(cursor, 4)?.try_into()?) as usize;
+    Ok(RasterizedGlyph {
+        width,
+        height,
+        xmin,
+        ymin,
+        advance,
+        coverage: take(cursor, cov_len)?.to_vec(),
+    })
+}
+
 fn rasterize_char(font: &Font, c: char, size: f32) -> RasterizedGlyph {
     let (metrics, coverage) = font.rasterize(c, size);
     RasterizedGlyph {
         width: metrics.width,
         height: metrics.height,
+        xmin: metrics.xmin,
+        ymin: metrics.ymin,
+        advance: metrics.advance_width.round() as usize,
         coverage,
     }
 }
@@ -336,6 +468,65 @@ fn rasterize_string(font: &Font, s: &str, size: f32) -> RasterizedGlyph {
     RasterizedGlyph {
         width: total_width,
         height: total_height,
+        xmin: min_x,
+        ymin: min_y,
+        advance: current_x.round() as usize,
         coverage: final_coverage,
     }
 }
+
+/// Microphone with a slash through it, drawn from shapes since text fonts rarely
+/// have one. `height` is the digit height so it sits with the other symbols.
+fn mic_muted_glyph(height: usize) -> RasterizedGlyph {
+    const SAMPLES: usize = 4;
+    let s = height.max(8) as f32;
+    let width = (s * 0.8).round() as usize;
+    let height = s as usize;
+    // Distance from p to the segment a-b, all in units of the glyph height
+    let segment = |(px, py): (f32, f32), (ax, ay): (f32, f32), (bx, by): (f32, f32)| {
+        let (dx, dy) = (bx - ax, by - ay);
+        let t = (((px - ax) * dx + (py - ay) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+        ((px - ax - t * dx).powi(2) + (py - ay - t * dy).powi(2)).sqrt()
+    };
+    let center = 0.4;
+    let covered = |p: (f32, f32)| {
+        let (x, y) = p;
+        let slash = segment(p, (0.05, 0.08), (0.75, 0.92));
+        if slash < 0.05 {
+            return true;
+        }
+        // Keep a gap either side of the slash so it reads at small sizes
+        if slash < 0.1 {
+            return false;
+        }
+        let capsule = segment(p, (center, 0.18), (center, 0.4)) < 0.15;
+        let ring = ((x - center).powi(2) + (y - 0.4).powi(2)).sqrt();
+        let holder = y >= 0.4 && (ring - 0.28).abs() < 0.045;
+        let stem = (x - center).abs() < 0.045 && (0.68..=0.95).contains(&y);
+        let base = (y - 0.93).abs() < 0.045 && (x - center).abs() < 0.18;
+        capsule || holder || stem || base
+    };
+
+    let mut coverage = vec![0u8; width * height];
+    for row in 0..height {
+        for col in 0..width {
+            let mut hits = 0;
+            for sy in 0..SAMPLES {
+                for sx in 0..SAMPLES {
+                    let x = (col as f32 + (sx as f32 + 0.5) / SAMPLES as f32) / s;
+                    let y = (row as f32 + (sy as f32 + 0.5) / SAMPLES as f32) / s;
+                    hits += covered((x, y)) as usize;
+                }
+            }
+            coverage[row * width + col] = (hits * 255 / (SAMPLES * SAMPLES)) as u8;
+        }
+    }
+    RasterizedGlyph {
+        width,
+        height,
+        xmin: 0,
+        ymin: 0,
+        advance: width,
+        coverage,
+    }
+}
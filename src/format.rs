@@ -0,0 +1,190 @@
+//! Number formatting shared by every numeric module.
+//!
+//! Formatters return a `Formatted` run of parts that the renderer measures and
+//! draws the same way everywhere: 1px between digits and 1px around every other
+//! part, so values line up across modules.
+
+/// Symbols drawn from the named glyphs of the atlas.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Symbol {
+    Colon,
+    Slash,
+    Percent,
+    Dot,
+    Watt,
+    Space,
+    Am,
+    Pm,
+    Up,
+    Down,
+    MicMuted,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Part {
+    /// Decimal number, zero padded to `pad` digits.
+    Num {
+        value: u32,
+        pad: usize,
+    },
+    Symbol(Symbol),
+    /// Unit letter drawn from the ASCII glyphs.
+    Unit(char),
+    /// Configured text such as a battery indicator.
+    Text(&'static str),
+    /// Extra horizontal space in pixels.
+    Gap(usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Formatted {
+    pub parts: Vec<Part>,
+    /// Minimum width in digit cells, keeps fixed width formats from shifting
+    /// their neighbours. Narrower content is right aligned.
+    pub min_cells: usize,
+}
+
+impl Formatted {
+    pub fn num(mut self, value: u32, pad: usize) -> Self {
+        self.parts.push(Part::Num { value, pad });
+        self
+    }
+
+    pub fn symbol(mut self, symbol: Symbol) -> Self {
+        self.parts.push(Part::Symbol(symbol));
+        self
+    }
+
+    pub fn unit(mut self, c: char) -> Self {
+        self.parts.push(Part::Unit(c));
+        self
+    }
+
+    pub fn text(mut self, text: &'static str) -> Self {
+        self.parts.push(Part::Text(text));
+        self
+    }
+
+    pub fn gap(mut self, px: usize) -> Self {
+        self.parts.push(Part::Gap(px));
+        self
+    }
+
+    pub fn then(mut self, other: Formatted) -> Self {
+        self.parts.extend(other.parts);
+        self.min_cells += other.min_cells;
+        self
+    }
+}
+
+/// "42%"
+pub fn percent(value: u8) -> Formatted {
+    Formatted::default()
+        .num(value as u32, 1)
+        .symbol(Symbol::Percent)
+}
+
+/// Minutes as "H:MM".
+pub fn hours_minutes(minutes: u32) -> Formatted {
+    Formatted::default()
+        .num(minutes / 60, 1)
+        .symbol(Symbol::Colon)
+        .num(minutes % 60, 2)
+}
+
+/// Seconds as "MM:SS", minutes keep counting past an hour.
+pub fn minutes_seconds(seconds: u32) -> Formatted {
+    Formatted::default()
+        .num(seconds / 60, 2)
+        .symbol(Symbol::Colon)
+        .num(seconds % 60, 2)
+}
+
+/// Elapsed time at a granularity that coarsens as it grows: "42s", "17m",
+/// "4h 12m", "3d 4h".
+pub fn elapsed(seconds: u32) -> Formatted {
+    let (minutes, hours, days) = (seconds / 60, seconds / 3600, seconds / 86400);
+    let formatted = Formatted::default();
+    match seconds {
+        0..60 => formatted.num(seconds, 1).unit('s'),
+        60..3600 => formatted.num(minutes, 1).unit('m'),
+        3600..86400 => formatted
+            .num(hours, 1)
+            .unit('h')
+            .gap(4)
+            .num(minutes % 60, 1)
+            .unit('m'),
+        _ => formatted
+            .num(days, 1)
+            .unit('d')
+            .gap(4)
+            .num(hours % 24, 1)
+            .unit('h'),
+    }
+}
+
+/// Fixed point number with one decimal place, 123 -> "12.3".
+pub fn tenths(tenths: u32) -> Formatted {
+    Formatted::default()
+        .num(tenths / 10, 1)
+        .symbol(Symbol::Dot)
+        .num(tenths % 10, 1)
+}
+
+/// Deciwatts as "12.3W".
+pub fn watts(deciwatts: u32) -> Formatted {
+    tenths(deciwatts).symbol(Symbol::Watt)
+}
+
+/// Whole degrees as "54C".
+pub fn celsius(degrees: u8) -> Formatted {
+    Formatted::default().num(degrees as u32, 1).unit('C')
+}
+
+/// Byte count scaled to K/M/G/T with three significant digits, "512K", "1.2G".
+/// Always takes four digit cells so the value never shifts its neighbours.
+pub fn bytes(bytes: u64) -> Formatted {
+    const UNITS: [char; 5] = ['B', 'K', 'M', 'G', 'T'];
+    let mut value = bytes;
+    let mut rem = 0;
+    let mut unit = 0;
+    while value >= 1000 && unit < UNITS.len() - 1 {
+        rem = value % 1024;
+        value /= 1024;
+        unit += 1;
+    }
+    let formatted = if value < 10 && unit > 0 {
+        tenths((value * 10 + rem * 10 / 1024) as u32)
+    } else {
+        Formatted::default().num(value as u32, 1)
+    };
+    Formatted {
+        min_cells: 4,
+        ..formatted.unit(UNITS[unit])
+    }
+}
+
+/// "DD/MM/YY"
+pub fn date(day: u8, month: u8, year: u8) -> Formatted {
+    Formatted::default()
+        .num(day as u32, 2)
+        .symbol(Symbol::Slash)
+        .num(month as u32, 2)
+        .symbol(Symbol::Slash)
+        .num(year as u32, 2)
+}
+
+/// 12 hour clock, "09:41 AM".
+pub fn clock_12h(hour: u8, minute: u8) -> Formatted {
+    let hour_12 = match hour {
+        0 => 12,
+        13.. => hour - 12,
+        _ => hour,
+    };
+    Formatted::default()
+        .num(hour_12 as u32, 2)
+        .symbol(Symbol::Colon)
+        .num(minute as u32, 2)
+        .symbol(Symbol::Space)
+        .symbol(if hour >= 12 { Symbol::Pm } else { Symbol::Am })
+}
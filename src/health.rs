@@ -0,0 +1,171 @@
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Every module that reports its health, in the order `leanbar-ctl modules` lists them.
+pub const MODULES: [&str; 40] = [
+    "workspaces",
+    "clock",
+    "battery",
+    "power",
+    "power_profile",
+    "temperature",
+    "gpu",
+    "disk",
+    "network",
+    "wifi",
+    "ethernet",
+    "connectivity",
+    "bluetooth",
+    "idle",
+    "caffeine",
+    "dnd",
+    "night_light",
+    "rfkill",
+    "removable",
+    "failed_units",
+    "next_event",
+    "todo",
+    "mail",
+    "updates",
+    "containers",
+    "custom",
+    "status",
+    "fifo",
+    "tray",
+    "taskbar",
+    "pressure",
+    "backlight",
+    "keyboard_backlight",
+    "volume",
+    "privacy",
+    "mpris",
+    "keyboard_layout",
+    "tiling",
+    "control",
+    "dbus",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum State {
+    /// Not reported yet, the module is still starting up.
+    Pending,
+    Ok,
+    /// Running, but the last attempt to read its source failed.
+    Degraded,
+    /// Its source does not exist on this machine.
+    Disabled,
+}
+
+impl State {
+    fn as_str(self) -> &'static str {
+        match self {
+            State::Pending => "pending",
+            State::Ok => "ok",
+            State::Degraded => "degraded",
+            State::Disabled => "disabled",
+        }
+    }
+}
+
+struct ModuleStatus {
+    name: &'static str,
+    state: State,
+    last_update: Option<Instant>,
+    last_error: Option<String>,
+}
+
+static STATUS: Mutex<Vec<ModuleStatus>> = Mutex::new(Vec::new());
+
+fn with_status(name: &'static str, f: impl FnOnce(&mut ModuleStatus)) {
+    let Ok(mut guard) = STATUS.lock() else {
+        return;
+    };
+    let idx = match guard.iter().position(|m| m.name == name) {
+        Some(idx) => idx,
+        None => {
+            guard.push(ModuleStatus {
+                name,
+                state: State::Pending,
+                last_update: None,
+                last_error: None,
+            });
+            guard.len() - 1
+        }
+    };
+    f(&mut guard[idx]);
+}
+
+/// The module read its source successfully.
+pub fn ok(name: &'static str) {
+    with_status(name, |m| {
+        m.state = State::Ok;
+        m.last_update = Some(Instant::now());
+    });
+}
+
+/// The module failed to read its source, the error is kept until the next report.
+pub fn degraded(name: &'static str, error: impl Into<String>) {
+    let error = error.into();
+    with_status(name, |m| {
+        // Only log when something new goes wrong, failing reads repeat every tick
+        if m.state != State::Degraded || m.last_error.as_deref() != Some(error.as_str()) {
+            eprintln!("[Health] {} degraded: {}", name, error);
+        }
+        m.state = State::Degraded;
+        m.last_error = Some(error);
+    });
+}
+
+/// The module has nothing to show on this machine and stopped.
+pub fn disabled(name: &'static str, reason: impl Into<String>) {
+    with_status(name, |m| {
+        m.state = State::Disabled;
+        m.last_error = Some(reason.into());
+    });
+}
+
+/// Table of every module for `leanbar-ctl modules`.
+pub fn report() -> String {
+    let guard = STATUS.lock().ok();
+    let mut out = format!(
+        "{:<16} {:<9} {:<12} {}\n",
+        "MODULE", "STATE", "LAST UPDATE", "LAST ERROR"
+    );
+    for name in MODULES {
+        let status = guard
+            .as_ref()
+            .and_then(|g| g.iter().find(|m| m.name == name));
+        let state = status.map_or(State::Pending, |m| m.state);
+        let last_update = status
+            .and_then(|m| m.last_update)
+            .map(|t| format!("{}s ago", t.elapsed().as_secs()))
+            .unwrap_or_else(|| "never".to_string());
+        let last_error = status.and_then(|m| m.last_error.as_deref()).unwrap_or("");
+        let _ = writeln!(
+            out,
+            "{:<16} {:<9} {:<12} {}",
+            name,
+            state.as_str(),
+            last_update,
+            last_error
+        );
+    }
+    out
+}
+
+/// Name, state and last error of every module, in the order of `MODULES`.
+pub fn states() -> Vec<(&'static str, &'static str, String)> {
+    let guard = STATUS.lock().ok();
+    MODULES
+        .into_iter()
+        .map(|name| {
+            let status = guard
+                .as_ref()
+                .and_then(|g| g.iter().find(|m| m.name == name));
+            let state = status.map_or(State::Pending, |m| m.state);
+            let last_error = status.and_then(|m| m.last_error.clone());
+            (name, state.as_str(), last_error.unwrap_or_default())
+        })
+        .collect()
+}
@@ -0,0 +1,137 @@
+//! Seat idle tracking for the idle module. A short ext-idle-notify timeout marks
+//! when input stopped, the polling thread turns that into the time left until the
+//! idle daemon's configured action.
+//!
+//! The caffeine module keeps the seat from going idle at all with an idle
+//! inhibitor on one of the bars, which are always visible.
+
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+use wayland_protocols::wp::idle_inhibit::zv1::client::{
+    zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+};
+
+use crate::{IDLE_INHIBITED, IDLE_SINCE_S, app_state::AppState, config, health};
+
+/// Inactivity before the seat counts as idle. Kept short so the countdown starts
+/// about when input stops, the difference is added back when it fires.
+const IDLE_PROBE_MS: u32 = 1000;
+
+#[derive(Default)]
+pub struct IdleState {
+    pub notifier: Option<ExtIdleNotifierV1>,
+    notification: Option<ExtIdleNotificationV1>,
+    pub inhibit_manager: Option<ZwpIdleInhibitManagerV1>,
+    /// The bar it was created on, with its surface it stops inhibiting.
+    inhibitor: Option<(u32, ZwpIdleInhibitorV1)>,
+}
+
+impl AppState {
+    /// Starts watching the seat once both it and the notifier are bound, called
+    /// after the initial globals are known.
+    pub fn watch_idle(&mut self, qh: &QueueHandle<Self>) {
+        if config::get().idle_timeout == 0 {
+            health::disabled("idle", "idle_timeout not set");
+            return;
+        }
+        let (Some(notifier), Some(seat)) = (&self.idle.notifier, &self.seat) else {
+            health::disabled("idle", "compositor lacks ext_idle_notifier_v1");
+            return;
+        };
+        if self.idle.notification.is_none() {
+            self.idle.notification =
+                Some(notifier.get_idle_notification(IDLE_PROBE_MS, seat, qh, ()));
+        }
+        health::ok("idle");
+    }
+
+    /// Shows the caffeine module if the compositor can inhibit idling, called
+    /// after the initial globals are known.
+    pub fn offer_idle_inhibitor(&mut self) {
+        if self.idle.inhibit_manager.is_none() {
+            health::disabled("caffeine", "compositor lacks zwp_idle_inhibit_manager_v1");
+            return;
+        }
+        IDLE_INHIBITED.store(0, Ordering::Release);
+        health::ok("caffeine");
+    }
+
+    /// Creates the inhibitor on the clicked bar, or destroys it.
+    pub(crate) fn toggle_idle_inhibitor(&mut self, bar: u32) {
+        if let Some((_, inhibitor)) = self.idle.inhibitor.take() {
+            inhibitor.destroy();
+        } else {
+            self.inhibit_idle_on(bar);
+        }
+        self.store_idle_inhibited();
+    }
+
+    /// Moves the inhibitor off a bar that is going away, onto any other.
+    pub(crate) fn rehome_idle_inhibitor(&mut self, removed: u32) {
+        if self
+            .idle
+            .inhibitor
+            .as_ref()
+            .is_none_or(|(bar, _)| *bar != removed)
+        {
+            return;
+        }
+        if let Some((_, inhibitor)) = self.idle.inhibitor.take() {
+            inhibitor.destroy();
+        }
+        if let Some(bar) = self.bar_ids().into_iter().find(|id| *id != removed) {
+            self.inhibit_idle_on(bar);
+        }
+        self.store_idle_inhibited();
+    }
+
+    fn inhibit_idle_on(&mut self, bar: u32) {
+        let (Some(manager), Some(surface), Some(qh)) =
+            (&self.idle.inhibit_manager, self.bar_surface(bar), &self.qh)
+        else {
+            return;
+        };
+        self.idle.inhibitor = Some((bar, manager.create_inhibitor(&surface, qh, ())));
+    }
+
+    fn store_idle_inhibited(&mut self) {
+        let inhibited = self.idle.inhibitor.is_some() as u8;
+        if IDLE_INHIBITED.swap(inhibited, Ordering::AcqRel) != inhibited {
+            self.redraw_and_commit();
+        }
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        notification: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(notification, event.opcode(), &event);
+        let since = match event {
+            ext_idle_notification_v1::Event::Idled => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                now.saturating_sub((IDLE_PROBE_MS / 1000) as u64)
+            }
+            ext_idle_notification_v1::Event::Resumed => 0,
+            _ => return,
+        };
+        IDLE_SINCE_S.store(since, Ordering::Release);
+    }
+}
+
+wayland_client::delegate_noop!(AppState: ignore ExtIdleNotifierV1);
+wayland_client::delegate_noop!(AppState: ignore ZwpIdleInhibitManagerV1);
+wayland_client::delegate_noop!(AppState: ignore ZwpIdleInhibitorV1);
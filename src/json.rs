@@ -0,0 +1,257 @@
+//! Just enough JSON for what status commands print, Waybar's custom module
+//! lines and the i3bar protocol.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Arrays and objects nested deeper make the text invalid rather than run the
+/// parser out of stack on a command's garbage.
+const MAX_DEPTH: usize = 32;
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    List(Vec<Json>),
+    /// Fields in the order they were written.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// None unless all of `text` is one value.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut chars = text.chars().peekable();
+        skip_whitespace(&mut chars);
+        let value = parse_value(&mut chars, 0)?;
+        skip_whitespace(&mut chars).is_none().then_some(value)
+    }
+
+    /// The field `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+/// `text` as a JSON string, quotes included.
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns the next character without taking it.
+fn skip_whitespace(chars: &mut Peekable<Chars>) -> Option<char> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.peek().copied()
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Option<()> {
+    (chars.next()? == expected).then_some(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Json> {
+    match chars.peek()? {
+        '[' | '{' if depth >= MAX_DEPTH => None,
+        '"' => parse_string(chars).map(Json::Text),
+        '[' => parse_list(chars, ']', |chars| parse_value(chars, depth + 1)).map(Json::List),
+        '{' => parse_list(chars, '}', |chars| {
+            let key = parse_string(chars)?;
+            skip_whitespace(chars);
+            expect(chars, ':')?;
+            skip_whitespace(chars);
+            Some((key, parse_value(chars, depth + 1)?))
+        })
+        .map(Json::Object),
+        _ => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-.".contains(*c)) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "null" => Some(Json::Null),
+                "true" => Some(Json::Bool(true)),
+                "false" => Some(Json::Bool(false)),
+                _ => word.parse().ok().map(Json::Number),
+            }
+        }
+    }
+}
+
+/// The items of an array or the fields of an object, from the opening bracket
+/// through `close`.
+fn parse_list<T>(
+    chars: &mut Peekable<Chars>,
+    close: char,
+    parse_item: impl Fn(&mut Peekable<Chars>) -> Option<T>,
+) -> Option<Vec<T>> {
+    chars.next();
+    let mut items = Vec::new();
+    if skip_whitespace(chars) == Some(close) {
+        chars.next();
+        return Some(items);
+    }
+    loop {
+        skip_whitespace(chars);
+        items.push(parse_item(chars)?);
+        match skip_whitespace(chars)? {
+            ',' => {
+                chars.next();
+            }
+            c if c == close => {
+                chars.next();
+                return Some(items);
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    expect(chars, '"')?;
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                'n' => s.push('\n'),
+                't' => s.push('\t'),
+                'r' => s.push('\r'),
+                'b' => s.push('\u{8}'),
+                'f' => s.push('\u{c}'),
+                'u' => {
+                    let unit = parse_hex(chars)?;
+                    let c = match unit {
+                        // Characters past the BMP come as a high and a low
+                        // surrogate, each escaped
+                        0xd800..=0xdbff => {
+                            let mut ahead = chars.clone();
+                            match (ahead.next(), ahead.next(), parse_hex(&mut ahead)) {
+                                (Some('\\'), Some('u'), Some(low @ 0xdc00..=0xdfff)) => {
+                                    *chars = ahead;
+                                    char::from_u32(
+                                        0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00),
+                                    )
+                                }
+                                _ => None,
+                            }
+                        }
+                        _ => char::from_u32(unit),
+                    };
+                    // A lone half of a pair
+                    s.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                c => s.push(c),
+            },
+            c => s.push(c),
+        }
+    }
+}
+
+/// The four hex digits of a `\u` escape.
+fn parse_hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let hex: String = chars.by_ref().take(4).collect();
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let json = Json::parse(r#" {"a": [1, -2.5e1, {"b": null}], "c": true} "#).unwrap();
+        let Some(Json::List(items)) = json.get("a") else {
+            panic!("a is not a list");
+        };
+        assert_eq!(items[0].as_f64(), Some(1.0));
+        assert_eq!(items[1].as_f64(), Some(-25.0));
+        assert!(matches!(items[2].get("b"), Some(Json::Null)));
+        assert_eq!(json.get("c").and_then(Json::as_bool), Some(true));
+        assert!(Json::parse("[[]]").is_some());
+        assert!(Json::parse("{}").is_some());
+    }
+
+    #[test]
+    fn rejects_trailing_and_unterminated_text() {
+        assert!(Json::parse("[1] 2").is_none());
+        assert!(Json::parse("[1,").is_none());
+        assert!(Json::parse(r#"{"a" 1}"#).is_none());
+        assert!(Json::parse(r#""open"#).is_none());
+    }
+
+    #[test]
+    fn caps_nesting() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_some());
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_none());
+        // Deep enough to overflow the stack without the cap
+        assert!(Json::parse(&nested(100_000)).is_none());
+        assert!(Json::parse(&"{\"a\":".repeat(MAX_DEPTH + 1)).is_none());
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        let text = |json: &str| Json::parse(json).and_then(|j| j.as_str().map(str::to_string));
+        assert_eq!(
+            text(r#""a\"b\\c\/d\n\t\r\b\f""#).as_deref(),
+            Some("a\"b\\c/d\n\t\r\u{8}\u{c}")
+        );
+        assert_eq!(text(r#""\u00e9\u20AC""#).as_deref(), Some("é€"));
+        assert!(text(r#""\u12""#).is_none());
+        assert!(text(r#""\u12g4""#).is_none());
+    }
+
+    #[test]
+    fn joins_surrogate_pairs() {
+        let text = |json: &str| Json::parse(json).and_then(|j| j.as_str().map(str::to_string));
+        assert_eq!(text(r#""\ud83d\ude00""#).as_deref(), Some("😀"));
+        // Lone halves, the character after one is kept
+        assert_eq!(text(r#""\ud83dx""#).as_deref(), Some("\u{fffd}x"));
+        assert_eq!(text(r#""\ud83d\n""#).as_deref(), Some("\u{fffd}\n"));
+        assert_eq!(text(r#""\ude00""#).as_deref(), Some("\u{fffd}"));
+    }
+
+    #[test]
+    fn quotes_round_trip() {
+        let original = "say \"hi\"\\\n\u{1}";
+        let quoted = quote(original);
+        assert_eq!(Json::parse(&quoted).unwrap().as_str(), Some(original));
+    }
+}
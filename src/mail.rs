@@ -0,0 +1,25 @@
+//! Unread mail for the mail module. The Maildir and IMAP workers each count
+//! their own accounts, what the bar shows is kept here per account label.
+
+use std::sync::atomic::Ordering;
+
+use crate::{MAIL_ACCOUNTS, MAIL_UNREAD};
+
+/// Sets an account's unread count and the total with it, returns whether
+/// anything changed.
+pub fn store_unread(label: &str, unread: u32) -> bool {
+    let Ok(mut accounts) = MAIL_ACCOUNTS.lock() else {
+        return false;
+    };
+    match accounts.iter_mut().find(|(l, _)| l == label) {
+        Some((_, current)) if *current == unread => return false,
+        Some((_, current)) => *current = unread,
+        None => accounts.push((label.to_string(), unread)),
+    }
+    let total = accounts
+        .iter()
+        .fold(0u32, |sum, (_, n)| sum.saturating_add(*n))
+        .min(u32::MAX - 1);
+    MAIL_UNREAD.store(total, Ordering::Release);
+    true
+}
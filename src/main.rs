@@ -1,15 +1,45 @@
-use rustix::event::{EventfdFlags, PollFd, PollFlags, eventfd, poll};
+#[cfg(not(target_os = "openbsd"))]
+use rustix::event::{EventfdFlags, eventfd};
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
 use rustix::io::{read, write};
-use std::fs;
+use std::collections::VecDeque;
 use std::os::fd::OwnedFd;
-use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU16, Ordering};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU16, AtomicU32, AtomicU64};
 
-use wayland_client::Connection;
+use wayland_client::{Connection, EventQueue};
 
 mod app_state;
+mod backlight;
+mod calendar;
+mod color;
+mod config;
+mod dbus;
+mod diagnostics;
 mod error;
+mod evdev;
 mod font_renderer;
+mod format;
+mod health;
+mod idle;
+mod json;
+mod mail;
+#[cfg(target_os = "linux")]
+mod netlink;
+mod night_light;
+mod platform;
+mod pointer;
+mod preview;
+mod pulse;
+mod quality;
+mod rate;
+mod sd_notify;
+mod signals;
+mod taskbar;
 mod threads;
+mod timer;
+mod tray_menu;
+mod tz;
 
 // Colors are 0xAARRGGBB
 pub const COLOR_WS_FOCUSED: u32 = 0xffffffff;
@@ -17,54 +47,400 @@ pub const COLOR_WS_OPEN: u32 = 0xffcba6f7;
 pub const COLOR_TIME: u32 = 0xffcba6f7;
 pub const COLOR_DATE: u32 = 0xff74c7ec;
 pub const COLOR_BAT: u32 = 0xffa6e3a1;
+pub const COLOR_BAT_WARNING: u32 = 0xfff9e2af;
+pub const COLOR_BAT_CRITICAL: u32 = 0xfff38ba8;
+pub const COLOR_POWER: u32 = 0xfff9e2af;
+pub const COLOR_POWER_PROFILE: u32 = 0xfffab387;
+pub const COLOR_LAYOUT: u32 = 0xff89b4fa;
+pub const COLOR_TILING: u32 = 0xffcba6f7;
+pub const COLOR_TEMP_COOL: u32 = 0xffa6e3a1;
+pub const COLOR_TEMP_WARM: u32 = 0xfff9e2af;
+pub const COLOR_TEMP_HOT: u32 = 0xfff38ba8;
+pub const COLOR_GPU: u32 = 0xff94e2d5;
+pub const COLOR_DISK: u32 = 0xfffab387;
+pub const COLOR_NET: u32 = 0xff89dceb;
+pub const COLOR_WIFI: u32 = 0xffb4befe;
+pub const COLOR_ETHERNET: u32 = 0xff94e2d5;
+pub const COLOR_CONNECTIVITY: u32 = 0xffa6e3a1;
+pub const COLOR_BLUETOOTH: u32 = 0xff89b4fa;
+pub const COLOR_IDLE: u32 = 0xfff5c2e7;
+pub const COLOR_CAFFEINE: u32 = 0xfffab387;
+pub const COLOR_DND: u32 = 0xffcba6f7;
+pub const COLOR_NIGHT_LIGHT: u32 = 0xfff9e2af;
+pub const COLOR_RFKILL: u32 = 0xff89dceb;
+pub const COLOR_REMOVABLE: u32 = 0xfff2cdcd;
+pub const COLOR_FAILED_UNITS: u32 = 0xfff38ba8;
+pub const COLOR_NEXT_EVENT: u32 = 0xffb4befe;
+pub const COLOR_TODO: u32 = 0xfffab387;
+pub const COLOR_MAIL: u32 = 0xff94e2d5;
+pub const COLOR_UPDATES: u32 = 0xff89b4fa;
+pub const COLOR_CONTAINERS: u32 = 0xff74c7ec;
+pub const COLOR_CUSTOM: u32 = 0xffcdd6f4;
+pub const COLOR_STATUS: u32 = 0xffcdd6f4;
+pub const COLOR_FIFO: u32 = 0xfff9e2af;
+pub const COLOR_LEMONBAR: u32 = 0xffcdd6f4;
+pub const COLOR_TRAY: u32 = 0xffcdd6f4;
+pub const COLOR_TASKBAR: u32 = 0xffa6adc8;
+pub const COLOR_TASKBAR_ACTIVE: u32 = 0xffffffff;
+pub const COLOR_SINCE: u32 = 0xfff2cdcd;
+pub const COLOR_TIMER: u32 = 0xfff5c2e7;
+pub const COLOR_PRESSURE: u32 = 0xffa6adc8;
+pub const COLOR_BACKLIGHT: u32 = 0xfff9e2af;
+pub const COLOR_KBD_BACKLIGHT: u32 = 0xffeba0ac;
+pub const COLOR_VOLUME: u32 = 0xff74c7ec;
+pub const COLOR_MIC_MUTED: u32 = 0xfff38ba8;
+pub const COLOR_PRIVACY_MIC: u32 = 0xfffab387;
+pub const COLOR_PRIVACY_CAMERA: u32 = 0xffa6e3a1;
+pub const COLOR_PRIVACY_SCREEN: u32 = 0xfff38ba8;
+pub const COLOR_AUDIO_DEVICE: u32 = 0xff89b4fa;
+pub const COLOR_MPRIS: u32 = 0xfff5e0dc;
+pub const COLOR_TITLE: u32 = 0xffcdd6f4;
+pub const COLOR_HIGHLIGHT: u32 = 0xfffab387;
+pub const COLOR_REGION_OUTLINE: u32 = 0xfff38ba8;
+pub const COLOR_CAPSULE_BG: u32 = 0xe01e1e2e;
+pub const COLOR_TEXT_EFFECT: u32 = 0xb4000000;
 
 use app_state::AppState;
 use error::LeanbarError;
 
-pub static WORKSPACES: [AtomicBool; 10] = [
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-    AtomicBool::new(false),
-];
-pub static ACTIVE_WORKSPACE: AtomicU8 = AtomicU8::new(1);
+/// A Hyprland workspace. Named workspaces get negative IDs from Hyprland and
+/// special workspaces have names starting with "special".
+#[derive(Clone, PartialEq, Eq)]
+pub struct Workspace {
+    pub id: i32,
+    pub name: String,
+    pub monitor: String,
+    /// Windows on the workspace, only kept up to date for the minimap.
+    pub windows: u16,
+    /// Holds a fullscreen window, only kept up to date for `fullscreen_overlay`.
+    pub fullscreen: bool,
+}
+
+impl Workspace {
+    pub fn is_special(&self) -> bool {
+        self.name.starts_with("special")
+    }
+}
+
+pub static WORKSPACES: Mutex<Vec<Workspace>> = Mutex::new(Vec::new());
+pub static ACTIVE_WORKSPACE: AtomicI32 = AtomicI32::new(1);
+
+/// A Hyprland monitor and the workspace currently shown on it.
+pub struct MonitorWorkspace {
+    pub id: u8,
+    pub name: String,
+    pub active_ws: i32,
+}
+pub static MONITORS: Mutex<Vec<MonitorWorkspace>> = Mutex::new(Vec::new());
+/// Name of the monitor holding keyboard focus, empty until Hyprland reports one.
+pub static FOCUSED_MONITOR: Mutex<String> = Mutex::new(String::new());
+
+/// A StatusNotifierItem as the tray module shows it.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct TrayItem {
+    pub service: String,
+    pub path: String,
+    /// Passive items are left out.
+    pub hidden: bool,
+    /// `tray::ICON_SIZE` squared premultiplied ARGB, empty without a pixmap.
+    pub pixels: Vec<u32>,
+    /// Drawn instead of a missing pixmap.
+    pub label: char,
+    /// Object path of its com.canonical.dbusmenu, empty without one.
+    pub menu: String,
+}
+
+/// Items shown in the tray, in the order they registered.
+pub static TRAY: Mutex<Vec<TrayItem>> = Mutex::new(Vec::new());
+pub static TRAY_GENERATION: AtomicU32 = AtomicU32::new(0); // Bumped whenever TRAY changes
+
+/// A window as the taskbar module shows it.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct TaskbarEntry {
+    /// The app id, or the title without one.
+    pub label: String,
+    pub activated: bool,
+    pub minimized: bool,
+}
+
+/// Open windows, in the order they were opened.
+pub static TASKBAR: Mutex<Vec<TaskbarEntry>> = Mutex::new(Vec::new());
+pub static TASKBAR_GENERATION: AtomicU32 = AtomicU32::new(0); // Bumped whenever TASKBAR changes
+
+/// A block of `status_command`'s i3bar protocol output.
+#[derive(Clone, PartialEq, Default)]
+pub struct StatusBlock {
+    pub full_text: String,
+    /// Drawn instead when the blocks don't fit, empty falls back to `full_text`.
+    pub short_text: String,
+    /// 0: COLOR_STATUS
+    pub color: u32,
+    pub urgent: bool,
+    /// Sent back with clicks so the command knows which block it was.
+    pub name: String,
+    pub instance: String,
+}
+
+/// The blocks of the status module, left to right.
+pub static STATUS_BLOCKS: Mutex<Vec<StatusBlock>> = Mutex::new(Vec::new());
+pub static STATUS_GENERATION: AtomicU32 = AtomicU32::new(0); // Bumped whenever STATUS_BLOCKS changes
+
+/// A run of `--lemonbar` text with one alignment and color.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LemonSegment {
+    /// See lemonbar::LEFT, CENTER and RIGHT
+    pub align: u8,
+    /// 0: COLOR_LEMONBAR
+    pub color: u32,
+    pub text: String,
+}
+
+/// The last line read by `--lemonbar`, in the order it was written.
+pub static LEMONBAR: Mutex<Vec<LemonSegment>> = Mutex::new(Vec::new());
+pub static LEMONBAR_GENERATION: AtomicU32 = AtomicU32::new(0); // Bumped whenever LEMONBAR changes
+
+/// Layout of the main keyboard, `code` is the short name shown on the bar (e.g. "US").
+pub struct KeyboardLayout {
+    pub device: String,
+    pub code: String,
+    /// The focused window uses a layout other than the default one.
+    pub differs_from_default: bool,
+}
+pub static KEYBOARD_LAYOUT: Mutex<KeyboardLayout> = Mutex::new(KeyboardLayout {
+    device: String::new(),
+    code: String::new(),
+    differs_from_default: false,
+});
+
+/// Hyprland tiling layout of the active workspace.
+pub struct TilingLayout {
+    /// "dwindle", "master" or empty when unknown.
+    pub name: String,
+    /// Windows in the master area, only tracked for the master layout.
+    pub masters: u8,
+    /// Tiled windows on the active workspace.
+    pub tiled: u8,
+}
+pub static TILING_LAYOUT: Mutex<TilingLayout> = Mutex::new(TilingLayout {
+    name: String::new(),
+    masters: 0,
+    tiled: 0,
+});
+
+/// Title of the focused window, empty when nothing is focused.
+pub static WINDOW_TITLE: Mutex<String> = Mutex::new(String::new());
+/// Class (app ID) of the focused window, picks its icon from `window_icons`.
+pub static WINDOW_CLASS: Mutex<String> = Mutex::new(String::new());
 
 pub static TIME_HOURS: AtomicU8 = AtomicU8::new(0);
 pub static TIME_MINUTES: AtomicU8 = AtomicU8::new(0);
+pub static UTC_OFFSET_S: AtomicI32 = AtomicI32::new(i32::MIN); // i32::MIN: Not read yet
+/// Shown after the clock: the local time zone abbreviation with `clock_timezone`,
+/// followed by "?" while the time is the fallback rather than confirmed local.
+pub static TIMEZONE: Mutex<String> = Mutex::new(String::new());
 pub static DATE_DAY: AtomicU8 = AtomicU8::new(0);
 pub static DATE_MONTH: AtomicU8 = AtomicU8::new(0);
 pub static DATE_YEAR: AtomicU8 = AtomicU8::new(0);
 pub static BATTERY_PERCENT: AtomicU8 = AtomicU8::new(100);
 pub static BATTERY_STATE: AtomicU8 = AtomicU8::new(255); // 0: Unknown, 1: Discharging, 2: Charging, 3: Full, 255: No Battery
 pub static BATTERY_ESTIMATE_M: AtomicU16 = AtomicU16::new(0);
+pub static BATTERY_RATE_DW: AtomicU16 = AtomicU16::new(0); // Deciwatts in or out of the battery, 0: Unknown
+pub static BATTERY_BLINK: AtomicBool = AtomicBool::new(false); // Blinking battery currently drawn dimmed
+pub static BATTERY_HEALTH: AtomicU8 = AtomicU8::new(255); // Full against design capacity in percent, 255: Unknown
+pub static BATTERY_CYCLES: AtomicU16 = AtomicU16::new(u16::MAX); // u16::MAX: Not counted
+pub static AC_ONLINE: AtomicU8 = AtomicU8::new(255); // 0: Unplugged, 1: Plugged in, 255: No adapter
+pub static RAPL_POWER_DW: AtomicU16 = AtomicU16::new(u16::MAX); // Package power in deciwatts, u16::MAX: No RAPL
+pub static POWER_PROFILE: AtomicU8 = AtomicU8::new(255); // Index into power_profile::PROFILES, 255: No daemon
+pub static TEMPERATURE_C: AtomicU8 = AtomicU8::new(255); // Degrees Celsius, 255: No sensor
+pub static CPU_THROTTLED: AtomicBool = AtomicBool::new(false);
+pub static GPU_BUSY_PERCENT: AtomicU8 = AtomicU8::new(255); // 255: No GPU
+pub static GPU_VRAM_USED_MB: AtomicU32 = AtomicU32::new(u32::MAX); // u32::MAX: Unknown
+pub static DISK_READ_KBS: AtomicU32 = AtomicU32::new(u32::MAX); // KiB/s, u32::MAX: No disk
+pub static DISK_WRITE_KBS: AtomicU32 = AtomicU32::new(u32::MAX); // KiB/s, u32::MAX: No disk
+/// "some" avg10 of cpu, memory and io pressure in tenths of a percent, u16::MAX: No PSI.
+pub static PRESSURE_SOME: [AtomicU16; 3] = [const { AtomicU16::new(u16::MAX) }; 3];
+pub static BACKLIGHT_PERCENT: AtomicU8 = AtomicU8::new(255); // 255: No backlight
+pub static KBD_BACKLIGHT_LEVEL: AtomicU8 = AtomicU8::new(255); // 255: No keyboard backlight
+pub static KBD_BACKLIGHT_LEVELS: AtomicU8 = AtomicU8::new(0); // Levels above off
+pub static VOLUME_PERCENT: AtomicU8 = AtomicU8::new(255); // Default sink, 255: No audio server
+pub static VOLUME_MUTED: AtomicBool = AtomicBool::new(false);
+pub static AUDIO_DEVICE: Mutex<String> = Mutex::new(String::new()); // Default sink label, empty: Unknown
+pub static MIC_MUTED: AtomicU8 = AtomicU8::new(255); // Default source, 0: Live, 1: Muted, 255: No source
+pub static PRIVACY: AtomicU8 = AtomicU8::new(0); // Bits of privacy::MICROPHONE, CAMERA and SCREEN in use
+pub static MPRIS_STATUS: AtomicU8 = AtomicU8::new(255); // 1: Playing, 2: Paused, 255: No player
+/// Artist and title of the active player, the visible part while it scrolls.
+pub static MPRIS_TEXT: Mutex<String> = Mutex::new(String::new());
+pub static PRESSURE_FULL_MAX: AtomicU16 = AtomicU16::new(0); // Highest "full" avg10 of the three, tenths of a percent
+
+/// Samples kept for the network sparkline, one per second.
+pub const NET_HISTORY_LEN: usize = 30;
+/// Recent network throughput (received + sent) in KiB/s, oldest first.
+pub static NET_HISTORY: Mutex<VecDeque<u32>> = Mutex::new(VecDeque::new());
+pub static NET_SAMPLES: AtomicU32 = AtomicU32::new(0); // Samples taken so far, 0: No network
+/// SSID of the connected wifi network, empty when unknown.
+pub static WIFI_SSID: Mutex<String> = Mutex::new(String::new());
+pub static WIFI_SIGNAL: AtomicU8 = AtomicU8::new(255); // Percent, 255: Not connected
+pub static ETHERNET_LINK: AtomicU8 = AtomicU8::new(255); // 0: No carrier, 1: Up, 255: No wired interface
+/// IPv4 address of the wired interface, empty until one is assigned.
+pub static ETHERNET_ADDRESS: Mutex<String> = Mutex::new(String::new());
+pub static NM_CONNECTIVITY: AtomicU8 = AtomicU8::new(255); // networkmanager::UNKNOWN to FULL, 255: No NetworkManager
+/// Name of NetworkManager's primary connection, empty without one.
+pub static NM_CONNECTION: Mutex<String> = Mutex::new(String::new());
+pub static BLUETOOTH_POWERED: AtomicU8 = AtomicU8::new(255); // 0: Off, 1: On, 255: No adapter
+pub static BLUETOOTH_CONNECTED: AtomicU8 = AtomicU8::new(0); // Connected devices
+/// Name of the first connected Bluetooth device, empty with none.
+pub static BLUETOOTH_DEVICE: Mutex<String> = Mutex::new(String::new());
+pub static BLUETOOTH_BATTERY: AtomicU8 = AtomicU8::new(255); // Percent of that device, 255: Not reported
+/// Unix time the seat went idle, 0 while there is input. Set by the main thread.
+pub static IDLE_SINCE_S: AtomicU64 = AtomicU64::new(0);
+pub static IDLE_REMAINING_S: AtomicU32 = AtomicU32::new(0); // Seconds until the idle action
+pub static IDLE_DAEMON: AtomicU8 = AtomicU8::new(255); // 0: Not running, 1: Running, 255: Module off
+pub static DND: AtomicU8 = AtomicU8::new(255); // 0: Off, 1: Notifications held back, 255: No swaync or mako
+pub static NIGHT_LIGHT: AtomicU8 = AtomicU8::new(0); // 0: No wlsunset or gammastep, 1: Neutral, 2: Warm
+pub static RFKILL: AtomicU8 = AtomicU8::new(255); // Bits of rfkill::WIFI, BLUETOOTH, HARD and AIRPLANE, 255: No radios
+pub static REMOVABLE_MOUNTS: AtomicU8 = AtomicU8::new(0); // Mounted filesystems of removable drives
+pub static FAILED_UNITS: AtomicU16 = AtomicU16::new(u16::MAX); // System and user units together, u16::MAX: No systemd
+/// Start and title of the next calendar event, e.g. "tue 14:00 standup".
+pub static NEXT_EVENT: Mutex<String> = Mutex::new(String::new());
+pub static NEXT_EVENT_SOON: AtomicU8 = AtomicU8::new(255); // 0: Later, 1: Starting soon or under way, 255: No upcoming event
+pub static TODO_OPEN: AtomicU16 = AtomicU16::new(u16::MAX); // Open items of the todo files, u16::MAX: No todo file
+pub static UPDATES_PENDING: AtomicU16 = AtomicU16::new(u16::MAX); // Package updates, u16::MAX: Not checked yet
+pub static CONTAINERS_RUNNING: AtomicU16 = AtomicU16::new(u16::MAX); // u16::MAX: No container engine
+/// Last line of `custom_command`, or the `text` of its JSON.
+pub static CUSTOM_TEXT: Mutex<String> = Mutex::new(String::new());
+/// The `tooltip` of `custom_command`'s JSON, shown while hovered.
+pub static CUSTOM_TOOLTIP: Mutex<String> = Mutex::new(String::new());
+/// Last line written to `$XDG_RUNTIME_DIR/leanbar/custom.fifo`.
+pub static FIFO_TEXT: Mutex<String> = Mutex::new(String::new());
+pub static CUSTOM_COLOR: AtomicU32 = AtomicU32::new(0); // From `custom_colors` by class, 0: COLOR_CUSTOM
+pub static MAIL_UNREAD: AtomicU32 = AtomicU32::new(u32::MAX); // Unread messages of every mail account, u32::MAX: No accounts
+/// Label and unread count of every mail account, in the order they were first
+/// counted. Set through `mail::store_unread`.
+pub static MAIL_ACCOUNTS: Mutex<Vec<(String, u32)>> = Mutex::new(Vec::new());
+pub static IDLE_INHIBITED: AtomicU8 = AtomicU8::new(255); // 0: Off, 1: Inhibited, 255: No inhibit manager
+/// Unix time the "time since" module counts from, 0: Hidden. Set from the config
+/// and the control socket.
+pub static SINCE_S: AtomicU64 = AtomicU64::new(0);
+pub static SINCE_ELAPSED_S: AtomicU32 = AtomicU32::new(u32::MAX); // Rounded to what is shown, u32::MAX: Hidden
+pub static TIMER_S: AtomicU32 = AtomicU32::new(u32::MAX); // Stopwatch elapsed or countdown left, u32::MAX: Hidden
+pub static TIMER_STATE: AtomicU8 = AtomicU8::new(0); // See timer::STOPPED, RUNNING and FINISHED
 
 pub fn ping_main_thread(fd: &OwnedFd) {
     let _ = write(fd, &1u64.to_ne_bytes());
 }
 
+/// A wakeup, the end to poll and the end to ping it through (worker threads
+/// ping the event loop's). The same eventfd where there is one.
+#[cfg(not(target_os = "openbsd"))]
+pub fn wake_fds() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let fd = eventfd(0, EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK)?;
+    Ok((fd.try_clone()?, fd))
+}
+
+/// A wakeup, the end to poll and the end to ping it through (worker threads
+/// ping the event loop's). A pipe where there is no eventfd.
+#[cfg(target_os = "openbsd")]
+pub fn wake_fds() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let (reader, writer) = std::io::pipe()?;
+    let (reader, writer) = (OwnedFd::from(reader), OwnedFd::from(writer));
+    for fd in [&reader, &writer] {
+        rustix::fs::fcntl_setfl(
+            fd,
+            rustix::fs::fcntl_getfl(fd)? | rustix::fs::OFlags::NONBLOCK,
+        )?;
+    }
+    Ok((reader, writer))
+}
+
+/// `EventQueue::roundtrip`, reporting a protocol error that made it fail the
+/// way the event loop does.
+fn roundtrip(
+    conn: &Connection,
+    event_queue: &mut EventQueue<AppState>,
+    state: &mut AppState,
+) -> Result<(), LeanbarError> {
+    if let Err(e) = event_queue.roundtrip(state) {
+        if let Some(err) = conn.protocol_error() {
+            diagnostics::report_protocol_error(&err, &state.event_log);
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), LeanbarError> {
     let args: Vec<String> = std::env::args().collect();
     if font_renderer::maybe_run_builder_mode(&args)? {
         return Ok(());
     }
+    if preview::maybe_run_preview_mode(&args)? {
+        return Ok(());
+    }
+    if preview::maybe_run_module_preview(&args)? {
+        return Ok(());
+    }
+    if threads::control::maybe_run_client(&args)? {
+        return Ok(());
+    }
 
     println!("Starting leanbar...");
+    let exit_fd = signals::start();
 
-    let font_path = "/usr/share/fonts/noto/NotoSans-Regular.ttf";
-    let glyph_cache = font_renderer::GlyphCache::load_or_build(font_path, 15.0).ok();
-    if glyph_cache.is_none() {
-        eprintln!("Failed to load font. Make sure the path is correct.");
+    // Only the clock is read up front, everything slower is initialized by the
+    // worker threads while the font loads and the Wayland connection comes up
+    threads::poll::update_clock();
+    let (wake_read_fd, wake_fd) = wake_fds()?;
+    threads::poll::start(wake_fd.try_clone()?);
+    threads::upower::start(wake_fd.try_clone()?);
+    threads::power_profile::start(wake_fd.try_clone()?);
+    backlight::start(wake_fd.try_clone()?);
+    threads::hyprland::start(wake_fd.try_clone()?);
+    threads::gpu::start(wake_fd.try_clone()?);
+    #[cfg(target_os = "linux")]
+    {
+        threads::wifi::start(wake_fd.try_clone()?);
+        threads::ethernet::start(wake_fd.try_clone()?);
+        threads::todo::start(wake_fd.try_clone()?);
+        threads::maildir::start(wake_fd.try_clone()?);
+    }
+    threads::imap::start(wake_fd.try_clone()?);
+    let imap_stopper = threads::imap::Stopper;
+    threads::updates::start(wake_fd.try_clone()?);
+    threads::containers::start(wake_fd.try_clone()?);
+    threads::custom::start(wake_fd.try_clone()?);
+    threads::i3bar::start(wake_fd.try_clone()?);
+    threads::fifo::start(wake_fd.try_clone()?);
+    #[cfg(not(target_os = "linux"))]
+    {
+        for module in ["wifi", "ethernet"] {
+            health::disabled(module, "needs Linux netlink");
+        }
+        health::disabled("todo", "needs Linux inotify");
+    }
+    threads::networkmanager::start(wake_fd.try_clone()?);
+    threads::bluetooth::start(wake_fd.try_clone()?);
+    threads::volume::start(wake_fd.try_clone()?);
+    threads::privacy::start(wake_fd.try_clone()?);
+    threads::mpris::start(wake_fd.try_clone()?);
+    threads::dnd::start(wake_fd.try_clone()?);
+    threads::rfkill::start(wake_fd.try_clone()?);
+    threads::removable::start(wake_fd.try_clone()?);
+    threads::systemd::start(wake_fd.try_clone()?);
+    threads::ics::start(wake_fd.try_clone()?);
+    threads::tray::start(wake_fd.try_clone()?);
+    threads::control::start(wake_fd.try_clone()?);
+    threads::dbus_service::start(wake_fd.try_clone()?);
+    if args.iter().any(|arg| arg == "--lemonbar") {
+        threads::lemonbar::start(wake_fd.try_clone()?);
     }
 
-    // Check if battery exists on startup
-    if fs::metadata("/sys/class/power_supply/BAT0/capacity").is_ok() {
-        BATTERY_STATE.store(0, Ordering::Release);
+    let glyph_cache = font_renderer::GlyphCache::load_or_build(
+        font_renderer::FONT_PATH,
+        font_renderer::FONT_SIZE,
+    )
+    .ok();
+    if glyph_cache.is_none() {
+        eprintln!("Failed to load font. Make sure the path is correct.");
     }
 
     let conn = Connection::connect_to_env()?;
@@ -76,40 +452,76 @@ fn main() -> Result<(), LeanbarError> {
 
     let mut state = AppState::new(glyph_cache);
 
-    event_queue.roundtrip(&mut state)?;
+    roundtrip(&conn, &mut event_queue, &mut state)?;
     if !state.has_required_globals() {
         eprintln!("Failed to bind essential Wayland globals.");
         return Ok(());
     }
 
-    state.initialize_layer_surface(&qh)?;
-    event_queue.roundtrip(&mut state)?;
-
-    let wake_fd = eventfd(0, EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK)?;
-
-    threads::linux_poll::start(wake_fd.try_clone()?);
-    threads::hyprland::start(wake_fd.try_clone()?);
+    state.initialize_layer_surfaces(&qh)?;
+    state.watch_idle(&qh);
+    state.offer_idle_inhibitor();
+    state.watch_toplevels();
+    roundtrip(&conn, &mut event_queue, &mut state)?;
 
     println!("[Main Thread] Entering event loop");
 
     let backend = conn.backend();
     let wayland_fd = backend.poll_fd();
     let mut poll_fds = vec![
-        PollFd::new(&wake_fd, PollFlags::IN),
+        PollFd::new(&wake_read_fd, PollFlags::IN),
         PollFd::new(&wayland_fd, PollFlags::IN),
     ];
+    if let Some(exit_fd) = &exit_fd {
+        poll_fds.push(PollFd::new(exit_fd, PollFlags::IN));
+    }
     let mut buf = [0u8; 8];
+    let mut notifier = sd_notify::Notifier::from_env();
+    let mut ready = false;
 
-    loop {
+    let exit = loop {
+        state.tick_animations();
         let _ = conn.flush();
 
-        match poll(&mut poll_fds, None) {
+        // systemd counts the service as started once a bar is on screen
+        if let Some(notifier) = &mut notifier {
+            if !ready && state.has_committed_frame() {
+                notifier.notify("READY=1");
+                ready = true;
+            }
+            notifier.feed_watchdog();
+        }
+        let watchdog = notifier
+            .as_ref()
+            .and_then(sd_notify::Notifier::watchdog_timeout);
+        let timeout = [watchdog, state.animation_timeout()]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(|timeout| Timespec {
+                tv_sec: timeout.as_secs() as i64,
+                tv_nsec: timeout.subsec_nanos() as i64,
+            });
+
+        match poll(&mut poll_fds, timeout.as_ref()) {
             Ok(_) => {
                 if poll_fds[0].revents().contains(PollFlags::IN) {
-                    let _ = read(&wake_fd, &mut buf);
+                    // A pipe holds one ping per write, an eventfd adds them up
+                    while read(&wake_read_fd, &mut buf).is_ok_and(|n| n > 0) {}
+                    if let Some(exit) = signals::requested_exit() {
+                        break exit;
+                    }
+                    state.open_read_tray_menu();
                     state.redraw_and_commit();
                 }
 
+                if let Some(exit_fd) = &exit_fd
+                    && poll_fds[2].revents().contains(PollFlags::IN)
+                    && let Some(exit) = signals::read_exit_signal(exit_fd)
+                {
+                    break exit;
+                }
+
                 if poll_fds[1].revents().contains(PollFlags::IN) {
                     if let Err(e) = conn.prepare_read().unwrap().read() {
                         eprintln!("Wayland read error: {}", e);
@@ -118,10 +530,33 @@ fn main() -> Result<(), LeanbarError> {
                         eprintln!("Wayland dispatch error: {}", e);
                     }
                 }
+
+                // A protocol error is fatal, the compositor has already closed the connection
+                if let Some(err) = conn.protocol_error() {
+                    diagnostics::report_protocol_error(&err, &state.event_log);
+                    return Err(LeanbarError::Wayland(err.message));
+                }
             }
             Err(e) => {
                 eprintln!("Poll error: {}", e);
             }
         }
+    };
+
+    // Destroys the layer surfaces and unmaps the buffers rather than leaving
+    // them to the compositor
+    println!("[Main Thread] Leaving event loop");
+    if let Some(notifier) = &notifier {
+        notifier.notify(match exit {
+            signals::Exit::Quit => "STOPPING=1",
+            signals::Exit::Reload => "RELOADING=1",
+        });
+    }
+    drop(state);
+    let _ = conn.flush();
+    drop(imap_stopper);
+    if exit == signals::Exit::Reload {
+        signals::reload();
     }
+    Ok(())
 }
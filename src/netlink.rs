@@ -0,0 +1,288 @@
+//! Minimal netlink client for the kernel interfaces leanbar listens to, nl80211
+//! over generic netlink for wifi, rtnetlink for wired links and kernel uevents
+//! for power supplies.
+//!
+//! Requests and event subscriptions use separate sockets so replies never have to
+//! be picked out from between events.
+
+use std::io;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+
+use rustix::net::netlink::{self, SocketAddrNetlink};
+use rustix::net::{
+    AddressFamily, Protocol, RecvFlags, SendFlags, SocketFlags, SocketType, bind, recv, send,
+    socket_with,
+};
+
+const HEADER_LEN: usize = 16;
+const GENL_HEADER_LEN: usize = 4;
+
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_MULTI: u16 = 0x2;
+pub const NLM_F_DUMP: u16 = 0x300;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+const CTRL_ATTR_MCAST_GROUPS: u16 = 7;
+const CTRL_ATTR_MCAST_GRP_NAME: u16 = 1;
+const CTRL_ATTR_MCAST_GRP_ID: u16 = 2;
+
+pub struct Message {
+    pub kind: u16,
+    pub payload: Vec<u8>,
+}
+
+pub struct Socket {
+    fd: OwnedFd,
+    seq: u32,
+}
+
+impl Socket {
+    /// Opens a socket for `protocol`, subscribed to the multicast groups in the
+    /// `groups` bit mask (group N is bit N - 1).
+    pub fn open(protocol: Option<Protocol>, groups: u32) -> io::Result<Self> {
+        let fd = socket_with(
+            AddressFamily::NETLINK,
+            SocketType::RAW,
+            SocketFlags::CLOEXEC,
+            protocol,
+        )?;
+        bind(&fd, &SocketAddrNetlink::new(0, groups))?;
+        Ok(Self { fd, seq: 0 })
+    }
+
+    pub fn generic(groups: u32) -> io::Result<Self> {
+        Self::open(Some(netlink::GENERIC), groups)
+    }
+
+    /// rtnetlink, NETLINK_ROUTE is protocol 0 which rustix spells as None.
+    pub fn route(groups: u32) -> io::Result<Self> {
+        Self::open(None, groups)
+    }
+
+    /// Kernel uevents, plain "KEY=value" strings rather than netlink messages.
+    pub fn uevent(groups: u32) -> io::Result<Self> {
+        Self::open(Some(netlink::KOBJECT_UEVENT), groups)
+    }
+
+    /// Sends a request and collects every reply message, following dumps until
+    /// the kernel says they are done.
+    pub fn request(&mut self, kind: u16, flags: u16, payload: &[u8]) -> io::Result<Vec<Message>> {
+        self.seq = self.seq.wrapping_add(1);
+        let mut msg = Vec::with_capacity(HEADER_LEN + payload.len());
+        msg.extend_from_slice(&((HEADER_LEN + payload.len()) as u32).to_ne_bytes());
+        msg.extend_from_slice(&kind.to_ne_bytes());
+        msg.extend_from_slice(&(flags | NLM_F_REQUEST).to_ne_bytes());
+        msg.extend_from_slice(&self.seq.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(payload);
+        send(&self.fd, &msg, SendFlags::empty())?;
+
+        let mut replies = Vec::new();
+        let mut buf = vec![0u8; 32 * 1024];
+        loop {
+            let (len, _) = recv(&self.fd, &mut buf[..], RecvFlags::empty())?;
+            for (header, payload) in messages(&buf[..len]) {
+                if header.seq != self.seq {
+                    continue;
+                }
+                match header.kind {
+                    NLMSG_DONE => return Ok(replies),
+                    NLMSG_ERROR => {
+                        let errno = payload
+                            .get(..4)
+                            .map_or(0, |b| i32::from_ne_bytes(b.try_into().unwrap()));
+                        return if errno == 0 {
+                            Ok(replies)
+                        } else {
+                            Err(io::Error::from_raw_os_error(-errno))
+                        };
+                    }
+                    kind => {
+                        replies.push(Message {
+                            kind,
+                            payload: payload.to_vec(),
+                        });
+                        if header.flags & NLM_F_MULTI == 0 {
+                            return Ok(replies);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Blocks until the next datagram of multicast events arrives.
+    pub fn recv_events(&self) -> io::Result<Vec<Message>> {
+        let mut buf = vec![0u8; 32 * 1024];
+        let (len, _) = recv(&self.fd, &mut buf[..], RecvFlags::empty())?;
+        Ok(messages(&buf[..len])
+            .map(|(header, payload)| Message {
+                kind: header.kind,
+                payload: payload.to_vec(),
+            })
+            .collect())
+    }
+
+    /// Blocks until the next datagram arrives and returns it as is.
+    pub fn recv_raw(&self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; 8 * 1024];
+        let (len, _) = recv(&self.fd, &mut buf[..], RecvFlags::empty())?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+struct Header {
+    kind: u16,
+    flags: u16,
+    seq: u32,
+}
+
+fn messages(mut data: &[u8]) -> impl Iterator<Item = (Header, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < HEADER_LEN {
+            return None;
+        }
+        let len = u32::from_ne_bytes(data[0..4].try_into().unwrap()) as usize;
+        if len < HEADER_LEN || len > data.len() {
+            return None;
+        }
+        let header = Header {
+            kind: u16::from_ne_bytes(data[4..6].try_into().unwrap()),
+            flags: u16::from_ne_bytes(data[6..8].try_into().unwrap()),
+            seq: u32::from_ne_bytes(data[8..12].try_into().unwrap()),
+        };
+        let payload = &data[HEADER_LEN..len];
+        data = &data[align(len).min(data.len())..];
+        Some((header, payload))
+    })
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Attributes of a message payload or of a nested attribute, as (type, data).
+pub fn attributes(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 4 {
+            return None;
+        }
+        let len = u16::from_ne_bytes(data[0..2].try_into().unwrap()) as usize;
+        if len < 4 || len > data.len() {
+            return None;
+        }
+        // The top bits flag nested and byte order, not part of the type
+        let kind = u16::from_ne_bytes(data[2..4].try_into().unwrap()) & 0x3fff;
+        let value = &data[4..len];
+        data = &data[align(len).min(data.len())..];
+        Some((kind, value))
+    })
+}
+
+pub fn push_attribute(buf: &mut Vec<u8>, kind: u16, value: &[u8]) {
+    buf.extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+    buf.extend_from_slice(&kind.to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(align(buf.len()), 0);
+}
+
+pub fn attr_u32(value: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(value.get(..4)?.try_into().ok()?))
+}
+
+/// Generic netlink payload: command, version and attributes.
+pub fn genl_payload(cmd: u8, attributes: &[u8]) -> Vec<u8> {
+    let mut payload = vec![cmd, 1, 0, 0];
+    payload.extend_from_slice(attributes);
+    payload
+}
+
+/// Command and attributes of a generic netlink message.
+pub fn genl_parts(payload: &[u8]) -> Option<(u8, &[u8])> {
+    Some((*payload.first()?, payload.get(GENL_HEADER_LEN..)?))
+}
+
+/// A generic netlink family's ID and its multicast groups as (name, ID).
+pub struct Family {
+    pub id: u16,
+    pub groups: Vec<(String, u32)>,
+}
+
+impl Family {
+    pub fn resolve(socket: &mut Socket, name: &str) -> io::Result<Self> {
+        let mut attrs = Vec::new();
+        push_attribute(
+            &mut attrs,
+            CTRL_ATTR_FAMILY_NAME,
+            format!("{}\0", name).as_bytes(),
+        );
+        let replies = socket.request(GENL_ID_CTRL, 0, &genl_payload(CTRL_CMD_GETFAMILY, &attrs))?;
+        let mut family = Family {
+            id: 0,
+            groups: Vec::new(),
+        };
+        for reply in &replies {
+            let Some((_, attrs)) = genl_parts(&reply.payload) else {
+                continue;
+            };
+            for (kind, value) in attributes(attrs) {
+                match kind {
+                    CTRL_ATTR_FAMILY_ID => {
+                        family.id = value
+                            .get(..2)
+                            .map_or(0, |b| u16::from_ne_bytes(b.try_into().unwrap()))
+                    }
+                    CTRL_ATTR_MCAST_GROUPS => {
+                        for (_, group) in attributes(value) {
+                            let mut name = String::new();
+                            let mut id = None;
+                            for (kind, value) in attributes(group) {
+                                match kind {
+                                    CTRL_ATTR_MCAST_GRP_NAME => name = attr_string(value),
+                                    CTRL_ATTR_MCAST_GRP_ID => id = attr_u32(value),
+                                    _ => {}
+                                }
+                            }
+                            if let Some(id) = id {
+                                family.groups.push((name, id));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if family.id == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("netlink family {} not found", name),
+            ));
+        }
+        Ok(family)
+    }
+
+    pub fn group(&self, name: &str) -> Option<u32> {
+        self.groups
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, id)| *id)
+    }
+}
+
+/// NUL terminated string attribute.
+pub fn attr_string(value: &[u8]) -> String {
+    let end = value.iter().position(|&b| b == 0).unwrap_or(value.len());
+    String::from_utf8_lossy(&value[..end]).into_owned()
+}
@@ -0,0 +1,221 @@
+//! Whether wlsunset or gammastep currently shifts the display warm. Neither
+//! reports its state, so the running daemon is found in /proc and its schedule
+//! worked out from its arguments, gammastep's config file or
+//! `night_light_hours`. Clicking runs `night_light_command`.
+
+use std::env;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::config;
+
+/// Set after `night_light_command` finished, the daemon may have come or gone.
+pub static RECHECK: AtomicBool = AtomicBool::new(false);
+
+/// When the display is warm.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Schedule {
+    /// From and until, in minutes after midnight.
+    Hours(u16, u16),
+    /// Between sunset and sunrise at a latitude and longitude.
+    Location(f64, f64),
+    /// A fixed temperature such as `gammastep -O 4000`, or nothing known.
+    Always,
+}
+
+impl Schedule {
+    /// Whether the display is warm at `minute` after local midnight on
+    /// `day_of_year`, `utc_offset_s` away from UTC.
+    pub fn is_warm(self, minute: u16, day_of_year: u16, utc_offset_s: i32) -> bool {
+        match self {
+            Schedule::Hours(from, until) => between(minute, from, until),
+            Schedule::Location(latitude, longitude) => {
+                match sun_times(latitude, longitude, day_of_year) {
+                    SunTimes::Rises(sunrise, sunset) => {
+                        let local =
+                            |utc: f64| (utc + utc_offset_s as f64 / 60.0).rem_euclid(1440.0) as u16;
+                        between(minute, local(sunset), local(sunrise))
+                    }
+                    SunTimes::PolarNight => true,
+                    SunTimes::MidnightSun => false,
+                }
+            }
+            Schedule::Always => true,
+        }
+    }
+}
+
+/// Whether `minute` is in `[from, until)`, wrapping past midnight.
+fn between(minute: u16, from: u16, until: u16) -> bool {
+    if from <= until {
+        (from..until).contains(&minute)
+    } else {
+        minute >= from || minute < until
+    }
+}
+
+/// The schedule of the running wlsunset or gammastep, `night_light_hours`
+/// taking precedence. None while neither runs.
+pub fn find() -> Option<Schedule> {
+    let (name, args) = running_daemon()?;
+    if let Some((from, until)) = config::get().night_light_hours {
+        return Some(Schedule::Hours(from, until));
+    }
+    let schedule = if name == "wlsunset" {
+        wlsunset_schedule(&args)
+    } else {
+        gammastep_schedule(&args)
+    };
+    Some(schedule.unwrap_or(Schedule::Always))
+}
+
+/// Name and arguments of the first wlsunset or gammastep process.
+fn running_daemon() -> Option<(String, Vec<String>)> {
+    fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+        let name = entry.file_name();
+        if !name.to_str()?.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let comm = fs::read_to_string(entry.path().join("comm")).ok()?;
+        let comm = comm.trim_end();
+        if !matches!(comm, "wlsunset" | "gammastep") {
+            return None;
+        }
+        let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+        let args = cmdline
+            .split(|b| *b == 0)
+            .skip(1)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        Some((comm.to_string(), args))
+    })
+}
+
+/// Value following `flag`, or attached to it as in `-l52.5`.
+fn arg<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == flag {
+            args.get(i + 1).map(String::as_str)
+        } else {
+            arg.strip_prefix(flag).filter(|rest| !rest.is_empty())
+        }
+    })
+}
+
+/// `-s 20:00 -S 07:00` for fixed times, `-l 52.5 -L 13.4` for a location.
+fn wlsunset_schedule(args: &[String]) -> Option<Schedule> {
+    let time = |flag| arg(args, flag).and_then(config::parse_time_of_day);
+    if let (Some(sunset), Some(sunrise)) = (time("-s"), time("-S")) {
+        return Some(Schedule::Hours(sunset, sunrise));
+    }
+    let coordinate = |flag| arg(args, flag)?.parse().ok();
+    Some(Schedule::Location(coordinate("-l")?, coordinate("-L")?))
+}
+
+/// `-O 4000` for a fixed temperature, `-l 52.5:13.4` for a location, otherwise
+/// `dawn-time`, `dusk-time`, `lat` and `lon` from its config file.
+fn gammastep_schedule(args: &[String]) -> Option<Schedule> {
+    if arg(args, "-O").is_some() {
+        return Some(Schedule::Always);
+    }
+    if let Some((latitude, longitude)) = arg(args, "-l").and_then(|l| l.split_once(':')) {
+        return Some(Schedule::Location(
+            latitude.parse().ok()?,
+            longitude.parse().ok()?,
+        ));
+    }
+    let path = arg(args, "-c").map(PathBuf::from).or_else(|| {
+        let root = env::var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .ok()?;
+        Some(root.join("gammastep").join("config.ini"))
+    })?;
+    let content = fs::read_to_string(path).ok()?;
+    let value = |key: &str| {
+        content.lines().find_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == key).then(|| v.trim())
+        })
+    };
+    // Transitions are written "6:00-7:45", the display is warm until dawn ends
+    // and from when dusk begins
+    let (dawn, dusk) = (value("dawn-time"), value("dusk-time"));
+    if let (Some(dawn), Some(dusk)) = (dawn, dusk) {
+        let dawn_end = dawn.rsplit('-').next().unwrap_or(dawn);
+        let dusk_start = dusk.split('-').next().unwrap_or(dusk);
+        let time = |t: &str| config::parse_time_of_day(t.trim());
+        return Some(Schedule::Hours(time(dusk_start)?, time(dawn_end)?));
+    }
+    Some(Schedule::Location(
+        value("lat")?.parse().ok()?,
+        value("lon")?.parse().ok()?,
+    ))
+}
+
+enum SunTimes {
+    /// Sunrise and sunset in minutes after midnight UTC.
+    Rises(f64, f64),
+    PolarNight,
+    MidnightSun,
+}
+
+/// NOAA's approximation of sunrise and sunset, within a few minutes.
+fn sun_times(latitude: f64, longitude: f64, day_of_year: u16) -> SunTimes {
+    let gamma = 2.0 * PI / 365.0 * (day_of_year as f64 - 1.0);
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+    // The sun's center 50 arc minutes below the horizon, for its radius and refraction
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = 90.833_f64.to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if cos_hour_angle > 1.0 {
+        return SunTimes::PolarNight;
+    }
+    if cos_hour_angle < -1.0 {
+        return SunTimes::MidnightSun;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    SunTimes::Rises(
+        720.0 - 4.0 * (longitude + hour_angle) - equation_of_time,
+        720.0 - 4.0 * (longitude - hour_angle) - equation_of_time,
+    )
+}
+
+/// Runs `night_light_command`, the next poll looks for the daemon again once
+/// it has finished.
+pub fn toggle() {
+    let command = &config::get().night_light_command;
+    if command.is_empty() {
+        return;
+    }
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .spawn();
+    match child {
+        Ok(mut child) => {
+            let _ = thread::Builder::new()
+                .stack_size(128 * 1024)
+                .spawn(move || {
+                    let _ = child.wait();
+                    RECHECK.store(true, Ordering::Release);
+                });
+        }
+        Err(e) => eprintln!("[Night Light] night_light_command failed: {}", e),
+    }
+}
@@ -0,0 +1,140 @@
+use std::ffi::{CStr, c_char, c_int, c_uint, c_void};
+use std::process::{Command, Stdio};
+use std::ptr;
+
+/// Link level addresses carry the interface's `if_data` counters.
+const AF_LINK: u8 = 18;
+
+#[repr(C)]
+struct IfAddrs {
+    ifa_next: *mut IfAddrs,
+    ifa_name: *const c_char,
+    ifa_flags: c_uint,
+    ifa_addr: *const SockAddr,
+    ifa_netmask: *const SockAddr,
+    ifa_dstaddr: *const SockAddr,
+    ifa_data: *const c_void,
+}
+
+#[repr(C)]
+struct SockAddr {
+    sa_len: u8,
+    sa_family: u8,
+}
+
+/// `struct if_data` up to the byte counters, the fields after them are never read.
+#[cfg(target_os = "freebsd")]
+#[repr(C)]
+#[allow(dead_code)]
+struct IfData {
+    ifi_type: u8,
+    ifi_physical: u8,
+    ifi_addrlen: u8,
+    ifi_hdrlen: u8,
+    ifi_link_state: u8,
+    ifi_vhid: u8,
+    ifi_datalen: u16,
+    ifi_mtu: u32,
+    ifi_metric: u32,
+    ifi_baudrate: u64,
+    ifi_ipackets: u64,
+    ifi_ierrors: u64,
+    ifi_opackets: u64,
+    ifi_oerrors: u64,
+    ifi_collisions: u64,
+    ifi_ibytes: u64,
+    ifi_obytes: u64,
+}
+
+/// `struct if_data` up to the byte counters, the fields after them are never read.
+#[cfg(target_os = "openbsd")]
+#[repr(C)]
+#[allow(dead_code)]
+struct IfData {
+    ifi_type: u8,
+    ifi_addrlen: u8,
+    ifi_hdrlen: u8,
+    ifi_link_state: u8,
+    ifi_mtu: u32,
+    ifi_metric: u32,
+    ifi_rdomain: u32,
+    ifi_baudrate: u64,
+    ifi_ipackets: u64,
+    ifi_ierrors: u64,
+    ifi_opackets: u64,
+    ifi_oerrors: u64,
+    ifi_collisions: u64,
+    ifi_ibytes: u64,
+    ifi_obytes: u64,
+}
+
+unsafe extern "C" {
+    fn getifaddrs(ifap: *mut *mut IfAddrs) -> c_int;
+    fn freeifaddrs(ifa: *mut IfAddrs);
+}
+
+/// Power supply change notifications, none here so the battery is polled.
+pub struct PowerSupplyEvents;
+
+impl PowerSupplyEvents {
+    pub fn open() -> Option<Self> {
+        None
+    }
+
+    pub fn wait(&self) -> bool {
+        false
+    }
+}
+
+/// Throttling isn't exposed here, the temperature module goes without a badge.
+pub struct Throttling;
+
+impl Throttling {
+    pub fn find() -> Option<Self> {
+        None
+    }
+
+    pub fn check(&mut self) -> bool {
+        false
+    }
+}
+
+/// Interface of the default route as `route -n get default` reports it.
+pub fn default_route_interface() -> Option<String> {
+    let output = Command::new("route")
+        .args(["-n", "get", "default"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("interface:"))
+        .map(|interface| interface.trim().to_string())
+}
+
+/// Bytes received and sent by an interface so far, from the `if_data` of its
+/// link level address.
+pub fn interface_bytes(interface: &str) -> Option<(u64, u64)> {
+    let mut addresses = ptr::null_mut();
+    if unsafe { getifaddrs(&mut addresses) } != 0 {
+        return None;
+    }
+    let mut found = None;
+    let mut address = addresses;
+    while !address.is_null() {
+        let entry = unsafe { &*address };
+        address = entry.ifa_next;
+        if entry.ifa_addr.is_null()
+            || entry.ifa_data.is_null()
+            || unsafe { (*entry.ifa_addr).sa_family } != AF_LINK
+            || unsafe { CStr::from_ptr(entry.ifa_name) }.to_bytes() != interface.as_bytes()
+        {
+            continue;
+        }
+        let data = unsafe { ptr::read_unaligned(entry.ifa_data.cast::<IfData>()) };
+        found = Some((data.ifi_ibytes, data.ifi_obytes));
+        break;
+    }
+    unsafe { freeifaddrs(addresses) };
+    found
+}
@@ -0,0 +1,118 @@
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::mem;
+use std::ptr;
+
+use super::{BatteryHealth, BatteryReading};
+
+/// `hw.acpi.battery.state` bits.
+const ACPI_BATT_STAT_DISCHARG: c_int = 0x1;
+const ACPI_BATT_STAT_CHARGING: c_int = 0x2;
+/// Read when no sensor is configured, the first core's digital thermal sensor
+/// from coretemp or amdtemp.
+const CPU_SENSOR: &str = "dev.cpu.0.temperature";
+
+unsafe extern "C" {
+    fn sysctlbyname(
+        name: *const c_char,
+        oldp: *mut c_void,
+        oldlenp: *mut usize,
+        newp: *const c_void,
+        newlen: usize,
+    ) -> c_int;
+}
+
+/// An integer sysctl such as `hw.acpi.battery.life`.
+fn sysctl_int(name: &str) -> Option<c_int> {
+    let name = CString::new(name).ok()?;
+    let mut value: c_int = 0;
+    let mut len = mem::size_of::<c_int>();
+    let result = unsafe {
+        sysctlbyname(
+            name.as_ptr(),
+            (&mut value as *mut c_int).cast(),
+            &mut len,
+            ptr::null(),
+            0,
+        )
+    };
+    (result == 0 && len == mem::size_of::<c_int>()).then_some(value)
+}
+
+/// All ACPI batteries, `hw.acpi.battery` already combines them.
+pub struct Battery;
+
+impl Battery {
+    pub fn find() -> Option<Self> {
+        // -1 without a battery
+        sysctl_int("hw.acpi.battery.life")
+            .filter(|life| *life >= 0)
+            .map(|_| Self)
+    }
+
+    pub fn read(&self) -> Result<BatteryReading, String> {
+        let percent = sysctl_int("hw.acpi.battery.life")
+            .filter(|life| *life >= 0)
+            .ok_or("read hw.acpi.battery.life failed")?;
+        let flags = sysctl_int("hw.acpi.battery.state").unwrap_or(0);
+        let on_ac = sysctl_int("hw.acpi.acline") == Some(1);
+        let state = if flags & ACPI_BATT_STAT_CHARGING != 0 {
+            2
+        } else if flags & ACPI_BATT_STAT_DISCHARG != 0 {
+            1
+        } else if on_ac && percent >= 100 {
+            3
+        } else {
+            0
+        };
+        // Minutes left while discharging, -1 otherwise
+        let estimate_m = match sysctl_int("hw.acpi.battery.time") {
+            Some(minutes) if state == 1 && minutes > 0 => minutes.min(u16::MAX as c_int) as u16,
+            _ => 0,
+        };
+        // Milliwatts in or out, -1 when unknown
+        let rate_dw = match sysctl_int("hw.acpi.battery.rate") {
+            Some(milliwatts) if (state == 1 || state == 2) && milliwatts > 0 => {
+                (milliwatts / 100).min(u16::MAX as c_int) as u16
+            }
+            _ => 0,
+        };
+        Ok(BatteryReading {
+            percent: percent.min(100) as u8,
+            state,
+            estimate_m,
+            rate_dw,
+        })
+    }
+
+    /// Design capacity isn't among the sysctls.
+    pub fn health(&self) -> Option<BatteryHealth> {
+        None
+    }
+}
+
+/// Whether the AC adapter is plugged in, `None` without one.
+pub fn ac_online() -> Option<bool> {
+    sysctl_int("hw.acpi.acline").map(|line| line == 1)
+}
+
+/// A temperature sysctl in decikelvin, `temperature_sensor` names it, e.g.
+/// `hw.acpi.thermal.tz0.temperature`.
+pub struct TemperatureSensor {
+    name: String,
+}
+
+impl TemperatureSensor {
+    pub fn find(spec: &str) -> Option<Self> {
+        let name = if spec.is_empty() { CPU_SENSOR } else { spec };
+        sysctl_int(name)?;
+        Some(Self {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn millidegrees(&self) -> Result<i64, String> {
+        let decikelvin =
+            sysctl_int(&self.name).ok_or_else(|| format!("read {} failed", self.name))?;
+        Ok(decikelvin as i64 * 100 - 273_150)
+    }
+}
@@ -0,0 +1,516 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{BatteryHealth, BatteryReading};
+use crate::netlink;
+
+/// Uevents straight from the kernel rather than udev's re-broadcast.
+const UEVENT_KERNEL_GROUP: u32 = 0x1;
+
+/// Weight of a new rate sample in its moving average. Read every 30 seconds,
+/// the estimate follows a lasting change within a few minutes.
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// hwmon drivers tried in order when no sensor is configured.
+const CPU_SENSORS: [&str; 5] = ["coretemp", "k10temp", "zenpower", "cpu_thermal", "acpitz"];
+
+/// The power supplies in `/sys/class/power_supply` shown by the battery module,
+/// combined into one reading.
+pub struct Battery {
+    supplies: Vec<PathBuf>,
+    /// Moving average of the rate and the state it was taken in, the raw rate
+    /// jumps with every load spike.
+    average_rate: Option<(f64, u8)>,
+}
+
+/// One supply's state.
+struct Supply {
+    capacity: u8,
+    status: u8,
+    amounts: Option<Amounts>,
+    /// Reported by the supply itself, UPSes usually do.
+    runtime_s: Option<u64>,
+}
+
+/// The two sets of attributes a supply reports its contents in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Units {
+    /// `energy_*` and `power_now`, uWh and uW.
+    Energy,
+    /// `charge_*` and `current_now`, uAh and uA.
+    Charge,
+}
+
+/// Contents and rate of a supply in the units it reports them in.
+struct Amounts {
+    units: Units,
+    now: u64,
+    full: u64,
+    rate: u64,
+    microvolts: Option<u64>,
+}
+
+impl Amounts {
+    /// Now, full and rate in uWh and uW, charge takes the voltage to convert.
+    fn energy(&self) -> Option<(u64, u64, u64)> {
+        match self.units {
+            Units::Energy => Some((self.now, self.full, self.rate)),
+            Units::Charge => {
+                let microvolts = self.microvolts?;
+                let convert = |value: u64| value * microvolts / 1_000_000;
+                Some((convert(self.now), convert(self.full), convert(self.rate)))
+            }
+        }
+    }
+}
+
+/// Now, full and rate of every supply in units they share, which only takes
+/// voltages when batteries report in different ones.
+fn common_amounts(amounts: &[&Amounts]) -> Option<Vec<(u64, u64, u64)>> {
+    if amounts.iter().all(|a| a.units == amounts[0].units) {
+        return Some(amounts.iter().map(|a| (a.now, a.full, a.rate)).collect());
+    }
+    amounts.iter().map(|a| a.energy()).collect()
+}
+
+impl Battery {
+    /// Every system battery, whatever the driver names it, ThinkPads have an
+    /// internal and an external one. Otherwise the first UPS so desktops
+    /// running on one still get a meaningful module.
+    pub fn find() -> Option<Self> {
+        let root = Path::new("/sys/class/power_supply");
+        let mut supplies: Vec<PathBuf> = fs::read_dir(root)
+            .ok()?
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.join("capacity").exists())
+            .collect();
+        supplies.sort();
+        let is_type = |p: &Path, wanted: &str| {
+            fs::read_to_string(p.join("type")).is_ok_and(|t| t.trim() == wanted)
+        };
+        // Mice and headsets report their batteries here too, scoped to the device
+        let batteries: Vec<PathBuf> = supplies
+            .iter()
+            .filter(|p| is_type(p, "Battery"))
+            .filter(|p| !fs::read_to_string(p.join("scope")).is_ok_and(|s| s.trim() == "Device"))
+            .cloned()
+            .collect();
+        if !batteries.is_empty() {
+            return Some(Self {
+                supplies: batteries,
+                average_rate: None,
+            });
+        }
+        let ups = supplies.into_iter().find(|p| is_type(p, "UPS"))?;
+        Some(Self {
+            supplies: vec![ups],
+            average_rate: None,
+        })
+    }
+
+    pub fn read(&mut self) -> Result<BatteryReading, String> {
+        let supplies = self
+            .supplies
+            .iter()
+            .map(|path| read_supply(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Any battery charging or discharging decides, full only once all are
+        let statuses = || supplies.iter().map(|s| s.status);
+        let state = if statuses().any(|s| s == 2) {
+            2
+        } else if statuses().any(|s| s == 1) {
+            1
+        } else if statuses().all(|s| s == 3) {
+            3
+        } else {
+            0
+        };
+
+        let amounts = supplies
+            .iter()
+            .map(|s| s.amounts.as_ref())
+            .collect::<Option<Vec<_>>>()
+            .and_then(|amounts| common_amounts(&amounts));
+        // Weighted by how much each battery holds, a plain average without amounts
+        let percent = match &amounts {
+            Some(amounts) if amounts.iter().any(|(_, full, _)| *full > 0) => {
+                let full: u64 = amounts.iter().map(|(_, full, _)| full).sum();
+                let weighted: u64 = supplies
+                    .iter()
+                    .zip(amounts)
+                    .map(|(s, (_, full, _))| s.capacity as u64 * full)
+                    .sum();
+                ((weighted + full / 2) / full).min(100) as u8
+            }
+            _ => {
+                let total: u32 = supplies.iter().map(|s| s.capacity as u32).sum();
+                (total / supplies.len() as u32) as u8
+            }
+        };
+
+        let rate = amounts.as_ref().map_or(0.0, |amounts| {
+            self.smooth_rate(amounts.iter().map(|(_, _, rate)| rate).sum(), state)
+        });
+        let estimate_m = match (&supplies[..], &amounts) {
+            ([supply], _) if supply.runtime_s.is_some() && (state == 1 || state == 2) => supply
+                .runtime_s
+                .map_or(0, |s| (s / 60).min(u16::MAX as u64) as u16),
+            (_, Some(amounts)) if rate > 0.0 && (state == 1 || state == 2) => {
+                let now: u64 = amounts.iter().map(|(now, _, _)| now).sum();
+                let full: u64 = amounts.iter().map(|(_, full, _)| full).sum();
+                let remaining = if state == 1 {
+                    now
+                } else {
+                    full.saturating_sub(now)
+                };
+                let hours = remaining as f64 / rate;
+                (hours * 60.0).min(u16::MAX as f64) as u16
+            }
+            _ => 0,
+        };
+
+        // Instantaneous power in or out in deciwatts, unlike the smoothed rate
+        // it follows every load spike. Only batteries moving the same way count.
+        let rate_dw = if state == 1 || state == 2 {
+            let microwatts: u64 = supplies
+                .iter()
+                .filter(|s| s.status == state)
+                .filter_map(|s| s.amounts.as_ref()?.energy())
+                .map(|(_, _, microwatts)| microwatts)
+                .sum();
+            (microwatts / 100_000).min(u16::MAX as u64) as u16
+        } else {
+            0
+        };
+
+        Ok(BatteryReading {
+            percent,
+            state,
+            estimate_m,
+            rate_dw,
+        })
+    }
+
+    /// Full capacity against the design capacity, summed over the batteries
+    /// when they report in the same units and averaged otherwise. The highest
+    /// cycle count, 0 means the battery doesn't count them.
+    pub fn health(&self) -> Option<BatteryHealth> {
+        let capacities = self
+            .supplies
+            .iter()
+            .map(|path| design_capacity(path))
+            .collect::<Option<Vec<_>>>()?;
+        let percent = if capacities
+            .iter()
+            .all(|(units, ..)| *units == capacities[0].0)
+        {
+            let full: u64 = capacities.iter().map(|(_, full, _)| full).sum();
+            let design: u64 = capacities.iter().map(|(_, _, design)| design).sum();
+            full * 100 / design.max(1)
+        } else {
+            let ratios: u64 = capacities
+                .iter()
+                .map(|(_, full, design)| full * 100 / (*design).max(1))
+                .sum();
+            ratios / capacities.len() as u64
+        };
+        let cycles = self
+            .supplies
+            .iter()
+            .filter_map(|path| read_u64(&path.join("cycle_count")))
+            .filter(|cycles| *cycles > 0)
+            .max();
+        Some(BatteryHealth {
+            percent: percent.min(100) as u8,
+            cycles: cycles.map(|c| c.min(u16::MAX as u64) as u16),
+        })
+    }
+
+    /// Folds a rate sample into the moving average, which starts over when
+    /// the battery switches between charging and discharging.
+    fn smooth_rate(&mut self, rate: u64, state: u8) -> f64 {
+        let rate = rate as f64;
+        let average = match self.average_rate {
+            Some((average, previous)) if previous == state && rate > 0.0 => {
+                average + RATE_SMOOTHING * (rate - average)
+            }
+            _ => rate,
+        };
+        self.average_rate = Some((average, state));
+        average
+    }
+}
+
+fn read_supply(bat: &Path) -> Result<Supply, String> {
+    let name = bat.file_name().unwrap_or_default().to_string_lossy();
+    let capacity = match fs::read_to_string(bat.join("capacity")).map(|c| c.trim().parse()) {
+        Ok(Ok(c)) => c,
+        Ok(Err(e)) => return Err(format!("bad {} capacity: {}", name, e)),
+        Err(e) => return Err(format!("read {} capacity: {}", name, e)),
+    };
+    let status = match fs::read_to_string(bat.join("status")) {
+        Ok(status) => match status.trim() {
+            "Discharging" => 1,
+            "Charging" => 2,
+            "Full" => 3,
+            _ => 0,
+        },
+        Err(_) => 0,
+    };
+    let runtime_s = match status {
+        1 => read_u64(&bat.join("time_to_empty_now")),
+        2 => read_u64(&bat.join("time_to_full_now")),
+        _ => None,
+    };
+    Ok(Supply {
+        capacity,
+        status,
+        amounts: amounts(bat),
+        runtime_s,
+    })
+}
+
+/// Full and design capacity in the same set of attributes.
+fn design_capacity(bat: &Path) -> Option<(Units, u64, u64)> {
+    let read = |attribute: &str| read_u64(&bat.join(attribute));
+    match (read("energy_full"), read("energy_full_design")) {
+        (Some(full), Some(design)) if design > 0 => Some((Units::Energy, full, design)),
+        _ => Some((
+            Units::Charge,
+            read("charge_full")?,
+            read("charge_full_design").filter(|d| *d > 0)?,
+        )),
+    }
+}
+
+/// Contents and rate in whichever set of attributes the supply has. A rate
+/// given in the other set is converted with the voltage, drivers mix them.
+fn amounts(bat: &Path) -> Option<Amounts> {
+    let read = |attribute: &str| read_u64(&bat.join(attribute));
+    let microvolts = read("voltage_now").filter(|v| *v > 0);
+    let (units, now, full) = match (read("energy_now"), read("energy_full")) {
+        (Some(now), Some(full)) => (Units::Energy, now, full),
+        _ => (Units::Charge, read("charge_now")?, read("charge_full")?),
+    };
+    let rate = match units {
+        Units::Energy => {
+            read("power_now").or_else(|| Some(read("current_now")? * microvolts? / 1_000_000))
+        }
+        Units::Charge => {
+            read("current_now").or_else(|| Some(read("power_now")? * 1_000_000 / microvolts?))
+        }
+    };
+    Some(Amounts {
+        units,
+        now,
+        full,
+        rate: rate.unwrap_or(0),
+        microvolts,
+    })
+}
+
+/// Whether a mains supply (`AC`, `ADP1`, `ACAD`...) is online, `None` without
+/// one. Tracked apart from the batteries, which stop charging at their charge
+/// thresholds while still plugged in.
+pub fn ac_online() -> Option<bool> {
+    let mut online = None;
+    for supply in fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+        let path = supply.path();
+        if !fs::read_to_string(path.join("type")).is_ok_and(|t| t.trim() == "Mains") {
+            continue;
+        }
+        if let Some(state) = read_u64(&path.join("online")) {
+            online = Some(online.unwrap_or(false) || state == 1);
+        }
+    }
+    online
+}
+
+/// Kernel uevents of the power_supply class, batteries and adapters send one
+/// whenever they are plugged, unplugged or change status.
+pub struct PowerSupplyEvents {
+    socket: netlink::Socket,
+}
+
+impl PowerSupplyEvents {
+    pub fn open() -> Option<Self> {
+        let socket = netlink::Socket::uevent(UEVENT_KERNEL_GROUP).ok()?;
+        Some(Self { socket })
+    }
+
+    /// Blocks until the next power supply event, false once the socket fails.
+    pub fn wait(&self) -> bool {
+        loop {
+            let Ok(event) = self.socket.recv_raw() else {
+                return false;
+            };
+            // "change@/devices/...\0ACTION=change\0SUBSYSTEM=power_supply\0..."
+            if event
+                .split(|b| *b == 0)
+                .any(|field| field == b"SUBSYSTEM=power_supply")
+            {
+                return true;
+            }
+        }
+    }
+}
+
+/// The `tempN_input` file of an hwmon sensor.
+pub struct TemperatureSensor {
+    input: PathBuf,
+}
+
+impl TemperatureSensor {
+    /// Finds the sensor named by `spec` ("name" or "name:label"), or the first
+    /// CPU sensor when `spec` is empty.
+    pub fn find(spec: &str) -> Option<Self> {
+        let mut hwmons: Vec<PathBuf> = fs::read_dir("/sys/class/hwmon")
+            .ok()?
+            .flatten()
+            .map(|e| e.path())
+            .collect();
+        hwmons.sort();
+        let name_of = |hwmon: &Path| {
+            fs::read_to_string(hwmon.join("name"))
+                .map(|n| n.trim().to_string())
+                .unwrap_or_default()
+        };
+
+        let input = if spec.is_empty() {
+            CPU_SENSORS.iter().find_map(|wanted| {
+                hwmons
+                    .iter()
+                    .filter(|h| name_of(h) == *wanted)
+                    .find_map(|h| sensor_input(h, None))
+            })
+        } else {
+            let (name, label) = match spec.split_once(':') {
+                Some((name, label)) => (name, Some(label)),
+                None => (spec, None),
+            };
+            hwmons
+                .iter()
+                .filter(|h| name_of(h) == name)
+                .find_map(|h| sensor_input(h, label))
+        };
+        Some(Self { input: input? })
+    }
+
+    pub fn millidegrees(&self) -> Result<i64, String> {
+        // hwmon reports millidegrees
+        fs::read_to_string(&self.input)
+            .ok()
+            .and_then(|t| t.trim().parse().ok())
+            .ok_or_else(|| format!("read {} failed", self.input.display()))
+    }
+}
+
+/// First temperature input of an hwmon device, or the one with the given label.
+fn sensor_input(hwmon: &Path, label: Option<&str>) -> Option<PathBuf> {
+    let mut inputs: Vec<PathBuf> = fs::read_dir(hwmon)
+        .ok()?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("temp") && n.ends_with("_input"))
+        })
+        .collect();
+    // temp2_input sorts before temp10_input
+    inputs.sort_by_key(|p| (p.as_os_str().len(), p.clone()));
+    inputs.into_iter().find(|input| {
+        let Some(wanted) = label else {
+            return true;
+        };
+        let label_path = PathBuf::from(input.to_string_lossy().replace("_input", "_label"));
+        fs::read_to_string(label_path).is_ok_and(|l| l.trim() == wanted)
+    })
+}
+
+/// Signs of the CPU slowing itself down to cool off: the per CPU throttle
+/// counters Intel CPUs keep and the alarm attributes of the CPU hwmon sensors.
+pub struct Throttling {
+    counters: Vec<PathBuf>,
+    alarms: Vec<PathBuf>,
+    /// Sum of the counters at the last check.
+    last_count: Option<u64>,
+}
+
+impl Throttling {
+    pub fn find() -> Option<Self> {
+        let mut counters = Vec::new();
+        for cpu in fs::read_dir("/sys/devices/system/cpu").ok()?.flatten() {
+            let dir = cpu.path().join("thermal_throttle");
+            for counter in ["core_throttle_count", "package_throttle_count"] {
+                if dir.join(counter).exists() {
+                    counters.push(dir.join(counter));
+                }
+            }
+        }
+        let alarms: Vec<PathBuf> = fs::read_dir("/sys/class/hwmon")
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path())
+            .filter(|hwmon| {
+                fs::read_to_string(hwmon.join("name"))
+                    .is_ok_and(|name| CPU_SENSORS.contains(&name.trim()))
+            })
+            .flat_map(|hwmon| fs::read_dir(hwmon).into_iter().flatten().flatten())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("temp") && n.ends_with("_alarm"))
+            })
+            .collect();
+        if counters.is_empty() && alarms.is_empty() {
+            return None;
+        }
+        Some(Self {
+            counters,
+            alarms,
+            last_count: None,
+        })
+    }
+
+    /// Whether a counter went up since the last check or an alarm is raised.
+    pub fn check(&mut self) -> bool {
+        let count: u64 = self.counters.iter().filter_map(|c| read_u64(c)).sum();
+        let counted = self.last_count.is_some_and(|last| count > last);
+        self.last_count = Some(count);
+        counted
+            || self
+                .alarms
+                .iter()
+                .any(|a| read_u64(a).is_some_and(|v| v > 0))
+    }
+}
+
+/// Interface of the IPv4 default route from `/proc/net/route`.
+pub fn default_route_interface() -> Option<String> {
+    let routes = fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let interface = fields.next()?;
+        (fields.next()? == "00000000").then(|| interface.to_string())
+    })
+}
+
+/// Bytes received and sent by an interface so far.
+pub fn interface_bytes(interface: &str) -> Option<(u64, u64)> {
+    let stats = Path::new("/sys/class/net")
+        .join(interface)
+        .join("statistics");
+    Some((
+        read_u64(&stats.join("rx_bytes"))?,
+        read_u64(&stats.join("tx_bytes"))?,
+    ))
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
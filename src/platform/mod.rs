@@ -0,0 +1,50 @@
+//! Operating system specific sources of the polling thread: battery, AC
+//! adapter, CPU temperature and throttling and network interface counters.
+//! Linux reads sysfs and procfs, FreeBSD asks sysctl, OpenBSD apm and the
+//! hw.sensors sysctl tree. Every platform provides the same items, the one for
+//! the target is picked at compile time.
+//!
+//! Disk, RAPL, pressure and backlight stay Linux interfaces that are simply not
+//! found elsewhere, their modules report themselves disabled.
+
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+mod bsd;
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "openbsd")]
+mod openbsd;
+
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+pub use bsd::{PowerSupplyEvents, Throttling, default_route_interface, interface_bytes};
+#[cfg(target_os = "freebsd")]
+pub use freebsd::{Battery, TemperatureSensor, ac_online};
+#[cfg(target_os = "linux")]
+pub use linux::{
+    Battery, PowerSupplyEvents, TemperatureSensor, Throttling, ac_online, default_route_interface,
+    interface_bytes,
+};
+#[cfg(target_os = "openbsd")]
+pub use openbsd::{Battery, TemperatureSensor, ac_online};
+
+/// One reading of the battery shown by the battery module.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct BatteryReading {
+    pub percent: u8,
+    /// 0: Unknown, 1: Discharging, 2: Charging, 3: Full
+    pub state: u8,
+    /// Minutes until empty or full, 0 when not known.
+    pub estimate_m: u16,
+    /// Power flowing in while charging or out while discharging, in
+    /// deciwatts, 0 when not known.
+    pub rate_dw: u16,
+}
+
+/// How worn the battery is, changes over months.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BatteryHealth {
+    /// Full charge as a percentage of the design capacity.
+    pub percent: u8,
+    pub cycles: Option<u16>,
+}
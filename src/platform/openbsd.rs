@@ -0,0 +1,169 @@
+use std::ffi::{c_int, c_uint, c_void};
+use std::os::fd::OwnedFd;
+use std::ptr;
+
+use rustix::fs::{Mode, OFlags, open};
+use rustix::ioctl::{Getter, ioctl, opcode};
+
+use super::{BatteryHealth, BatteryReading};
+
+/// `struct apm_power_info` from `<machine/apmvar.h>`.
+#[repr(C)]
+struct ApmPowerInfo {
+    battery_state: u8,
+    ac_state: u8,
+    battery_life: u8,
+    spare1: u8,
+    minutes_left: c_uint,
+    spare2: [c_uint; 6],
+}
+
+const APM_IOC_GETPOWER: rustix::ioctl::Opcode = opcode::read::<ApmPowerInfo>(b'A', 3);
+const APM_BATT_CHARGING: u8 = 3;
+/// Also reported as `APM_BATTERY_ABSENT`.
+const APM_BATT_UNKNOWN: u8 = 4;
+const APM_AC_OFF: u8 = 0;
+const APM_AC_ON: u8 = 1;
+
+/// `hw.sensors` in the sysctl tree, a device number below it gives its `struct
+/// sensordev`, a sensor type and index the `struct sensor`.
+const CTL_HW: c_int = 6;
+const HW_SENSORS: c_int = 11;
+const SENSOR_TEMP: c_int = 0;
+/// `xname` in `struct sensordev`, after its `int num`.
+const SENSORDEV_XNAME: usize = 4;
+/// `value` in `struct sensor`, after `char desc[32]` and a `struct timeval`.
+const SENSOR_VALUE: usize = 48;
+/// Devices are numbered as they attach, gaps come from detached ones.
+const MAX_SENSOR_DEVICES: c_int = 256;
+/// Read when no sensor is configured, the CPU's own temperature sensor.
+const CPU_SENSOR: &str = "cpu0";
+
+unsafe extern "C" {
+    fn sysctl(
+        name: *const c_int,
+        namelen: c_uint,
+        oldp: *mut c_void,
+        oldlenp: *mut usize,
+        newp: *mut c_void,
+        newlen: usize,
+    ) -> c_int;
+}
+
+/// The battery apm(4) reports, which combines all of them.
+pub struct Battery {
+    apm: OwnedFd,
+}
+
+impl Battery {
+    pub fn find() -> Option<Self> {
+        let apm = open("/dev/apm", OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty()).ok()?;
+        let battery = Self { apm };
+        let info = battery.power_info()?;
+        (info.battery_state != APM_BATT_UNKNOWN).then_some(battery)
+    }
+
+    fn power_info(&self) -> Option<ApmPowerInfo> {
+        power_info(&self.apm)
+    }
+
+    pub fn read(&self) -> Result<BatteryReading, String> {
+        let info = self.power_info().ok_or("APM_IOC_GETPOWER failed")?;
+        let percent = info.battery_life.min(100);
+        let state = match (info.battery_state, info.ac_state) {
+            (APM_BATT_CHARGING, _) => 2,
+            (APM_BATT_UNKNOWN, _) => 0,
+            (_, APM_AC_ON) if percent >= 100 => 3,
+            (_, APM_AC_ON) => 0,
+            _ => 1,
+        };
+        // Minutes left while discharging, -1 when unknown
+        let estimate_m = match info.minutes_left {
+            minutes if state == 1 && minutes != c_uint::MAX => {
+                minutes.min(u16::MAX as c_uint) as u16
+            }
+            _ => 0,
+        };
+        Ok(BatteryReading {
+            percent,
+            state,
+            estimate_m,
+            rate_dw: 0,
+        })
+    }
+
+    /// apm(4) doesn't report the design capacity.
+    pub fn health(&self) -> Option<BatteryHealth> {
+        None
+    }
+}
+
+/// Whether the AC adapter is plugged in, `None` when apm(4) can't tell.
+pub fn ac_online() -> Option<bool> {
+    let apm = open("/dev/apm", OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty()).ok()?;
+    match power_info(&apm)?.ac_state {
+        APM_AC_ON => Some(true),
+        APM_AC_OFF => Some(false),
+        _ => None,
+    }
+}
+
+fn power_info(apm: &OwnedFd) -> Option<ApmPowerInfo> {
+    unsafe { ioctl(apm, Getter::<APM_IOC_GETPOWER, ApmPowerInfo>::new()) }.ok()
+}
+
+/// The first temperature of a sensor device, `temperature_sensor` names the
+/// device, e.g. `acpitz0`.
+pub struct TemperatureSensor {
+    device: c_int,
+    name: String,
+}
+
+impl TemperatureSensor {
+    pub fn find(spec: &str) -> Option<Self> {
+        let name = if spec.is_empty() { CPU_SENSOR } else { spec };
+        let device = (0..MAX_SENSOR_DEVICES).find(|&device| {
+            let mut sensordev = [0u8; 256];
+            sysctl_read(&[CTL_HW, HW_SENSORS, device], &mut sensordev).is_some_and(|_| {
+                let xname = &sensordev[SENSORDEV_XNAME..SENSORDEV_XNAME + 16];
+                xname.split(|b| *b == 0).next() == Some(name.as_bytes())
+            })
+        })?;
+        let sensor = Self {
+            device,
+            name: name.to_string(),
+        };
+        sensor.millidegrees().ok()?;
+        Some(sensor)
+    }
+
+    pub fn millidegrees(&self) -> Result<i64, String> {
+        let mut sensor = [0u8; 256];
+        sysctl_read(
+            &[CTL_HW, HW_SENSORS, self.device, SENSOR_TEMP, 0],
+            &mut sensor,
+        )
+        .filter(|len| *len >= SENSOR_VALUE + 8)
+        .ok_or_else(|| format!("read hw.sensors.{}.temp0 failed", self.name))?;
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&sensor[SENSOR_VALUE..SENSOR_VALUE + 8]);
+        // Microkelvin
+        Ok((i64::from_ne_bytes(value) - 273_150_000) / 1000)
+    }
+}
+
+/// Reads a sysctl node into the buffer, returns the length written.
+fn sysctl_read(mib: &[c_int], buffer: &mut [u8]) -> Option<usize> {
+    let mut len = buffer.len();
+    let result = unsafe {
+        sysctl(
+            mib.as_ptr(),
+            mib.len() as c_uint,
+            buffer.as_mut_ptr().cast(),
+            &mut len,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    (result == 0).then_some(len)
+}
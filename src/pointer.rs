@@ -0,0 +1,403 @@
+use wayland_client::{
+    Connection, Dispatch, QueueHandle, WEnum,
+    protocol::{
+        wl_pointer::{self, WlPointer},
+        wl_seat::{self, WlSeat},
+    },
+};
+
+use crate::{
+    app_state::AppState,
+    backlight, config,
+    evdev::Keyboards,
+    night_light,
+    threads::{dnd, hyprland, power_profile, removable, rfkill, tray, volume},
+};
+
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+/// Scroll distance of one step, wheels report 10 per notch on most compositors
+/// and touchpads add up many small events.
+const SCROLL_STEP: f64 = 10.0;
+
+/// Something on the bar that reacts to the pointer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClickTarget {
+    Workspace(i32),
+    KeyboardLayout,
+    TilingLayout,
+    AudioDevice,
+    PowerProfile,
+    /// Toggles the idle inhibitor.
+    Caffeine,
+    /// Toggles do-not-disturb.
+    Dnd,
+    /// Runs `night_light_command`.
+    NightLight,
+    /// Soft blocks or unblocks every radio.
+    Rfkill,
+    /// Unmounts the last mounted removable drive.
+    Removable,
+    /// Shows the unread mail per account while hovered.
+    Mail,
+    /// Shows the tooltip of `custom_command`'s JSON while hovered.
+    Custom,
+    /// A StatusNotifierItem by its index in `TRAY`.
+    Tray(u8),
+    /// A window by its index in `TASKBAR`.
+    Taskbar(u8),
+    /// A block of `status_command` by its index in `STATUS_BLOCKS`.
+    Status(u8),
+    /// Shows the battery's health while hovered.
+    Battery,
+    Backlight,
+    KeyboardBacklight,
+    /// Date and clock, they open the calendar.
+    Calendar,
+}
+
+impl ClickTarget {
+    /// Module the target belongs to, as listed by `leanbar-ctl regions`.
+    pub fn name(self) -> String {
+        match self {
+            ClickTarget::Workspace(id) => format!("workspace {}", id),
+            ClickTarget::KeyboardLayout => "keyboard_layout".to_string(),
+            ClickTarget::TilingLayout => "tiling".to_string(),
+            ClickTarget::AudioDevice => "audio_device".to_string(),
+            ClickTarget::PowerProfile => "power_profile".to_string(),
+            ClickTarget::Battery => "battery".to_string(),
+            ClickTarget::Caffeine => "caffeine".to_string(),
+            ClickTarget::Dnd => "dnd".to_string(),
+            ClickTarget::NightLight => "night_light".to_string(),
+            ClickTarget::Rfkill => "rfkill".to_string(),
+            ClickTarget::Removable => "removable".to_string(),
+            ClickTarget::Mail => "mail".to_string(),
+            ClickTarget::Custom => "custom".to_string(),
+            ClickTarget::Tray(index) => format!("tray {}", index),
+            ClickTarget::Taskbar(index) => format!("taskbar {}", index),
+            ClickTarget::Status(index) => format!("status {}", index),
+            ClickTarget::Backlight => "backlight".to_string(),
+            ClickTarget::KeyboardBacklight => "keyboard_backlight".to_string(),
+            ClickTarget::Calendar => "clock".to_string(),
+        }
+    }
+
+    /// What the pointer does there, see `click` and `update_hover`.
+    pub fn action(self) -> &'static str {
+        match self {
+            ClickTarget::Workspace(_) => "click: focus, hover with modifier: peek",
+            ClickTarget::KeyboardLayout => "click: next keyboard layout",
+            ClickTarget::TilingLayout => "click: next tiling layout",
+            ClickTarget::AudioDevice => "click: next audio sink",
+            ClickTarget::PowerProfile => "click: next power profile",
+            ClickTarget::Battery => "hover: battery health",
+            ClickTarget::Caffeine => "click: toggle idle inhibitor",
+            ClickTarget::Dnd => "click: toggle do-not-disturb",
+            ClickTarget::NightLight => "click: night_light_command",
+            ClickTarget::Rfkill => "click: toggle airplane mode",
+            ClickTarget::Removable => "click: unmount the last drive",
+            ClickTarget::Mail => "hover: unread mail per account",
+            ClickTarget::Custom => "hover: tooltip",
+            ClickTarget::Tray(_) => "click: activate, middle: secondary, right: menu",
+            ClickTarget::Taskbar(_) => "click: focus or minimize, middle: close",
+            ClickTarget::Status(_) => "click and scroll: sent to status_command",
+            ClickTarget::Backlight => "scroll: brightness",
+            ClickTarget::KeyboardBacklight => "click: next level, scroll: brightness",
+            ClickTarget::Calendar => "click: calendar, scroll: calendar month",
+        }
+    }
+}
+
+/// Horizontal span of a bar occupied by a click target.
+pub struct ClickRegion {
+    pub start: usize,
+    pub end: usize,
+    pub target: ClickTarget,
+}
+
+/// A workspace temporarily shown while hovering with the peek modifier held.
+struct Peek {
+    origin: i32,
+    shown: i32,
+}
+
+#[derive(Default)]
+pub struct PointerState {
+    pointer: Option<WlPointer>,
+    focus: Option<u32>,
+    x: f64,
+    y: f64,
+    /// Over the calendar popup rather than a bar.
+    on_calendar: bool,
+    /// Over a tray item's menu rather than a bar.
+    on_tray_menu: bool,
+    hovered: Option<ClickTarget>,
+    /// Scroll distance not yet turned into a step.
+    scroll: f64,
+    peek: Option<Peek>,
+    keyboards: Keyboards,
+}
+
+impl AppState {
+    fn update_hover(&mut self) {
+        let target = self
+            .pointer
+            .focus
+            .and_then(|bar| self.target_at(bar, self.pointer.x));
+        if target == self.pointer.hovered {
+            return;
+        }
+        let battery = Some(ClickTarget::Battery);
+        if self.pointer.hovered == battery || target == battery {
+            self.show_battery_health(self.pointer.focus.filter(|_| target == battery));
+        }
+        let mail = Some(ClickTarget::Mail);
+        if self.pointer.hovered == mail || target == mail {
+            self.show_mail_accounts(self.pointer.focus.filter(|_| target == mail));
+        }
+        let custom = Some(ClickTarget::Custom);
+        if self.pointer.hovered == custom || target == custom {
+            self.show_custom_tooltip(self.pointer.focus.filter(|_| target == custom));
+        }
+        self.pointer.hovered = target;
+        self.pointer.scroll = 0.0;
+
+        let modifier = config::get().peek_modifier;
+        match target {
+            Some(ClickTarget::Workspace(ws))
+                if self.pointer.peek.is_some()
+                    || self.pointer.keyboards.modifier_held(modifier) =>
+            {
+                let origin = match &self.pointer.peek {
+                    Some(peek) => peek.origin,
+                    None => self
+                        .pointer
+                        .focus
+                        .map(|bar| self.bar_active_workspace(bar))
+                        .unwrap_or(ws),
+                };
+                let shown = self.pointer.peek.as_ref().map_or(origin, |p| p.shown);
+                if shown != ws {
+                    hyprland::focus_workspace(ws);
+                }
+                self.pointer.peek = Some(Peek { origin, shown: ws });
+            }
+            _ => self.end_peek(),
+        }
+    }
+
+    /// Switches back to the workspace that was active before peeking.
+    fn end_peek(&mut self) {
+        if let Some(peek) = self.pointer.peek.take()
+            && peek.shown != peek.origin
+        {
+            hyprland::focus_workspace(peek.origin);
+        }
+    }
+
+    /// Vertical scrolling over the calendar, the clock, the backlight module or
+    /// a status block.
+    fn scroll(&mut self, delta: f64) {
+        let on_calendar =
+            self.pointer.on_calendar || self.pointer.hovered == Some(ClickTarget::Calendar);
+        let scrollable = matches!(
+            self.pointer.hovered,
+            Some(ClickTarget::Backlight | ClickTarget::KeyboardBacklight | ClickTarget::Status(_))
+        );
+        if !on_calendar && !scrollable {
+            return;
+        }
+        self.pointer.scroll += delta;
+        let steps = (self.pointer.scroll / SCROLL_STEP).trunc();
+        if steps == 0.0 {
+            return;
+        }
+        self.pointer.scroll -= steps * SCROLL_STEP;
+        let steps = steps as i32;
+        if let (Some(ClickTarget::Status(index)), Some(bar)) =
+            (self.pointer.hovered, self.pointer.focus)
+        {
+            // Buttons 4 and 5 scroll up and down
+            let button = if steps < 0 { 4 } else { 5 };
+            for _ in 0..steps.unsigned_abs() {
+                self.click_status_block(bar, index, button, self.pointer.x, self.pointer.y);
+            }
+            return;
+        }
+        if on_calendar {
+            self.scroll_calendar(steps);
+            return;
+        }
+        // Scrolling up brightens
+        if self.pointer.hovered == Some(ClickTarget::KeyboardBacklight) {
+            backlight::adjust_keyboard(-steps, false);
+        } else {
+            backlight::adjust(-steps);
+        }
+    }
+
+    fn click(&mut self, button: u32) {
+        if self.pointer.on_tray_menu {
+            // The surface goes away without a leave event when an entry is picked
+            if button == BTN_LEFT && self.click_tray_menu(self.pointer.y) {
+                self.pointer.on_tray_menu = false;
+            }
+            return;
+        }
+        if let Some(ClickTarget::Tray(index)) = self.pointer.hovered {
+            match (button, self.pointer.focus) {
+                (BTN_RIGHT, Some(bar)) => self.toggle_tray_menu(bar, index, self.pointer.x),
+                (BTN_LEFT, _) => tray::activate(index, "Activate"),
+                (BTN_MIDDLE, _) => tray::activate(index, "SecondaryActivate"),
+                _ => {}
+            }
+            return;
+        }
+        // Clicking anywhere else on a bar dismisses an open menu
+        self.close_tray_menu();
+        if let Some(ClickTarget::Taskbar(index)) = self.pointer.hovered {
+            match button {
+                BTN_LEFT => self.activate_toplevel(index),
+                BTN_MIDDLE => self.close_toplevel(index),
+                _ => {}
+            }
+            return;
+        }
+        if let (Some(ClickTarget::Status(index)), Some(bar)) =
+            (self.pointer.hovered, self.pointer.focus)
+        {
+            let button = match button {
+                BTN_LEFT => 1,
+                BTN_MIDDLE => 2,
+                BTN_RIGHT => 3,
+                _ => return,
+            };
+            self.click_status_block(bar, index, button, self.pointer.x, self.pointer.y);
+            return;
+        }
+        if button != BTN_LEFT {
+            return;
+        }
+        if self.pointer.on_calendar {
+            self.click_calendar(self.pointer.x, self.pointer.y);
+            return;
+        }
+        match self.pointer.hovered {
+            // Clicking while peeking keeps the peeked workspace
+            Some(ClickTarget::Workspace(ws)) if self.pointer.peek.take().is_none() => {
+                hyprland::focus_workspace(ws);
+            }
+            Some(ClickTarget::KeyboardLayout) => hyprland::cycle_keyboard_layout(),
+            Some(ClickTarget::TilingLayout) => hyprland::cycle_tiling_layout(),
+            Some(ClickTarget::AudioDevice) => volume::cycle_sink(),
+            Some(ClickTarget::PowerProfile) => power_profile::cycle(),
+            Some(ClickTarget::Caffeine) => {
+                if let Some(bar) = self.pointer.focus {
+                    self.toggle_idle_inhibitor(bar);
+                }
+            }
+            Some(ClickTarget::Dnd) => dnd::toggle(),
+            Some(ClickTarget::NightLight) => night_light::toggle(),
+            Some(ClickTarget::Rfkill) => rfkill::toggle(),
+            Some(ClickTarget::Removable) => removable::unmount_last(),
+            Some(ClickTarget::KeyboardBacklight) => backlight::adjust_keyboard(1, true),
+            Some(ClickTarget::Calendar) => {
+                if let Some(bar) = self.pointer.focus {
+                    self.toggle_calendar(bar);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        seat: &WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(seat, event.opcode(), &event);
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            let has_pointer = capabilities.contains(wl_seat::Capability::Pointer);
+            if has_pointer && state.pointer.pointer.is_none() {
+                state.pointer.pointer = Some(seat.get_pointer(qhandle, ()));
+            } else if !has_pointer && let Some(pointer) = state.pointer.pointer.take() {
+                pointer.release();
+            }
+        }
+    }
+}
+
+impl Dispatch<WlPointer, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        pointer: &WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(pointer, event.opcode(), &event);
+        match event {
+            wl_pointer::Event::Enter {
+                surface,
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.pointer.focus = state.bar_for_surface(&surface);
+                state.pointer.on_calendar = state.is_calendar_surface(&surface);
+                state.pointer.on_tray_menu = state.is_tray_menu_surface(&surface);
+                state.pointer.x = surface_x;
+                state.pointer.y = surface_y;
+                if state.pointer.on_tray_menu {
+                    state.hover_tray_menu(Some(surface_y));
+                }
+                state.update_hover();
+            }
+            wl_pointer::Event::Leave { .. } => {
+                state.pointer.focus = None;
+                state.pointer.on_calendar = false;
+                if std::mem::take(&mut state.pointer.on_tray_menu) {
+                    state.hover_tray_menu(None);
+                }
+                state.update_hover();
+            }
+            wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.pointer.x = surface_x;
+                state.pointer.y = surface_y;
+                if state.pointer.on_tray_menu {
+                    state.hover_tray_menu(Some(surface_y));
+                }
+                state.update_hover();
+            }
+            wl_pointer::Event::Axis {
+                axis: WEnum::Value(wl_pointer::Axis::VerticalScroll),
+                value,
+                ..
+            } => {
+                state.scroll(value);
+            }
+            wl_pointer::Event::Button {
+                button,
+                state: WEnum::Value(wl_pointer::ButtonState::Pressed),
+                ..
+            } => {
+                state.click(button);
+            }
+            _ => {}
+        }
+    }
+}
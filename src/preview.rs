@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+use crate::{
+    app_state,
+    config::Module,
+    error::LeanbarError,
+    font_renderer::{FONT_PATH, FONT_SIZE, GlyphCache},
+};
+
+const PREVIEW_WIDTH: usize = 1000;
+/// The bar is transparent, composite the preview over a dark wallpaper-ish color.
+const PREVIEW_BACKGROUND: u32 = 0xff1e1e2e;
+
+/// `leanbar --preview-font <path> <size> <out.png>` renders a sample bar with the
+/// given font so fonts and sizes can be compared before changing the config.
+pub fn maybe_run_preview_mode(args: &[String]) -> Result<bool, LeanbarError> {
+    if args.get(1).map(String::as_str) != Some("--preview-font") {
+        return Ok(false);
+    }
+    let font_path = args
+        .get(2)
+        .ok_or_else(|| LeanbarError::Font("missing font path".into()))?;
+    let size: f32 = args
+        .get(3)
+        .ok_or_else(|| LeanbarError::Font("missing size".into()))?
+        .parse()?;
+    let out_path = args
+        .get(4)
+        .ok_or_else(|| LeanbarError::Font("missing output path".into()))?;
+
+    let glyphs = GlyphCache::from_font(font_path, size)?;
+    let (pixels, height) = app_state::render_preview(&glyphs, PREVIEW_WIDTH);
+    write_png(Path::new(out_path), &pixels, PREVIEW_WIDTH, height)?;
+    println!("Preview written to {}", out_path);
+    Ok(true)
+}
+
+/// `leanbar --render-module <module> [--state '<json>']` draws a single module
+/// with the bar's font and prints it to the terminal, so modules can be
+/// worked on without a compositor. The state is a flat JSON object of the
+/// values the module is drawn from, missing ones keep their sample values, e.g.
+/// `--state '{"percent":42,"state":"discharging"}'` for the battery.
+pub fn maybe_run_module_preview(args: &[String]) -> Result<bool, LeanbarError> {
+    if args.get(1).map(String::as_str) != Some("--render-module") {
+        return Ok(false);
+    }
+    let name = args
+        .get(2)
+        .ok_or_else(|| LeanbarError::Preview("missing module name".into()))?;
+    let module = Module::parse(name)
+        .ok_or_else(|| LeanbarError::Preview(format!("unknown module: {}", name)))?;
+    let state = match args.get(3).map(String::as_str) {
+        Some("--state") => State::parse(
+            args.get(4)
+                .ok_or_else(|| LeanbarError::Preview("missing state".into()))?,
+        )?,
+        Some(other) => {
+            return Err(LeanbarError::Preview(format!(
+                "unexpected argument: {}",
+                other
+            )));
+        }
+        None => State::default(),
+    };
+
+    let glyphs = GlyphCache::load_or_build(FONT_PATH, FONT_SIZE)?;
+    let (pixels, width, height) = app_state::render_module(&glyphs, module, &state);
+    print_blocks(&pixels, width, height);
+    Ok(true)
+}
+
+/// Two pixels per character cell: the upper half block takes the top pixel as
+/// its foreground and the bottom one as background. With `NO_COLOR` set the
+/// cells are shaded with ASCII by coverage instead.
+fn print_blocks(pixels: &[u32], width: usize, height: usize) {
+    const SHADES: &[u8] = b" .:-=+*#%@";
+    let plain = env::var_os("NO_COLOR").is_some();
+    let mut out = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let top = pixels[y * width + x];
+            let bottom = if y + 1 < height {
+                pixels[(y + 1) * width + x]
+            } else {
+                0
+            };
+            if plain {
+                let alpha = ((top >> 24) + (bottom >> 24)) as usize / 2;
+                out.push(SHADES[alpha * SHADES.len() / 256] as char);
+            } else {
+                let ([tr, tg, tb], [br, bg, bb]) = (composite(top), composite(bottom));
+                out.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                    tr, tg, tb, br, bg, bb
+                ));
+            }
+        }
+        if !plain {
+            out.push_str("\x1b[0m");
+        }
+        out.push('\n');
+    }
+    print!("{}", out);
+}
+
+/// A premultiplied ARGB pixel over `PREVIEW_BACKGROUND`.
+fn composite(px: u32) -> [u8; 3] {
+    let inv = 255 - (px >> 24);
+    [16, 8, 0].map(|shift| {
+        let bg = (PREVIEW_BACKGROUND >> shift) & 0xff;
+        (((px >> shift) & 0xff) + bg * inv / 255).min(255) as u8
+    })
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+/// Values from `--state`, read by the renderer with the sample value as fallback.
+#[derive(Default)]
+pub struct State(HashMap<String, Value>);
+
+impl State {
+    pub fn num(&self, key: &str, default: u32) -> u32 {
+        match self.0.get(key) {
+            Some(Value::Number(n)) => n.round().max(0.0) as u32,
+            Some(Value::Bool(b)) => *b as u32,
+            _ => default,
+        }
+    }
+
+    pub fn text(&self, key: &str, default: &str) -> String {
+        match self.0.get(key) {
+            Some(Value::Text(t)) => t.clone(),
+            Some(Value::Number(n)) => n.to_string(),
+            _ => default.to_string(),
+        }
+    }
+
+    pub fn flag(&self, key: &str, default: bool) -> bool {
+        self.num(key, default as u32) != 0
+    }
+
+    /// A number, or the index of a name in `names`, e.g. battery states.
+    pub fn choice(&self, key: &str, names: &[&str], default: u32) -> u32 {
+        match self.0.get(key) {
+            Some(Value::Text(t)) => names
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(t))
+                .map_or(default, |i| i as u32),
+            _ => self.num(key, default),
+        }
+    }
+
+    /// Parses a flat JSON object, nested objects and arrays aren't needed.
+    fn parse(json: &str) -> Result<Self, LeanbarError> {
+        let mut chars = json.trim().chars().peekable();
+        let mut values = HashMap::new();
+        expect(&mut chars, '{')?;
+        skip_whitespace(&mut chars);
+        if chars.peek() == Some(&'}') {
+            return Ok(Self(values));
+        }
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            expect(&mut chars, ':')?;
+            skip_whitespace(&mut chars);
+            values.insert(key, parse_value(&mut chars)?);
+            skip_whitespace(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Self(values)),
+                _ => return Err(LeanbarError::Preview("expected ',' or '}'".into())),
+            }
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: char) -> Result<(), LeanbarError> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        _ => Err(LeanbarError::Preview(format!("expected '{}'", expected))),
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, LeanbarError> {
+    expect(chars, '"')?;
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| LeanbarError::Preview(format!("bad escape \\u{}", hex)))?;
+                    s.push(c);
+                }
+                Some(c) => s.push(c),
+                None => break,
+            },
+            Some(c) => s.push(c),
+            None => break,
+        }
+    }
+    Err(LeanbarError::Preview("unterminated string".into()))
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, LeanbarError> {
+    if chars.peek() == Some(&'"') {
+        return parse_string(chars).map(Value::Text);
+    }
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-.".contains(*c)) {
+        word.push(c);
+    }
+    match word.as_str() {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => word
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| LeanbarError::Preview(format!("bad value: {}", word))),
+    }
+}
+
+/// Writes premultiplied ARGB pixels as an RGB PNG over `PREVIEW_BACKGROUND`.
+/// The image data is stored uncompressed, previews are small.
+fn write_png(path: &Path, pixels: &[u32], width: usize, height: usize) -> Result<(), LeanbarError> {
+    let mut raw = Vec::with_capacity(height * (1 + width * 3));
+    for row in pixels.chunks(width) {
+        raw.push(0); // No filter
+        for &px in row {
+            raw.extend_from_slice(&composite(px));
+        }
+    }
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit RGB, default compression, filter and no interlacing
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    fs::write(path, png)?;
+    Ok(())
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
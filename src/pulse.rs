@@ -0,0 +1,455 @@
+//! Minimal PulseAudio native protocol client, enough to read sink and source
+//! volumes, to be told when they change and to switch the default sink. Used
+//! where PipeWire's `wpctl` is not around, pipewire-pulse speaks the same
+//! protocol.
+//!
+//! Every packet is a 20 byte descriptor followed by a "tagstruct", a sequence of
+//! values each prefixed with a one byte type tag. Only the command channel is
+//! used, leanbar never opens streams.
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+const PROTOCOL_VERSION: u32 = 32;
+const DESCRIPTOR_LEN: usize = 20;
+const COMMAND_CHANNEL: u32 = u32::MAX;
+const MAX_PACKET_LEN: usize = 1024 * 1024;
+const COOKIE_LEN: usize = 256;
+
+const COMMAND_ERROR: u32 = 0;
+const COMMAND_REPLY: u32 = 2;
+const COMMAND_AUTH: u32 = 8;
+const COMMAND_SET_CLIENT_NAME: u32 = 9;
+const COMMAND_GET_SINK_INFO: u32 = 21;
+const COMMAND_GET_SINK_INFO_LIST: u32 = 22;
+const COMMAND_GET_SOURCE_INFO: u32 = 23;
+const COMMAND_SUBSCRIBE: u32 = 35;
+const COMMAND_SET_DEFAULT_SINK: u32 = 44;
+const COMMAND_SUBSCRIBE_EVENT: u32 = 66;
+
+const TAG_STRING: u8 = b't';
+const TAG_STRING_NULL: u8 = b'N';
+const TAG_U32: u8 = b'L';
+const TAG_U8: u8 = b'B';
+const TAG_SAMPLE_SPEC: u8 = b'a';
+const TAG_ARBITRARY: u8 = b'x';
+const TAG_TRUE: u8 = b'1';
+const TAG_FALSE: u8 = b'0';
+const TAG_CHANNEL_MAP: u8 = b'm';
+const TAG_CVOLUME: u8 = b'v';
+const TAG_USEC: u8 = b'U';
+const TAG_VOLUME: u8 = b'V';
+const TAG_PROPLIST: u8 = b'P';
+const TAG_FORMAT_INFO: u8 = b'f';
+
+const INVALID_INDEX: u32 = u32::MAX;
+const VOLUME_NORM: u32 = 0x10000;
+
+pub const SUBSCRIPTION_SINK: u32 = 0x0001;
+pub const SUBSCRIPTION_SOURCE: u32 = 0x0002;
+pub const SUBSCRIPTION_SERVER: u32 = 0x0080;
+
+/// Facility of a subscription event, `SUBSCRIPTION_*` without the mask bits.
+pub const FACILITY_SINK: u32 = 0;
+pub const FACILITY_SOURCE: u32 = 1;
+pub const FACILITY_SERVER: u32 = 7;
+const FACILITY_MASK: u32 = 0x0F;
+
+/// A sink or source with its volume, the loudest channel in percent.
+pub struct Device {
+    pub name: String,
+    pub description: String,
+    pub percent: u32,
+    pub muted: bool,
+}
+
+pub struct Connection {
+    stream: UnixStream,
+    tag: u32,
+    /// Negotiated protocol version, later versions append fields to replies.
+    version: u32,
+    /// Facilities of events that arrived while waiting for a reply.
+    events: VecDeque<u32>,
+}
+
+impl Connection {
+    /// Connects to the server from `$PULSE_SERVER` or the per user socket and
+    /// authenticates with the cookie, if there is one.
+    pub fn connect() -> io::Result<Self> {
+        let path = socket_path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no PulseAudio socket"))?;
+        let stream = UnixStream::connect(&path)?;
+        let mut connection = Self {
+            stream,
+            tag: 0,
+            version: PROTOCOL_VERSION,
+            events: VecDeque::new(),
+        };
+
+        let mut auth = Tags::default();
+        auth.u32(PROTOCOL_VERSION);
+        auth.arbitrary(&cookie());
+        let reply = connection.request(COMMAND_AUTH, auth)?;
+        // The upper bits flag shared memory support
+        let server_version = Reader::new(&reply).u32()? & 0xFFFF;
+        connection.version = PROTOCOL_VERSION.min(server_version);
+
+        let mut name = Tags::default();
+        name.proplist(&[("application.name", "leanbar")]);
+        connection.request(COMMAND_SET_CLIENT_NAME, name)?;
+        Ok(connection)
+    }
+
+    /// A sink, `@DEFAULT_SINK@` names the default one.
+    pub fn sink(&mut self, name: &str) -> io::Result<Device> {
+        self.device(COMMAND_GET_SINK_INFO, name)
+    }
+
+    /// A source, `@DEFAULT_SOURCE@` names the default one.
+    pub fn source(&mut self, name: &str) -> io::Result<Device> {
+        self.device(COMMAND_GET_SOURCE_INFO, name)
+    }
+
+    fn device(&mut self, command: u32, name: &str) -> io::Result<Device> {
+        let mut args = Tags::default();
+        args.u32(INVALID_INDEX);
+        args.string(name);
+        let reply = self.request(command, args)?;
+        read_device(&mut Reader::new(&reply))
+    }
+
+    /// Every sink, the list reply is the sink info of each one after another so
+    /// all of their fields have to be walked.
+    pub fn sinks(&mut self) -> io::Result<Vec<Device>> {
+        let reply = self.request(COMMAND_GET_SINK_INFO_LIST, Tags::default())?;
+        let mut reader = Reader::new(&reply);
+        let mut sinks = Vec::new();
+        while reader.pos < reply.len() {
+            sinks.push(read_device(&mut reader)?);
+            reader.sink_tail(self.version)?;
+        }
+        Ok(sinks)
+    }
+
+    pub fn set_default_sink(&mut self, name: &str) -> io::Result<()> {
+        let mut args = Tags::default();
+        args.string(name);
+        self.request(COMMAND_SET_DEFAULT_SINK, args).map(|_| ())
+    }
+
+    /// Asks for events of the `SUBSCRIPTION_*` facilities in `mask`.
+    pub fn subscribe(&mut self, mask: u32) -> io::Result<()> {
+        let mut args = Tags::default();
+        args.u32(mask);
+        self.request(COMMAND_SUBSCRIBE, args).map(|_| ())
+    }
+
+    /// Blocks until the next subscription event, returns its `FACILITY_*`.
+    pub fn next_event(&mut self) -> io::Result<u32> {
+        if let Some(facility) = self.events.pop_front() {
+            return Ok(facility);
+        }
+        loop {
+            let packet = self.read_packet()?;
+            let mut reader = Reader::new(&packet);
+            if reader.u32()? == COMMAND_SUBSCRIBE_EVENT {
+                reader.u32()?; // tag
+                return Ok(reader.u32()? & FACILITY_MASK);
+            }
+        }
+    }
+
+    /// Sends a command and waits for its reply, returning the reply's values
+    /// after the command and tag.
+    fn request(&mut self, command: u32, args: Tags) -> io::Result<Vec<u8>> {
+        self.tag = self.tag.wrapping_add(1);
+        let mut payload = Tags::default();
+        payload.u32(command);
+        payload.u32(self.tag);
+        payload.0.extend_from_slice(&args.0);
+
+        let mut packet = Vec::with_capacity(DESCRIPTOR_LEN + payload.0.len());
+        packet.extend_from_slice(&(payload.0.len() as u32).to_be_bytes());
+        packet.extend_from_slice(&COMMAND_CHANNEL.to_be_bytes());
+        packet.extend_from_slice(&[0; 12]); // offset and flags
+        packet.extend_from_slice(&payload.0);
+        self.stream.write_all(&packet)?;
+
+        loop {
+            let packet = self.read_packet()?;
+            let mut reader = Reader::new(&packet);
+            let kind = reader.u32()?;
+            let tag = reader.u32()?;
+            match kind {
+                COMMAND_SUBSCRIBE_EVENT => self.events.push_back(reader.u32()? & FACILITY_MASK),
+                COMMAND_REPLY if tag == self.tag => return Ok(packet[reader.pos..].to_vec()),
+                COMMAND_ERROR if tag == self.tag => {
+                    return Err(io::Error::other(format!(
+                        "PulseAudio error {} for command {}",
+                        reader.u32()?,
+                        command
+                    )));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads the next packet on the command channel, anything else is skipped.
+    fn read_packet(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            let mut descriptor = [0u8; DESCRIPTOR_LEN];
+            self.stream.read_exact(&mut descriptor)?;
+            let len = u32::from_be_bytes(descriptor[0..4].try_into().unwrap()) as usize;
+            let channel = u32::from_be_bytes(descriptor[4..8].try_into().unwrap());
+            if len > MAX_PACKET_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "PulseAudio packet too large",
+                ));
+            }
+            let mut payload = vec![0u8; len];
+            self.stream.read_exact(&mut payload)?;
+            if channel == COMMAND_CHANNEL {
+                return Ok(payload);
+            }
+        }
+    }
+}
+
+/// Sink and source info replies start with the same fields, the volume and mute
+/// flag come before anything that depends on the protocol version.
+fn read_device(reader: &mut Reader) -> io::Result<Device> {
+    reader.u32()?; // index
+    let name = reader.string()?.unwrap_or_default();
+    let description = reader.string()?.unwrap_or_default();
+    reader.sample_spec()?;
+    reader.channel_map()?;
+    reader.u32()?; // owner module
+    let volumes = reader.cvolume()?;
+    let muted = reader.bool()?;
+    let loudest = volumes.into_iter().max().unwrap_or(0) as u64;
+    Ok(Device {
+        name,
+        description,
+        percent: ((loudest * 100 + VOLUME_NORM as u64 / 2) / VOLUME_NORM as u64) as u32,
+        muted,
+    })
+}
+
+/// `$PULSE_SERVER` if it names a local socket, otherwise the default
+/// `$XDG_RUNTIME_DIR/pulse/native`.
+fn socket_path() -> Option<PathBuf> {
+    if let Ok(server) = env::var("PULSE_SERVER") {
+        let path = server.strip_prefix("unix:").unwrap_or(&server);
+        if path.starts_with('/') {
+            return Some(PathBuf::from(path));
+        }
+    }
+    let runtime_dir = env::var("XDG_RUNTIME_DIR").ok()?;
+    Some(PathBuf::from(runtime_dir).join("pulse").join("native"))
+}
+
+/// The auth cookie. pipewire-pulse doesn't check it, so when no cookie file
+/// exists zeroes are sent rather than giving up.
+fn cookie() -> Vec<u8> {
+    let mut candidates = Vec::new();
+    if let Ok(path) = env::var("PULSE_COOKIE") {
+        candidates.push(PathBuf::from(path));
+    }
+    if let Ok(config) = env::var("XDG_CONFIG_HOME") {
+        candidates.push(PathBuf::from(config).join("pulse").join("cookie"));
+    }
+    if let Ok(home) = env::var("HOME") {
+        candidates.push(PathBuf::from(&home).join(".config/pulse/cookie"));
+        candidates.push(PathBuf::from(&home).join(".pulse-cookie"));
+    }
+    candidates
+        .into_iter()
+        .filter_map(|path| fs::read(path).ok())
+        .find(|cookie| cookie.len() == COOKIE_LEN)
+        .unwrap_or_else(|| vec![0; COOKIE_LEN])
+}
+
+/// Builds a tagstruct.
+#[derive(Default)]
+struct Tags(Vec<u8>);
+
+impl Tags {
+    fn u32(&mut self, value: u32) {
+        self.0.push(TAG_U32);
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.0.push(TAG_STRING);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+    }
+
+    fn arbitrary(&mut self, value: &[u8]) {
+        self.0.push(TAG_ARBITRARY);
+        self.0
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.0.extend_from_slice(value);
+    }
+
+    /// Property values are stored with their terminating NUL.
+    fn proplist(&mut self, properties: &[(&str, &str)]) {
+        self.0.push(TAG_PROPLIST);
+        for (key, value) in properties {
+            self.string(key);
+            self.u32(value.len() as u32 + 1);
+            let mut data = value.as_bytes().to_vec();
+            data.push(0);
+            self.arbitrary(&data);
+        }
+        self.0.push(TAG_STRING_NULL);
+    }
+}
+
+/// Reads values out of a tagstruct, checking each tag.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "truncated tagstruct"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn tag(&mut self, expected: u8) -> io::Result<()> {
+        if self.take(1)?[0] == expected {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected tag '{}'", expected as char),
+            ))
+        }
+    }
+
+    fn raw_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn raw_u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.tag(TAG_U32)?;
+        self.raw_u32()
+    }
+
+    fn string(&mut self) -> io::Result<Option<String>> {
+        if self.data.get(self.pos) == Some(&TAG_STRING_NULL) {
+            self.pos += 1;
+            return Ok(None);
+        }
+        self.tag(TAG_STRING)?;
+        let rest = &self.data[self.pos..];
+        let end = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unterminated string"))?;
+        let value = String::from_utf8_lossy(&rest[..end]).into_owned();
+        self.pos += end + 1;
+        Ok(Some(value))
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        match self.raw_u8()? {
+            TAG_TRUE => Ok(true),
+            TAG_FALSE => Ok(false),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "expected bool")),
+        }
+    }
+
+    /// Format, channel count and rate, none of which leanbar needs.
+    fn sample_spec(&mut self) -> io::Result<()> {
+        self.tag(TAG_SAMPLE_SPEC)?;
+        self.take(2 + 4).map(|_| ())
+    }
+
+    fn channel_map(&mut self) -> io::Result<()> {
+        self.tag(TAG_CHANNEL_MAP)?;
+        let channels = self.raw_u8()? as usize;
+        self.take(channels).map(|_| ())
+    }
+
+    fn cvolume(&mut self) -> io::Result<Vec<u32>> {
+        self.tag(TAG_CVOLUME)?;
+        let channels = self.raw_u8()?;
+        (0..channels).map(|_| self.raw_u32()).collect()
+    }
+
+    fn skip(&mut self, tag: u8, len: usize) -> io::Result<()> {
+        self.tag(tag)?;
+        self.take(len).map(|_| ())
+    }
+
+    fn proplist(&mut self) -> io::Result<()> {
+        self.tag(TAG_PROPLIST)?;
+        while self.string()?.is_some() {
+            let len = self.u32()? as usize;
+            self.skip(TAG_ARBITRARY, 4 + len)?;
+        }
+        Ok(())
+    }
+
+    /// The fields of a sink info after its mute flag, by the protocol version
+    /// that added them.
+    fn sink_tail(&mut self, version: u32) -> io::Result<()> {
+        self.u32()?; // monitor source
+        self.string()?; // monitor source name
+        self.skip(TAG_USEC, 8)?; // latency
+        self.string()?; // driver
+        self.u32()?; // flags
+        if version >= 13 {
+            self.proplist()?;
+            self.skip(TAG_USEC, 8)?; // configured latency
+        }
+        if version >= 15 {
+            self.skip(TAG_VOLUME, 4)?; // base volume
+            self.u32()?; // state
+            self.u32()?; // volume steps
+            self.u32()?; // card
+        }
+        if version >= 16 {
+            for _ in 0..self.u32()? {
+                self.string()?; // name
+                self.string()?; // description
+                self.u32()?; // priority
+                if version >= 24 {
+                    self.u32()?; // available
+                }
+            }
+            self.string()?; // active port
+        }
+        if version >= 21 {
+            self.tag(TAG_U8)?;
+            let formats = self.raw_u8()?;
+            for _ in 0..formats {
+                self.tag(TAG_FORMAT_INFO)?;
+                self.skip(TAG_U8, 1)?; // encoding
+                self.proplist()?;
+            }
+        }
+        Ok(())
+    }
+}
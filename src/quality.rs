@@ -0,0 +1,61 @@
+//! Render quality policy. Below `reduce_quality_below` percent on battery the bar
+//! drops everything that costs CPU without carrying information, and goes back to
+//! full quality once the charger is plugged in.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{BATTERY_PERCENT, BATTERY_STATE, config, config::TextEffect};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Quality {
+    Full,
+    /// No change animations, no text effect passes and sparklines redraw less often.
+    Reduced,
+}
+
+impl Quality {
+    pub fn text_effect(self) -> TextEffect {
+        match self {
+            Quality::Full => config::get().text_effect,
+            Quality::Reduced => TextEffect::None,
+        }
+    }
+
+    pub fn animations(self) -> bool {
+        self == Quality::Full && config::get().highlight_changes
+    }
+
+    /// New samples a sparkline collects before it is redrawn.
+    pub fn sparkline_interval(self) -> u32 {
+        match self {
+            Quality::Full => 1,
+            Quality::Reduced => 5,
+        }
+    }
+}
+
+static REDUCED: AtomicBool = AtomicBool::new(false);
+
+/// Quality the renderer should use right now.
+pub fn current() -> Quality {
+    let threshold = config::get().reduce_quality_below;
+    // 1: Discharging, see BATTERY_STATE
+    let reduced = threshold > 0
+        && BATTERY_STATE.load(Ordering::Acquire) == 1
+        && BATTERY_PERCENT.load(Ordering::Acquire) < threshold;
+    if REDUCED.swap(reduced, Ordering::AcqRel) != reduced {
+        println!(
+            "[Quality] {}",
+            if reduced {
+                "battery low, reducing render quality"
+            } else {
+                "restoring full render quality"
+            }
+        );
+    }
+    if reduced {
+        Quality::Reduced
+    } else {
+        Quality::Full
+    }
+}
@@ -0,0 +1,27 @@
+//! Per second rates from the ever growing counters the kernel exposes, shared by
+//! the disk and network modules.
+
+use std::time::Instant;
+
+pub struct Rate {
+    last: Option<(u64, Instant)>,
+}
+
+impl Rate {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Feeds the counter's current value and returns how much it grew per second
+    /// since the previous sample. The first sample only primes the rate, a counter
+    /// that went backwards (device reset) reads as 0.
+    pub fn update(&mut self, value: u64) -> Option<u64> {
+        let now = Instant::now();
+        let (last_value, last_time) = self.last.replace((value, now))?;
+        let elapsed_ms = now.duration_since(last_time).as_millis() as u64;
+        if elapsed_ms == 0 {
+            return None;
+        }
+        Some(value.saturating_sub(last_value) * 1000 / elapsed_ms)
+    }
+}
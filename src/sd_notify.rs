@@ -0,0 +1,88 @@
+//! The systemd service notification protocol, for running leanbar as a
+//! `Type=notify` user service with `WatchdogSec=`: datagrams such as `READY=1`
+//! to the socket in `NOTIFY_SOCKET`.
+
+use std::env;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::time::{Duration, Instant};
+
+pub struct Notifier {
+    socket: UnixDatagram,
+    path: String,
+    /// How often the watchdog is fed, half the timeout systemd set.
+    watchdog_interval: Option<Duration>,
+    last_ping: Instant,
+}
+
+impl Notifier {
+    /// None when not started by systemd with a notification socket.
+    pub fn from_env() -> Option<Self> {
+        let path = env::var("NOTIFY_SOCKET").ok().filter(|p| !p.is_empty())?;
+        let socket = match UnixDatagram::unbound() {
+            Ok(socket) => socket,
+            Err(e) => {
+                eprintln!("[Notify] Failed to create a socket: {}", e);
+                return None;
+            }
+        };
+        // The watchdog belongs to the main process of the service
+        let for_us = env::var("WATCHDOG_PID")
+            .ok()
+            .is_none_or(|pid| pid.parse() == Ok(process::id()));
+        let watchdog_interval = env::var("WATCHDOG_USEC")
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| *usec > 0 && for_us)
+            .map(|usec| Duration::from_micros(usec / 2));
+        Some(Self {
+            socket,
+            path,
+            watchdog_interval,
+            last_ping: Instant::now(),
+        })
+    }
+
+    /// Sends a state such as `READY=1` or `STOPPING=1`.
+    pub fn notify(&self, state: &str) {
+        if let Err(e) = self.send(state.as_bytes()) {
+            eprintln!("[Notify] Sending {} failed: {}", state, e);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn send(&self, datagram: &[u8]) -> std::io::Result<usize> {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+
+        // An @ stands for the abstract namespace
+        match self.path.strip_prefix('@') {
+            Some(name) => self
+                .socket
+                .send_to_addr(datagram, &SocketAddr::from_abstract_name(name)?),
+            None => self.socket.send_to(datagram, &self.path),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn send(&self, datagram: &[u8]) -> std::io::Result<usize> {
+        self.socket.send_to(datagram, &self.path)
+    }
+
+    /// How long the event loop may wait before `feed_watchdog` is due.
+    pub fn watchdog_timeout(&self) -> Option<Duration> {
+        let interval = self.watchdog_interval?;
+        Some(interval.saturating_sub(self.last_ping.elapsed()))
+    }
+
+    /// Tells systemd the event loop is alive once per interval, called on
+    /// every pass through it.
+    pub fn feed_watchdog(&mut self) {
+        if let Some(interval) = self.watchdog_interval
+            && self.last_ping.elapsed() >= interval
+        {
+            self.notify("WATCHDOG=1");
+            self.last_ping = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Signals leanbar acts on. SIGTERM ends the event loop so the bars are
+//! destroyed before exiting and SIGHUP does the same and starts over, reading
+//! the config again. Realtime signals make a module refresh right away instead
+//! of at its next interval, like i3blocks: with `updates_signal = 3`, `pkill
+//! -RTMIN+3 leanbar` counts the updates again.
+//!
+//! The signals are blocked in every thread, SIGTERM and SIGHUP are read from a
+//! signalfd in the main loop and the realtime ones taken with `sigwait` by a
+//! thread of their own, so nothing runs in a signal handler. Child processes
+//! start with an empty signal mask again.
+
+use std::env;
+use std::ffi::c_int;
+#[cfg(target_os = "linux")]
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Module};
+
+/// Large enough for `sigset_t` on every supported platform.
+#[repr(C)]
+struct SigSet([u64; 16]);
+
+#[cfg(target_os = "linux")]
+const SIG_BLOCK: c_int = 0;
+#[cfg(not(target_os = "linux"))]
+const SIG_BLOCK: c_int = 1;
+const SIGHUP: c_int = 1;
+const SIGTERM: c_int = 15;
+#[cfg(target_os = "linux")]
+const SFD_NONBLOCK: c_int = 0o4000;
+#[cfg(target_os = "linux")]
+const SFD_CLOEXEC: c_int = 0o2000000;
+/// `struct signalfd_siginfo`, `ssi_signo` comes first.
+#[cfg(target_os = "linux")]
+const SIGINFO_SIZE: usize = 128;
+
+unsafe extern "C" {
+    fn sigemptyset(set: *mut SigSet) -> c_int;
+    fn sigaddset(set: *mut SigSet, signal: c_int) -> c_int;
+    fn pthread_sigmask(how: c_int, set: *const SigSet, old: *mut SigSet) -> c_int;
+    fn sigwait(set: *const SigSet, signal: *mut c_int) -> c_int;
+    #[cfg(target_os = "linux")]
+    fn signalfd(fd: c_int, mask: *const SigSet, flags: c_int) -> c_int;
+    #[cfg(target_os = "linux")]
+    fn __libc_current_sigrtmin() -> c_int;
+    #[cfg(target_os = "linux")]
+    fn __libc_current_sigrtmax() -> c_int;
+}
+
+/// Modules signalled since they last refreshed.
+static PENDING: Mutex<Vec<Module>> = Mutex::new(Vec::new());
+static SIGNALLED: Condvar = Condvar::new();
+/// An `Exit` asked for through the control socket, 0 for none.
+static REQUESTED_EXIT: AtomicU8 = AtomicU8::new(0);
+
+/// How the event loop ends.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Exit {
+    Quit,
+    /// Start over with the same arguments, reading the config again.
+    Reload,
+}
+
+/// `SIGRTMIN` and `SIGRTMAX`, None where there are no realtime signals.
+#[cfg(target_os = "linux")]
+fn realtime_range() -> Option<(c_int, c_int)> {
+    Some(unsafe { (__libc_current_sigrtmin(), __libc_current_sigrtmax()) })
+}
+
+#[cfg(target_os = "freebsd")]
+fn realtime_range() -> Option<(c_int, c_int)> {
+    Some((65, 126))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+fn realtime_range() -> Option<(c_int, c_int)> {
+    None
+}
+
+/// Blocks SIGTERM, SIGHUP and the configured realtime signals and starts
+/// waiting for the realtime ones, returns the signalfd the others arrive on
+/// where there is one. Has to run before any other thread starts, threads
+/// inherit the mask of the one spawning them.
+pub fn start() -> Option<OwnedFd> {
+    let mut set = SigSet([0; 16]);
+    unsafe {
+        sigemptyset(&mut set);
+        sigaddset(&mut set, SIGHUP);
+        sigaddset(&mut set, SIGTERM);
+    }
+    let exit_fd = exit_signal_fd(&set);
+    if exit_fd.is_some() {
+        if unsafe { pthread_sigmask(SIG_BLOCK, &set, std::ptr::null_mut()) } != 0 {
+            eprintln!("[Signals] Blocking SIGTERM and SIGHUP failed");
+        }
+    } else {
+        eprintln!("[Signals] No signalfd, SIGTERM and SIGHUP end leanbar right away");
+    }
+    start_refresh();
+    exit_fd
+}
+
+#[cfg(target_os = "linux")]
+fn exit_signal_fd(set: &SigSet) -> Option<OwnedFd> {
+    let fd = unsafe { signalfd(-1, set, SFD_NONBLOCK | SFD_CLOEXEC) };
+    (fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exit_signal_fd(_set: &SigSet) -> Option<OwnedFd> {
+    None
+}
+
+/// The `Exit` a signal read from the signalfd asks for.
+#[cfg(target_os = "linux")]
+pub fn read_exit_signal(fd: &OwnedFd) -> Option<Exit> {
+    let mut info = [0u8; SIGINFO_SIZE];
+    if rustix::io::read(fd, &mut info).ok()? < 4 {
+        return None;
+    }
+    match u32::from_ne_bytes([info[0], info[1], info[2], info[3]]) as c_int {
+        SIGHUP => Some(Exit::Reload),
+        SIGTERM => Some(Exit::Quit),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_exit_signal(_fd: &OwnedFd) -> Option<Exit> {
+    None
+}
+
+/// Ends the event loop from another thread, which has to wake the main thread.
+pub fn request_exit(exit: Exit) {
+    let code = match exit {
+        Exit::Quit => 1,
+        Exit::Reload => 2,
+    };
+    REQUESTED_EXIT.store(code, Ordering::Release);
+}
+
+pub fn requested_exit() -> Option<Exit> {
+    match REQUESTED_EXIT.load(Ordering::Acquire) {
+        1 => Some(Exit::Quit),
+        2 => Some(Exit::Reload),
+        _ => None,
+    }
+}
+
+/// Replaces the process with a new leanbar with the same arguments. Only
+/// returns if that fails, by exiting.
+pub fn reload() -> ! {
+    let mut args = env::args_os();
+    let program = args.next().unwrap_or_else(|| "leanbar".into());
+    println!("Reloading");
+    let e = Command::new(program).args(args).exec();
+    eprintln!("[Signals] Reloading failed: {}", e);
+    process::exit(1);
+}
+
+/// Blocks the configured realtime signals and starts waiting for them.
+fn start_refresh() {
+    let signals = &config::get().module_signals;
+    if signals.is_empty() {
+        return;
+    }
+    let Some((min, max)) = realtime_range() else {
+        eprintln!("[Signals] No realtime signals on this platform");
+        return;
+    };
+    let mut set = SigSet([0; 16]);
+    unsafe { sigemptyset(&mut set) };
+    for offset in signals.values() {
+        if min + *offset as c_int > max {
+            eprintln!("[Signals] SIGRTMIN+{} is past SIGRTMAX", offset);
+            continue;
+        }
+        unsafe { sigaddset(&mut set, min + *offset as c_int) };
+    }
+    if unsafe { pthread_sigmask(SIG_BLOCK, &set, std::ptr::null_mut()) } != 0 {
+        eprintln!("[Signals] Blocking the realtime signals failed");
+        return;
+    }
+
+    let _ = thread::Builder::new().stack_size(64 * 1024).spawn(move || {
+        println!("[Signals Thread] Started");
+        loop {
+            let mut signal = 0;
+            if unsafe { sigwait(&set, &mut signal) } != 0 {
+                continue;
+            }
+            let Ok(mut pending) = PENDING.lock() else {
+                return;
+            };
+            for (module, offset) in signals {
+                if min + *offset as c_int == signal && !pending.contains(module) {
+                    pending.push(*module);
+                }
+            }
+            SIGNALLED.notify_all();
+        }
+    });
+}
+
+/// Sleeps like `thread::sleep`, returning early once `module`'s signal arrives.
+pub fn sleep(module: Module, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    let Ok(mut pending) = PENDING.lock() else {
+        thread::sleep(duration);
+        return;
+    };
+    loop {
+        if let Some(index) = pending.iter().position(|m| *m == module) {
+            pending.remove(index);
+            return;
+        }
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            return;
+        };
+        match SIGNALLED.wait_timeout(pending, left) {
+            Ok((guard, _)) => pending = guard,
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Open windows for the taskbar module, as wlr-foreign-toplevel-management
+//! reports them. Clicking an entry focuses its window or minimizes it when it
+//! already has focus, middle-clicking closes it.
+
+use std::sync::atomic::Ordering;
+
+use wayland_client::{Connection, Dispatch, QueueHandle, event_created_child};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use crate::{TASKBAR, TASKBAR_GENERATION, TaskbarEntry, app_state::AppState, config, health};
+
+#[derive(Default)]
+pub struct TaskbarState {
+    pub manager: Option<ZwlrForeignToplevelManagerV1>,
+    /// In the order they were opened, the same as `TASKBAR`.
+    toplevels: Vec<Toplevel>,
+}
+
+struct Toplevel {
+    handle: ZwlrForeignToplevelHandleV1,
+    title: String,
+    app_id: String,
+    activated: bool,
+    minimized: bool,
+}
+
+impl AppState {
+    /// Reports whether the taskbar can list windows, called after the initial
+    /// globals are known.
+    pub fn watch_toplevels(&mut self) {
+        if config::get().taskbar_entries == 0 {
+            health::disabled("taskbar", "taskbar_entries not set");
+        } else if self.taskbar.manager.is_none() {
+            health::disabled(
+                "taskbar",
+                "compositor lacks zwlr_foreign_toplevel_manager_v1",
+            );
+        } else {
+            health::ok("taskbar");
+        }
+    }
+
+    /// Focuses the window of a taskbar entry, or minimizes it when it has focus.
+    pub(crate) fn activate_toplevel(&self, index: u8) {
+        let (Some(toplevel), Some(seat)) = (self.taskbar.toplevels.get(index as usize), &self.seat)
+        else {
+            return;
+        };
+        if toplevel.activated && !toplevel.minimized {
+            toplevel.handle.set_minimized();
+        } else {
+            toplevel.handle.activate(seat);
+        }
+    }
+
+    /// Asks the window of a taskbar entry to close.
+    pub(crate) fn close_toplevel(&self, index: u8) {
+        if let Some(toplevel) = self.taskbar.toplevels.get(index as usize) {
+            toplevel.handle.close();
+        }
+    }
+
+    fn store_taskbar(&mut self) {
+        let entries: Vec<TaskbarEntry> = self
+            .taskbar
+            .toplevels
+            .iter()
+            .map(|toplevel| TaskbarEntry {
+                label: if toplevel.app_id.is_empty() {
+                    toplevel.title.clone()
+                } else {
+                    toplevel.app_id.clone()
+                },
+                activated: toplevel.activated,
+                minimized: toplevel.minimized,
+            })
+            .collect();
+        let Ok(mut guard) = TASKBAR.lock() else {
+            return;
+        };
+        if *guard == entries {
+            return;
+        }
+        *guard = entries;
+        drop(guard);
+        TASKBAR_GENERATION.fetch_add(1, Ordering::AcqRel);
+        self.redraw_and_commit();
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        manager: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(manager, event.opcode(), &event);
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.taskbar.toplevels.push(Toplevel {
+                    handle: toplevel,
+                    title: String::new(),
+                    app_id: String::new(),
+                    activated: false,
+                    minimized: false,
+                });
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                state.taskbar.manager = None;
+                health::disabled("taskbar", "the compositor stopped sending windows");
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(AppState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for AppState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        state.event_log.record(handle, event.opcode(), &event);
+        let Some(index) = state
+            .taskbar
+            .toplevels
+            .iter()
+            .position(|t| t.handle == *handle)
+        else {
+            return;
+        };
+        let toplevel = &mut state.taskbar.toplevels[index];
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => toplevel.title = title,
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.app_id = app_id,
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                let states = parse_states(&states);
+                toplevel.activated =
+                    states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Activated as u32));
+                toplevel.minimized =
+                    states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Minimized as u32));
+            }
+            // Title, app id and state changes are applied together
+            zwlr_foreign_toplevel_handle_v1::Event::Done => state.store_taskbar(),
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.taskbar.toplevels.remove(index).handle.destroy();
+                state.store_taskbar();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The state event's array, native endian `State` values.
+fn parse_states(array: &[u8]) -> Vec<u32> {
+    array
+        .chunks_exact(4)
+        .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
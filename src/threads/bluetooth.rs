@@ -0,0 +1,142 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use crate::{
+    BLUETOOTH_BATTERY, BLUETOOTH_CONNECTED, BLUETOOTH_DEVICE, BLUETOOTH_POWERED, dbus, health,
+    ping_main_thread,
+};
+
+const BLUEZ: &str = "org.bluez";
+const ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const BATTERY_INTERFACE: &str = "org.bluez.Battery1";
+const OBJECT_MANAGER: &str = "org.freedesktop.DBus.ObjectManager";
+
+/// Adapter power, the connected devices and the battery of the first one from
+/// BlueZ. Everything is read again when an adapter or device changes one of the
+/// properties shown, when one appears or goes away, and when BlueZ itself starts
+/// or stops.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Bluetooth Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::system, |connection| {
+                let Err(e) = watch(connection, &wake_fd);
+                health::degraded("bluetooth", format!("system bus connection lost: {}", e));
+            });
+            health::disabled("bluetooth", format!("no system bus: {}", e));
+        });
+}
+
+fn watch(connection: &mut dbus::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    connection.add_match(
+        "type='signal',sender='org.bluez',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path_namespace='/org/bluez'",
+    )?;
+    connection.add_match(
+        "type='signal',sender='org.bluez',interface='org.freedesktop.DBus.ObjectManager'",
+    )?;
+    connection.add_match(
+        "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='org.bluez'",
+    )?;
+
+    loop {
+        match connection.call(BLUEZ, "/", OBJECT_MANAGER, "GetManagedObjects", &[]) {
+            Ok(reply) => {
+                let objects = reply
+                    .into_iter()
+                    .next()
+                    .unwrap_or(dbus::Value::Struct(Vec::new()));
+                store(&objects, wake_fd);
+                health::ok("bluetooth");
+            }
+            // Not running (yet), it is picked up when it claims its name
+            Err(e) => {
+                BLUETOOTH_POWERED.store(255, Ordering::Release);
+                ping_main_thread(wake_fd);
+                health::disabled("bluetooth", format!("BlueZ unavailable: {}", e));
+            }
+        }
+        while !connection
+            .next_signal(None)?
+            .is_some_and(|signal| is_relevant(&signal))
+        {}
+    }
+}
+
+/// Whether a signal can change what is shown, device properties such as the
+/// signal strength change constantly while scanning.
+fn is_relevant(signal: &dbus::Message) -> bool {
+    if !signal.is_signal("org.freedesktop.DBus.Properties", "PropertiesChanged") {
+        return true;
+    }
+    let changed = signal.body.get(1);
+    match signal.body.first().and_then(dbus::Value::as_str) {
+        Some(ADAPTER_INTERFACE) => changed.is_some_and(|c| c.get("Powered").is_some()),
+        Some(DEVICE_INTERFACE) => {
+            changed.is_some_and(|c| c.get("Connected").is_some() || c.get("Alias").is_some())
+        }
+        Some(BATTERY_INTERFACE) => true,
+        _ => false,
+    }
+}
+
+/// Stores the state from `GetManagedObjects`, a dictionary from object path to
+/// the interfaces of the object and their properties.
+fn store(objects: &dbus::Value, wake_fd: &OwnedFd) {
+    let mut adapters = 0;
+    let mut powered = false;
+    let mut devices = Vec::new();
+    for (path, interfaces) in objects.entries() {
+        if let Some(adapter) = interfaces.get(ADAPTER_INTERFACE) {
+            adapters += 1;
+            powered |= adapter
+                .get("Powered")
+                .and_then(dbus::Value::as_bool)
+                .unwrap_or(false);
+        }
+        let connected = interfaces.get(DEVICE_INTERFACE).filter(|device| {
+            device
+                .get("Connected")
+                .and_then(dbus::Value::as_bool)
+                .unwrap_or(false)
+        });
+        if let Some(device) = connected {
+            let name = device
+                .get("Alias")
+                .or_else(|| device.get("Name"))
+                .and_then(dbus::Value::as_str)
+                .unwrap_or("");
+            // Headsets and mice that report their charge get the Battery1 interface
+            let battery = interfaces
+                .get(BATTERY_INTERFACE)
+                .and_then(|b| b.get("Percentage"))
+                .and_then(dbus::Value::as_i64)
+                .map_or(255, |p| p.clamp(0, 100) as u8);
+            devices.push((path.as_str().unwrap_or(""), name.to_string(), battery));
+        }
+    }
+    // Object paths keep the order stable while devices come and go
+    devices.sort();
+
+    let state = match (adapters, powered) {
+        (0, _) => 255,
+        (_, false) => 0,
+        (_, true) => 1,
+    };
+    let (name, battery) = devices
+        .first()
+        .map_or((String::new(), 255), |(_, name, battery)| {
+            (name.clone(), *battery)
+        });
+    if let Ok(mut device) = BLUETOOTH_DEVICE.lock() {
+        *device = name;
+    }
+    BLUETOOTH_BATTERY.store(battery, Ordering::Release);
+    BLUETOOTH_CONNECTED.store(devices.len().min(255) as u8, Ordering::Release);
+    BLUETOOTH_POWERED.store(state, Ordering::Release);
+    ping_main_thread(wake_fd);
+}
@@ -0,0 +1,185 @@
+use std::convert::Infallible;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{CONTAINERS_RUNNING, config, health, ping_main_thread};
+
+/// Between attempts to reach an engine that stopped or isn't running yet.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Running containers of Docker or Podman, listed again whenever the engine
+/// reports one starting or stopping. Both speak the same HTTP API on their
+/// socket, requests are HTTP/1.0 so responses come unchunked and end with the
+/// connection.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Containers Thread] Started");
+            let mut reported = false;
+            loop {
+                let Some(socket) = find_socket() else {
+                    if !reported {
+                        health::disabled("containers", "no Docker or Podman socket");
+                        reported = true;
+                    }
+                    thread::sleep(RECONNECT_DELAY);
+                    continue;
+                };
+                let Err(e) = watch(&socket, &wake_fd);
+                health::degraded("containers", format!("{}: {}", socket.display(), e));
+                if CONTAINERS_RUNNING.swap(u16::MAX, Ordering::AcqRel) != u16::MAX {
+                    ping_main_thread(&wake_fd);
+                }
+                reported = false;
+                thread::sleep(RECONNECT_DELAY);
+            }
+        });
+}
+
+/// `containers_socket`, otherwise a unix `$DOCKER_HOST` or the first of the
+/// usual Docker and Podman sockets that exists.
+fn find_socket() -> Option<PathBuf> {
+    let configured = &config::get().containers_socket;
+    if !configured.is_empty() {
+        return Some(config::expand_home(configured));
+    }
+    if let Some(path) = env::var("DOCKER_HOST")
+        .ok()
+        .and_then(|host| host.strip_prefix("unix://").map(PathBuf::from))
+    {
+        return Some(path);
+    }
+    let mut candidates = vec![PathBuf::from("/var/run/docker.sock")];
+    if let Ok(runtime) = env::var("XDG_RUNTIME_DIR") {
+        candidates.push(Path::new(&runtime).join("docker.sock"));
+        candidates.push(Path::new(&runtime).join("podman/podman.sock"));
+    }
+    candidates.push(PathBuf::from("/run/podman/podman.sock"));
+    candidates.into_iter().find(|path| path.exists())
+}
+
+fn watch(socket: &Path, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    let label = &config::get().containers_label;
+    let mut filters = String::from(r#"{"type":["container"],"event":["start","die"]"#);
+    if !label.is_empty() {
+        filters.push_str(&format!(r#","label":["{}"]"#, json_escape(label)));
+    }
+    filters.push('}');
+    let mut events = request(socket, &format!("/events?filters={}", url_encode(&filters)))?;
+    // Skip the response headers, whatever follows is events
+    let mut buf = [0u8; 4096];
+    let mut head = Vec::new();
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = events.read(&mut buf)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no events"));
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    check_status(&head)?;
+
+    loop {
+        let running = list_running(socket, label)?;
+        health::ok("containers");
+        if CONTAINERS_RUNNING.swap(running, Ordering::AcqRel) != running {
+            ping_main_thread(wake_fd);
+        }
+        // Only that something happened matters, the list says what
+        if events.read(&mut buf)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "event stream closed",
+            ));
+        }
+    }
+}
+
+fn list_running(socket: &Path, label: &str) -> io::Result<u16> {
+    let mut path = String::from("/containers/json");
+    if !label.is_empty() {
+        let filters = format!(r#"{{"label":["{}"]}}"#, json_escape(label));
+        path.push_str(&format!("?filters={}", url_encode(&filters)));
+    }
+    let mut response = Vec::new();
+    request(socket, &path)?.read_to_end(&mut response)?;
+    check_status(&response)?;
+    let body_start = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map_or(response.len(), |i| i + 4);
+    let count = count_objects(&response[body_start..]);
+    Ok(count.min(u16::MAX as usize - 1) as u16)
+}
+
+fn request(socket: &Path, path: &str) -> io::Result<UnixStream> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(format!("GET {} HTTP/1.0\r\nHost: localhost\r\n\r\n", path).as_bytes())?;
+    Ok(stream)
+}
+
+/// Fails unless the status line says 200.
+fn check_status(response: &[u8]) -> io::Result<()> {
+    let status_line = response.split(|b| *b == b'\n').next().unwrap_or_default();
+    let status_line = String::from_utf8_lossy(status_line);
+    match status_line.split(' ').nth(1) {
+        Some("200") => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "engine answered {}",
+            status_line.trim()
+        ))),
+    }
+}
+
+/// Objects directly inside the top level JSON array, strings skipped so
+/// braces in names and labels don't count.
+fn count_objects(json: &[u8]) -> usize {
+    let (mut depth, mut count) = (0usize, 0);
+    let (mut in_string, mut escaped) = (false, false);
+    for &b in json {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                if b == b'{' && depth == 1 {
+                    count += 1;
+                }
+                depth += 1;
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    count
+}
+
+fn json_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Percent encoding of everything but unreserved URL characters.
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
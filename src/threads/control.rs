@@ -0,0 +1,221 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::fd::OwnedFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    SINCE_S, app_state,
+    config::Module,
+    error::LeanbarError,
+    health, ping_main_thread,
+    signals::{self, Exit},
+    threads::{custom, poll},
+    timer,
+};
+
+/// A client gets this long to send its command and read the reply, the
+/// commands are served one at a time.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `$XDG_RUNTIME_DIR/leanbar/control.sock`, also hardcoded in `leanbar-ctl`.
+fn socket_path() -> Option<PathBuf> {
+    let runtime_dir = env::var("XDG_RUNTIME_DIR").ok()?;
+    Some(
+        PathBuf::from(runtime_dir)
+            .join("leanbar")
+            .join("control.sock"),
+    )
+}
+
+/// `leanbar ctl <command>` does what `leanbar-ctl <command>` does, for setups
+/// that only install the one binary.
+pub fn maybe_run_client(args: &[String]) -> Result<bool, LeanbarError> {
+    if args.get(1).map(String::as_str) != Some("ctl") {
+        return Ok(false);
+    }
+    print!("{}", send(&args[2..].join(" "))?);
+    Ok(true)
+}
+
+/// Sends one command to the running bar and returns its reply.
+fn send(command: &str) -> io::Result<String> {
+    let path = socket_path().ok_or_else(|| io::Error::other("XDG_RUNTIME_DIR not set"))?;
+    let mut stream = UnixStream::connect(&path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "failed to connect to {}: {}. Is leanbar running?",
+                path.display(),
+                e
+            ),
+        )
+    })?;
+    let mut reply = String::new();
+    stream.write_all(format!("{}\n", command).as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Serves one line commands from `leanbar-ctl` on the control socket.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            let Some(path) = socket_path() else {
+                health::disabled("control", "XDG_RUNTIME_DIR not set");
+                return;
+            };
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            if UnixStream::connect(&path).is_ok() {
+                health::disabled(
+                    "control",
+                    format!("another leanbar is listening on {}", path.display()),
+                );
+                return;
+            }
+            // A previous instance that crashed leaves its socket behind
+            let _ = fs::remove_file(&path);
+            let listener = match UnixListener::bind(&path) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    health::degraded("control", format!("bind {}: {}", path.display(), e));
+                    return;
+                }
+            };
+            println!("[Control Thread] Listening on {}", path.display());
+            health::ok("control");
+
+            for stream in listener.incoming().flatten() {
+                handle_client(stream, &wake_fd);
+            }
+        });
+}
+
+fn handle_client(stream: UnixStream, wake_fd: &OwnedFd) {
+    if stream.set_read_timeout(Some(CLIENT_TIMEOUT)).is_err()
+        || stream.set_write_timeout(Some(CLIENT_TIMEOUT)).is_err()
+    {
+        return;
+    }
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line).is_err() {
+        return;
+    }
+    let mut words = line.split_whitespace();
+    let reply = match words.next() {
+        Some("reload") => {
+            signals::request_exit(Exit::Reload);
+            ping_main_thread(wake_fd);
+            "ok\n".to_string()
+        }
+        Some("quit") => {
+            signals::request_exit(Exit::Quit);
+            ping_main_thread(wake_fd);
+            "ok\n".to_string()
+        }
+        Some("toggle") => match words.next().map(|name| (name, Module::parse(name))) {
+            Some((_, Some(module))) => {
+                let shown = app_state::toggle_module(module);
+                ping_main_thread(wake_fd);
+                if shown { "shown\n" } else { "hidden\n" }.to_string()
+            }
+            Some((name, None)) => format!("unknown module: {}\n", name),
+            None => "usage: leanbar-ctl toggle MODULE\n".to_string(),
+        },
+        Some("set") => match words.next() {
+            Some("custom.text") => {
+                // Whatever follows the key, quoted or not
+                let value = line
+                    .trim()
+                    .strip_prefix("set")
+                    .and_then(|rest| rest.trim_start().strip_prefix("custom.text"))
+                    .unwrap_or_default()
+                    .trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                custom::set_text(value);
+                ping_main_thread(wake_fd);
+                "ok\n".to_string()
+            }
+            Some(key) => format!("unknown setting: {}\n", key),
+            None => "usage: leanbar-ctl set custom.text TEXT\n".to_string(),
+        },
+        Some("modules") => health::report(),
+        Some("surface") => app_state::surface_report(),
+        Some("regions") => match (words.next(), words.next()) {
+            (None, _) => app_state::regions_report(),
+            (Some("overlay"), Some(state @ ("on" | "off"))) => {
+                app_state::set_region_overlay(state == "on");
+                ping_main_thread(wake_fd);
+                "ok\n".to_string()
+            }
+            _ => "usage: leanbar-ctl regions [overlay on|off]\n".to_string(),
+        },
+        Some("since") => match words.next() {
+            Some("start") => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs());
+                SINCE_S.store(now, Ordering::Release);
+                poll::update_since();
+                ping_main_thread(wake_fd);
+                "ok\n".to_string()
+            }
+            Some("stop") => {
+                SINCE_S.store(0, Ordering::Release);
+                poll::update_since();
+                ping_main_thread(wake_fd);
+                "ok\n".to_string()
+            }
+            _ => "usage: leanbar-ctl since start|stop\n".to_string(),
+        },
+        Some("timer") => {
+            let reply = match (words.next(), words.next().map(timer::parse_duration)) {
+                (Some("start"), None) => {
+                    timer::start(None);
+                    None
+                }
+                (Some("start"), Some(Some(duration))) => {
+                    timer::start(Some(duration));
+                    None
+                }
+                (Some("set"), Some(Some(duration))) => {
+                    timer::set(duration);
+                    None
+                }
+                (Some("stop"), None) => {
+                    timer::stop();
+                    None
+                }
+                (Some("reset"), None) => {
+                    timer::reset();
+                    None
+                }
+                _ => Some(
+                    "usage: leanbar-ctl timer start [10m|1h30m|90s]|stop|set DURATION|reset\n"
+                        .to_string(),
+                ),
+            };
+            reply.unwrap_or_else(|| {
+                if timer::update() {
+                    ping_main_thread(wake_fd);
+                }
+                "ok\n".to_string()
+            })
+        }
+        Some(other) => format!("unknown command: {}\n", other),
+        None => "usage: leanbar-ctl modules|surface|regions|since start|since stop|timer start|timer stop|toggle MODULE|set custom.text TEXT|reload|quit\n".to_string(),
+    };
+    let _ = (&stream).write_all(reply.as_bytes());
+}
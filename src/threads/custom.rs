@@ -0,0 +1,210 @@
+use std::io::{self, BufRead, BufReader};
+use std::os::fd::OwnedFd;
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    CUSTOM_COLOR, CUSTOM_TEXT, CUSTOM_TOOLTIP,
+    config::{self, Module},
+    health,
+    json::Json,
+    ping_main_thread, signals,
+};
+
+/// Before a long running `custom_command` that exited is started again.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Text of `custom_command`. With a `custom_interval` the command runs that
+/// often and its last line is shown, a `custom_signal` runs it right away.
+/// Without one it keeps running and every line it prints replaces the text.
+/// With `custom_json` the lines are read as the JSON Waybar's custom modules
+/// print.
+pub fn start(wake_fd: OwnedFd) {
+    let command = &config::get().custom_command;
+    if command.is_empty() {
+        health::disabled("custom", "custom_command not set");
+        return;
+    }
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Custom Thread] Started");
+            let interval = config::get().custom_interval;
+            loop {
+                if interval == 0 {
+                    match follow(command, &wake_fd) {
+                        Ok(status) => health::degraded("custom", status),
+                        Err(e) => health::degraded("custom", format!("custom_command: {}", e)),
+                    }
+                    thread::sleep(RESTART_DELAY);
+                    continue;
+                }
+                match run(command) {
+                    Ok(text) => {
+                        health::ok("custom");
+                        if store(&text) {
+                            ping_main_thread(&wake_fd);
+                        }
+                    }
+                    // The last text stays until a run succeeds again
+                    Err(e) => health::degraded("custom", e),
+                }
+                signals::sleep(Module::Custom, Duration::from_secs(interval as u64));
+            }
+        });
+}
+
+/// Replaces the text from outside, with `leanbar-ctl set custom.text` or over
+/// D-Bus. A running `custom_command` replaces it again with its next line.
+pub fn set_text(text: &str) {
+    if let Ok(mut current) = CUSTOM_TEXT.lock() {
+        *current = text.to_string();
+    }
+}
+
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    shell
+}
+
+/// The last non-empty line the command printed.
+fn run(command: &str) -> Result<String, String> {
+    let output = shell(command)
+        .output()
+        .map_err(|e| format!("custom_command: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("custom_command exited with {}", output.status));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .unwrap_or_default()
+        .to_string())
+}
+
+/// Shows every line the command prints until it exits, returns how it did.
+fn follow(command: &str, wake_fd: &OwnedFd) -> io::Result<String> {
+    let mut child = shell(command).stdout(Stdio::piped()).spawn()?;
+    let Some(stdout) = child.stdout.take() else {
+        return Err(io::Error::other("no stdout"));
+    };
+    health::ok("custom");
+    let mut stdout = BufReader::new(stdout);
+    let mut line = Vec::new();
+    // Read errors end the loop like an exit, the child is still waited for
+    while stdout.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+        if store(String::from_utf8_lossy(&line).trim()) {
+            ping_main_thread(wake_fd);
+        }
+        line.clear();
+    }
+    drop(stdout);
+    let _ = child.kill();
+    Ok(format!("custom_command exited with {}", child.wait()?))
+}
+
+/// Stores a line of output, returns true if anything shown changed.
+fn store(line: &str) -> bool {
+    let output = if config::get().custom_json {
+        Output::parse(line)
+    } else {
+        Output {
+            text: line.to_string(),
+            ..Output::default()
+        }
+    };
+    let color = config::get()
+        .custom_colors
+        .iter()
+        .find(|(class, _)| output.classes.contains(class))
+        .map_or(0, |(_, color)| *color);
+    let mut changed = CUSTOM_COLOR.swap(color, Ordering::AcqRel) != color;
+    for (current, new) in [
+        (&CUSTOM_TEXT, output.text),
+        (&CUSTOM_TOOLTIP, output.tooltip),
+    ] {
+        if let Ok(mut current) = current.lock()
+            && *current != new
+        {
+            *current = new;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// The fields of Waybar's per-line JSON the custom module uses.
+#[derive(Default)]
+struct Output {
+    text: String,
+    tooltip: String,
+    classes: Vec<String>,
+}
+
+impl Output {
+    /// `{"text": "5", "tooltip": "...", "class": ["warning"], "percentage": 50}`.
+    /// Without a `text` the percentage is shown, a line that isn't an object
+    /// is shown as it is.
+    fn parse(line: &str) -> Self {
+        let json = match Json::parse(line) {
+            Some(json @ Json::Object(_)) => json,
+            _ => {
+                return Output {
+                    text: line.to_string(),
+                    ..Output::default()
+                };
+            }
+        };
+        let field = |key: &str| json.get(key).and_then(Json::as_str).unwrap_or_default();
+        let mut text = field("text").to_string();
+        if text.is_empty()
+            && let Some(percentage) = json.get("percentage").and_then(Json::as_f64)
+        {
+            text = format!("{}%", percentage.round() as i64);
+        }
+        let classes = match json.get("class") {
+            Some(Json::Text(class)) => vec![class.clone()],
+            Some(Json::List(classes)) => classes
+                .iter()
+                .filter_map(Json::as_str)
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        };
+        // Waybar scripts mark up with Pango, the text is drawn without it
+        Output {
+            text: strip_markup(&text).trim().to_string(),
+            tooltip: strip_markup(field("tooltip")).replace('\n', " "),
+            classes,
+        }
+    }
+}
+
+/// Text without Pango tags, with the entities Pango needs escaped undone.
+pub fn strip_markup(text: &str) -> String {
+    let mut plain = String::new();
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,136 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::thread;
+
+use crate::{
+    CUSTOM_TEXT, app_state, config::Module, dbus, health, ping_main_thread, threads::custom,
+};
+
+const NAME: &str = "org.leanbar";
+const PATH: &str = "/org/leanbar";
+const INTERFACE: &str = "org.leanbar";
+const INTROSPECTION: &str = r#"<node>
+ <interface name="org.leanbar">
+  <method name="SetCustomText"><arg name="text" type="s" direction="in"/></method>
+  <method name="GetCustomText"><arg name="text" type="s" direction="out"/></method>
+  <method name="ToggleModule">
+   <arg name="module" type="s" direction="in"/>
+   <arg name="shown" type="b" direction="out"/>
+  </method>
+  <method name="GetModules"><arg name="modules" type="a(sssb)" direction="out"/></method>
+ </interface>
+</node>"#;
+
+/// `org.leanbar` on the session bus, what the control socket does for
+/// scripts that would rather use D-Bus, e.g.
+/// `busctl --user call org.leanbar /org/leanbar org.leanbar ToggleModule s mpris`.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[DBus Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::session, |connection| {
+                let Err(e) = serve(connection, &wake_fd);
+                health::degraded("dbus", e.to_string());
+            });
+            health::disabled("dbus", format!("no session bus: {}", e));
+        });
+}
+
+/// Answers calls until the connection fails. With another leanbar owning the
+/// name this one tries again after reconnecting, taking over once it quits.
+fn serve(connection: &mut dbus::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    if !connection.request_name(NAME)? {
+        return Err(io::Error::other(format!(
+            "{} is owned by another process",
+            NAME
+        )));
+    }
+    health::ok("dbus");
+    loop {
+        let Some(message) = connection.next_message(None)? else {
+            continue;
+        };
+        if message.kind == dbus::METHOD_CALL && answer(connection, &message)? {
+            ping_main_thread(wake_fd);
+        }
+    }
+}
+
+/// Returns true if the bar needs a redraw.
+fn answer(connection: &mut dbus::Connection, call: &dbus::Message) -> io::Result<bool> {
+    let first = call
+        .body
+        .first()
+        .and_then(dbus::Value::as_str)
+        .unwrap_or("");
+    if call.path.as_deref() != Some(PATH) {
+        connection.reply_error(
+            call,
+            "org.freedesktop.DBus.Error.UnknownObject",
+            call.path.as_deref().unwrap_or(""),
+        )?;
+        return Ok(false);
+    }
+    match (call.interface.as_deref(), call.member.as_deref()) {
+        (Some(INTERFACE), Some("SetCustomText")) => {
+            custom::set_text(first);
+            connection.reply(call, &[])?;
+            Ok(true)
+        }
+        (Some(INTERFACE), Some("GetCustomText")) => {
+            let text = CUSTOM_TEXT.lock().map(|t| t.clone()).unwrap_or_default();
+            connection.reply(call, &[dbus::Value::Str(text)])?;
+            Ok(false)
+        }
+        (Some(INTERFACE), Some("ToggleModule")) => match Module::parse(first) {
+            Some(module) => {
+                let shown = app_state::toggle_module(module);
+                connection.reply(call, &[dbus::Value::Bool(shown)])?;
+                Ok(true)
+            }
+            None => {
+                connection.reply_error(
+                    call,
+                    "org.freedesktop.DBus.Error.InvalidArgs",
+                    &format!("unknown module: {}", first),
+                )?;
+                Ok(false)
+            }
+        },
+        (Some(INTERFACE), Some("GetModules")) => {
+            let hidden = app_state::hidden_modules();
+            let modules = health::states()
+                .into_iter()
+                .map(|(name, state, last_error)| {
+                    let shown = Module::parse(name).is_none_or(|m| !hidden.contains(&m));
+                    dbus::Value::Struct(vec![
+                        dbus::Value::Str(name.to_string()),
+                        dbus::Value::Str(state.to_string()),
+                        dbus::Value::Str(last_error),
+                        dbus::Value::Bool(shown),
+                    ])
+                })
+                .collect();
+            connection.reply(call, &[dbus::Value::Array("(sssb)".into(), modules)])?;
+            Ok(false)
+        }
+        (Some("org.freedesktop.DBus.Introspectable"), Some("Introspect")) => {
+            connection.reply(call, &[dbus::Value::Str(INTROSPECTION.to_string())])?;
+            Ok(false)
+        }
+        (Some("org.freedesktop.DBus.Peer"), Some("Ping")) => {
+            connection.reply(call, &[])?;
+            Ok(false)
+        }
+        _ => {
+            connection.reply_error(
+                call,
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                call.member.as_deref().unwrap_or(""),
+            )?;
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{DND, dbus, health, ping_main_thread};
+
+const SWAYNC_SERVICE: &str = "org.erikreider.swaync.cc";
+const SWAYNC_PATH: &str = "/org/erikreider/swaync/cc";
+/// mako runs under the notification service name with its own interface.
+const MAKO_SERVICE: &str = "org.freedesktop.Notifications";
+const MAKO_PATH: &str = "/fr/emersion/Mako";
+const MAKO_INTERFACE: &str = "fr.emersion.Mako";
+/// The mode mako's documentation suggests hiding notifications under.
+const MAKO_DND_MODE: &str = "do-not-disturb";
+/// Longest a toggle may take, someone clicked and waits for it.
+const TOGGLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Clicks on the module, toggled on this thread's connection.
+static TOGGLES: dbus::Mailbox<()> = dbus::Mailbox::new();
+
+/// The notification daemon as found on the bus.
+enum Daemon {
+    Swaync,
+    /// With its active modes.
+    Mako(Vec<String>),
+}
+
+/// Do-not-disturb from swaync or mako, read again whenever swaync reports a
+/// change, when either daemon starts or stops and after a click toggled it,
+/// mako announces nothing.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[DND Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::session, |connection| {
+                let Err(e) = watch(connection, &wake_fd);
+                health::degraded("dnd", format!("session bus connection lost: {}", e));
+            });
+            health::disabled("dnd", format!("no session bus: {}", e));
+        });
+}
+
+fn watch(connection: &mut dbus::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    // Subscribe and SubscribeV2 carry the state after every change
+    connection.add_match(&format!(
+        "type='signal',sender='{}',interface='{}'",
+        SWAYNC_SERVICE, SWAYNC_SERVICE
+    ))?;
+    for service in [SWAYNC_SERVICE, MAKO_SERVICE] {
+        connection.add_match(&format!(
+            "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'",
+            service
+        ))?;
+    }
+
+    connection.wake_on(&TOGGLES);
+    // Clicks from while the bus was away are stale by now
+    TOGGLES.take();
+
+    loop {
+        let dnd = match read(connection) {
+            Ok((_, enabled)) => {
+                health::ok("dnd");
+                enabled as u8
+            }
+            // Not running (yet), it is picked up when it claims its name
+            Err(e) => {
+                health::disabled("dnd", format!("no swaync or mako: {}", e));
+                255
+            }
+        };
+        if DND.swap(dnd, Ordering::AcqRel) != dnd {
+            ping_main_thread(wake_fd);
+        }
+        connection.next_signal(None)?;
+        for () in TOGGLES.take() {
+            if let Err(e) = toggle_on(connection) {
+                eprintln!("[DND] Toggling do-not-disturb failed: {}", e);
+            }
+        }
+    }
+}
+
+/// The running daemon and whether it holds notifications back, swaync first
+/// since it also owns the notification service name.
+fn read(connection: &mut dbus::Connection) -> io::Result<(Daemon, bool)> {
+    match connection.call(SWAYNC_SERVICE, SWAYNC_PATH, SWAYNC_SERVICE, "GetDnd", &[]) {
+        Ok(reply) => {
+            let enabled = reply.first().and_then(dbus::Value::as_bool);
+            return Ok((Daemon::Swaync, enabled.unwrap_or(false)));
+        }
+        Err(e) if !is_missing(&e) => return Err(e),
+        Err(_) => {}
+    }
+    let reply = connection.call(MAKO_SERVICE, MAKO_PATH, MAKO_INTERFACE, "ListModes", &[])?;
+    let modes: Vec<String> = reply
+        .first()
+        .and_then(dbus::Value::as_array)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|mode| mode.as_str().map(str::to_string))
+        .collect();
+    let enabled = modes.iter().any(|mode| mode == MAKO_DND_MODE);
+    Ok((Daemon::Mako(modes), enabled))
+}
+
+/// Whether a call failed because nothing answers under the name.
+fn is_missing(e: &io::Error) -> bool {
+    let message = e.to_string();
+    message.contains("ServiceUnknown") || message.contains("NameHasNoOwner")
+}
+
+/// Turns do-not-disturb on or off in whichever daemon runs, called from the
+/// main thread on a click.
+pub fn toggle() {
+    TOGGLES.post(());
+}
+
+fn toggle_on(connection: &mut dbus::Connection) -> io::Result<()> {
+    match read(connection)? {
+        (Daemon::Swaync, _) => connection
+            .call_within(
+                TOGGLE_TIMEOUT,
+                SWAYNC_SERVICE,
+                SWAYNC_PATH,
+                SWAYNC_SERVICE,
+                "ToggleDnd",
+                &[],
+            )
+            .map(|_| ()),
+        (Daemon::Mako(mut modes), enabled) => {
+            if enabled {
+                modes.retain(|mode| mode != MAKO_DND_MODE);
+            } else {
+                modes.push(MAKO_DND_MODE.to_string());
+            }
+            let modes = modes.into_iter().map(dbus::Value::Str).collect();
+            connection
+                .call_within(
+                    TOGGLE_TIMEOUT,
+                    MAKO_SERVICE,
+                    MAKO_PATH,
+                    MAKO_INTERFACE,
+                    "SetModes",
+                    &[dbus::Value::Array("s".into(), modes)],
+                )
+                .map(|_| ())
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use crate::netlink::{self, NLM_F_DUMP, Socket};
+use crate::{ETHERNET_ADDRESS, ETHERNET_LINK, config, health, ping_main_thread};
+
+const RTMGRP_LINK: u32 = 0x1;
+const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+
+const RTM_GETLINK: u16 = 18;
+const RTM_GETADDR: u16 = 22;
+const IFINFOMSG_LEN: usize = 16;
+const IFADDRMSG_LEN: usize = 8;
+const IFLA_IFNAME: u16 = 3;
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const ARPHRD_ETHER: u16 = 1;
+const AF_INET: u8 = 2;
+/// Carrier present, the cable is plugged in and the other end answers.
+const IFF_LOWER_UP: u32 = 0x10000;
+
+/// Link state and IPv4 address of the wired interface, updated from rtnetlink
+/// link and address events so plugging a cable or a finished DHCP lease shows up
+/// immediately.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Ethernet Thread] Started");
+            let sockets = Socket::route(0)
+                .and_then(|r| Ok((r, Socket::route(RTMGRP_LINK | RTMGRP_IPV4_IFADDR)?)));
+            let (mut requests, events) = match sockets {
+                Ok(sockets) => sockets,
+                Err(e) => {
+                    health::disabled("ethernet", format!("rtnetlink: {}", e));
+                    return;
+                }
+            };
+
+            loop {
+                match read(&mut requests) {
+                    Ok(Some((up, address))) => {
+                        health::ok("ethernet");
+                        if store(up, &address) {
+                            ping_main_thread(&wake_fd);
+                        }
+                    }
+                    Ok(None) => {
+                        health::disabled("ethernet", "no wired interface");
+                        return;
+                    }
+                    Err(e) => health::degraded("ethernet", format!("rtnetlink: {}", e)),
+                }
+
+                // Any link or address change re-reads everything, they are rare
+                if let Err(e) = events.recv_events() {
+                    health::degraded("ethernet", format!("rtnetlink events: {}", e));
+                    return;
+                }
+            }
+        });
+}
+
+/// Link state and address of the configured (or first physical wired)
+/// interface, None when there is no such interface.
+fn read(socket: &mut Socket) -> io::Result<Option<(bool, String)>> {
+    let wanted = &config::get().ethernet_interface;
+    let links = socket.request(RTM_GETLINK, NLM_F_DUMP, &[0; IFINFOMSG_LEN])?;
+    let link = links.iter().find_map(|link| {
+        let header = link.payload.get(..IFINFOMSG_LEN)?;
+        let kind = u16::from_ne_bytes(header[2..4].try_into().unwrap());
+        let index = u32::from_ne_bytes(header[4..8].try_into().unwrap());
+        let flags = u32::from_ne_bytes(header[8..12].try_into().unwrap());
+        let name = netlink::attributes(&link.payload[IFINFOMSG_LEN..])
+            .find(|(k, _)| *k == IFLA_IFNAME)
+            .map(|(_, v)| netlink::attr_string(v))?;
+        let matches = if wanted.is_empty() {
+            kind == ARPHRD_ETHER && is_physical_wired(&name)
+        } else {
+            name == *wanted
+        };
+        matches.then_some((index, flags & IFF_LOWER_UP != 0))
+    });
+    let Some((index, up)) = link else {
+        return Ok(None);
+    };
+    if !up {
+        return Ok(Some((false, String::new())));
+    }
+
+    let mut request = [0; IFADDRMSG_LEN];
+    request[0] = AF_INET;
+    let addresses = socket.request(RTM_GETADDR, NLM_F_DUMP, &request)?;
+    let address = addresses.iter().find_map(|addr| {
+        let header = addr.payload.get(..IFADDRMSG_LEN)?;
+        if u32::from_ne_bytes(header[4..8].try_into().unwrap()) != index {
+            return None;
+        }
+        let attrs = || netlink::attributes(&addr.payload[IFADDRMSG_LEN..]);
+        // IFA_LOCAL is the interface's own address, IFA_ADDRESS the peer on
+        // point to point links and the same address otherwise
+        let (_, value) = attrs()
+            .find(|(k, _)| *k == IFA_LOCAL)
+            .or_else(|| attrs().find(|(k, _)| *k == IFA_ADDRESS))?;
+        let octets: [u8; 4] = value.get(..4)?.try_into().ok()?;
+        Some(Ipv4Addr::from(octets).to_string())
+    });
+    Ok(Some((true, address.unwrap_or_default())))
+}
+
+/// Backed by a device and not a wireless one, which leaves out bridges, veths,
+/// VPN tunnels and wifi cards that also report as ethernet.
+fn is_physical_wired(name: &str) -> bool {
+    let dir = Path::new("/sys/class/net").join(name);
+    dir.join("device").exists() && !dir.join("wireless").exists()
+}
+
+fn store(up: bool, address: &str) -> bool {
+    let link = up as u8;
+    let mut changed = ETHERNET_LINK.swap(link, Ordering::AcqRel) != link;
+    if let Ok(mut guard) = ETHERNET_ADDRESS.lock()
+        && *guard != address
+    {
+        guard.clear();
+        guard.push_str(address);
+        changed = true;
+    }
+    changed
+}
@@ -0,0 +1,77 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::thread;
+
+use rustix::fs::{CWD, Mode, mkfifoat};
+use rustix::io::Errno;
+
+use crate::{FIFO_TEXT, health, ping_main_thread};
+
+/// `$XDG_RUNTIME_DIR/leanbar/custom.fifo`, next to the control socket.
+fn fifo_path() -> Option<PathBuf> {
+    let runtime_dir = env::var("XDG_RUNTIME_DIR").ok()?;
+    Some(
+        PathBuf::from(runtime_dir)
+            .join("leanbar")
+            .join("custom.fifo"),
+    )
+}
+
+/// Text of the fifo module, whatever line was last written to the named pipe,
+/// e.g. `echo building > $XDG_RUNTIME_DIR/leanbar/custom.fifo`. An empty line
+/// hides the module.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            let Some(path) = fifo_path() else {
+                health::disabled("fifo", "XDG_RUNTIME_DIR not set");
+                return;
+            };
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            // One left by a previous instance is used as it is
+            match mkfifoat(CWD, &path, Mode::RUSR | Mode::WUSR) {
+                Ok(()) | Err(Errno::EXIST) => {}
+                Err(e) => {
+                    health::degraded("fifo", format!("mkfifo {}: {}", path.display(), e));
+                    return;
+                }
+            }
+            if !fs::metadata(&path).is_ok_and(|m| m.file_type().is_fifo()) {
+                health::degraded("fifo", format!("{} is not a fifo", path.display()));
+                return;
+            }
+            // Holding the write end too means writers closing it is never an
+            // end of file, and opening doesn't wait for the first writer
+            let fifo = match OpenOptions::new().read(true).write(true).open(&path) {
+                Ok(fifo) => fifo,
+                Err(e) => {
+                    health::degraded("fifo", format!("{}: {}", path.display(), e));
+                    return;
+                }
+            };
+            println!("[FIFO Thread] Reading {}", path.display());
+            health::ok("fifo");
+
+            let mut reader = BufReader::new(fifo);
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+                let text = String::from_utf8_lossy(&line).trim().to_string();
+                line.clear();
+                if let Ok(mut current) = FIFO_TEXT.lock()
+                    && *current != text
+                {
+                    *current = text;
+                    drop(current);
+                    ping_main_thread(&wake_fd);
+                }
+            }
+            health::degraded("fifo", format!("reading {} failed", path.display()));
+        });
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{GPU_BUSY_PERCENT, GPU_VRAM_USED_MB, health, ping_main_thread};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// GPU utilization from the DRM sysfs interface (amdgpu, and i915/xe where the
+/// kernel exposes it), falling back to a long running `nvidia-smi` for NVIDIA.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[GPU Thread] Started");
+            if let Some(device) = find_drm_gpu() {
+                println!("[GPU Thread] Using {}", device.display());
+                loop {
+                    if poll_drm(&device) {
+                        ping_main_thread(&wake_fd);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+            if !run_nvidia_smi(&wake_fd) {
+                health::disabled(
+                    "gpu",
+                    "no gpu_busy_percent in /sys/class/drm and no nvidia-smi",
+                );
+            }
+        });
+}
+
+/// First `/sys/class/drm/cardN/device` reporting its busy percentage.
+fn find_drm_gpu() -> Option<PathBuf> {
+    let mut cards: Vec<PathBuf> = fs::read_dir("/sys/class/drm")
+        .ok()?
+        .flatten()
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            // card1-DP-2 and friends are connectors, not devices
+            name.starts_with("card") && !name.contains('-')
+        })
+        .map(|e| e.path().join("device"))
+        .collect();
+    cards.sort();
+    cards
+        .into_iter()
+        .find(|d| read_u64(&d.join("gpu_busy_percent")).is_some())
+}
+
+fn poll_drm(device: &Path) -> bool {
+    let Some(busy) = read_u64(&device.join("gpu_busy_percent")) else {
+        health::degraded("gpu", "read gpu_busy_percent failed");
+        return false;
+    };
+    health::ok("gpu");
+    // Integrated GPUs have no VRAM counters
+    let vram_mb = read_u64(&device.join("mem_info_vram_used")).map(|b| b / (1024 * 1024));
+    store(busy, vram_mb)
+}
+
+/// Reads samples from `nvidia-smi` in loop mode. Returns false if it could not
+/// be started, otherwise runs until it exits.
+fn run_nvidia_smi(wake_fd: &OwnedFd) -> bool {
+    let child = Command::new("nvidia-smi")
+        .args([
+            "--query-gpu=utilization.gpu,memory.used",
+            "--format=csv,noheader,nounits",
+            &format!("--loop-ms={}", POLL_INTERVAL.as_millis()),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return false;
+    };
+    println!("[GPU Thread] Using nvidia-smi");
+
+    // "37, 2150" per sample, only the first GPU is shown
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        let mut fields = line.split(',').map(|f| f.trim().parse::<u64>().ok());
+        match (fields.next().flatten(), fields.next().flatten()) {
+            (Some(busy), vram_mb) => {
+                health::ok("gpu");
+                if store(busy, vram_mb) {
+                    ping_main_thread(wake_fd);
+                }
+            }
+            _ => health::degraded("gpu", format!("unexpected nvidia-smi output: {}", line)),
+        }
+    }
+    let _ = child.wait();
+    health::degraded("gpu", "nvidia-smi exited");
+    true
+}
+
+fn store(busy: u64, vram_mb: Option<u64>) -> bool {
+    let busy = busy.min(100) as u8;
+    let vram_mb = vram_mb.map_or(u32::MAX, |mb| mb.min(u32::MAX as u64 - 1) as u32);
+    let changed = GPU_BUSY_PERCENT.swap(busy, Ordering::AcqRel) != busy;
+    GPU_VRAM_USED_MB.swap(vram_mb, Ordering::AcqRel) != vram_mb || changed
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -1,12 +1,31 @@
+use std::collections::HashMap;
 use std::env;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::os::fd::OwnedFd;
 use std::os::unix::net::UnixStream;
-use std::process::Command;
+use std::sync::Mutex;
 use std::sync::atomic::Ordering;
 use std::thread;
 
-use crate::{ACTIVE_WORKSPACE, WORKSPACES, ping_main_thread};
+use rustix::event::{PollFd, PollFlags, poll};
+
+use crate::{
+    ACTIVE_WORKSPACE, FOCUSED_MONITOR, KEYBOARD_LAYOUT, MONITORS, MonitorWorkspace, TILING_LAYOUT,
+    WINDOW_CLASS, WINDOW_TITLE, WORKSPACES, Workspace,
+    config::{self, Module},
+    dbus, health, ping_main_thread,
+    threads::privacy,
+};
+
+/// Socket1 requests from the main thread, which must not wait for Hyprland to
+/// answer. The event thread runs them between events.
+static REQUESTS: dbus::Mailbox<Request> = dbus::Mailbox::new();
+
+enum Request {
+    FocusWorkspace(i32),
+    CycleKeyboardLayout,
+    CycleTilingLayout,
+}
 
 pub fn start(wake_fd: OwnedFd) {
     let _ = thread::Builder::new()
@@ -14,34 +33,51 @@ pub fn start(wake_fd: OwnedFd) {
         .spawn(move || {
             println!("[Hyprland Thread] Started");
 
-        // 1. Initialize current workspaces using `hyprctl`
+        // 1. Initialize current workspaces and monitors over socket1
         init_workspaces();
+        if let Some(focused) = init_monitors() {
+            set_focused_monitor(&focused);
+        }
+        let mut state = HyprState {
+            xkb_names: load_xkb_layout_names(),
+            layouts: Vec::new(),
+            focused_window: String::new(),
+            window_layouts: HashMap::new(),
+            title_v2: false,
+        };
+        init_active_window(&mut state);
+        init_keyboard_layout(&mut state);
+        if tiling_shown() {
+            refresh_tiling_layout();
+        } else {
+            health::disabled("tiling", "tiling_width = 0");
+        }
         ping_main_thread(&wake_fd);
 
         // 2. Connect to the event socket
-        let his =
-            env::var("HYPRLAND_INSTANCE_SIGNATURE").expect("HYPRLAND_INSTANCE_SIGNATURE not set.");
-        let runtime_dir = env::var("XDG_RUNTIME_DIR").expect("XDG_RUNTIME_DIR not set.");
-        let socket_path = format!("{}/hypr/{}/.socket2.sock", runtime_dir, his);
+        let Some(socket_path) = socket_path(".socket2.sock") else {
+            eprintln!("[Hyprland Thread] HYPRLAND_INSTANCE_SIGNATURE or XDG_RUNTIME_DIR not set, not running under Hyprland?");
+            for module in ["workspaces", "keyboard_layout", "tiling"] {
+                health::disabled(module, "not running under Hyprland");
+            }
+            return;
+        };
 
         loop {
             match UnixStream::connect(&socket_path) {
                 Ok(stream) => {
                     println!("[Hyprland Thread] Connected to IPC socket.");
-                    let mut reader = BufReader::new(stream);
-                    let mut line = String::with_capacity(128);
-
-                    while reader.read_line(&mut line).map(|n| n > 0).unwrap_or(false) {
-                        handle_event(&line, &wake_fd);
-                        line.clear();
-                    }
+                    health::ok("workspaces");
+                    follow_events(stream, &mut state, &wake_fd);
                     println!("[Hyprland Thread] Connection closed.");
+                    health::degraded("workspaces", "event socket closed");
                 }
                 Err(e) => {
                     eprintln!(
                         "[Hyprland Thread] Failed to connect to IPC socket: {}. Retrying in 2s...",
                         e
                     );
+                    health::degraded("workspaces", format!("event socket: {}", e));
                     thread::sleep(std::time::Duration::from_secs(2));
                 }
             }
@@ -49,66 +85,800 @@ pub fn start(wake_fd: OwnedFd) {
     });
 }
 
+/// Event thread state that is not shared with the renderer.
+struct HyprState {
+    /// xkb layout descriptions ("English (US)") mapped to layout codes ("us").
+    xkb_names: Vec<(String, String)>,
+    /// Layout codes configured on the main keyboard.
+    layouts: Vec<String>,
+    focused_window: String,
+    /// Last layout code seen while each window was focused.
+    window_layouts: HashMap<String, String>,
+    /// Hyprland sends `windowtitlev2`, which carries the title itself.
+    title_v2: bool,
+}
+
+/// Handles events until the event socket closes, running requests posted in
+/// the meantime.
+fn follow_events(stream: UnixStream, state: &mut HyprState, wake_fd: &OwnedFd) {
+    // Clicks from before reconnecting are stale
+    REQUESTS.take();
+    let mut reader = BufReader::new(stream);
+    let mut line = String::with_capacity(128);
+    loop {
+        // Buffered events are handled before waiting again
+        if reader.buffer().is_empty() {
+            let wake = REQUESTS.wake_fd();
+            let mut fds = vec![PollFd::new(reader.get_ref(), PollFlags::IN)];
+            if let Some(wake) = &wake {
+                fds.push(PollFd::new(wake, PollFlags::IN));
+            }
+            match poll(&mut fds, None) {
+                Ok(_) | Err(rustix::io::Errno::INTR) => {}
+                Err(_) => return,
+            }
+            let readable = !fds[0].revents().is_empty();
+            for request in REQUESTS.take() {
+                run(request, wake_fd);
+            }
+            if !readable {
+                continue;
+            }
+        }
+        if !reader.read_line(&mut line).is_ok_and(|n| n > 0) {
+            return;
+        }
+        handle_event(&line, state, wake_fd);
+        line.clear();
+    }
+}
+
+fn run(request: Request, wake_fd: &OwnedFd) {
+    match request {
+        Request::FocusWorkspace(id) => focus_workspace_now(id),
+        Request::CycleKeyboardLayout => cycle_keyboard_layout_now(),
+        Request::CycleTilingLayout => {
+            cycle_tiling_layout_now();
+            // Layout messages emit no event
+            if refresh_tiling_layout() {
+                ping_main_thread(wake_fd);
+            }
+        }
+    }
+}
+
+fn socket_path(socket: &str) -> Option<String> {
+    let his = env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let runtime_dir = env::var("XDG_RUNTIME_DIR").ok()?;
+    Some(format!("{}/hypr/{}/{}", runtime_dir, his, socket))
+}
+
+/// Sends a command over Hyprland's request socket (socket1) and returns the reply.
+pub fn request(command: &str) -> Option<String> {
+    let mut stream = UnixStream::connect(socket_path(".socket.sock")?).ok()?;
+    stream.write_all(command.as_bytes()).ok()?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).ok()?;
+    Some(reply)
+}
+
+/// Runs a Hyprland dispatcher. Blocks until Hyprland has handled it, so
+/// consecutive dispatches are applied in order.
+pub fn dispatch(args: &str) {
+    match request(&format!("dispatch {}", args)) {
+        Some(reply) if reply.trim() == "ok" => {}
+        Some(reply) => eprintln!("[Hyprland] dispatch {} failed: {}", args, reply.trim()),
+        None => eprintln!("[Hyprland] dispatch {} failed: socket unavailable", args),
+    }
+}
+
+/// Switches the main keyboard to its next configured layout.
+pub fn cycle_keyboard_layout() {
+    REQUESTS.post(Request::CycleKeyboardLayout);
+}
+
+fn cycle_keyboard_layout_now() {
+    let device = KEYBOARD_LAYOUT
+        .lock()
+        .map(|l| l.device.clone())
+        .unwrap_or_default();
+    let device = if device.is_empty() { "all" } else { &device };
+    match request(&format!("switchxkblayout {} next", device)) {
+        Some(reply) if reply.trim() == "ok" => {}
+        reply => eprintln!(
+            "[Hyprland] switchxkblayout failed: {}",
+            reply.as_deref().unwrap_or("socket unavailable").trim()
+        ),
+    }
+}
+
+/// Dwindle toggles the split of the focused window, master grows the master area
+/// by one window and wraps back to a single master.
+pub fn cycle_tiling_layout() {
+    REQUESTS.post(Request::CycleTilingLayout);
+}
+
+fn cycle_tiling_layout_now() {
+    let (name, masters, tiled) = TILING_LAYOUT
+        .lock()
+        .map(|l| (l.name.clone(), l.masters, l.tiled))
+        .unwrap_or_default();
+    match name.as_str() {
+        "dwindle" => dispatch("layoutmsg togglesplit"),
+        "master" if masters < tiled => dispatch("layoutmsg addmaster"),
+        "master" => {
+            for _ in 1..masters {
+                dispatch("layoutmsg removemaster");
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads a string option with socket1 `getoption`, the reply starts with "str: VALUE".
+fn get_string_option(name: &str) -> Option<String> {
+    let reply = request(&format!("getoption {}", name))?;
+    reply
+        .lines()
+        .find_map(|l| l.strip_prefix("str: "))
+        .map(|v| v.trim().to_string())
+}
+
+/// The tiling module takes a socket1 request and a clients dump per refresh, only
+/// pay for it when the bar shows it.
+fn tiling_shown() -> bool {
+    let config = config::get();
+    config.capsule.is_none() && config.module_widths.get(&Module::Tiling) != Some(&0)
+}
+
+/// Re-reads the tiling layout and, for the master layout, the master area of the
+/// active workspace. Returns true when anything changed.
+fn refresh_tiling_layout() -> bool {
+    let Some(name) = get_string_option("general:layout") else {
+        health::degraded("tiling", "getoption general:layout failed");
+        return false;
+    };
+    health::ok("tiling");
+    let (masters, tiled) = if name == "master" {
+        count_master_windows()
+    } else {
+        (0, 0)
+    };
+    let Ok(mut guard) = TILING_LAYOUT.lock() else {
+        return false;
+    };
+    if guard.name == name && guard.masters == masters && guard.tiled == tiled {
+        return false;
+    }
+    guard.name = name;
+    guard.masters = masters;
+    guard.tiled = tiled;
+    true
+}
+
+/// Hyprland does not say which windows are masters, so infer it from geometry: the
+/// masters are the tiled windows lined up on the edge set by `master:orientation`.
+/// Returns (masters, tiled windows) for the active workspace.
+fn count_master_windows() -> (u8, u8) {
+    let Some(clients) = request("clients") else {
+        return (0, 0);
+    };
+    let active_ws = ACTIVE_WORKSPACE.load(Ordering::Acquire);
+    let windows: Vec<[i32; 4]> = clients
+        .split("\n\n")
+        .filter_map(|block| parse_tiled_window(block, active_ws))
+        .collect();
+
+    let orientation = get_string_option("master:orientation").unwrap_or_default();
+    let edge = |w: &[i32; 4]| match orientation.as_str() {
+        "top" => w[1],
+        "right" => -w[2],
+        "bottom" => -w[3],
+        _ => w[0],
+    };
+    let first = windows.iter().map(edge).min();
+    let masters = windows.iter().filter(|w| Some(edge(w)) == first).count();
+    (
+        masters.min(u8::MAX as usize) as u8,
+        windows.len().min(u8::MAX as usize) as u8,
+    )
+}
+
+/// Parses one window block of socket1 `clients` and returns its [left, top, right,
+/// bottom] edges if it is tiled on the given workspace.
+fn parse_tiled_window(block: &str, workspace: i32) -> Option<[i32; 4]> {
+    let mut pos = None;
+    let mut size = None;
+    let mut on_workspace = false;
+    let mut floating = true;
+    for line in block.lines() {
+        let Some((key, value)) = line.trim().split_once(": ") else {
+            continue;
+        };
+        match key {
+            "at" => pos = parse_pair(value),
+            "size" => size = parse_pair(value),
+            // "workspace: 3 (3)"
+            "workspace" => {
+                on_workspace = value
+                    .split_whitespace()
+                    .next()
+                    .and_then(|id| id.parse::<i32>().ok())
+                    == Some(workspace)
+            }
+            "floating" => floating = value != "0",
+            _ => {}
+        }
+    }
+    let ((x, y), (w, h)) = (pos?, size?);
+    (on_workspace && !floating).then_some([x, y, x + w, y + h])
+}
+
+fn parse_pair(value: &str) -> Option<(i32, i32)> {
+    let (a, b) = value.split_once(',')?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+/// Parses a workspace header line as printed by `workspaces` and `activeworkspace`:
+/// "workspace ID -1337 (name with spaces) on monitor DP-1:".
+fn parse_workspace_header(line: &str) -> Option<Workspace> {
+    let remainder = line.strip_prefix("workspace ID ")?;
+    let (id_str, rest) = remainder.split_once(' ')?;
+    let (name_part, monitor) = rest.rsplit_once(" on monitor ")?;
+    Some(Workspace {
+        id: id_str.parse().ok()?,
+        name: name_part
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .to_string(),
+        monitor: monitor.trim_end_matches(':').to_string(),
+        windows: 0,
+        fullscreen: false,
+    })
+}
+
 fn init_workspaces() {
-    // hyprctl activeworkspace
-    if let Ok(output) = Command::new("hyprctl").arg("activeworkspace").output() {
-        let out_str = String::from_utf8_lossy(&output.stdout);
-        // Look for "workspace ID " then parse the next token
-        if let Some(ws_idx) = out_str.find("workspace ID ") {
-            let remainder = &out_str[ws_idx + 13..];
+    if let Some(workspaces) = list_workspaces()
+        && let Ok(mut guard) = WORKSPACES.lock()
+    {
+        *guard = workspaces;
+    }
+
+    if let Some(out_str) = request("activeworkspace")
+        && let Some(ws) = out_str.lines().find_map(parse_workspace_header)
+    {
+        ACTIVE_WORKSPACE.store(ws.id, Ordering::Release);
+        upsert_workspace(ws.id, &ws.name, &ws.monitor);
+    }
+}
+
+/// Every workspace from socket1 `workspaces` with its window count and
+/// fullscreen state.
+fn list_workspaces() -> Option<Vec<Workspace>> {
+    let out_str = request("workspaces")?;
+    let mut workspaces: Vec<Workspace> = Vec::new();
+    for line in out_str.lines() {
+        if let Some(ws) = parse_workspace_header(line) {
+            workspaces.push(ws);
+        } else if let Some(count) = line.trim().strip_prefix("windows: ")
+            && let Some(ws) = workspaces.last_mut()
+        {
+            ws.windows = count.parse().unwrap_or(0);
+        } else if let Some(flag) = line.trim().strip_prefix("hasfullscreen: ")
+            && let Some(ws) = workspaces.last_mut()
+        {
+            ws.fullscreen = flag == "1";
+        }
+    }
+    Some(workspaces)
+}
+
+/// Re-reads how many windows each workspace holds and whether one of them is
+/// fullscreen. Returns true if any changed.
+fn refresh_workspace_details() -> bool {
+    let Some(fresh) = list_workspaces() else {
+        return false;
+    };
+    let Ok(mut guard) = WORKSPACES.lock() else {
+        return false;
+    };
+    let mut changed = false;
+    for ws in guard.iter_mut() {
+        let (windows, fullscreen) = fresh
+            .iter()
+            .find(|f| f.id == ws.id)
+            .map_or((0, false), |f| (f.windows, f.fullscreen));
+        changed |= ws.windows != windows || ws.fullscreen != fullscreen;
+        ws.windows = windows;
+        ws.fullscreen = fullscreen;
+    }
+    changed
+}
+
+/// Adds a workspace to the model or updates the one with the same ID.
+/// An empty monitor leaves the known monitor untouched.
+fn upsert_workspace(id: i32, name: &str, monitor: &str) {
+    if let Ok(mut guard) = WORKSPACES.lock() {
+        if let Some(ws) = guard.iter_mut().find(|w| w.id == id) {
+            ws.name = name.to_string();
+            if !monitor.is_empty() {
+                ws.monitor = monitor.to_string();
+            }
+        } else {
+            guard.push(Workspace {
+                id,
+                name: name.to_string(),
+                monitor: monitor.to_string(),
+                windows: 0,
+                fullscreen: false,
+            });
+        }
+    }
+}
+
+fn workspace_id_by_name(name: &str) -> Option<i32> {
+    WORKSPACES
+        .lock()
+        .ok()?
+        .iter()
+        .find(|w| w.name == name)
+        .map(|w| w.id)
+        .or_else(|| name.parse().ok())
+}
+
+/// Switches to a workspace, named workspaces are addressed by name since their
+/// IDs are assigned by Hyprland.
+pub fn focus_workspace(id: i32) {
+    REQUESTS.post(Request::FocusWorkspace(id));
+}
+
+fn focus_workspace_now(id: i32) {
+    if id > 0 {
+        dispatch(&format!("workspace {}", id));
+        return;
+    }
+    let name = WORKSPACES
+        .lock()
+        .ok()
+        .and_then(|g| g.iter().find(|w| w.id == id).map(|w| w.name.clone()));
+    match name {
+        Some(name) => dispatch(&format!("workspace name:{}", name)),
+        None => eprintln!("[Hyprland] unknown workspace {}", id),
+    }
+}
+
+/// Re-reads every monitor and its active workspace from `hyprctl monitors`.
+/// Returns the name of the focused monitor. Bars match monitors by their
+/// wl_output name, one without a match shows the focused workspace.
+fn init_monitors() -> Option<String> {
+    let Some(out_str) = request("monitors") else {
+        eprintln!("[Hyprland Thread] monitors request failed, bars show the focused workspace");
+        health::degraded("workspaces", "monitors request failed");
+        return None;
+    };
+
+    let mut monitors: Vec<MonitorWorkspace> = Vec::new();
+    let mut focused = None;
+    for line in out_str.lines() {
+        let line = line.trim();
+        // "Monitor eDP-1 (ID 0):"
+        if let Some(remainder) = line.strip_prefix("Monitor ") {
+            let mut parts = remainder.split_whitespace();
+            let name = parts.next().unwrap_or("").to_string();
+            let id = parts
+                .next()
+                .filter(|p| *p == "(ID")
+                .and_then(|_| parts.next())
+                .and_then(|p| p.trim_end_matches("):").parse::<u8>().ok())
+                .unwrap_or(monitors.len() as u8);
+            monitors.push(MonitorWorkspace {
+                id,
+                name,
+                active_ws: 0,
+            });
+        } else if let Some(remainder) = line.strip_prefix("active workspace: ") {
             let ws_str = remainder.split_whitespace().next().unwrap_or("");
-            if let Ok(ws) = ws_str.parse::<u8>() {
-                ACTIVE_WORKSPACE.store(ws, Ordering::Release);
-                if ws > 0 && ws <= 10 {
-                    WORKSPACES[(ws - 1) as usize].store(true, Ordering::Release);
-                }
+            if let (Some(monitor), Ok(ws)) = (monitors.last_mut(), ws_str.parse::<i32>()) {
+                monitor.active_ws = ws;
+            }
+        } else if line == "focused: yes" {
+            focused = monitors.last().map(|m| m.name.clone());
+        }
+    }
+
+    if let Ok(mut guard) = MONITORS.lock() {
+        *guard = monitors;
+    }
+    focused
+}
+
+/// Reads the focused window, its class and its title from `activewindow`.
+fn init_active_window(state: &mut HyprState) {
+    let (address, class, title) = active_window().unwrap_or_default();
+    state.focused_window = address;
+    set_window_class(&class);
+    set_window_title(&title);
+}
+
+/// Address, class and title of the focused window.
+fn active_window() -> Option<(String, String, String)> {
+    let out_str = request("activewindow")?;
+    let mut address = None;
+    let mut class = String::new();
+    let mut title = String::new();
+    for line in out_str.lines() {
+        let trimmed = line.trim_start();
+        // "Window 55d8c7a1e2f0 -> kitty:"
+        if let Some(remainder) = line.strip_prefix("Window ") {
+            address = remainder.split_whitespace().next().map(str::to_string);
+        } else if let Some(c) = trimmed.strip_prefix("class: ") {
+            class = c.to_string();
+        } else if let Some(t) = trimmed.strip_prefix("title: ") {
+            title = t.to_string();
+        }
+    }
+    Some((address?, class, title))
+}
+
+/// Returns true when the title changed.
+fn set_window_title(title: &str) -> bool {
+    replace_string(&WINDOW_TITLE, title)
+}
+
+/// Returns true when the class changed.
+fn set_window_class(class: &str) -> bool {
+    replace_string(&WINDOW_CLASS, class)
+}
+
+fn replace_string(target: &Mutex<String>, value: &str) -> bool {
+    let Ok(mut guard) = target.lock() else {
+        return false;
+    };
+    if *guard == value {
+        return false;
+    }
+    guard.clear();
+    guard.push_str(value);
+    true
+}
+
+/// Reads the main keyboard and its active layout from socket1 `devices`.
+fn init_keyboard_layout(state: &mut HyprState) {
+    let Some(out_str) = request("devices") else {
+        return;
+    };
+
+    // (device, layouts, active keymap, main)
+    let mut keyboards: Vec<(String, String, String, bool)> = Vec::new();
+    let mut in_keyboards = false;
+    let mut expect_name = false;
+    for line in out_str.lines() {
+        let trimmed = line.trim();
+        // Section headers ("mice:", "Keyboards:") are the only unindented lines
+        if !line.starts_with(char::is_whitespace) {
+            in_keyboards = trimmed == "Keyboards:";
+            continue;
+        }
+        if !in_keyboards {
+            continue;
+        }
+        if trimmed.starts_with("Keyboard at ") {
+            keyboards.push(Default::default());
+            expect_name = true;
+            continue;
+        }
+        let Some(kb) = keyboards.last_mut() else {
+            continue;
+        };
+        if expect_name {
+            kb.0 = trimmed.to_string();
+            expect_name = false;
+        } else if let Some(rules) = trimmed.strip_prefix("rules: ") {
+            // rules: r "", m "", l "us,de", v "", o ""
+            if let Some(l) = rules.split(", ").find_map(|p| p.strip_prefix("l ")) {
+                kb.1 = l.trim_matches('"').to_string();
             }
+        } else if let Some(keymap) = trimmed.strip_prefix("active keymap: ") {
+            kb.2 = keymap.to_string();
+        } else if trimmed == "main: yes" {
+            kb.3 = true;
         }
     }
 
-    // hyprctl workspaces
-    if let Ok(output) = Command::new("hyprctl").arg("workspaces").output() {
-        let out_str = String::from_utf8_lossy(&output.stdout);
-        for line in out_str.lines() {
-            if let Some(remainder) = line.strip_prefix("workspace ID ") {
-                let ws_str = remainder.split_whitespace().next().unwrap_or("");
-                if let Ok(ws) = ws_str.parse::<u8>()
-                    && ws > 0
-                    && ws <= 10
-                {
-                    WORKSPACES[(ws - 1) as usize].store(true, Ordering::Release);
+    let Some(idx) = keyboards
+        .iter()
+        .position(|kb| kb.3)
+        .or((!keyboards.is_empty()).then_some(0))
+    else {
+        return;
+    };
+    let (device, layouts, keymap, _) = keyboards.swap_remove(idx);
+    state.layouts = layouts
+        .split(',')
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect();
+    let code = layout_code(&keymap, state);
+    health::ok("keyboard_layout");
+    if let Ok(mut guard) = KEYBOARD_LAYOUT.lock() {
+        guard.device = device;
+        guard.code = code;
+    }
+}
+
+/// Whether the per-window indicator should be shown for `code`. The default is
+/// the first layout configured on the keyboard.
+fn differs_from_default(code: &str, state: &HyprState) -> bool {
+    config::get().per_window_layout
+        && state
+            .layouts
+            .first()
+            .is_some_and(|default| !default.eq_ignore_ascii_case(code))
+}
+
+/// Turns an xkb layout description into the short code shown on the bar.
+fn layout_code(keymap: &str, state: &HyprState) -> String {
+    if let Some((_, code)) = state.xkb_names.iter().find(|(desc, _)| desc == keymap) {
+        return code.to_uppercase();
+    }
+    if let [only] = state.layouts.as_slice() {
+        return only.to_uppercase();
+    }
+    keymap
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .take(2)
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Parses the layout and variant descriptions from the xkb rules listing.
+fn load_xkb_layout_names() -> Vec<(String, String)> {
+    let Ok(list) = std::fs::read_to_string("/usr/share/X11/xkb/rules/evdev.lst") else {
+        return Vec::new();
+    };
+
+    let mut names = Vec::new();
+    let mut section = "";
+    for line in list.lines() {
+        if let Some(header) = line.strip_prefix("! ") {
+            section = header.trim();
+            continue;
+        }
+        let Some((code, desc)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+        let desc = desc.trim();
+        match section {
+            // "  us              English (US)"
+            "layout" => names.push((desc.to_string(), code.to_string())),
+            // "  intl            us: English (US, intl., with dead keys)"
+            "variant" => {
+                if let Some((layout, desc)) = desc.split_once(": ") {
+                    names.push((desc.to_string(), layout.to_string()));
                 }
             }
+            _ => {}
         }
     }
+    names
 }
 
-fn handle_event(event: &str, wake_fd: &OwnedFd) {
+fn focused_monitor() -> String {
+    FOCUSED_MONITOR
+        .lock()
+        .map(|m| m.clone())
+        .unwrap_or_default()
+}
+
+fn set_focused_monitor(monitor: &str) {
+    if let Ok(mut guard) = FOCUSED_MONITOR.lock() {
+        guard.clear();
+        guard.push_str(monitor);
+    }
+}
+
+fn set_monitor_workspace(monitor: &str, ws: i32) {
+    // Until the focused monitor is known bars go by `ACTIVE_WORKSPACE` instead
+    if monitor.is_empty() {
+        return;
+    }
+    if let Ok(mut guard) = MONITORS.lock() {
+        if let Some(m) = guard.iter_mut().find(|m| m.name == monitor) {
+            m.active_ws = ws;
+        } else {
+            let id = guard.len() as u8;
+            guard.push(MonitorWorkspace {
+                id,
+                name: monitor.to_string(),
+                active_ws: ws,
+            });
+        }
+    }
+}
+
+fn handle_event(event: &str, state: &mut HyprState, wake_fd: &OwnedFd) {
     // Some Hyprland events have trailing newlines or whitespace depending on the reader
     let event = event.trim();
 
-    if let Some(ws_str) = event.strip_prefix("workspace>>") {
-        if let Ok(ws) = ws_str.parse::<u8>() {
+    // Titles in activewindow>> may mention workspaces too, go by the event name
+    let name = event.split_once(">>").map_or("", |(name, _)| name);
+    if matches!(
+        name,
+        "workspace"
+            | "workspacev2"
+            | "createworkspace"
+            | "createworkspacev2"
+            | "destroyworkspace"
+            | "destroyworkspacev2"
+            | "moveworkspace"
+            | "moveworkspacev2"
+    ) {
+        health::ok("workspaces");
+    }
+
+    if let Some(args) = event.strip_prefix("workspacev2>>") {
+        // workspacev2>>ID,NAME is emitted for the focused monitor
+        let (id_str, name) = args.split_once(',').unwrap_or((args, ""));
+        if let Ok(ws) = id_str.parse::<i32>() {
+            ACTIVE_WORKSPACE.store(ws, Ordering::Release);
+            let monitor = focused_monitor();
+            upsert_workspace(ws, name, &monitor);
+            set_monitor_workspace(&monitor, ws);
+            ping_main_thread(wake_fd);
+        }
+    } else if let Some(args) = event.strip_prefix("focusedmon>>") {
+        // focusedmon>>MONNAME,WORKSPACENAME
+        let (monitor, ws_name) = args.split_once(',').unwrap_or((args, ""));
+        set_focused_monitor(monitor);
+        if let Some(ws) = workspace_id_by_name(ws_name) {
             ACTIVE_WORKSPACE.store(ws, Ordering::Release);
-            if ws > 0 && ws <= 10 {
-                WORKSPACES[(ws - 1) as usize].store(true, Ordering::Release);
+            set_monitor_workspace(monitor, ws);
+        }
+        ping_main_thread(wake_fd);
+    } else if let Some(args) = event.strip_prefix("createworkspacev2>>") {
+        // createworkspacev2>>ID,NAME, new workspaces open on the focused monitor
+        let (id_str, name) = args.split_once(',').unwrap_or((args, ""));
+        if let Ok(ws) = id_str.parse::<i32>() {
+            upsert_workspace(ws, name, &focused_monitor());
+            ping_main_thread(wake_fd);
+        }
+    } else if let Some(args) = event.strip_prefix("destroyworkspacev2>>") {
+        let id_str = args.split(',').next().unwrap_or("");
+        if let Ok(ws) = id_str.parse::<i32>()
+            && let Ok(mut guard) = WORKSPACES.lock()
+        {
+            guard.retain(|w| w.id != ws);
+            drop(guard);
+            ping_main_thread(wake_fd);
+        }
+    } else if let Some(args) = event.strip_prefix("renameworkspace>>") {
+        // renameworkspace>>ID,NEWNAME
+        let (id_str, name) = args.split_once(',').unwrap_or((args, ""));
+        if let Ok(ws) = id_str.parse::<i32>() {
+            upsert_workspace(ws, name, "");
+            ping_main_thread(wake_fd);
+        }
+    } else if let Some(args) = event.strip_prefix("moveworkspacev2>>") {
+        // moveworkspacev2>>ID,NAME,MONNAME, the name may contain commas
+        let mut parts = args.splitn(2, ',');
+        let id_str = parts.next().unwrap_or("");
+        if let (Ok(ws), Some((name, monitor))) = (
+            id_str.parse::<i32>(),
+            parts.next().and_then(|r| r.rsplit_once(',')),
+        ) {
+            upsert_workspace(ws, name, monitor);
+        }
+        // Active workspaces of both monitors may have changed, resync them
+        if let Some(focused) = init_monitors() {
+            set_focused_monitor(&focused);
+        }
+        ping_main_thread(wake_fd);
+    } else if event.starts_with("monitoradded>>") || event.starts_with("monitorremoved>>") {
+        // Workspace-to-monitor assignment changed, resync everything
+        if let Some(focused) = init_monitors() {
+            set_focused_monitor(&focused);
+        }
+        ping_main_thread(wake_fd);
+    } else if let Some(args) = event.strip_prefix("activelayout>>") {
+        // activelayout>>KEYBOARDNAME,LAYOUTNAME, the layout name may itself contain commas
+        let (device, keymap) = args.split_once(',').unwrap_or(("", args));
+        let code = layout_code(keymap, state);
+        health::ok("keyboard_layout");
+        if !state.focused_window.is_empty() {
+            state
+                .window_layouts
+                .insert(state.focused_window.clone(), code.clone());
+        }
+        if let Ok(mut guard) = KEYBOARD_LAYOUT.lock()
+            && (guard.device.is_empty() || guard.device == device)
+        {
+            let differs = differs_from_default(&code, state);
+            if guard.code != code || guard.differs_from_default != differs {
+                guard.code = code;
+                guard.differs_from_default = differs;
+                drop(guard);
+                ping_main_thread(wake_fd);
             }
+        }
+    } else if let Some(address) = event.strip_prefix("activewindowv2>>") {
+        state.focused_window = address.to_string();
+        // The per-window layout switch lands as a separate activelayout event, show
+        // the layout the window is known to use until then
+        if config::get().per_window_layout
+            && let Some(code) = state.window_layouts.get(address)
+            && let Ok(mut guard) = KEYBOARD_LAYOUT.lock()
+        {
+            let differs = differs_from_default(code, state);
+            if guard.code != *code || guard.differs_from_default != differs {
+                guard.code = code.clone();
+                guard.differs_from_default = differs;
+                drop(guard);
+                ping_main_thread(wake_fd);
+            }
+        }
+    } else if let Some(args) = event.strip_prefix("screencast>>") {
+        // screencast>>STATE,OWNER, 1 while a monitor or window is shared
+        let shared = args.split(',').next() == Some("1");
+        if privacy::set_screen_shared(shared) {
             ping_main_thread(wake_fd);
         }
-    } else if let Some(ws_str) = event.strip_prefix("createworkspace>>") {
-        if let Ok(ws) = ws_str.parse::<u8>()
-            && ws > 0
-            && ws <= 10
+    } else if let Some(args) = event.strip_prefix("activewindow>>") {
+        // activewindow>>CLASS,TITLE, classes never contain commas but titles may
+        let (class, title) = args.split_once(',').unwrap_or(("", ""));
+        if set_window_class(class) | set_window_title(title) {
+            ping_main_thread(wake_fd);
+        }
+    } else if let Some(args) = event.strip_prefix("windowtitlev2>>") {
+        // windowtitlev2>>ADDRESS,TITLE, titles change without a focus change
+        // (browser tabs, shell prompts)
+        state.title_v2 = true;
+        if let Some((address, title)) = args.split_once(',')
+            && address == state.focused_window
+            && set_window_title(title)
         {
-            WORKSPACES[(ws - 1) as usize].store(true, Ordering::Release);
             ping_main_thread(wake_fd);
         }
-    } else if let Some(ws_str) = event.strip_prefix("destroyworkspace>>")
-        && let Ok(ws) = ws_str.parse::<u8>()
-        && ws > 0
-        && ws <= 10
-    {
-        WORKSPACES[(ws - 1) as usize].store(false, Ordering::Release);
+    } else if let Some(address) = event.strip_prefix("windowtitle>>") {
+        // Older Hyprland only sends the address, ask for the new title
+        if !state.title_v2
+            && address == state.focused_window
+            && let Some((_, _, title)) = active_window()
+            && set_window_title(&title)
+        {
+            ping_main_thread(wake_fd);
+        }
+    } else if let Some(address) = event.strip_prefix("closewindow>>") {
+        state.window_layouts.remove(address);
+    }
+
+    // Windows coming and going change the master area, a config reload may switch
+    // the layout itself
+    let tiling_changed = [
+        "activelayout>>",
+        "workspace>>",
+        "workspacev2>>",
+        "openwindow>>",
+        "closewindow>>",
+        "configreloaded>>",
+    ]
+    .iter()
+    .any(|prefix| event.starts_with(prefix));
+    if tiling_changed && tiling_shown() && refresh_tiling_layout() {
+        ping_main_thread(wake_fd);
+    }
+
+    // The minimap and occupancy ramp follow each workspace's window count, the
+    // fullscreen overlay follows fullscreen windows wherever they go
+    let config = config::get();
+    let windows_changed = ["openwindow>>", "closewindow>>", "movewindowv2>>"]
+        .iter()
+        .any(|prefix| event.starts_with(prefix));
+    let counts_wanted = config.workspace_minimap_min_width > 0 || config.workspace_ramp_windows > 0;
+    let details_wanted = (windows_changed && counts_wanted)
+        || (config.fullscreen_overlay && (windows_changed || event.starts_with("fullscreen>>")));
+    if details_wanted && refresh_workspace_details() {
         ping_main_thread(wake_fd);
     }
 }
@@ -0,0 +1,229 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::OwnedFd;
+use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    STATUS_BLOCKS, STATUS_GENERATION, StatusBlock, config, health,
+    json::{self, Json},
+    ping_main_thread,
+    threads::custom,
+};
+
+/// Before a `status_command` that exited is started again.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Clicks not yet written when the command reads slowly, more are dropped.
+const CLICK_QUEUE: usize = 16;
+
+/// Feeds the thread writing to the command's stdin while it asked for click
+/// events, a command that stops reading can't block the main thread.
+static CLICKS: Mutex<Option<SyncSender<String>>> = Mutex::new(None);
+
+/// Where a status block was clicked, in the bar's pixels. Buttons are
+/// numbered like X11's, 4 and 5 scroll.
+pub struct ClickEvent {
+    pub button: u8,
+    pub x: usize,
+    pub y: usize,
+    pub relative_x: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Blocks of an i3bar protocol status command such as i3status or i3blocks,
+/// started as `status_command` and read for as long as it runs. A command that
+/// doesn't start with the protocol's header is read as plain text, every line
+/// one block.
+pub fn start(wake_fd: OwnedFd) {
+    let command = &config::get().status_command;
+    if command.is_empty() {
+        health::disabled("status", "status_command not set");
+        return;
+    }
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Status Thread] Started");
+            loop {
+                match follow(command, &wake_fd) {
+                    Ok(status) => health::degraded("status", status),
+                    Err(e) => health::degraded("status", format!("status_command: {}", e)),
+                }
+                if let Ok(mut clicks) = CLICKS.lock() {
+                    *clicks = None;
+                }
+                if store(Vec::new()) {
+                    ping_main_thread(&wake_fd);
+                }
+                thread::sleep(RESTART_DELAY);
+            }
+        });
+}
+
+/// Sends a click on the block at `index` to the command, if it asked for them.
+pub fn click(index: u8, event: ClickEvent) {
+    let block = STATUS_BLOCKS
+        .lock()
+        .ok()
+        .and_then(|blocks| blocks.get(index as usize).cloned());
+    let (Some(block), Ok(mut clicks)) = (block, CLICKS.lock()) else {
+        return;
+    };
+    let Some(sender) = clicks.as_ref() else {
+        return;
+    };
+    let line = format!(
+        "{{\"name\":{},\"instance\":{},\"button\":{},\"x\":{},\"y\":{},\"relative_x\":{},\"relative_y\":{},\"width\":{},\"height\":{}}},\n",
+        json::quote(&block.name),
+        json::quote(&block.instance),
+        event.button,
+        event.x,
+        event.y,
+        event.relative_x,
+        // Blocks span the bar's height
+        event.y,
+        event.width,
+        event.height,
+    );
+    match sender.try_send(line) {
+        Ok(()) => {}
+        Err(TrySendError::Full(_)) => {
+            eprintln!("[Status] Dropped a click, the command isn't reading")
+        }
+        // The writer gave up on the command
+        Err(TrySendError::Disconnected(_)) => *clicks = None,
+    }
+}
+
+/// Runs the command and stores its blocks until it exits, returns how it did.
+fn follow(command: &str, wake_fd: &OwnedFd) -> io::Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        return Err(io::Error::other("no pipes"));
+    };
+    health::ok("status");
+    let read = read(stdin, stdout, wake_fd);
+    let _ = child.kill();
+    let status = child.wait()?;
+    read.map(|()| format!("status_command exited with {}", status))
+}
+
+fn read(mut stdin: ChildStdin, stdout: ChildStdout, wake_fd: &OwnedFd) -> io::Result<()> {
+    let mut lines = BufReader::new(stdout).lines();
+    let mut header = String::new();
+    if let Some(line) = lines.next() {
+        header = line?;
+    }
+    let protocol = Json::parse(&header).filter(|h| h.get("version").is_some());
+    match &protocol {
+        Some(header) => {
+            if header.get("click_events").and_then(Json::as_bool) == Some(true) {
+                // Opens the endless array of them
+                stdin.write_all(b"[\n")?;
+                let (sender, receiver) = mpsc::sync_channel::<String>(CLICK_QUEUE);
+                thread::Builder::new()
+                    .stack_size(64 * 1024)
+                    .spawn(move || {
+                        for line in receiver {
+                            if let Err(e) = stdin.write_all(line.as_bytes()) {
+                                eprintln!("[Status] Sending a click failed: {}", e);
+                                return;
+                            }
+                        }
+                    })?;
+                if let Ok(mut clicks) = CLICKS.lock() {
+                    *clicks = Some(sender);
+                }
+            }
+        }
+        // Plain text, the first line already is a status
+        None => {
+            if store(plain(&header)) {
+                ping_main_thread(wake_fd);
+            }
+        }
+    }
+
+    // The blocks come as an endless array of arrays, one per line
+    let mut opened = false;
+    for line in lines {
+        let line = line?;
+        let blocks = if protocol.is_none() {
+            plain(&line)
+        } else {
+            let mut line = line.trim();
+            if !opened && let Some(rest) = line.strip_prefix('[') {
+                opened = true;
+                line = rest.trim_start();
+            }
+            let line = line.strip_prefix(',').unwrap_or(line).trim_start();
+            match Json::parse(line) {
+                Some(Json::List(blocks)) => blocks.iter().filter_map(parse_block).collect(),
+                _ => continue,
+            }
+        };
+        if store(blocks) {
+            ping_main_thread(wake_fd);
+        }
+    }
+    Ok(())
+}
+
+/// A line of a command without the protocol, shown as one block.
+fn plain(line: &str) -> Vec<StatusBlock> {
+    let text = line.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    vec![StatusBlock {
+        full_text: text.to_string(),
+        ..StatusBlock::default()
+    }]
+}
+
+fn parse_block(block: &Json) -> Option<StatusBlock> {
+    let field = |key: &str| block.get(key).and_then(Json::as_str).unwrap_or_default();
+    let text = |key: &str| {
+        if field("markup") == "pango" {
+            custom::strip_markup(field(key))
+        } else {
+            field(key).to_string()
+        }
+    };
+    let full_text = text("full_text");
+    if full_text.is_empty() {
+        return None;
+    }
+    Some(StatusBlock {
+        full_text,
+        short_text: text("short_text"),
+        color: config::parse_color(field("color")).unwrap_or(0),
+        urgent: block.get("urgent").and_then(Json::as_bool) == Some(true),
+        name: field("name").to_string(),
+        instance: field("instance").to_string(),
+    })
+}
+
+/// Returns true if the blocks changed.
+fn store(blocks: Vec<StatusBlock>) -> bool {
+    let Ok(mut current) = STATUS_BLOCKS.lock() else {
+        return false;
+    };
+    if *current == blocks {
+        return false;
+    }
+    *current = blocks;
+    STATUS_GENERATION.fetch_add(1, Ordering::AcqRel);
+    true
+}
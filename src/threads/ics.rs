@@ -0,0 +1,706 @@
+use std::fs;
+use std::os::fd::OwnedFd;
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset, Weekday};
+
+use crate::{NEXT_EVENT, NEXT_EVENT_SOON, UTC_OFFSET_S, config, health, ping_main_thread};
+
+/// How often local calendars are checked for changes, the text itself only
+/// changes once a minute.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Recurrence periods stepped through before a rule counts as finished, a
+/// daily event from ten years ago still fits.
+const MAX_PERIODS: u32 = 20_000;
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// The earliest event of the configured calendars that hasn't ended yet. Local
+/// files are read again when their modification time changes, remote ones are
+/// downloaded with curl every `next_event_refresh` minutes. Time zones aren't
+/// looked up, a time with a TZID is taken as local time like a floating one.
+pub fn start(wake_fd: OwnedFd) {
+    let locations = &config::get().next_event_sources;
+    if locations.is_empty() {
+        health::disabled("next_event", "no next_event_sources configured");
+        return;
+    }
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Calendar Thread] Started");
+            let mut sources: Vec<Source> = locations.iter().map(|l| Source::new(l)).collect();
+            let mut events = Vec::new();
+            let mut minute = -1;
+            loop {
+                let mut reload = false;
+                let mut failed = None;
+                for source in &mut sources {
+                    match source.refresh() {
+                        Ok(changed) => reload |= changed,
+                        Err(e) => failed = Some(e),
+                    }
+                }
+                match failed {
+                    Some(e) => health::degraded("next_event", e),
+                    None => health::ok("next_event"),
+                }
+                if reload {
+                    events = sources.iter().flat_map(|s| parse(&s.text)).collect();
+                }
+
+                let now = unix_now();
+                if reload || now / 60 != minute {
+                    minute = now / 60;
+                    if update(&events, now) {
+                        ping_main_thread(&wake_fd);
+                    }
+                }
+                thread::sleep(CHECK_INTERVAL);
+            }
+        });
+}
+
+/// One calendar and its last successfully read contents.
+struct Source {
+    location: String,
+    remote: bool,
+    text: String,
+    modified: Option<SystemTime>,
+    fetched: Option<Instant>,
+}
+
+impl Source {
+    fn new(location: &str) -> Self {
+        let remote = ["http://", "https://", "webcal://"]
+            .iter()
+            .any(|scheme| location.starts_with(scheme));
+        let location = if remote {
+            location.to_string()
+        } else {
+            config::expand_home(location).to_string_lossy().into_owned()
+        };
+        Self {
+            location,
+            remote,
+            text: String::new(),
+            modified: None,
+            fetched: None,
+        }
+    }
+
+    /// Reads the calendar again when it is due, returns whether its contents
+    /// changed. A failed read keeps the previous contents.
+    fn refresh(&mut self) -> Result<bool, String> {
+        let text = if self.remote {
+            let interval = Duration::from_secs(config::get().next_event_refresh as u64 * 60);
+            if self.fetched.is_some_and(|f| f.elapsed() < interval) {
+                return Ok(false);
+            }
+            self.fetched = Some(Instant::now());
+            self.download()?
+        } else {
+            let modified = fs::metadata(&self.location)
+                .and_then(|m| m.modified())
+                .map_err(|e| format!("{}: {}", self.location, e))?;
+            if self.modified == Some(modified) {
+                return Ok(false);
+            }
+            self.modified = Some(modified);
+            fs::read_to_string(&self.location).map_err(|e| format!("{}: {}", self.location, e))?
+        };
+        let changed = text != self.text;
+        self.text = text;
+        Ok(changed)
+    }
+
+    fn download(&self) -> Result<String, String> {
+        // webcal is plain https under another name
+        let url = match self.location.strip_prefix("webcal://") {
+            Some(rest) => format!("https://{}", rest),
+            None => self.location.clone(),
+        };
+        let output = Command::new("curl")
+            .args(["-fsSL", "--max-time", "30", &url])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("curl: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "downloading {}: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Whether a time is UTC, local or a whole day. Times with a TZID are taken as
+/// local, calendars shown on a bar are almost always in the user's own zone.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Utc,
+    Local,
+    Day,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The part of an RRULE that is understood. Rules using anything else only
+/// keep their first occurrence rather than showing made up dates.
+struct Rule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<(PrimitiveDateTime, Kind)>,
+    /// Weekdays of a weekly rule, empty repeats the start's weekday.
+    days: Vec<Weekday>,
+}
+
+struct Event {
+    start: PrimitiveDateTime,
+    kind: Kind,
+    /// Seconds, 0 for whole days since those never count as under way.
+    duration: i64,
+    summary: String,
+    rule: Option<Rule>,
+    /// Occurrences removed from the rule.
+    excluded: Vec<(PrimitiveDateTime, Kind)>,
+}
+
+/// Every VEVENT with a start, cancelled ones left out. Components nested in an
+/// event such as VALARM are skipped, their properties aren't the event's.
+fn parse(text: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut current: Option<Vec<(String, String)>> = None;
+    let mut nested = 0;
+    for line in unfold(text) {
+        let Some((name, value)) = split_property(&line) else {
+            continue;
+        };
+        match (name.as_str(), value.as_str()) {
+            ("BEGIN", "VEVENT") => current = Some(Vec::new()),
+            ("END", "VEVENT") => {
+                if let Some(event) = current.take().and_then(|p| build_event(&p)) {
+                    events.push(event);
+                }
+                nested = 0;
+            }
+            ("BEGIN", _) if current.is_some() => nested += 1,
+            ("END", _) if current.is_some() => nested -= 1,
+            _ if nested > 0 => {}
+            _ => {
+                if let Some(properties) = current.as_mut() {
+                    properties.push((name, value));
+                }
+            }
+        }
+    }
+    events
+}
+
+/// Lines with folded continuations joined back on.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// "DTSTART;TZID=Europe/Berlin:20261016T140000" as the upper case name and the
+/// value, parameters dropped. Quoted parameters may contain colons.
+fn split_property(line: &str) -> Option<(String, String)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        (c == ':' && !quoted).then_some(i)
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let name = head.split(';').next().unwrap_or(head);
+    Some((name.to_ascii_uppercase(), value.to_string()))
+}
+
+fn build_event(properties: &[(String, String)]) -> Option<Event> {
+    let property = |name: &str| {
+        properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    if property("STATUS").is_some_and(|s| s.eq_ignore_ascii_case("CANCELLED")) {
+        return None;
+    }
+    let (start, kind) = parse_time(property("DTSTART")?)?;
+    let duration = if kind == Kind::Day {
+        0
+    } else if let Some((end, end_kind)) = property("DTEND").and_then(parse_time) {
+        (unix(end, end_kind) - unix(start, kind)).max(0)
+    } else {
+        property("DURATION").and_then(parse_duration).unwrap_or(0)
+    };
+    let excluded = properties
+        .iter()
+        .filter(|(n, _)| n == "EXDATE")
+        .flat_map(|(_, v)| v.split(','))
+        .filter_map(parse_time)
+        .collect();
+    Some(Event {
+        start,
+        kind,
+        duration,
+        summary: unescape(property("SUMMARY").unwrap_or("")),
+        rule: property("RRULE").and_then(parse_rule),
+        excluded,
+    })
+}
+
+/// "20261016", "20261016T140000" or "20261016T120000Z".
+fn parse_time(value: &str) -> Option<(PrimitiveDateTime, Kind)> {
+    let value = value.trim();
+    let number = |range: std::ops::Range<usize>| value.get(range)?.parse::<u32>().ok();
+    let date = Date::from_calendar_date(
+        number(0..4)? as i32,
+        Month::try_from(number(4..6)? as u8).ok()?,
+        number(6..8)? as u8,
+    )
+    .ok()?;
+    if value.len() == 8 {
+        return Some((date.midnight(), Kind::Day));
+    }
+    if value.as_bytes().get(8) != Some(&b'T') {
+        return None;
+    }
+    let time = Time::from_hms(
+        number(9..11)? as u8,
+        number(11..13)? as u8,
+        number(13..15)? as u8,
+    )
+    .ok()?;
+    let kind = if value.ends_with('Z') {
+        Kind::Utc
+    } else {
+        Kind::Local
+    };
+    Some((PrimitiveDateTime::new(date, time), kind))
+}
+
+/// "PT1H30M", "P1D" or "P2W" in seconds.
+fn parse_duration(value: &str) -> Option<i64> {
+    let rest = value.trim().strip_prefix('+').unwrap_or(value.trim());
+    let rest = rest.strip_prefix('P')?;
+    let mut seconds: i64 = 0;
+    let mut number: i64 = 0;
+    for c in rest.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number = number
+                    .checked_mul(10)?
+                    .checked_add(c.to_digit(10)? as i64)?;
+                continue;
+            }
+            'W' => 7 * 86400,
+            'D' => 86400,
+            'H' => 3600,
+            'M' => 60,
+            'S' => 1,
+            'T' => 0,
+            _ => return None,
+        };
+        seconds = seconds.checked_add(number.checked_mul(unit)?)?;
+        number = 0;
+    }
+    Some(seconds)
+}
+
+fn parse_rule(value: &str) -> Option<Rule> {
+    let mut rule = Rule {
+        frequency: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        days: Vec::new(),
+    };
+    let mut frequency = None;
+    for part in value.split(';') {
+        let (key, value) = part.split_once('=')?;
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                frequency = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return None,
+                })
+            }
+            "INTERVAL" => rule.interval = value.parse().ok().filter(|i| *i > 0)?,
+            "COUNT" => rule.count = Some(value.parse().ok()?),
+            "UNTIL" => rule.until = Some(parse_time(value)?),
+            "BYDAY" => {
+                rule.days = value
+                    .split(',')
+                    .map(|day| {
+                        let index = WEEKDAYS
+                            .iter()
+                            .position(|name| name[..2].eq_ignore_ascii_case(day))?;
+                        Some(Weekday::Monday.nth_next(index as u8))
+                    })
+                    .collect::<Option<_>>()?;
+            }
+            "WKST" => {}
+            _ => return None,
+        }
+    }
+    rule.frequency = frequency?;
+    // BYDAY on anything but weekly rules picks e.g. the second Tuesday
+    if !rule.days.is_empty() && rule.frequency != Frequency::Weekly {
+        return None;
+    }
+    rule.days.sort_by_key(|d| d.number_days_from_monday());
+    Some(rule)
+}
+
+/// Text values have `\,`, `\;`, `\\` and `\n` escaped.
+fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => text.push(' '),
+                Some(c) => text.push(c),
+                None => {}
+            },
+            c => text.push(c),
+        }
+    }
+    text.trim().to_string()
+}
+
+fn local_offset() -> UtcOffset {
+    let offset = match UTC_OFFSET_S.load(Ordering::Acquire) {
+        i32::MIN => config::get().clock_fallback_offset,
+        offset => offset,
+    };
+    UtcOffset::from_whole_seconds(offset).unwrap_or(UtcOffset::UTC)
+}
+
+fn unix(time: PrimitiveDateTime, kind: Kind) -> i64 {
+    match kind {
+        Kind::Utc => time.assume_utc().unix_timestamp(),
+        Kind::Local | Kind::Day => time.assume_offset(local_offset()).unix_timestamp(),
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+impl Event {
+    /// Start of the first occurrence that is still under way or to come, whole
+    /// days only while still to come.
+    fn next_start(&self, now: i64) -> Option<i64> {
+        let ends_after = |start: i64| start + self.duration > now;
+        let Some(rule) = &self.rule else {
+            let start = unix(self.start, self.kind);
+            return ends_after(start).then_some(start);
+        };
+        let until = rule.until.map(|(time, kind)| unix(time, kind));
+        let mut seen = 0;
+        for period in 0..MAX_PERIODS {
+            // Past what dates can hold the rule ends
+            for start in rule.period(self.start, period)? {
+                if start < self.start {
+                    continue;
+                }
+                seen += 1;
+                if rule.count.is_some_and(|count| seen > count) {
+                    return None;
+                }
+                let unix_start = unix(start, self.kind);
+                if until.is_some_and(|until| unix_start > until) {
+                    return None;
+                }
+                let excluded = self
+                    .excluded
+                    .iter()
+                    .any(|&(time, kind)| unix(time, kind) == unix_start);
+                if ends_after(unix_start) && !excluded {
+                    return Some(unix_start);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Rule {
+    /// Occurrences in the nth period after the first, in order. Dates that
+    /// don't exist in a month or year (the 31st, February 29th) are skipped.
+    /// None when the period lies past what the arithmetic holds.
+    fn period(&self, first: PrimitiveDateTime, n: u32) -> Option<Vec<PrimitiveDateTime>> {
+        let step = self.interval.checked_mul(n)? as i64;
+        let date = first.date();
+        let dates = match self.frequency {
+            Frequency::Daily => vec![date.checked_add(time::Duration::days(step))],
+            Frequency::Weekly if self.days.is_empty() => {
+                vec![date.checked_add(time::Duration::weeks(step))]
+            }
+            Frequency::Weekly => {
+                let monday = date.checked_sub(time::Duration::days(
+                    date.weekday().number_days_from_monday() as i64,
+                ));
+                let week = monday.and_then(|m| m.checked_add(time::Duration::weeks(step)));
+                self.days
+                    .iter()
+                    .map(|day| {
+                        week?
+                            .checked_add(time::Duration::days(day.number_days_from_monday() as i64))
+                    })
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let months = date.year() as i64 * 12 + u8::from(date.month()) as i64 - 1 + step;
+                vec![
+                    Month::try_from((months % 12 + 1) as u8)
+                        .ok()
+                        .and_then(|month| {
+                            Date::from_calendar_date((months / 12) as i32, month, date.day()).ok()
+                        }),
+                ]
+            }
+            Frequency::Yearly => {
+                let year = date.year().checked_add(i32::try_from(step).ok()?)?;
+                vec![Date::from_calendar_date(year, date.month(), date.day()).ok()]
+            }
+        };
+        Some(
+            dates
+                .into_iter()
+                .flatten()
+                .map(|d| PrimitiveDateTime::new(d, first.time()))
+                .collect(),
+        )
+    }
+}
+
+/// Writes the next event's text, returns whether it or the highlight changed.
+fn update(events: &[Event], now: i64) -> bool {
+    let next = events
+        .iter()
+        .filter_map(|event| Some((event.next_start(now)?, event)))
+        .min_by_key(|(start, _)| *start);
+    let (text, soon) = match next {
+        Some((start, event)) => {
+            let soon_s = config::get().next_event_soon as i64 * 60;
+            let soon = event.kind != Kind::Day && start - now <= soon_s;
+            (event_text(start, event, now), soon as u8)
+        }
+        None => (String::new(), 255),
+    };
+    let text_changed = NEXT_EVENT.lock().is_ok_and(|mut current| {
+        let changed = *current != text;
+        *current = text;
+        changed
+    });
+    NEXT_EVENT_SOON.swap(soon, Ordering::AcqRel) != soon || text_changed
+}
+
+/// "14:00 standup" today, "tue 14:00 standup" within a week and "24/12
+/// holidays" after that. Whole day events leave out the time.
+fn event_text(start: i64, event: &Event, now: i64) -> String {
+    let offset = local_offset();
+    let local = |t: i64| {
+        OffsetDateTime::from_unix_timestamp(t)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+            .to_offset(offset)
+    };
+    let (start_time, today) = (local(start), local(now).date());
+    let days = (start_time.date() - today).whole_days();
+    let mut parts = Vec::new();
+    if days >= 7 {
+        parts.push(format!(
+            "{:02}/{:02}",
+            start_time.day(),
+            u8::from(start_time.month())
+        ));
+    } else if days > 0 {
+        parts.push(WEEKDAYS[start_time.weekday().number_days_from_monday() as usize].to_string());
+    }
+    if event.kind != Kind::Day {
+        parts.push(format!(
+            "{:02}:{:02}",
+            start_time.hour(),
+            start_time.minute()
+        ));
+    }
+    if !event.summary.is_empty() {
+        parts.push(event.summary.clone());
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> i64 {
+        let (time, kind) = parse_time(value).unwrap();
+        unix(time, kind)
+    }
+
+    fn event(properties: &str) -> Event {
+        // Local times as UTC, rather than the clock's offset or the config's
+        UTC_OFFSET_S.store(0, Ordering::Release);
+        let mut events = parse(&format!("BEGIN:VEVENT\n{}\nEND:VEVENT\n", properties));
+        assert_eq!(events.len(), 1);
+        events.pop().unwrap()
+    }
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        let lines = unfold("SUMMARY:Team\r\n  sync\r\n\tnotes\r\nDTSTART:20261016\r\n");
+        assert_eq!(lines, ["SUMMARY:Team syncnotes", "DTSTART:20261016"]);
+    }
+
+    #[test]
+    fn parses_events() {
+        let text = "BEGIN:VCALENDAR\n\
+            BEGIN:VEVENT\n\
+            SUMMARY:Lunch\\, with Sam\n\
+            DTSTART;TZID=\"Europe/Berlin:x\":20261016T120000\n\
+            DTEND:20261016T130000\n\
+            BEGIN:VALARM\n\
+            SUMMARY:Not the event's\n\
+            END:VALARM\n\
+            EXDATE:20261017T120000,20261018T120000\n\
+            RRULE:FREQ=DAILY\n\
+            END:VEVENT\n\
+            BEGIN:VEVENT\n\
+            SUMMARY:Called off\n\
+            DTSTART:20261016T150000Z\n\
+            STATUS:CANCELLED\n\
+            END:VEVENT\n\
+            BEGIN:VEVENT\n\
+            SUMMARY:Nap\n\
+            DTSTART:20261016T150000Z\n\
+            DURATION:PT20M\n\
+            END:VEVENT\n\
+            END:VCALENDAR\n";
+        UTC_OFFSET_S.store(0, Ordering::Release);
+        let events = parse(text);
+        assert_eq!(events.len(), 2);
+        let lunch = &events[0];
+        assert_eq!(lunch.summary, "Lunch, with Sam");
+        assert!(lunch.kind == Kind::Local);
+        assert_eq!(lunch.duration, 3600);
+        assert_eq!(lunch.excluded.len(), 2);
+        assert!(lunch.rule.is_some());
+        assert_eq!(events[1].summary, "Nap");
+        assert!(events[1].kind == Kind::Utc);
+        assert_eq!(events[1].duration, 20 * 60);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT1H30M"), Some(5400));
+        assert_eq!(parse_duration("+P1DT1S"), Some(86401));
+        assert_eq!(parse_duration("P2W"), Some(14 * 86400));
+        assert_eq!(parse_duration("1H"), None);
+        assert_eq!(parse_duration("P99999999999999999999D"), None);
+        assert_eq!(parse_duration("P999999999999999W"), None);
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule = parse_rule("FREQ=WEEKLY;BYDAY=FR,MO;INTERVAL=2;WKST=MO").unwrap();
+        assert!(rule.frequency == Frequency::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.days, [Weekday::Monday, Weekday::Friday]);
+        let rule = parse_rule("freq=daily;count=3;until=20261231").unwrap();
+        assert_eq!(rule.count, Some(3));
+        assert!(rule.until.is_some());
+        // Unsupported parts make the whole rule unknown
+        assert!(parse_rule("FREQ=MONTHLY;BYDAY=2TU").is_none());
+        assert!(parse_rule("FREQ=MONTHLY;BYDAY=TU").is_none());
+        assert!(parse_rule("FREQ=DAILY;BYHOUR=9").is_none());
+        assert!(parse_rule("FREQ=DAILY;INTERVAL=0").is_none());
+        assert!(parse_rule("FREQ=HOURLY").is_none());
+        assert!(parse_rule("INTERVAL=2").is_none());
+    }
+
+    #[test]
+    fn finds_the_next_start() {
+        let single = event("DTSTART:20261016T120000Z\nDTEND:20261016T130000Z");
+        assert_eq!(
+            single.next_start(at("20261016T123000Z")),
+            Some(at("20261016T120000Z"))
+        );
+        assert_eq!(single.next_start(at("20261016T130000Z")), None);
+
+        let daily = event("DTSTART:20261016T120000Z\nRRULE:FREQ=DAILY;COUNT=3");
+        assert_eq!(
+            daily.next_start(at("20261017T120001Z")),
+            Some(at("20261018T120000Z"))
+        );
+        assert_eq!(daily.next_start(at("20261018T120001Z")), None);
+
+        // 2026-10-12 is a Monday
+        let weekly = event(
+            "DTSTART:20261012T090000Z\n\
+             RRULE:FREQ=WEEKLY;BYDAY=MO,WE\n\
+             EXDATE:20261014T090000Z",
+        );
+        assert_eq!(
+            weekly.next_start(at("20261013T000000Z")),
+            Some(at("20261019T090000Z"))
+        );
+
+        let until = event("DTSTART:20261016T120000Z\nRRULE:FREQ=DAILY;UNTIL=20261017T120000Z");
+        assert_eq!(
+            until.next_start(at("20261016T120001Z")),
+            Some(at("20261017T120000Z"))
+        );
+        assert_eq!(until.next_start(at("20261017T120001Z")), None);
+
+        // Months without a 31st are skipped
+        let monthly = event("DTSTART:20260131T080000Z\nRRULE:FREQ=MONTHLY");
+        assert_eq!(
+            monthly.next_start(at("20260201T000000Z")),
+            Some(at("20260331T080000Z"))
+        );
+    }
+
+    #[test]
+    fn whole_days_count_until_they_start() {
+        let day = event("DTSTART;VALUE=DATE:20261016");
+        assert_eq!(day.next_start(at("20261015T230000Z")), Some(at("20261016")));
+        assert_eq!(day.next_start(at("20261016T000001Z")), None);
+    }
+
+    #[test]
+    fn ends_rules_that_overflow() {
+        let yearly = event("DTSTART:20261016T120000Z\nRRULE:FREQ=YEARLY;INTERVAL=4294967295");
+        assert_eq!(yearly.next_start(at("20261016T120001Z")), None);
+        let daily = event("DTSTART:20261016T120000Z\nRRULE:FREQ=DAILY;INTERVAL=4000000000");
+        assert_eq!(daily.next_start(at("20261016T120001Z")), None);
+        let monthly = event("DTSTART:20261016T120000Z\nRRULE:FREQ=MONTHLY;INTERVAL=4000000000");
+        assert_eq!(monthly.next_start(at("20261016T120001Z")), None);
+    }
+}
@@ -0,0 +1,301 @@
+use std::convert::Infallible;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::OwnedFd;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+use rustix::process::{Pid, Signal, WaitOptions, kill_process, waitpid};
+
+use crate::{
+    config::{self, ImapAccount},
+    health, mail, ping_main_thread,
+};
+
+/// Between attempts to reach a server that went away or refused the login.
+const RECONNECT_DELAY: Duration = Duration::from_secs(60);
+/// Servers drop an IDLE after 30 minutes, it is restarted well before.
+const IDLE_RESTART: Duration = Duration::from_secs(25 * 60);
+/// For the greeting and for every command but IDLE.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Process ids of the running `openssl` children, for `stop`.
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Unread mail in the INBOX of every `imap_accounts` entry, one thread each.
+/// The count is searched for once and again whenever the server reports a
+/// change while idling. TLS comes from `openssl s_client`, which verifies the
+/// certificate and the host name against the system's trust store.
+pub fn start(wake_fd: OwnedFd) {
+    let accounts = &config::get().imap_accounts;
+    if accounts.is_empty() {
+        if config::get().maildirs.is_empty() {
+            health::disabled("mail", "no maildirs or imap_accounts configured");
+        }
+        return;
+    }
+    for account in accounts {
+        let Ok(wake_fd) = wake_fd.try_clone() else {
+            continue;
+        };
+        let _ = thread::Builder::new()
+            .stack_size(128 * 1024)
+            .spawn(move || {
+                println!("[IMAP Thread] Started for {}", account.label);
+                loop {
+                    let Err(e) = watch(account, &wake_fd);
+                    eprintln!("[IMAP] {}: {}", account.label, e);
+                    health::degraded("mail", format!("{}: {}", account.label, e));
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            });
+    }
+}
+
+/// Kills and reaps the `openssl` children when dropped, which `main` does on
+/// every way out including reloads. The account threads never get to drop
+/// their sessions, orphaned children would keep the connections open since
+/// `-quiet` ignores the end of their input.
+pub struct Stopper;
+
+impl Drop for Stopper {
+    fn drop(&mut self) {
+        stop();
+    }
+}
+
+fn stop() {
+    let Ok(mut children) = CHILDREN.lock() else {
+        return;
+    };
+    for pid in children.drain(..) {
+        let Some(pid) = Pid::from_raw(pid as i32) else {
+            continue;
+        };
+        let _ = kill_process(pid, Signal::KILL);
+        let _ = waitpid(Some(pid), WaitOptions::empty());
+    }
+}
+
+/// Logs in and keeps the unread count current until the connection fails.
+fn watch(account: &ImapAccount, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    let password = password(&account.label)?;
+    let login = format!("LOGIN {} {}", quote(&account.user)?, quote(&password)?);
+    let mut session = Session::connect(&account.host, account.port)?;
+    session.command(&login)?;
+    // Read only, looking never marks anything seen
+    session.command("EXAMINE INBOX")?;
+    health::ok("mail");
+
+    loop {
+        let unread = session
+            .command("SEARCH UNSEEN")?
+            .iter()
+            .filter_map(|line| line.strip_prefix("* SEARCH"))
+            .map(|ids| ids.split_whitespace().count() as u32)
+            .sum();
+        if mail::store_unread(&account.label, unread) {
+            ping_main_thread(wake_fd);
+        }
+        session.idle()?;
+    }
+}
+
+/// Output of `imap_password_command` run with the account's label in
+/// `LEANBAR_IMAP_ACCOUNT`, without the trailing newline.
+fn password(label: &str) -> io::Result<String> {
+    let command = &config::get().imap_password_command;
+    if command.is_empty() {
+        return Err(io::Error::other("imap_password_command not set"));
+    }
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("LEANBAR_IMAP_ACCOUNT", label)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "imap_password_command exited with {}",
+            output.status
+        )));
+    }
+    let password = String::from_utf8_lossy(&output.stdout);
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// IMAP string syntax, quoted with backslashes before quotes and backslashes.
+/// Quoted strings can't hold CR or LF, sent as is they would end the command.
+fn quote(value: &str) -> io::Result<String> {
+    if value.contains(['\r', '\n']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "IMAP user or password contains a line break",
+        ));
+    }
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+/// An IMAP connection through an `openssl s_client` child. Killed when dropped.
+struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    tag: u32,
+}
+
+impl Session {
+    fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let mut child = Command::new("openssl")
+            .args(["s_client", "-quiet", "-verify_return_error"])
+            .args(["-verify_hostname", host, "-servername", host])
+            .arg("-connect")
+            .arg(format!("{}:{}", host, port))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Ok(mut children) = CHILDREN.lock() {
+            children.push(child.id());
+        }
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            reap(&mut child);
+            return Err(io::Error::other("openssl without pipes"));
+        };
+        let mut session = Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            tag: 0,
+        };
+        let greeting = session.read_line(RESPONSE_TIMEOUT)?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(io::Error::other(format!(
+                "unexpected greeting: {}",
+                greeting
+            )));
+        }
+        Ok(session)
+    }
+
+    /// Sends a command and returns the untagged lines answering it, an error
+    /// unless it completes with OK.
+    fn command(&mut self, command: &str) -> io::Result<Vec<String>> {
+        let tag = self.send(command)?;
+        let mut untagged = Vec::new();
+        loop {
+            let line = self.read_line(RESPONSE_TIMEOUT)?;
+            let Some(status) = line.strip_prefix(&tag) else {
+                untagged.push(line);
+                continue;
+            };
+            if status.starts_with("OK") {
+                return Ok(untagged);
+            }
+            // The LOGIN command line itself is never echoed, only the reply
+            let verb = command.split(' ').next().unwrap_or(command);
+            return Err(io::Error::other(format!("{} failed: {}", verb, status)));
+        }
+    }
+
+    /// Idles until the mailbox changes or it is time to restart, then ends the
+    /// IDLE so the count can be searched again.
+    fn idle(&mut self) -> io::Result<()> {
+        let tag = self.send("IDLE")?;
+        let continuation = self.read_line(RESPONSE_TIMEOUT)?;
+        if !continuation.starts_with('+') {
+            return Err(io::Error::other(format!("IDLE refused: {}", continuation)));
+        }
+        let started = Instant::now();
+        while let Some(left) = IDLE_RESTART.checked_sub(started.elapsed()) {
+            // New mail arrives as EXISTS, flags set elsewhere as FETCH
+            match self.read_line_within(left)? {
+                Some(line) if line.starts_with('*') && !line.contains(" OK") => break,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        self.stdin.write_all(b"DONE\r\n")?;
+        loop {
+            let line = self.read_line(RESPONSE_TIMEOUT)?;
+            if let Some(status) = line.strip_prefix(&tag) {
+                if !status.starts_with("OK") {
+                    return Err(io::Error::other(format!("IDLE failed: {}", status)));
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    /// Writes the command with the next tag, returns the tag and a space.
+    fn send(&mut self, command: &str) -> io::Result<String> {
+        self.tag += 1;
+        let tag = format!("a{} ", self.tag);
+        self.stdin
+            .write_all(format!("{}{}\r\n", tag, command).as_bytes())?;
+        Ok(tag)
+    }
+
+    fn read_line(&mut self, timeout: Duration) -> io::Result<String> {
+        self.read_line_within(timeout)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "server stopped answering"))
+    }
+
+    /// The next line without its line ending, None when none arrives in time.
+    fn read_line_within(&mut self, timeout: Duration) -> io::Result<Option<String>> {
+        if self.stdout.buffer().is_empty() {
+            let timeout = Timespec {
+                tv_sec: timeout.as_secs() as i64,
+                tv_nsec: timeout.subsec_nanos() as i64,
+            };
+            let mut fds = [PollFd::new(self.stdout.get_ref(), PollFlags::IN)];
+            if poll(&mut fds, Some(&timeout))? == 0 {
+                return Ok(None);
+            }
+        }
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
+            ));
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        reap(&mut self.child);
+    }
+}
+
+/// Kills the child unless `stop` already did.
+fn reap(child: &mut Child) {
+    let Ok(mut children) = CHILDREN.lock() else {
+        return;
+    };
+    if let Some(index) = children.iter().position(|pid| *pid == child.id()) {
+        children.swap_remove(index);
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_escapes_and_rejects_line_breaks() {
+        assert_eq!(quote(r#"a"b\c"#).unwrap(), r#""a\"b\\c""#);
+        assert!(quote("secret\r\na1 LOGOUT").is_err());
+        assert!(quote("secret\n").is_err());
+    }
+}
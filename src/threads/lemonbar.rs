@@ -0,0 +1,158 @@
+use std::io::{self, BufRead};
+use std::os::fd::OwnedFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::{LEMONBAR, LEMONBAR_GENERATION, LemonSegment, config, ping_main_thread};
+
+/// Set by `leanbar --lemonbar`, the bar then shows nothing but stdin.
+pub static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// `LemonSegment::align` values.
+pub const LEFT: u8 = 0;
+pub const CENTER: u8 = 1;
+pub const RIGHT: u8 = 2;
+
+/// Lines of lemonbar formatted text on stdin, each one replacing the bar's
+/// contents, so scripts written for lemonbar can drive leanbar.
+pub fn start(wake_fd: OwnedFd) {
+    ENABLED.store(true, Ordering::Release);
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Lemonbar Thread] Started");
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let segments = parse(&line);
+                let Ok(mut current) = LEMONBAR.lock() else {
+                    break;
+                };
+                if *current != segments {
+                    *current = segments;
+                    LEMONBAR_GENERATION.fetch_add(1, Ordering::AcqRel);
+                    ping_main_thread(&wake_fd);
+                }
+            }
+            // The bar keeps the last line like lemonbar does
+            eprintln!("[Lemonbar] stdin closed");
+        });
+}
+
+/// `%{l}`, `%{c}` and `%{r}` align what follows, `%{F#rrggbb}` colors it
+/// until `%{F-}` and `%%` is a literal `%`. Other commands, such as
+/// backgrounds, underlines and click areas, are skipped.
+pub fn parse(line: &str) -> Vec<LemonSegment> {
+    let mut segments = Vec::new();
+    let (mut align, mut color) = (LEFT, 0);
+    let mut text = String::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('%') else {
+            text.push_str(rest);
+            break;
+        };
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if !after.starts_with('{') {
+            // `%%` stands for one `%`, a lone one is kept as it is
+            text.push('%');
+            rest = after.strip_prefix('%').unwrap_or(after);
+            continue;
+        }
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let commands = &rest[start + 2..start + end];
+        rest = &rest[start + end + 1..];
+
+        let before = (align, color);
+        for command in commands.split_whitespace() {
+            match command {
+                "l" => align = LEFT,
+                "c" => align = CENTER,
+                "r" => align = RIGHT,
+                "F-" => color = 0,
+                _ => {
+                    if let Some(hex) = command.strip_prefix('F') {
+                        color = config::parse_color(hex).unwrap_or(0);
+                    }
+                }
+            }
+        }
+        if (align, color) != before && !text.is_empty() {
+            let (align, color) = before;
+            segments.push(LemonSegment {
+                align,
+                color,
+                text: std::mem::take(&mut text),
+            });
+        }
+    }
+    if !text.is_empty() {
+        segments.push(LemonSegment { align, color, text });
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(align: u8, color: u32, text: &str) -> LemonSegment {
+        LemonSegment {
+            align,
+            color,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn splits_on_alignment() {
+        assert_eq!(
+            parse("%{l}left%{c}center%{r}right"),
+            [
+                segment(LEFT, 0, "left"),
+                segment(CENTER, 0, "center"),
+                segment(RIGHT, 0, "right"),
+            ]
+        );
+        assert_eq!(parse("plain"), [segment(LEFT, 0, "plain")]);
+        assert_eq!(parse(""), []);
+    }
+
+    #[test]
+    fn colors_until_reset() {
+        let red = config::parse_color("#ff0000").unwrap();
+        assert_eq!(
+            parse("a%{F#ff0000}b%{F-}c"),
+            [
+                segment(LEFT, 0, "a"),
+                segment(LEFT, red, "b"),
+                segment(LEFT, 0, "c"),
+            ]
+        );
+        assert_eq!(parse("%{r F#ff0000}x"), [segment(RIGHT, red, "x")]);
+    }
+
+    #[test]
+    fn skips_other_commands() {
+        assert_eq!(
+            parse("%{B#000000}a%{U#ffffff +u}b%{A:cmd:}c%{A}"),
+            [segment(LEFT, 0, "abc")]
+        );
+        // An unterminated command ends the line
+        assert_eq!(parse("a%{F#ff0000"), [segment(LEFT, 0, "a")]);
+    }
+
+    #[test]
+    fn unescapes_percent_signs() {
+        assert_eq!(
+            parse("100%% %{r}50%%"),
+            [segment(LEFT, 0, "100% "), segment(RIGHT, 0, "50%"),]
+        );
+        assert_eq!(parse("%%{l}"), [segment(LEFT, 0, "%{l}")]);
+        assert_eq!(parse("5% off%"), [segment(LEFT, 0, "5% off%")]);
+    }
+}
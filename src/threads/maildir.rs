@@ -0,0 +1,101 @@
+use std::fs;
+use std::mem::MaybeUninit;
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use rustix::fs::inotify::{self, CreateFlags, WatchFlags};
+
+use crate::{config, health, mail, ping_main_thread};
+
+/// Unread mail of the configured Maildirs, the messages in each one's `new/`.
+/// A directory is counted again whenever a message arrives in it or is moved
+/// out by a mail client marking it seen.
+pub fn start(wake_fd: OwnedFd) {
+    let maildirs = &config::get().maildirs;
+    // Without any the module may still show IMAP accounts, see imap::start
+    if maildirs.is_empty() {
+        return;
+    }
+    let inotify = match inotify::init(CreateFlags::CLOEXEC) {
+        Ok(fd) => fd,
+        Err(e) => {
+            health::disabled("mail", format!("inotify: {}", e));
+            return;
+        }
+    };
+    let events = WatchFlags::CREATE
+        | WatchFlags::DELETE
+        | WatchFlags::MOVED_FROM
+        | WatchFlags::MOVED_TO
+        | WatchFlags::ONLYDIR;
+    // Label, new/ directory and its watch, None for accounts that can't be watched
+    let accounts: Vec<(String, PathBuf, Option<i32>)> = maildirs
+        .iter()
+        .map(|(label, path)| {
+            let new = config::expand_home(path).join("new");
+            let watch = inotify::add_watch(&inotify, &new, events)
+                .inspect_err(|e| eprintln!("[Mail] Watching {} failed: {}", new.display(), e))
+                .ok();
+            (label.clone(), new, watch)
+        })
+        .collect();
+    if accounts.iter().all(|(_, _, watch)| watch.is_none()) {
+        health::disabled("mail", "none of the maildirs has a readable new/");
+        return;
+    }
+
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Mail Thread] Started");
+            let mut dirty = vec![true; accounts.len()];
+            let mut buf = [MaybeUninit::uninit(); 4096];
+            let mut reader = inotify::Reader::new(&inotify, &mut buf);
+            loop {
+                if accounts.iter().any(|(_, _, watch)| watch.is_none()) {
+                    health::degraded("mail", "some maildirs have no readable new/");
+                } else {
+                    health::ok("mail");
+                }
+                let mut changed = false;
+                for ((label, new, _), dirty) in accounts.iter().zip(&mut dirty) {
+                    if *dirty {
+                        changed |= mail::store_unread(label, count(new));
+                        *dirty = false;
+                    }
+                }
+                if changed {
+                    ping_main_thread(&wake_fd);
+                }
+
+                // A sync delivers many messages at once, each directory is
+                // counted once for all the events already queued
+                loop {
+                    let watch = match reader.next() {
+                        Ok(event) => event.wd(),
+                        Err(e) => {
+                            health::degraded("mail", format!("inotify: {}", e));
+                            return;
+                        }
+                    };
+                    if let Some(index) = accounts.iter().position(|(_, _, w)| *w == Some(watch)) {
+                        dirty[index] = true;
+                    }
+                    if reader.is_buffer_empty() {
+                        break;
+                    }
+                }
+            }
+        });
+}
+
+/// Messages in a `new/` directory, dot files left out.
+fn count(new: &Path) -> u32 {
+    fs::read_dir(new).map_or(0, |entries| {
+        entries
+            .flatten()
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .count() as u32
+    })
+}
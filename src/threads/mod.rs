@@ -1,2 +1,33 @@
+pub mod bluetooth;
+pub mod containers;
+pub mod control;
+pub mod custom;
+pub mod dbus_service;
+pub mod dnd;
+#[cfg(target_os = "linux")]
+pub mod ethernet;
+pub mod fifo;
+pub mod gpu;
 pub mod hyprland;
-pub mod linux_poll;
+pub mod i3bar;
+pub mod ics;
+pub mod imap;
+pub mod lemonbar;
+#[cfg(target_os = "linux")]
+pub mod maildir;
+pub mod mpris;
+pub mod networkmanager;
+pub mod poll;
+pub mod power_profile;
+pub mod privacy;
+pub mod removable;
+pub mod rfkill;
+pub mod systemd;
+#[cfg(target_os = "linux")]
+pub mod todo;
+pub mod tray;
+pub mod updates;
+pub mod upower;
+pub mod volume;
+#[cfg(target_os = "linux")]
+pub mod wifi;
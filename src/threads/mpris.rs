@@ -0,0 +1,162 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{MPRIS_STATUS, MPRIS_TEXT, config, dbus, health, ping_main_thread};
+
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+/// Time each step of a long title stays on screen.
+const SCROLL_STEP: Duration = Duration::from_millis(400);
+/// Between the end of a scrolling title and its start coming around again.
+const SCROLL_SEPARATOR: &str = "   ";
+
+/// Artist and title of the active MPRIS player with its playback state. The
+/// players are read again whenever one changes a property or one comes or goes,
+/// while playing a title longer than `mpris_max_chars` scrolls.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[MPRIS Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::session, |connection| {
+                let Err(e) = watch(connection, &wake_fd);
+                health::degraded("mpris", format!("session bus connection lost: {}", e));
+                store(255, String::new(), &wake_fd);
+            });
+            health::disabled("mpris", format!("no session bus: {}", e));
+        });
+}
+
+fn watch(connection: &mut dbus::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    connection.add_match(&format!(
+        "type='signal',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='{}'",
+        PLAYER_PATH
+    ))?;
+    connection.add_match(
+        "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0namespace='org.mpris.MediaPlayer2'",
+    )?;
+
+    let max_chars = config::get().mpris_max_chars.max(1);
+    let mut scroll = 0;
+    let mut shown = (255, String::new());
+    loop {
+        let (status, text) = active_player(connection)?;
+        health::ok("mpris");
+        if (status, &text) != (shown.0, &shown.1) {
+            // A new track starts from its beginning
+            if text != shown.1 {
+                scroll = 0;
+            }
+            shown = (status, text);
+            store(status, window(&shown.1, scroll, max_chars), wake_fd);
+        }
+
+        // Scroll until something changes
+        let scrolling = status == 1 && shown.1.chars().count() > max_chars;
+        loop {
+            let timeout = scrolling.then_some(SCROLL_STEP);
+            match connection.next_signal(timeout)? {
+                Some(signal) if is_player_change(&signal) => break,
+                Some(_) => {}
+                None => {
+                    scroll += 1;
+                    store(status, window(&shown.1, scroll, max_chars), wake_fd);
+                }
+            }
+        }
+    }
+}
+
+fn is_player_change(signal: &dbus::Message) -> bool {
+    if signal.is_signal("org.freedesktop.DBus", "NameOwnerChanged") {
+        return signal
+            .body
+            .first()
+            .and_then(dbus::Value::as_str)
+            .is_some_and(|name| name.starts_with(PLAYER_PREFIX));
+    }
+    signal.is_signal("org.freedesktop.DBus.Properties", "PropertiesChanged")
+        && signal.path.as_deref() == Some(PLAYER_PATH)
+}
+
+/// Status (1: Playing, 2: Paused, 255: None) and "artist - title" of the player
+/// to show, a playing one before a paused one.
+fn active_player(connection: &mut dbus::Connection) -> io::Result<(u8, String)> {
+    let mut best = (255, String::new());
+    for name in connection.list_names()? {
+        if !name.starts_with(PLAYER_PREFIX) {
+            continue;
+        }
+        // A player may quit between listing and asking it
+        let Ok(properties) = connection.get_all(&name, PLAYER_PATH, PLAYER_INTERFACE) else {
+            continue;
+        };
+        let status = match properties
+            .get("PlaybackStatus")
+            .and_then(dbus::Value::as_str)
+        {
+            Some("Playing") => 1,
+            Some("Paused") => 2,
+            _ => continue,
+        };
+        if status < best.0 {
+            best = (status, track_text(&properties));
+        }
+        if status == 1 {
+            break;
+        }
+    }
+    Ok(best)
+}
+
+/// "artist - title" from the player's metadata, just the title without artists.
+fn track_text(properties: &dbus::Value) -> String {
+    let Some(metadata) = properties.get("Metadata") else {
+        return String::new();
+    };
+    let title = metadata
+        .get("xesam:title")
+        .and_then(dbus::Value::as_str)
+        .unwrap_or("");
+    let artists: Vec<&str> = metadata
+        .get("xesam:artist")
+        .and_then(dbus::Value::as_array)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(dbus::Value::as_str)
+        .filter(|artist| !artist.is_empty())
+        .collect();
+    let text = if artists.is_empty() {
+        title.to_string()
+    } else {
+        format!("{} - {}", artists.join(", "), title)
+    };
+    // Titles can carry newlines and tabs, the bar has one line
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `max_chars` characters of the text starting `scroll` steps in, wrapping
+/// around. Text that fits is returned whole.
+fn window(text: &str, scroll: usize, max_chars: usize) -> String {
+    let len = text.chars().count();
+    if len <= max_chars {
+        return text.to_string();
+    }
+    let looped: Vec<char> = text.chars().chain(SCROLL_SEPARATOR.chars()).collect();
+    (0..max_chars)
+        .map(|i| looped[(scroll + i) % looped.len()])
+        .collect()
+}
+
+fn store(status: u8, text: String, wake_fd: &OwnedFd) {
+    if let Ok(mut shown) = MPRIS_TEXT.lock() {
+        *shown = text;
+    }
+    MPRIS_STATUS.store(status, Ordering::Release);
+    ping_main_thread(wake_fd);
+}
@@ -0,0 +1,94 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use crate::{NM_CONNECTION, NM_CONNECTIVITY, dbus, health, ping_main_thread};
+
+const NM_SERVICE: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_ACTIVE_CONNECTION: &str = "org.freedesktop.NetworkManager.Connection.Active";
+
+/// NetworkManager's `NMConnectivityState`, stored as is in `NM_CONNECTIVITY`.
+pub const UNKNOWN: u8 = 0;
+pub const NONE: u8 = 1;
+pub const PORTAL: u8 = 2;
+pub const LIMITED: u8 = 3;
+pub const FULL: u8 = 4;
+
+/// Global connectivity and the name of the primary connection, read again
+/// whenever NetworkManager reports a property change or starts and stops. With
+/// NetworkManager nothing has to be polled, its own checks decide between
+/// full, limited and captive portal.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[NetworkManager Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::system, |connection| {
+                let Err(e) = watch(connection, &wake_fd);
+                health::degraded("connectivity", format!("system bus connection lost: {}", e));
+            });
+            health::disabled("connectivity", format!("no system bus: {}", e));
+        });
+}
+
+fn watch(connection: &mut dbus::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    connection.add_match(&format!(
+        "type='signal',sender='{}',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='{}'",
+        NM_SERVICE, NM_PATH
+    ))?;
+    connection.add_match(&format!(
+        "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'",
+        NM_SERVICE
+    ))?;
+
+    loop {
+        let (state, name) = match read(connection) {
+            Ok(read) => {
+                health::ok("connectivity");
+                read
+            }
+            // Not running (yet), it is picked up when it claims its name
+            Err(e) => {
+                health::disabled("connectivity", format!("no NetworkManager: {}", e));
+                (255, String::new())
+            }
+        };
+        let name_changed = NM_CONNECTION.lock().is_ok_and(|mut current| {
+            let changed = *current != name;
+            *current = name;
+            changed
+        });
+        if NM_CONNECTIVITY.swap(state, Ordering::AcqRel) != state || name_changed {
+            ping_main_thread(wake_fd);
+        }
+        connection.next_signal(None)?;
+    }
+}
+
+/// Connectivity state and the primary connection's name, empty without one.
+fn read(connection: &mut dbus::Connection) -> io::Result<(u8, String)> {
+    let properties = connection.get_all(NM_SERVICE, NM_PATH, NM_SERVICE)?;
+    let state = properties
+        .get("Connectivity")
+        .and_then(dbus::Value::as_i64)
+        .filter(|state| (0..=FULL as i64).contains(state))
+        .map_or(UNKNOWN, |state| state as u8);
+    let primary = properties
+        .get("PrimaryConnection")
+        .and_then(dbus::Value::as_str)
+        .filter(|path| *path != "/")
+        .map(str::to_string);
+    let name = match primary {
+        // It may have gone away since, the state alone is still worth showing
+        Some(path) => connection
+            .get_all(NM_SERVICE, &path, NM_ACTIVE_CONNECTION)
+            .ok()
+            .and_then(|active| active.get("Id")?.as_str().map(str::to_string))
+            .unwrap_or_default(),
+        None => String::new(),
+    };
+    Ok((state, name))
+}
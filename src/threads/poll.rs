@@ -0,0 +1,827 @@
+use std::fs;
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::{
+    AC_ONLINE, BACKLIGHT_PERCENT, BATTERY_BLINK, BATTERY_CYCLES, BATTERY_ESTIMATE_M,
+    BATTERY_HEALTH, BATTERY_PERCENT, BATTERY_RATE_DW, BATTERY_STATE, CPU_THROTTLED, DATE_DAY,
+    DATE_MONTH, DATE_YEAR, DISK_READ_KBS, DISK_WRITE_KBS, IDLE_DAEMON, IDLE_REMAINING_S,
+    IDLE_SINCE_S, KBD_BACKLIGHT_LEVEL, KBD_BACKLIGHT_LEVELS, NET_HISTORY, NET_HISTORY_LEN,
+    NET_SAMPLES, NIGHT_LIGHT, PRESSURE_FULL_MAX, PRESSURE_SOME, RAPL_POWER_DW, SINCE_ELAPSED_S,
+    SINCE_S, TEMPERATURE_C, TIME_HOURS, TIME_MINUTES, TIMEZONE, UTC_OFFSET_S, backlight,
+    config::{self, BatteryBackend, Module},
+    health, night_light, ping_main_thread, platform,
+    rate::Rate,
+    threads::upower,
+    timer, tz,
+};
+
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Polling Thread] Started");
+            // Probing sysfs happens here rather than before the first frame, the
+            // battery and power modules appear on the first wake after they are ready
+            let backend = config::get().battery_backend;
+            let mut battery = match backend {
+                BatteryBackend::UPower => None,
+                _ => find_battery(),
+            };
+            watch_power_supplies();
+            let mut rapl = RaplReader::new();
+            if rapl.is_none() {
+                health::disabled("power", "no readable intel-rapl energy counters");
+            }
+            let sensor_spec = &config::get().temperature_sensor;
+            let temperature = platform::TemperatureSensor::find(sensor_spec);
+            if temperature.is_none() && !sensor_spec.is_empty() {
+                eprintln!(
+                    "[Polling Thread] temperature sensor {} not found",
+                    sensor_spec
+                );
+            }
+            if temperature.is_none() {
+                health::disabled("temperature", "no matching temperature sensor");
+            }
+            let mut throttling = platform::Throttling::find();
+            let mut disk = DiskReader::new(&config::get().disk_device);
+            if disk.is_none() {
+                health::disabled("disk", "no matching device in /proc/diskstats");
+            }
+            let mut network = NetReader::new(&config::get().network_interface);
+            let pressure = Path::new(PRESSURE_DIR).exists();
+            if !pressure {
+                health::disabled("pressure", "no /proc/pressure, kernel without PSI");
+            }
+            let backlight = backlight::Device::find_backlight();
+            if backlight.is_none() {
+                health::disabled("backlight", "no device in /sys/class/backlight");
+            }
+            let keyboard_backlight = backlight::Device::find_keyboard();
+            if keyboard_backlight.is_none() {
+                health::disabled("keyboard_backlight", "no kbd_backlight in /sys/class/leds");
+            }
+            let idle_timeout = config::get().idle_timeout;
+            let mut idle_daemon = false;
+            SINCE_S.store(config::get().since, Ordering::Release);
+            let mut health_read = None;
+            let night_light_shown =
+                config::get().module_widths.get(&Module::NightLight) != Some(&0);
+            let mut night_light_schedule = None;
+            let mut tick_counter = 0;
+            loop {
+                // 1. Get current time
+                let mut changed = update_clock();
+
+                // 2. Read the AC adapter and battery as soon as the kernel reports
+                // a change, otherwise every 30 ticks or only every 120 to keep the
+                // estimate current while uevents arrive. Without a battery look
+                // for one instead
+                let battery_ticks = if UEVENTS.load(Ordering::Acquire) {
+                    120
+                } else {
+                    30
+                };
+                let supply_changed = POWER_SUPPLY_CHANGED.swap(false, Ordering::AcqRel);
+                if tick_counter % battery_ticks == 0 || supply_changed {
+                    if tick_counter % battery_ticks == 0 {
+                        tick_counter = 0;
+                    }
+                    if update_ac_state() {
+                        changed = true;
+                    }
+                    // A battery plugged in later or not ready at startup shows
+                    // up on the next probe, right away with uevents
+                    if battery.is_none()
+                        && backend != BatteryBackend::UPower
+                        && !upower::is_active()
+                    {
+                        battery = find_battery();
+                    }
+                    // UPower pushes its readings itself while it runs
+                    if let Some(battery) = battery.as_mut()
+                        && !upower::is_active()
+                        && update_battery_state(battery)
+                    {
+                        changed = true;
+                    }
+                    // Wear changes over months, reading it hourly is plenty
+                    if let Some(battery) = battery.as_ref()
+                        && health_read.is_none_or(|read: Instant| read.elapsed() >= HEALTH_INTERVAL)
+                    {
+                        health_read = Some(Instant::now());
+                        if update_battery_health(battery) {
+                            changed = true;
+                        }
+                    }
+                }
+
+                // 3. Sample RAPL energy counters every other tick
+                if tick_counter % 2 == 0
+                    && let Some(rapl) = rapl.as_mut()
+                    && rapl.update()
+                {
+                    changed = true;
+                }
+
+                // 4. Read the temperature sensor every tick, the throttle
+                // counters every other so a throttled stretch spans a sample
+                if let Some(sensor) = temperature.as_ref()
+                    && update_temperature(sensor)
+                {
+                    changed = true;
+                }
+                if tick_counter % 2 == 0
+                    && let Some(throttling) = throttling.as_mut()
+                {
+                    let throttled = throttling.check();
+                    if CPU_THROTTLED.swap(throttled, Ordering::AcqRel) != throttled {
+                        changed = true;
+                    }
+                }
+
+                // 5. Sample disk I/O every tick
+                if let Some(disk) = disk.as_mut()
+                    && disk.update()
+                {
+                    changed = true;
+                }
+
+                // 6. Sample network throughput every tick, follow default route changes
+                if tick_counter % 30 == 0 {
+                    network.follow_default_route();
+                }
+                if network.update() {
+                    changed = true;
+                }
+
+                // 7. Count down to the idle action, look for the daemon every 30 ticks
+                if idle_timeout > 0 {
+                    if tick_counter % 30 == 0 {
+                        idle_daemon = idle_daemon_running();
+                    }
+                    if update_idle(idle_daemon, idle_timeout) {
+                        changed = true;
+                    }
+                }
+
+                // 8. Read pressure stall averages every other tick, the kernel
+                // refreshes them every two seconds
+                if pressure && tick_counter % 2 == 0 && update_pressure() {
+                    changed = true;
+                }
+
+                // 9. Count up from the "time since" event, only wakes when the shown value moves
+                if update_since() {
+                    changed = true;
+                }
+                // A stopped timer keeps its value, only a running one wakes every second
+                if timer::update() {
+                    changed = true;
+                }
+
+                // 10. Read the backlights every tick, brightness keys change them behind our back
+                if let Some(backlight) = backlight.as_ref()
+                    && update_backlight(backlight)
+                {
+                    changed = true;
+                }
+                if let Some(keyboard) = keyboard_backlight.as_ref()
+                    && update_keyboard_backlight(keyboard)
+                {
+                    changed = true;
+                }
+
+                // 11. Blink a critically low battery, every tick flips it
+                if update_battery_blink() {
+                    changed = true;
+                }
+
+                // 12. Look for wlsunset or gammastep every 30 ticks and after the
+                // night light was clicked, follow its schedule every tick
+                if night_light_shown {
+                    if tick_counter % 30 == 0 || night_light::RECHECK.swap(false, Ordering::AcqRel)
+                    {
+                        night_light_schedule = night_light::find();
+                        if night_light_schedule.is_some() {
+                            health::ok("night_light");
+                        } else {
+                            health::disabled("night_light", "neither wlsunset nor gammastep runs");
+                        }
+                    }
+                    if update_night_light(night_light_schedule) {
+                        changed = true;
+                    }
+                }
+
+                // Only wake up the main thread if the minute, date, or battery actually changed
+                if changed {
+                    ping_main_thread(&wake_fd);
+                }
+
+                tick_counter += 1;
+                // Sleep until roughly the start of the next second to keep the clock accurate
+                thread::sleep(Duration::from_secs(1));
+            }
+        });
+}
+
+/// False while the clock shows UTC plus `clock_fallback_offset`.
+static CLOCK_CONFIRMED: AtomicBool = AtomicBool::new(true);
+
+/// Stores the current time and date, returns true if either changed. Also called
+/// on the main thread at startup so the very first frame shows the right time.
+pub fn update_clock() -> bool {
+    let (now, confirmed) = match OffsetDateTime::now_local() {
+        Ok(now) => {
+            health::ok("clock");
+            (now, true)
+        }
+        Err(_) => (fallback_time(), false),
+    };
+    let current_hour = now.hour();
+    let current_minute = now.minute();
+    let current_day = now.day();
+    let current_month = u8::from(now.month());
+    // Get the last two digits of the year (e.g., 2026 -> 26)
+    let current_year = (now.year() % 100) as u8;
+
+    let mut changed = false;
+    if TIME_MINUTES.load(Ordering::Acquire) != current_minute {
+        TIME_MINUTES.store(current_minute, Ordering::Release);
+        TIME_HOURS.store(current_hour, Ordering::Release);
+        changed = true;
+    }
+    if DATE_DAY.load(Ordering::Acquire) != current_day {
+        DATE_DAY.store(current_day, Ordering::Release);
+        DATE_MONTH.store(current_month, Ordering::Release);
+        DATE_YEAR.store(current_year, Ordering::Release);
+        changed = true;
+    }
+    // DST transitions change the offset and with it the abbreviation
+    let offset = now.offset().whole_seconds();
+    let offset_changed = UTC_OFFSET_S.swap(offset, Ordering::AcqRel) != offset;
+    if CLOCK_CONFIRMED.swap(confirmed, Ordering::AcqRel) != confirmed || offset_changed {
+        let mut label = if config::get().clock_timezone {
+            tz::abbreviation(offset)
+        } else {
+            String::new()
+        };
+        if !confirmed {
+            label.push('?');
+        }
+        if let Ok(mut guard) = TIMEZONE.lock()
+            && *guard != label
+        {
+            *guard = label;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// UTC plus `clock_fallback_offset`, for when `time` can't tell the local offset
+/// (it refuses to on some multi-threaded musl setups).
+fn fallback_time() -> OffsetDateTime {
+    static LOGGED: AtomicBool = AtomicBool::new(false);
+    let offset = config::get().clock_fallback_offset;
+    if !LOGGED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "[Polling Thread] Local UTC offset unavailable, showing UTC{}",
+            tz::numeric(offset)
+        );
+    }
+    health::degraded(
+        "clock",
+        "local UTC offset unavailable, showing the fallback offset",
+    );
+    let offset = UtcOffset::from_whole_seconds(offset).unwrap_or(UtcOffset::UTC);
+    OffsetDateTime::now_utc().to_offset(offset)
+}
+
+/// Whether hypridle or swayidle is running, without one nothing will happen
+/// when the countdown runs out.
+fn idle_daemon_running() -> bool {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let name = entry.file_name();
+        name.to_str()
+            .is_some_and(|n| n.bytes().all(|b| b.is_ascii_digit()))
+            && fs::read_to_string(entry.path().join("comm"))
+                .is_ok_and(|comm| matches!(comm.trim_end(), "hypridle" | "swayidle"))
+    })
+}
+
+/// Stores the seconds left until `timeout` of inactivity, returns true if the
+/// countdown or the daemon state changed.
+fn update_idle(daemon: bool, timeout: u32) -> bool {
+    let since = IDLE_SINCE_S.load(Ordering::Acquire);
+    let idle_for = if since == 0 {
+        0
+    } else {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        now.saturating_sub(since)
+    };
+    let remaining = timeout.saturating_sub(idle_for.min(u32::MAX as u64) as u32);
+    let daemon = daemon as u8;
+    let daemon_changed = IDLE_DAEMON.swap(daemon, Ordering::AcqRel) != daemon;
+    IDLE_REMAINING_S.swap(remaining, Ordering::AcqRel) != remaining || daemon_changed
+}
+
+/// Stores whether the display is warm right now, returns true if that changed.
+fn update_night_light(schedule: Option<night_light::Schedule>) -> bool {
+    let state = match schedule {
+        None => 0,
+        Some(schedule) => {
+            let minute = TIME_HOURS.load(Ordering::Acquire) as u16 * 60
+                + TIME_MINUTES.load(Ordering::Acquire) as u16;
+            let day_of_year = OffsetDateTime::now_utc().ordinal();
+            let offset = UTC_OFFSET_S.load(Ordering::Acquire);
+            if schedule.is_warm(minute, day_of_year, offset) {
+                2
+            } else {
+                1
+            }
+        }
+    };
+    NIGHT_LIGHT.swap(state, Ordering::AcqRel) != state
+}
+
+const PRESSURE_DIR: &str = "/proc/pressure";
+/// Between readings of the battery's wear.
+const HEALTH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Stores the "some" avg10 of every resource and the highest "full" avg10.
+/// Returns true if any changed.
+fn update_pressure() -> bool {
+    let mut changed = false;
+    let mut full_max = 0;
+    for (i, resource) in ["cpu", "memory", "io"].into_iter().enumerate() {
+        let path = Path::new(PRESSURE_DIR).join(resource);
+        let Ok(content) = fs::read_to_string(&path) else {
+            health::degraded("pressure", format!("read {} failed", path.display()));
+            return false;
+        };
+        let (mut some, mut full) = (0, 0);
+        // "some avg10=3.76 avg60=3.94 avg300=3.21 total=55662584"
+        for line in content.lines() {
+            let Some((kind, values)) = line.split_once(' ') else {
+                continue;
+            };
+            let avg10 = values
+                .split_whitespace()
+                .find_map(|v| v.strip_prefix("avg10="))
+                .and_then(parse_tenths)
+                .unwrap_or(0);
+            match kind {
+                "some" => some = avg10,
+                "full" => full = avg10,
+                _ => {}
+            }
+        }
+        full_max = full_max.max(full);
+        changed |= PRESSURE_SOME[i].swap(some, Ordering::AcqRel) != some;
+    }
+    health::ok("pressure");
+    changed |= PRESSURE_FULL_MAX.swap(full_max, Ordering::AcqRel) != full_max;
+    changed
+}
+
+/// "3.76" as 37.
+fn parse_tenths(value: &str) -> Option<u16> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, "0"));
+    let tenth = fraction
+        .chars()
+        .next()
+        .and_then(|c| c.to_digit(10))
+        .unwrap_or(0);
+    Some(whole.parse::<u16>().ok()?.saturating_mul(10) + tenth as u16)
+}
+
+/// Stores the time since `SINCE_S` rounded to what the module shows, returns
+/// true if that changed.
+pub fn update_since() -> bool {
+    let since = SINCE_S.load(Ordering::Acquire);
+    let elapsed = if since == 0 {
+        u32::MAX
+    } else {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let elapsed = now.saturating_sub(since).min(u32::MAX as u64 - 1) as u32;
+        // Seconds for the first minute, minutes for the first day, then hours
+        match elapsed {
+            0..60 => elapsed,
+            60..86400 => elapsed - elapsed % 60,
+            _ => elapsed - elapsed % 3600,
+        }
+    };
+    SINCE_ELAPSED_S.swap(elapsed, Ordering::AcqRel) != elapsed
+}
+
+fn update_backlight(device: &backlight::Device) -> bool {
+    let Some(percent) = device.percent() else {
+        health::degraded("backlight", "reading the brightness failed");
+        return false;
+    };
+    health::ok("backlight");
+    BACKLIGHT_PERCENT.swap(percent, Ordering::AcqRel) != percent
+}
+
+fn update_keyboard_backlight(device: &backlight::Device) -> bool {
+    let Some((level, levels)) = device.levels() else {
+        health::degraded("keyboard_backlight", "reading the brightness failed");
+        return false;
+    };
+    health::ok("keyboard_backlight");
+    let levels_changed = KBD_BACKLIGHT_LEVELS.swap(levels, Ordering::AcqRel) != levels;
+    KBD_BACKLIGHT_LEVEL.swap(level, Ordering::AcqRel) != level || levels_changed
+}
+
+/// Set by the uevent listener, the polling thread reads the battery on its next
+/// tick.
+static POWER_SUPPLY_CHANGED: AtomicBool = AtomicBool::new(false);
+/// True while power supply uevents arrive.
+static UEVENTS: AtomicBool = AtomicBool::new(false);
+
+/// Listens for power supply uevents on a thread of its own.
+fn watch_power_supplies() {
+    let Some(events) = platform::PowerSupplyEvents::open() else {
+        return;
+    };
+    UEVENTS.store(true, Ordering::Release);
+    let spawned = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Uevent Thread] Started");
+            while events.wait() {
+                POWER_SUPPLY_CHANGED.store(true, Ordering::Release);
+            }
+            UEVENTS.store(false, Ordering::Release);
+            eprintln!("[Uevent Thread] uevent socket failed, polling the battery");
+        });
+    if spawned.is_err() {
+        UEVENTS.store(false, Ordering::Release);
+    }
+}
+
+fn update_ac_state() -> bool {
+    let online = match platform::ac_online() {
+        Some(true) => 1,
+        Some(false) => 0,
+        None => 255,
+    };
+    AC_ONLINE.swap(online, Ordering::AcqRel) != online
+}
+
+fn update_battery_health(battery: &platform::Battery) -> bool {
+    let (percent, cycles) = match battery.health() {
+        Some(health) => (health.percent, health.cycles.unwrap_or(u16::MAX)),
+        None => (255, u16::MAX),
+    };
+    let cycles_changed = BATTERY_CYCLES.swap(cycles, Ordering::AcqRel) != cycles;
+    BATTERY_HEALTH.swap(percent, Ordering::AcqRel) != percent || cycles_changed
+}
+
+/// Probes for a battery or UPS, the module stays hidden until one is found.
+fn find_battery() -> Option<platform::Battery> {
+    let battery = platform::Battery::find();
+    if battery.is_some() {
+        BATTERY_STATE.store(0, Ordering::Release);
+    } else {
+        // UPower may already have found one the sysfs scan could not
+        if !upower::is_active() {
+            BATTERY_STATE.store(255, Ordering::Release);
+        }
+        health::disabled("battery", "no battery or UPS found");
+    }
+    battery
+}
+
+fn update_battery_state(battery: &mut platform::Battery) -> bool {
+    let reading = match battery.read() {
+        Ok(reading) => reading,
+        Err(e) => {
+            health::degraded("battery", e);
+            return false;
+        }
+    };
+    health::ok("battery");
+    store_battery(reading)
+}
+
+/// Stores a battery reading for the battery module, returns whether it changed.
+pub fn store_battery(reading: platform::BatteryReading) -> bool {
+    let percent_changed =
+        BATTERY_PERCENT.swap(reading.percent, Ordering::AcqRel) != reading.percent;
+    let state_changed = BATTERY_STATE.swap(reading.state, Ordering::AcqRel) != reading.state;
+    let estimate_changed =
+        BATTERY_ESTIMATE_M.swap(reading.estimate_m, Ordering::AcqRel) != reading.estimate_m;
+    let charge_changed = BATTERY_RATE_DW.swap(reading.rate_dw, Ordering::AcqRel) != reading.rate_dw;
+    check_battery_warnings(&reading);
+    percent_changed || state_changed || estimate_changed || charge_changed
+}
+
+/// Lowest warning level reached so far, 255 for none. Charging back above a
+/// level arms it again.
+static WARNED_LEVEL: AtomicU8 = AtomicU8::new(255);
+
+/// Runs `battery_warning_command` when a discharging battery drops to another
+/// warning level.
+fn check_battery_warnings(reading: &platform::BatteryReading) {
+    let config = config::get();
+    // Highest first, the lowest one reached counts
+    let level = config
+        .battery_warnings
+        .iter()
+        .rev()
+        .copied()
+        .find(|level| reading.percent <= *level)
+        .unwrap_or(255);
+    let previous = WARNED_LEVEL.swap(level, Ordering::AcqRel);
+    // 1: Discharging, see BATTERY_STATE
+    if level >= previous || reading.state != 1 || config.battery_warning_command.is_empty() {
+        return;
+    }
+    println!(
+        "[Battery] {}%, running battery_warning_command",
+        reading.percent
+    );
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(&config.battery_warning_command)
+        .env("LEANBAR_BATTERY", reading.percent.to_string())
+        .stdin(Stdio::null())
+        .spawn();
+    match child {
+        // Reaped on its own thread, notify-send may wait for the notification
+        Ok(mut child) => {
+            let _ = thread::Builder::new()
+                .stack_size(128 * 1024)
+                .spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("[Battery] battery_warning_command failed: {}", e),
+    }
+}
+
+/// Flips the blink phase while a discharging battery is at or below the last
+/// warning level, returns whether the battery needs drawing again.
+fn update_battery_blink() -> bool {
+    let config = config::get();
+    let critical = config.battery_blink
+        && BATTERY_STATE.load(Ordering::Acquire) == 1
+        && config
+            .battery_warnings
+            .last()
+            .is_some_and(|level| BATTERY_PERCENT.load(Ordering::Acquire) <= *level);
+    let dimmed = critical && !BATTERY_BLINK.load(Ordering::Acquire);
+    BATTERY_BLINK.swap(dimmed, Ordering::AcqRel) != dimmed
+}
+
+fn update_temperature(sensor: &platform::TemperatureSensor) -> bool {
+    let millidegrees = match sensor.millidegrees() {
+        Ok(millidegrees) => millidegrees,
+        Err(e) => {
+            health::degraded("temperature", e);
+            return false;
+        }
+    };
+    health::ok("temperature");
+    let degrees = (millidegrees / 1000).clamp(0, 254) as u8;
+    TEMPERATURE_C.swap(degrees, Ordering::AcqRel) != degrees
+}
+
+/// Package energy counters from the powercap interface. Power is derived from the
+/// energy delta between two samples, so the first sample only primes the reader.
+struct RaplReader {
+    zones: Vec<RaplZone>,
+    last_sample: Instant,
+}
+
+struct RaplZone {
+    energy_path: PathBuf,
+    max_energy_uj: u64,
+    last_energy_uj: u64,
+}
+
+impl RaplReader {
+    fn new() -> Option<Self> {
+        let mut zones = Vec::new();
+        for entry in fs::read_dir("/sys/class/powercap").ok()?.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // Top level zones only (intel-rapl:0), subzones (intel-rapl:0:0) are part of the package
+            let Some(zone_idx) = name.strip_prefix("intel-rapl:") else {
+                continue;
+            };
+            if zone_idx.contains(':') {
+                continue;
+            }
+
+            let path = entry.path();
+            let energy_path = path.join("energy_uj");
+            // The counter is root-only on kernels patched for PLATYPUS, skip it if unreadable
+            let Some(last_energy_uj) = read_u64(&energy_path) else {
+                continue;
+            };
+            zones.push(RaplZone {
+                energy_path,
+                max_energy_uj: read_u64(&path.join("max_energy_range_uj")).unwrap_or(u64::MAX),
+                last_energy_uj,
+            });
+        }
+
+        if zones.is_empty() {
+            return None;
+        }
+        Some(Self {
+            zones,
+            last_sample: Instant::now(),
+        })
+    }
+
+    fn update(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed_us = now.duration_since(self.last_sample).as_micros() as u64;
+        self.last_sample = now;
+
+        let mut delta_uj = 0;
+        for zone in self.zones.iter_mut() {
+            let Some(energy) = read_u64(&zone.energy_path) else {
+                health::degraded(
+                    "power",
+                    format!("read {} failed", zone.energy_path.display()),
+                );
+                continue;
+            };
+            health::ok("power");
+            // The counter wraps around at max_energy_range_uj
+            delta_uj += if energy >= zone.last_energy_uj {
+                energy - zone.last_energy_uj
+            } else {
+                zone.max_energy_uj - zone.last_energy_uj + energy
+            };
+            zone.last_energy_uj = energy;
+        }
+
+        if elapsed_us == 0 {
+            return false;
+        }
+
+        // uJ / us = W, scaled to deciwatts
+        let deciwatts = (delta_uj * 10 / elapsed_us).min(u16::MAX as u64 - 1) as u16;
+        if RAPL_POWER_DW.load(Ordering::Acquire) != deciwatts {
+            RAPL_POWER_DW.store(deciwatts, Ordering::Release);
+            return true;
+        }
+        false
+    }
+}
+
+/// Read and write throughput of one block device from `/proc/diskstats`.
+struct DiskReader {
+    device: String,
+    read: Rate,
+    write: Rate,
+}
+
+impl DiskReader {
+    fn new(device: &str) -> Option<Self> {
+        let stats = fs::read_to_string("/proc/diskstats").ok()?;
+        let found = stats
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(2))
+            .find(|name| {
+                if device.is_empty() {
+                    is_whole_disk(name)
+                } else {
+                    *name == device
+                }
+            });
+        if found.is_none() && !device.is_empty() {
+            eprintln!("[Polling Thread] disk {} not found", device);
+        }
+        let mut reader = Self {
+            device: found?.to_string(),
+            read: Rate::new(),
+            write: Rate::new(),
+        };
+        reader.update();
+        Some(reader)
+    }
+
+    fn update(&mut self) -> bool {
+        let Some((read_sectors, write_sectors)) = self.sectors() else {
+            health::degraded(
+                "disk",
+                format!("{} missing from /proc/diskstats", self.device),
+            );
+            return false;
+        };
+        health::ok("disk");
+        // diskstats counts 512 byte sectors regardless of the device's sector size
+        let (Some(read), Some(write)) = (
+            self.read.update(read_sectors * 512),
+            self.write.update(write_sectors * 512),
+        ) else {
+            return false;
+        };
+        let read_kbs = (read / 1024).min(u32::MAX as u64 - 1) as u32;
+        let write_kbs = (write / 1024).min(u32::MAX as u64 - 1) as u32;
+        let read_changed = DISK_READ_KBS.swap(read_kbs, Ordering::AcqRel) != read_kbs;
+        let write_changed = DISK_WRITE_KBS.swap(write_kbs, Ordering::AcqRel) != write_kbs;
+        read_changed || write_changed
+    }
+
+    /// Sectors read and written so far, the 6th and 10th fields of the device's line.
+    fn sectors(&self) -> Option<(u64, u64)> {
+        let stats = fs::read_to_string("/proc/diskstats").ok()?;
+        let fields: Vec<&str> = stats
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .find(|fields| fields.get(2) == Some(&self.device.as_str()))?;
+        Some((fields.get(5)?.parse().ok()?, fields.get(9)?.parse().ok()?))
+    }
+}
+
+/// Throughput of one network interface, appended to `NET_HISTORY` every tick.
+struct NetReader {
+    /// Set in the config, otherwise the default route's interface.
+    configured: bool,
+    interface: Option<String>,
+    rx: Rate,
+    tx: Rate,
+}
+
+impl NetReader {
+    fn new(interface: &str) -> Self {
+        let mut reader = Self {
+            configured: !interface.is_empty(),
+            interface: (!interface.is_empty()).then(|| interface.to_string()),
+            rx: Rate::new(),
+            tx: Rate::new(),
+        };
+        reader.follow_default_route();
+        reader
+    }
+
+    /// Switches to the interface of the default route when it changed, e.g. from
+    /// ethernet to wifi.
+    fn follow_default_route(&mut self) {
+        if self.configured {
+            return;
+        }
+        let interface = platform::default_route_interface();
+        if interface != self.interface {
+            self.interface = interface;
+            self.rx = Rate::new();
+            self.tx = Rate::new();
+        }
+    }
+
+    fn update(&mut self) -> bool {
+        let Some(interface) = self.interface.as_deref() else {
+            health::disabled("network", "no default route");
+            return false;
+        };
+        let Some((rx_bytes, tx_bytes)) = platform::interface_bytes(interface) else {
+            health::degraded("network", format!("read {} statistics failed", interface));
+            return false;
+        };
+        health::ok("network");
+        let (Some(rx), Some(tx)) = (self.rx.update(rx_bytes), self.tx.update(tx_bytes)) else {
+            return false;
+        };
+        let kbs = ((rx + tx) / 1024).min(u32::MAX as u64) as u32;
+        let Ok(mut history) = NET_HISTORY.lock() else {
+            return false;
+        };
+        if history.len() == NET_HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(kbs);
+        NET_SAMPLES.fetch_add(1, Ordering::AcqRel);
+        true
+    }
+}
+
+/// Physical disks, not partitions or virtual devices.
+fn is_whole_disk(name: &str) -> bool {
+    let virtual_prefixes = ["loop", "ram", "zram", "dm-", "md", "sr"];
+    Path::new("/sys/block").join(name).exists()
+        && !virtual_prefixes.iter().any(|p| name.starts_with(p))
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -0,0 +1,156 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use crate::{POWER_PROFILE, dbus, health, ping_main_thread};
+
+/// power-profiles-daemon 0.20 and later, and the name older versions use.
+const SERVICES: [(&str, &str); 2] = [
+    (
+        "org.freedesktop.UPower.PowerProfiles",
+        "/org/freedesktop/UPower/PowerProfiles",
+    ),
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+];
+/// In the order clicking steps through them, as stored in `POWER_PROFILE`.
+pub const PROFILES: [&str; 3] = ["power-saver", "balanced", "performance"];
+
+/// Clicks on the module, switched on this thread's connection.
+static CYCLES: dbus::Mailbox<()> = dbus::Mailbox::new();
+
+/// The daemon as found on the bus.
+struct Daemon {
+    service: &'static str,
+    path: &'static str,
+    /// Profiles the machine supports, performance needs a driver for it.
+    offered: Vec<String>,
+    active: String,
+}
+
+/// The active profile from power-profiles-daemon, read again whenever one of
+/// its properties changes and when the daemon starts or stops.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Power Profile Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::system, |connection| {
+                let Err(e) = watch(connection, &wake_fd);
+                health::degraded(
+                    "power_profile",
+                    format!("system bus connection lost: {}", e),
+                );
+            });
+            health::disabled("power_profile", format!("no system bus: {}", e));
+        });
+}
+
+fn watch(connection: &mut dbus::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    for (service, path) in SERVICES {
+        connection.add_match(&format!(
+            "type='signal',sender='{}',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='{}'",
+            service, path
+        ))?;
+        connection.add_match(&format!(
+            "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'",
+            service
+        ))?;
+    }
+
+    connection.wake_on(&CYCLES);
+    // Clicks from while the bus was away are stale by now
+    CYCLES.take();
+
+    loop {
+        let profile = match read(connection) {
+            Ok(daemon) => {
+                health::ok("power_profile");
+                PROFILES
+                    .iter()
+                    .position(|p| *p == daemon.active)
+                    .map_or(255, |i| i as u8)
+            }
+            // Not running (yet), it is picked up when it claims its name
+            Err(e) => {
+                health::disabled(
+                    "power_profile",
+                    format!("power-profiles-daemon unavailable: {}", e),
+                );
+                255
+            }
+        };
+        if POWER_PROFILE.swap(profile, Ordering::AcqRel) != profile {
+            ping_main_thread(wake_fd);
+        }
+        connection.next_signal(None)?;
+        for () in CYCLES.take() {
+            if let Err(e) = cycle_on(connection) {
+                eprintln!("[Power Profile] Switching the profile failed: {}", e);
+            }
+        }
+    }
+}
+
+/// The profiles the daemon offers and the active one, from whichever name it
+/// runs under.
+fn read(connection: &mut dbus::Connection) -> io::Result<Daemon> {
+    let mut last_error = None;
+    for (service, path) in SERVICES {
+        match connection.get_all(service, path, service) {
+            Ok(properties) => {
+                let active = properties
+                    .get("ActiveProfile")
+                    .and_then(dbus::Value::as_str)
+                    .unwrap_or("")
+                    .to_string();
+                // An array of dictionaries, each with the name under "Profile"
+                let offered = properties
+                    .get("Profiles")
+                    .and_then(dbus::Value::as_array)
+                    .unwrap_or(&[])
+                    .iter()
+                    .filter_map(|p| p.get("Profile")?.as_str().map(str::to_string))
+                    .collect();
+                return Ok(Daemon {
+                    service,
+                    path,
+                    offered,
+                    active,
+                });
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::other("no service")))
+}
+
+/// Switches to the next profile the daemon offers, called from the main thread
+/// on a click. The daemon's property change updates the module.
+pub fn cycle() {
+    CYCLES.post(());
+}
+
+fn cycle_on(connection: &mut dbus::Connection) -> io::Result<()> {
+    let daemon = read(connection)?;
+    let offered: Vec<&str> = PROFILES
+        .into_iter()
+        .filter(|p| daemon.offered.iter().any(|o| o == p))
+        .collect();
+    let next = offered
+        .iter()
+        .position(|p| *p == daemon.active)
+        .map_or(offered.first(), |i| offered.get((i + 1) % offered.len()));
+    let Some(next) = next else {
+        return Ok(());
+    };
+    let value = dbus::Value::Str(next.to_string());
+    connection.set(
+        daemon.service,
+        daemon.path,
+        daemon.service,
+        "ActiveProfile",
+        value,
+    )
+}
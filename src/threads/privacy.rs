@@ -0,0 +1,178 @@
+use std::fs;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::{PRIVACY, health, ping_main_thread};
+
+/// Bits of `PRIVACY`.
+pub const MICROPHONE: u8 = 1;
+pub const CAMERA: u8 = 2;
+pub const SCREEN: u8 = 4;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The screen is shared while either reports it.
+static HYPRLAND_SCREENCAST: AtomicBool = AtomicBool::new(false);
+static PIPEWIRE_SCREENCAST: AtomicBool = AtomicBool::new(false);
+
+/// Whether the microphone or the camera is in use. A capture stream of an ALSA
+/// card in the running state means something records, PipeWire and PulseAudio
+/// only keep them running while a client does. The camera is in use while any
+/// process holds a `/dev/video*` node open. Screen sharing is reported by
+/// Hyprland, see `set_screen_shared`, and found among PipeWire's nodes for
+/// portal screencasts.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Privacy Thread] Started");
+            let mut pw_cli = true;
+            loop {
+                if pw_cli {
+                    match pipewire_screencast() {
+                        Ok(casting) => {
+                            PIPEWIRE_SCREENCAST.store(casting, Ordering::Release);
+                            if store_screen() {
+                                ping_main_thread(&wake_fd);
+                            }
+                        }
+                        // Without PipeWire, Hyprland's events are all there is
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => pw_cli = false,
+                        Err(_) => {}
+                    }
+                }
+                let mut in_use = 0;
+                if microphone_recording() {
+                    in_use |= MICROPHONE;
+                }
+                if camera_open() {
+                    in_use |= CAMERA;
+                }
+                health::ok("privacy");
+                if store(MICROPHONE | CAMERA, in_use) {
+                    ping_main_thread(&wake_fd);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+}
+
+/// From Hyprland's `screencast` event, returns whether it changed.
+pub fn set_screen_shared(shared: bool) -> bool {
+    HYPRLAND_SCREENCAST.store(shared, Ordering::Release);
+    store_screen()
+}
+
+fn store_screen() -> bool {
+    let shared =
+        HYPRLAND_SCREENCAST.load(Ordering::Acquire) || PIPEWIRE_SCREENCAST.load(Ordering::Acquire);
+    store(SCREEN, if shared { SCREEN } else { 0 })
+}
+
+/// Replaces the bits in `mask`, the others belong to another thread.
+fn store(mask: u8, bits: u8) -> bool {
+    let previous = PRIVACY
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+            Some(current & !mask | bits)
+        })
+        .unwrap_or_default();
+    previous & mask != bits
+}
+
+/// `/proc/asound/cardN/pcmMc/subK/status` reads "state: RUNNING" while the
+/// capture substream records and "closed" otherwise.
+fn microphone_recording() -> bool {
+    let entries = |path: &Path| {
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path())
+    };
+    entries(Path::new("/proc/asound"))
+        .filter(|card| file_name_starts_with(card, "card"))
+        .flat_map(|card| entries(&card))
+        .filter(|pcm| file_name_starts_with(pcm, "pcm") && pcm.to_string_lossy().ends_with('c'))
+        .flat_map(|pcm| entries(&pcm))
+        .filter(|sub| file_name_starts_with(sub, "sub"))
+        .any(|sub| {
+            fs::read_to_string(sub.join("status"))
+                .is_ok_and(|status| status.contains("state: RUNNING"))
+        })
+}
+
+/// Any other process with a video4linux device open, only processes of the
+/// same user can be looked into, which is where cameras get opened.
+fn camera_open() -> bool {
+    let own = process::id().to_string();
+    let Ok(processes) = fs::read_dir("/proc") else {
+        return false;
+    };
+    processes.flatten().any(|entry| {
+        let name = entry.file_name();
+        let Some(pid) = name.to_str() else {
+            return false;
+        };
+        if pid == own || !pid.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+            return false;
+        };
+        fds.flatten().any(|fd| {
+            fs::read_link(fd.path())
+                .is_ok_and(|target| target.to_string_lossy().starts_with("/dev/video"))
+        })
+    })
+}
+
+/// Whether PipeWire has a video source that no device backs, which is what
+/// xdg-desktop-portal creates for the length of a screencast. From `pw-cli ls
+/// Node`:
+///
+/// ```text
+///     id 87, type PipeWire:Interface:Node/3
+///         node.name = "xdph-streaming-0"
+///         media.class = "Video/Source"
+/// ```
+fn pipewire_screencast() -> io::Result<bool> {
+    let output = Command::new("pw-cli")
+        .args(["ls", "Node"])
+        .stderr(Stdio::null())
+        .output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut casting = false;
+    let (mut video_source, mut device) = (false, false);
+    for line in text.lines() {
+        if line.trim_start().starts_with("id ") {
+            casting |= video_source && !device;
+            (video_source, device) = (false, false);
+            continue;
+        }
+        let Some((key, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        match key.trim() {
+            "media.class" => {
+                video_source = matches!(
+                    value.trim_matches('"'),
+                    "Video/Source" | "Stream/Output/Video"
+                )
+            }
+            "device.id" => device = true,
+            _ => {}
+        }
+    }
+    Ok(casting || (video_source && !device))
+}
+
+fn file_name_starts_with(path: &Path, prefix: &str) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with(prefix))
+}
@@ -0,0 +1,142 @@
+use std::fs::{self, File};
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use rustix::event::{PollFd, PollFlags, poll};
+
+use crate::{REMOVABLE_MOUNTS, dbus, health, ping_main_thread};
+
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+const UDISKS_SERVICE: &str = "org.freedesktop.UDisks2";
+const UDISKS_FILESYSTEM: &str = "org.freedesktop.UDisks2.Filesystem";
+
+/// Block devices of the mounted removable filesystems such as "sdb1", in the
+/// order they were mounted.
+static MOUNTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Filesystems mounted from removable drives, read again whenever the kernel
+/// flags /proc/self/mountinfo as changed, which it does on every mount and
+/// unmount.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Removable Thread] Started");
+            let watched = match File::open(MOUNTINFO) {
+                Ok(file) => file,
+                Err(e) => {
+                    health::disabled("removable", format!("{}: {}", MOUNTINFO, e));
+                    return;
+                }
+            };
+
+            loop {
+                match fs::read_to_string(MOUNTINFO) {
+                    Ok(mountinfo) => {
+                        health::ok("removable");
+                        let devices = removable_mounts(&mountinfo);
+                        let count = devices.len().min(254) as u8;
+                        if let Ok(mut mounted) = MOUNTED.lock() {
+                            *mounted = devices;
+                        }
+                        if REMOVABLE_MOUNTS.swap(count, Ordering::AcqRel) != count {
+                            ping_main_thread(&wake_fd);
+                        }
+                    }
+                    Err(e) => health::degraded("removable", format!("{}: {}", MOUNTINFO, e)),
+                }
+
+                let mut fds = [PollFd::new(&watched, PollFlags::PRI)];
+                if let Err(e) = poll(&mut fds, None) {
+                    health::degraded("removable", format!("polling {}: {}", MOUNTINFO, e));
+                    return;
+                }
+            }
+        });
+}
+
+/// Devices under /dev of the mounts whose drive is removable or sits on USB,
+/// each listed once even when mounted in several places.
+fn removable_mounts(mountinfo: &str) -> Vec<String> {
+    let mut devices: Vec<String> = Vec::new();
+    for line in mountinfo.lines() {
+        // The mount source follows the filesystem type after the separator
+        let Some((_, after)) = line.split_once(" - ") else {
+            continue;
+        };
+        let Some(name) = after
+            .split(' ')
+            .nth(1)
+            .and_then(|source| source.strip_prefix("/dev/"))
+        else {
+            continue;
+        };
+        if !devices.iter().any(|d| d == name) && is_removable(name) {
+            devices.push(name.to_string());
+        }
+    }
+    devices
+}
+
+/// Whether the block device, or the disk a partition belongs to, is flagged
+/// removable or attached over USB. Many USB drives don't claim to be removable.
+fn is_removable(name: &str) -> bool {
+    let Ok(path) = fs::canonicalize(Path::new("/sys/class/block").join(name)) else {
+        return false;
+    };
+    if path.to_string_lossy().contains("/usb") {
+        return true;
+    }
+    let flagged = |dir: &Path| {
+        fs::read_to_string(dir.join("removable")).is_ok_and(|value| value.trim() == "1")
+    };
+    flagged(&path) || path.parent().is_some_and(flagged)
+}
+
+/// Unmounts the most recently mounted removable filesystem through udisks,
+/// which lets the user who mounted it do so without a password. Runs on its own
+/// thread since flushing a slow drive takes a while, the mountinfo watch picks
+/// up the result.
+pub fn unmount_last() {
+    let Some(device) = MOUNTED.lock().ok().and_then(|m| m.last().cloned()) else {
+        return;
+    };
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            let path = format!(
+                "/org/freedesktop/UDisks2/block_devices/{}",
+                object_path_escape(&device)
+            );
+            let options = dbus::Value::Array("{sv}".into(), Vec::new());
+            let result = dbus::Connection::system().and_then(|mut connection| {
+                connection.call(
+                    UDISKS_SERVICE,
+                    &path,
+                    UDISKS_FILESYSTEM,
+                    "Unmount",
+                    &[options],
+                )
+            });
+            if let Err(e) = result {
+                eprintln!("[Removable] Unmounting {} failed: {}", device, e);
+            }
+        });
+}
+
+/// udisks names objects after the device with everything but ASCII letters and
+/// digits written as `_` and two hex digits.
+fn object_path_escape(name: &str) -> String {
+    name.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() {
+                (b as char).to_string()
+            } else {
+                format!("_{:02x}", b)
+            }
+        })
+        .collect()
+}
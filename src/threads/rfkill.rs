@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use crate::{RFKILL, health, ping_main_thread};
+
+const RFKILL_PATH: &str = "/dev/rfkill";
+/// Size of the original `struct rfkill_event`, newer kernels append fields that
+/// are left out when reading or writing fewer bytes.
+const EVENT_LEN: usize = 8;
+
+const RFKILL_TYPE_ALL: u8 = 0;
+const RFKILL_TYPE_WLAN: u8 = 1;
+const RFKILL_TYPE_BLUETOOTH: u8 = 2;
+const RFKILL_OP_DEL: u8 = 1;
+const RFKILL_OP_CHANGE_ALL: u8 = 3;
+
+/// Bits of `RFKILL`. A WiFi radio is blocked.
+pub const WIFI: u8 = 1;
+/// A Bluetooth radio is blocked.
+pub const BLUETOOTH: u8 = 2;
+/// A blocked radio is held by a hardware switch, clicking can't unblock it.
+pub const HARD: u8 = 4;
+/// Every radio is blocked.
+pub const AIRPLANE: u8 = 8;
+
+/// A radio's type and whether it is soft and hard blocked.
+type Radio = (u8, bool, bool);
+
+/// Block state of the radios, updated from /dev/rfkill. Opening it lists every
+/// radio as added, after that each read blocks until one changes, appears or
+/// goes away.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Rfkill Thread] Started");
+            let mut file = match File::open(RFKILL_PATH) {
+                Ok(file) => file,
+                Err(e) => {
+                    health::disabled("rfkill", format!("{}: {}", RFKILL_PATH, e));
+                    return;
+                }
+            };
+
+            let mut radios: HashMap<u32, Radio> = HashMap::new();
+            let mut event = [0u8; EVENT_LEN];
+            loop {
+                if let Err(e) = file.read_exact(&mut event) {
+                    health::degraded("rfkill", format!("{}: {}", RFKILL_PATH, e));
+                    return;
+                }
+                let index = u32::from_ne_bytes(event[0..4].try_into().unwrap());
+                let (kind, op, soft, hard) = (event[4], event[5], event[6] != 0, event[7] != 0);
+                if op == RFKILL_OP_DEL {
+                    radios.remove(&index);
+                } else {
+                    radios.insert(index, (kind, soft, hard));
+                }
+
+                let state = summarize(&radios);
+                if state == 255 {
+                    health::disabled("rfkill", "no radios");
+                } else {
+                    health::ok("rfkill");
+                }
+                if RFKILL.swap(state, Ordering::AcqRel) != state {
+                    ping_main_thread(&wake_fd);
+                }
+            }
+        });
+}
+
+/// `RFKILL` bits for the known radios, 255 without any.
+fn summarize(radios: &HashMap<u32, Radio>) -> u8 {
+    if radios.is_empty() {
+        return 255;
+    }
+    let mut state = AIRPLANE;
+    for &(kind, soft, hard) in radios.values() {
+        if !soft && !hard {
+            state &= !AIRPLANE;
+            continue;
+        }
+        state |= match kind {
+            RFKILL_TYPE_WLAN => WIFI,
+            RFKILL_TYPE_BLUETOOTH => BLUETOOTH,
+            _ => 0,
+        };
+        if hard {
+            state |= HARD;
+        }
+    }
+    state
+}
+
+/// Soft blocks every radio, or unblocks them all when they already are. The
+/// rfkill thread sees the change and redraws. Needs write access to
+/// /dev/rfkill, which logind grants the active session.
+pub fn toggle() {
+    let state = RFKILL.load(Ordering::Acquire);
+    if state == 255 {
+        return;
+    }
+    let block = state & AIRPLANE == 0;
+    let mut event = [0u8; EVENT_LEN];
+    event[4] = RFKILL_TYPE_ALL;
+    event[5] = RFKILL_OP_CHANGE_ALL;
+    event[6] = block as u8;
+    let result = OpenOptions::new()
+        .write(true)
+        .open(RFKILL_PATH)
+        .and_then(|mut file| file.write_all(&event));
+    if let Err(e) = result {
+        eprintln!("[Rfkill] Changing the soft block failed: {}", e);
+    }
+}
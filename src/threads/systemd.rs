@@ -0,0 +1,82 @@
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{FAILED_UNITS, config::Module, dbus, health, ping_main_thread, signals};
+
+const SYSTEMD_SERVICE: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const SYSTEMD_MANAGER: &str = "org.freedesktop.systemd1.Manager";
+/// systemd doesn't announce changes of the failed unit count, it is read this often.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Failed units of the system and the user's service manager added up, from
+/// their `NFailedUnits` property.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Systemd Thread] Started");
+            let mut system = None;
+            let mut session = None;
+            loop {
+                // Connections that failed are opened again on the next poll
+                let counts = [
+                    failed_units(&mut system, dbus::Connection::system),
+                    failed_units(&mut session, dbus::Connection::session),
+                ];
+                let failed = match counts {
+                    [Err(e), Err(_)] => {
+                        health::disabled("failed_units", format!("no systemd: {}", e));
+                        u16::MAX
+                    }
+                    _ => {
+                        health::ok("failed_units");
+                        counts
+                            .iter()
+                            .flatten()
+                            .fold(0u16, |sum, n| sum.saturating_add(*n))
+                            .min(u16::MAX - 1)
+                    }
+                };
+                if FAILED_UNITS.swap(failed, Ordering::AcqRel) != failed {
+                    ping_main_thread(&wake_fd);
+                }
+                signals::sleep(Module::FailedUnits, POLL_INTERVAL);
+            }
+        });
+}
+
+/// `NFailedUnits` of the manager on a bus, connecting first when needed.
+fn failed_units(
+    connection: &mut Option<dbus::Connection>,
+    connect: fn() -> io::Result<dbus::Connection>,
+) -> io::Result<u16> {
+    let bus = match connection {
+        Some(bus) => bus,
+        None => connection.insert(connect()?),
+    };
+    let args = [
+        dbus::Value::Str(SYSTEMD_MANAGER.to_string()),
+        dbus::Value::Str("NFailedUnits".to_string()),
+    ];
+    let reply = bus.call(
+        SYSTEMD_SERVICE,
+        SYSTEMD_PATH,
+        "org.freedesktop.DBus.Properties",
+        "Get",
+        &args,
+    );
+    match reply {
+        Ok(reply) => Ok(reply
+            .first()
+            .and_then(dbus::Value::as_i64)
+            .map_or(0, |n| n.clamp(0, u16::MAX as i64) as u16)),
+        Err(e) => {
+            *connection = None;
+            Err(e)
+        }
+    }
+}
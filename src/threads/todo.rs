@@ -0,0 +1,146 @@
+use std::fs;
+use std::mem::MaybeUninit;
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use rustix::fs::inotify::{self, CreateFlags, WatchFlags};
+
+use crate::{TODO_OPEN, config, health, ping_main_thread};
+
+/// Open items of the todo files, counted again whenever one of them is saved,
+/// created, renamed over or deleted. The directory is watched rather than the
+/// files since editors usually save by writing a new file and renaming it.
+pub fn start(wake_fd: OwnedFd) {
+    let (dir, pattern) = split_pattern(&config::get().todo_file);
+    let inotify = inotify::init(CreateFlags::CLOEXEC).and_then(|fd| {
+        let events = WatchFlags::CLOSE_WRITE
+            | WatchFlags::CREATE
+            | WatchFlags::DELETE
+            | WatchFlags::MOVED_FROM
+            | WatchFlags::MOVED_TO;
+        inotify::add_watch(&fd, &dir, events | WatchFlags::ONLYDIR)?;
+        Ok(fd)
+    });
+    let inotify = match inotify {
+        Ok(fd) => fd,
+        Err(e) => {
+            health::disabled("todo", format!("watching {}: {}", dir.display(), e));
+            return;
+        }
+    };
+
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Todo Thread] Started");
+            let mut buf = [MaybeUninit::uninit(); 4096];
+            let mut reader = inotify::Reader::new(&inotify, &mut buf);
+            loop {
+                let count = count_open(&dir, &pattern);
+                if count == u16::MAX {
+                    health::disabled("todo", format!("no {} in {}", pattern, dir.display()));
+                } else {
+                    health::ok("todo");
+                }
+                if TODO_OPEN.swap(count, Ordering::AcqRel) != count {
+                    ping_main_thread(&wake_fd);
+                }
+
+                // Everything already queued is taken in at once, one save
+                // produces several events
+                let mut relevant = false;
+                loop {
+                    match reader.next() {
+                        Ok(event) => {
+                            relevant |= event
+                                .file_name()
+                                .is_some_and(|name| matches(&pattern, &name.to_string_lossy()));
+                        }
+                        Err(e) => {
+                            health::degraded("todo", format!("inotify: {}", e));
+                            return;
+                        }
+                    }
+                    if relevant && reader.is_buffer_empty() {
+                        break;
+                    }
+                }
+            }
+        });
+}
+
+/// "~/notes/*.todo.txt" as the directory to watch and the file name pattern.
+fn split_pattern(spec: &str) -> (PathBuf, String) {
+    let path = config::expand_home(spec);
+    let pattern = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    (dir, pattern)
+}
+
+/// Lines of the matching files that are neither blank nor done, todo.txt marks
+/// finished items with a leading "x ". u16::MAX when no file matches.
+fn count_open(dir: &Path, pattern: &str) -> u16 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return u16::MAX;
+    };
+    let mut found = false;
+    let mut open = 0usize;
+    for entry in entries.flatten() {
+        if !matches(pattern, &entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let Ok(text) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        found = true;
+        open += text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("x "))
+            .count();
+    }
+    if found {
+        open.min(u16::MAX as usize - 1) as u16
+    } else {
+        u16::MAX
+    }
+}
+
+/// Shell style matching of a file name, `*` for any run of characters and `?`
+/// for a single one.
+fn matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // Where the last star was and the name position it currently covers up to
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,686 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::process;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{TRAY, TRAY_GENERATION, TrayItem, dbus, health, ping_main_thread};
+
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+/// Where items live that register with only a bus name.
+const DEFAULT_ITEM_PATH: &str = "/StatusNotifierItem";
+const INTROSPECTION: &str = r#"<node>
+ <interface name="org.kde.StatusNotifierWatcher">
+  <method name="RegisterStatusNotifierItem"><arg type="s" direction="in"/></method>
+  <method name="RegisterStatusNotifierHost"><arg type="s" direction="in"/></method>
+  <property name="RegisteredStatusNotifierItems" type="as" access="read"/>
+  <property name="IsStatusNotifierHostRegistered" type="b" access="read"/>
+  <property name="ProtocolVersion" type="i" access="read"/>
+  <signal name="StatusNotifierItemRegistered"><arg type="s"/></signal>
+  <signal name="StatusNotifierItemUnregistered"><arg type="s"/></signal>
+  <signal name="StatusNotifierHostRegistered"/>
+ </interface>
+</node>"#;
+/// Icons are scaled to this square, leaving a few pixels of the bar above and below.
+pub const ICON_SIZE: usize = 20;
+/// Longest reading a menu may take, someone is waiting for it to open.
+const MENU_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Clicks the main thread hands over, the calls are made on the tray thread's
+/// connection so a slow item can't hold up the bars.
+static REQUESTS: dbus::Mailbox<Request> = dbus::Mailbox::new();
+/// The menu last read for a right click, taken by `take_menu`.
+static READ_MENU: Mutex<Option<Menu>> = Mutex::new(None);
+
+enum Request {
+    /// Reads the menu of the item, or asks it to show its own without one.
+    Menu {
+        index: u8,
+        service: String,
+        path: String,
+        menu: String,
+    },
+    MenuClicked {
+        service: String,
+        path: String,
+        id: i32,
+    },
+    Activate {
+        service: String,
+        path: String,
+        method: &'static str,
+    },
+}
+
+/// A registered item as the tray thread tracks it.
+struct Item {
+    /// Unique name of the connection serving it, what its signals come from.
+    owner: String,
+    shown: TrayItem,
+}
+
+/// StatusNotifierItems from the session bus. leanbar becomes the
+/// StatusNotifierWatcher when no other program is, otherwise it registers as a
+/// host with the one running. Items are read again whenever they signal a
+/// change and dropped when their connection goes away.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(move || {
+            println!("[Tray Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::session, |connection| {
+                let Err(e) = watch(connection, &wake_fd);
+                health::degraded("tray", format!("session bus connection lost: {}", e));
+                publish(&[], &wake_fd);
+            });
+            health::disabled("tray", format!("no session bus: {}", e));
+        });
+}
+
+fn watch(connection: &mut dbus::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    connection.add_match(&format!("type='signal',interface='{}'", ITEM_INTERFACE))?;
+    connection.add_match(&format!("type='signal',interface='{}'", WATCHER))?;
+    connection.add_match(
+        "type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',member='NameOwnerChanged'",
+    )?;
+
+    connection.wake_on(&REQUESTS);
+    // Clicks from while the bus was away are stale by now
+    REQUESTS.take();
+
+    let mut items: Vec<Item> = Vec::new();
+    let mut watcher = connection.request_name(WATCHER)?;
+    if !watcher {
+        for id in register_host(connection)? {
+            add_item(connection, &mut items, &id, None);
+        }
+    }
+    health::ok("tray");
+    publish(&items, wake_fd);
+
+    loop {
+        for request in REQUESTS.take() {
+            handle(connection, request, wake_fd);
+        }
+        let Some(message) = connection.next_message(None)? else {
+            continue;
+        };
+        let changed = if message.kind == dbus::METHOD_CALL {
+            answer(connection, &mut items, &message)?
+        } else if message.is_signal("org.freedesktop.DBus", "NameOwnerChanged") {
+            let name = message.body.first().and_then(dbus::Value::as_str);
+            let new_owner = message.body.get(2).and_then(dbus::Value::as_str);
+            match (name, new_owner) {
+                // Gone, with every item it served
+                (Some(name), Some("")) => {
+                    let (gone, kept): (Vec<Item>, Vec<Item>) = items
+                        .drain(..)
+                        .partition(|item| item.owner == name || item.shown.service == name);
+                    items = kept;
+                    if watcher {
+                        for item in &gone {
+                            let id = format!("{}{}", item.shown.service, item.shown.path);
+                            connection.emit(
+                                WATCHER_PATH,
+                                WATCHER,
+                                "StatusNotifierItemUnregistered",
+                                &[dbus::Value::Str(id)],
+                            )?;
+                        }
+                    }
+                    let changed = !gone.is_empty();
+                    if name == WATCHER && !watcher && connection.request_name(WATCHER)? {
+                        println!("[Tray] The StatusNotifierWatcher quit, taking over");
+                        watcher = true;
+                    }
+                    changed
+                }
+                _ => false,
+            }
+        } else if message.is_signal(WATCHER, "StatusNotifierItemRegistered") && !watcher {
+            let id = message.body.first().and_then(dbus::Value::as_str);
+            id.is_some_and(|id| add_item(connection, &mut items, id, None))
+        } else if message.is_signal(WATCHER, "StatusNotifierItemUnregistered") && !watcher {
+            let id = message.body.first().and_then(dbus::Value::as_str);
+            let (service, path) = split_id(id.unwrap_or(""), None);
+            let before = items.len();
+            items.retain(|item| (&item.shown.service, &item.shown.path) != (&service, &path));
+            items.len() != before
+        } else if message.interface.as_deref() == Some(ITEM_INTERFACE) {
+            // NewIcon, NewStatus, NewTitle and the like
+            let item = items.iter_mut().find(|item| {
+                Some(&item.owner) == message.sender.as_ref()
+                    && Some(&item.shown.path) == message.path.as_ref()
+            });
+            match item {
+                Some(item) => refresh(connection, item),
+                None => false,
+            }
+        } else {
+            false
+        };
+        if changed {
+            publish(&items, wake_fd);
+        }
+    }
+}
+
+/// Registers with the running watcher, returns the items it already knows.
+fn register_host(connection: &mut dbus::Connection) -> io::Result<Vec<String>> {
+    let host = format!("org.kde.StatusNotifierHost-{}", process::id());
+    connection.request_name(&host)?;
+    connection.call(
+        WATCHER,
+        WATCHER_PATH,
+        WATCHER,
+        "RegisterStatusNotifierHost",
+        &[dbus::Value::Str(host)],
+    )?;
+    let properties = connection.get_all(WATCHER, WATCHER_PATH, WATCHER)?;
+    Ok(properties
+        .get("RegisteredStatusNotifierItems")
+        .and_then(dbus::Value::as_array)
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|id| id.as_str().map(str::to_string))
+        .collect())
+}
+
+/// Answers a call to the watcher, returns whether an item was added.
+fn answer(
+    connection: &mut dbus::Connection,
+    items: &mut Vec<Item>,
+    call: &dbus::Message,
+) -> io::Result<bool> {
+    let first = call
+        .body
+        .first()
+        .and_then(dbus::Value::as_str)
+        .unwrap_or("");
+    match (call.interface.as_deref(), call.member.as_deref()) {
+        (Some(WATCHER), Some("RegisterStatusNotifierItem")) => {
+            connection.reply(call, &[])?;
+            let added = add_item(connection, items, first, call.sender.as_deref());
+            if let Some(item) = items.last().filter(|_| added) {
+                let id = format!("{}{}", item.shown.service, item.shown.path);
+                connection.emit(
+                    WATCHER_PATH,
+                    WATCHER,
+                    "StatusNotifierItemRegistered",
+                    &[dbus::Value::Str(id)],
+                )?;
+            }
+            Ok(added)
+        }
+        (Some(WATCHER), Some("RegisterStatusNotifierHost")) => {
+            connection.reply(call, &[])?;
+            connection.emit(WATCHER_PATH, WATCHER, "StatusNotifierHostRegistered", &[])?;
+            Ok(false)
+        }
+        (Some("org.freedesktop.DBus.Properties"), Some("Get")) => {
+            let property = call.body.get(1).and_then(dbus::Value::as_str).unwrap_or("");
+            match watcher_property(items, property) {
+                Some(value) => connection.reply(call, &[dbus::Value::Variant(Box::new(value))])?,
+                None => connection.reply_error(
+                    call,
+                    "org.freedesktop.DBus.Error.UnknownProperty",
+                    property,
+                )?,
+            }
+            Ok(false)
+        }
+        (Some("org.freedesktop.DBus.Properties"), Some("GetAll")) => {
+            let entries = [
+                "RegisteredStatusNotifierItems",
+                "IsStatusNotifierHostRegistered",
+                "ProtocolVersion",
+            ]
+            .into_iter()
+            .filter_map(|name| {
+                let value = watcher_property(items, name)?;
+                Some(dbus::Value::DictEntry(
+                    Box::new(dbus::Value::Str(name.to_string())),
+                    Box::new(dbus::Value::Variant(Box::new(value))),
+                ))
+            })
+            .collect();
+            connection.reply(call, &[dbus::Value::Array("{sv}".into(), entries)])?;
+            Ok(false)
+        }
+        (Some("org.freedesktop.DBus.Introspectable"), Some("Introspect")) => {
+            connection.reply(call, &[dbus::Value::Str(INTROSPECTION.to_string())])?;
+            Ok(false)
+        }
+        (Some("org.freedesktop.DBus.Peer"), Some("Ping")) => {
+            connection.reply(call, &[])?;
+            Ok(false)
+        }
+        _ => {
+            connection.reply_error(
+                call,
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                call.member.as_deref().unwrap_or(""),
+            )?;
+            Ok(false)
+        }
+    }
+}
+
+fn watcher_property(items: &[Item], name: &str) -> Option<dbus::Value> {
+    match name {
+        "RegisteredStatusNotifierItems" => Some(dbus::Value::Array(
+            "s".into(),
+            items
+                .iter()
+                .map(|item| dbus::Value::Str(format!("{}{}", item.shown.service, item.shown.path)))
+                .collect(),
+        )),
+        // leanbar is the host
+        "IsStatusNotifierHostRegistered" => Some(dbus::Value::Bool(true)),
+        "ProtocolVersion" => Some(dbus::Value::Int32(0)),
+        _ => None,
+    }
+}
+
+/// Bus name and object path of an item registered as `id`: a bus name, a
+/// path served by `sender` (libappindicator does this), or both run together
+/// as `:1.42/org/ayatana/NotificationItem/app`.
+fn split_id(id: &str, sender: Option<&str>) -> (String, String) {
+    if id.starts_with('/') {
+        return (sender.unwrap_or("").to_string(), id.to_string());
+    }
+    match id.find('/') {
+        Some(slash) => (id[..slash].to_string(), id[slash..].to_string()),
+        None => (id.to_string(), DEFAULT_ITEM_PATH.to_string()),
+    }
+}
+
+/// Reads a newly registered item, returns whether it was added.
+fn add_item(
+    connection: &mut dbus::Connection,
+    items: &mut Vec<Item>,
+    id: &str,
+    sender: Option<&str>,
+) -> bool {
+    let (service, path) = split_id(id, sender);
+    if service.is_empty()
+        || items
+            .iter()
+            .any(|item| (&item.shown.service, &item.shown.path) == (&service, &path))
+    {
+        return false;
+    }
+    let owner = if service.starts_with(':') {
+        service.clone()
+    } else {
+        match connection.name_owner(&service) {
+            Ok(owner) => owner,
+            Err(_) => return false,
+        }
+    };
+    let mut item = Item {
+        owner,
+        shown: TrayItem {
+            service,
+            path,
+            ..TrayItem::default()
+        },
+    };
+    refresh(connection, &mut item);
+    items.push(item);
+    true
+}
+
+/// Reads the item's status and icon again, returns whether what is shown of it
+/// changed.
+fn refresh(connection: &mut dbus::Connection, item: &mut Item) -> bool {
+    let Ok(properties) = connection.get_all(&item.shown.service, &item.shown.path, ITEM_INTERFACE)
+    else {
+        return false;
+    };
+    let text = |key: &str| {
+        properties
+            .get(key)
+            .and_then(dbus::Value::as_str)
+            .unwrap_or("")
+    };
+    let status = text("Status");
+    let attention = status == "NeedsAttention";
+    let pixmaps = [
+        properties.get("AttentionIconPixmap").filter(|_| attention),
+        properties.get("IconPixmap"),
+    ];
+    let pixels = pixmaps
+        .into_iter()
+        .flatten()
+        .find_map(best_pixmap)
+        .map(|(width, height, argb)| scale_to_icon(width, height, &argb))
+        .unwrap_or_default();
+    // Without a pixmap (themed icons only) the first letter stands in
+    let name = [text("Title"), text("Id"), text("IconName")]
+        .into_iter()
+        .find(|name| !name.is_empty())
+        .unwrap_or("?");
+    let shown = TrayItem {
+        service: item.shown.service.clone(),
+        path: item.shown.path.clone(),
+        hidden: status == "Passive",
+        pixels,
+        label: name.chars().next().unwrap_or('?').to_ascii_uppercase(),
+        menu: text("Menu").to_string(),
+    };
+    let changed = shown != item.shown;
+    item.shown = shown;
+    changed
+}
+
+/// The smallest pixmap of an `a(iiay)` at least `ICON_SIZE` high, or else the
+/// largest, as width, height and big endian ARGB bytes.
+fn best_pixmap(value: &dbus::Value) -> Option<(usize, usize, Vec<u8>)> {
+    let pixmaps: Vec<(usize, usize, Vec<u8>)> = value
+        .as_array()?
+        .iter()
+        .filter_map(|pixmap| {
+            let dbus::Value::Struct(parts) = pixmap else {
+                return None;
+            };
+            let width = parts.first()?.as_i64()? as usize;
+            let height = parts.get(1)?.as_i64()? as usize;
+            let bytes: Vec<u8> = parts
+                .get(2)?
+                .as_array()?
+                .iter()
+                .filter_map(|b| b.as_i64().map(|b| b as u8))
+                .collect();
+            (width > 0 && height > 0 && bytes.len() >= width * height * 4)
+                .then_some((width, height, bytes))
+        })
+        .collect();
+    let large_enough = pixmaps
+        .iter()
+        .filter(|(_, height, _)| *height >= ICON_SIZE)
+        .min_by_key(|(_, height, _)| *height);
+    large_enough
+        .or_else(|| pixmaps.iter().max_by_key(|(_, height, _)| *height))
+        .cloned()
+}
+
+/// Averages the straight alpha pixmap down (or repeats it up) into an
+/// `ICON_SIZE` square of premultiplied ARGB, keeping its aspect ratio.
+fn scale_to_icon(width: usize, height: usize, argb: &[u8]) -> Vec<u32> {
+    let scale = width.max(height) as f32 / ICON_SIZE as f32;
+    let (scaled_w, scaled_h) = (
+        ((width as f32 / scale).round() as usize).clamp(1, ICON_SIZE),
+        ((height as f32 / scale).round() as usize).clamp(1, ICON_SIZE),
+    );
+    let (offset_x, offset_y) = ((ICON_SIZE - scaled_w) / 2, (ICON_SIZE - scaled_h) / 2);
+    let mut pixels = vec![0; ICON_SIZE * ICON_SIZE];
+    for y in 0..scaled_h {
+        let (y0, y1) = source_span(y, scaled_h, height);
+        for x in 0..scaled_w {
+            let (x0, x1) = source_span(x, scaled_w, width);
+            let mut sum = [0u32; 4];
+            for sy in y0..y1 {
+                for sx in x0..x1 {
+                    let i = (sy * width + sx) * 4;
+                    let alpha = argb[i] as u32;
+                    for (c, channel) in sum.iter_mut().enumerate() {
+                        let value = argb[i + c] as u32;
+                        *channel += if c == 0 { value } else { value * alpha / 255 };
+                    }
+                }
+            }
+            let count = ((y1 - y0) * (x1 - x0)) as u32;
+            let [a, r, g, b] = sum.map(|channel| channel / count);
+            pixels[(offset_y + y) * ICON_SIZE + offset_x + x] =
+                (a << 24) | (r << 16) | (g << 8) | b;
+        }
+    }
+    pixels
+}
+
+/// Source pixels covered by pixel `i` of `scaled`, at least one.
+fn source_span(i: usize, scaled: usize, source: usize) -> (usize, usize) {
+    let start = i * source / scaled;
+    let end = ((i + 1) * source / scaled).max(start + 1).min(source);
+    (start, end)
+}
+
+/// Hands the shown items to the renderer.
+fn publish(items: &[Item], wake_fd: &OwnedFd) {
+    let shown: Vec<TrayItem> = items
+        .iter()
+        .map(|item| item.shown.clone())
+        .filter(|item| !item.hidden)
+        .collect();
+    if let Ok(mut guard) = TRAY.lock()
+        && *guard != shown
+    {
+        *guard = shown;
+        TRAY_GENERATION.fetch_add(1, Ordering::AcqRel);
+        ping_main_thread(wake_fd);
+    }
+}
+
+/// One row of a tray item's menu, submenus are flattened below their parent.
+pub struct MenuEntry {
+    pub id: i32,
+    pub label: String,
+    /// Levels of submenus it is in.
+    pub depth: u8,
+    pub separator: bool,
+    pub enabled: bool,
+    /// Checked state of checkmark and radio entries.
+    pub toggled: Option<bool>,
+}
+
+/// A tray item's menu as last read, with where to send clicks.
+pub struct Menu {
+    /// Of the item among the shown ones.
+    pub index: u8,
+    pub service: String,
+    pub path: String,
+    pub entries: Vec<MenuEntry>,
+}
+
+/// Asks for the menu of the shown item at `index`, called from the main thread
+/// on a right click. Once read it is left for `take_menu`, items without one
+/// are asked to show their own.
+pub fn request_menu(index: u8) {
+    let Some((service, path, menu)) = TRAY.lock().ok().and_then(|items| {
+        let item = items.get(index as usize)?;
+        Some((item.service.clone(), item.path.clone(), item.menu.clone()))
+    }) else {
+        return;
+    };
+    REQUESTS.post(Request::Menu {
+        index,
+        service,
+        path,
+        menu,
+    });
+}
+
+/// The menu read since the last call.
+pub fn take_menu() -> Option<Menu> {
+    READ_MENU.lock().ok()?.take()
+}
+
+/// Makes the calls a request stands for, failures only matter to the log.
+fn handle(connection: &mut dbus::Connection, request: Request, wake_fd: &OwnedFd) {
+    match request {
+        Request::Menu {
+            index,
+            service,
+            path,
+            menu,
+        } if !menu.is_empty() => match read_menu(connection, &service, &menu) {
+            Ok(entries) => {
+                if let Ok(mut read) = READ_MENU.lock() {
+                    *read = Some(Menu {
+                        index,
+                        service,
+                        path: menu,
+                        entries,
+                    });
+                }
+                ping_main_thread(wake_fd);
+            }
+            Err(e) => {
+                eprintln!("[Tray] Reading the menu of {} failed: {}", service, e);
+                activate_on(connection, &service, &path, "ContextMenu");
+            }
+        },
+        Request::Menu { service, path, .. } => {
+            activate_on(connection, &service, &path, "ContextMenu");
+        }
+        Request::MenuClicked { service, path, id } => {
+            let args = [
+                dbus::Value::Int32(id),
+                dbus::Value::Str("clicked".to_string()),
+                dbus::Value::Variant(Box::new(dbus::Value::Int32(0))),
+                dbus::Value::Uint32(0),
+            ];
+            if let Err(e) = connection.send(&service, &path, MENU_INTERFACE, "Event", &args) {
+                eprintln!("[Tray] Clicking a menu entry of {} failed: {}", service, e);
+            }
+        }
+        Request::Activate {
+            service,
+            path,
+            method,
+        } => activate_on(connection, &service, &path, method),
+    }
+}
+
+/// The entries of the dbusmenu at `path`.
+fn read_menu(
+    connection: &mut dbus::Connection,
+    service: &str,
+    path: &str,
+) -> io::Result<Vec<MenuEntry>> {
+    // Lets the item fill in submenus it builds lazily, whether it did doesn't
+    // matter since the layout is read next
+    let _ = connection.call_within(
+        MENU_TIMEOUT,
+        service,
+        path,
+        MENU_INTERFACE,
+        "AboutToShow",
+        &[dbus::Value::Int32(0)],
+    );
+    let args = [
+        dbus::Value::Int32(0),
+        dbus::Value::Int32(-1),
+        dbus::Value::Array("s".into(), Vec::new()),
+    ];
+    let reply = connection.call_within(
+        MENU_TIMEOUT,
+        service,
+        path,
+        MENU_INTERFACE,
+        "GetLayout",
+        &args,
+    )?;
+    // (revision, (id, properties, children))
+    let mut entries = Vec::new();
+    if let Some(root) = reply.get(1) {
+        flatten_menu(root, 0, &mut entries);
+    }
+    Ok(entries)
+}
+
+/// Appends the visible children of a `(ia{sv}av)` layout node, with their own
+/// children one level deeper.
+fn flatten_menu(node: &dbus::Value, depth: u8, entries: &mut Vec<MenuEntry>) {
+    let Some(children) = node.as_struct().and_then(|n| n.get(2)?.as_array()) else {
+        return;
+    };
+    for child in children {
+        let Some(fields) = child.as_struct() else {
+            continue;
+        };
+        let (Some(id), Some(properties)) =
+            (fields.first().and_then(dbus::Value::as_i64), fields.get(1))
+        else {
+            continue;
+        };
+        let flag = |key: &str| properties.get(key).and_then(dbus::Value::as_bool);
+        if flag("visible") == Some(false) {
+            continue;
+        }
+        let text = |key: &str| properties.get(key).and_then(dbus::Value::as_str);
+        let toggled = match text("toggle-type") {
+            Some("checkmark" | "radio") => {
+                Some(properties.get("toggle-state").and_then(dbus::Value::as_i64) == Some(1))
+            }
+            _ => None,
+        };
+        entries.push(MenuEntry {
+            id: id as i32,
+            label: strip_mnemonic(text("label").unwrap_or("")),
+            depth,
+            separator: text("type") == Some("separator"),
+            enabled: flag("enabled") != Some(false),
+            toggled,
+        });
+        if text("children-display") == Some("submenu") {
+            flatten_menu(child, depth.saturating_add(1), entries);
+        }
+    }
+}
+
+/// Drops the underscores marking access keys, a doubled one is a literal
+/// underscore.
+fn strip_mnemonic(label: &str) -> String {
+    let mut stripped = String::with_capacity(label.len());
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        if c != '_' {
+            stripped.push(c);
+        } else if let Some(next) = chars.next() {
+            stripped.push(next);
+        }
+    }
+    stripped
+}
+
+/// Tells the menu an entry was clicked, the item acts on it itself.
+pub fn menu_clicked(menu: &Menu, id: i32) {
+    REQUESTS.post(Request::MenuClicked {
+        service: menu.service.clone(),
+        path: menu.path.clone(),
+        id,
+    });
+}
+
+/// Calls `method` ("Activate" or "SecondaryActivate") on the shown item at
+/// `index`. Called from the main thread.
+pub fn activate(index: u8, method: &'static str) {
+    let Some((service, path)) = TRAY.lock().ok().and_then(|items| {
+        let item = items.get(index as usize)?;
+        Some((item.service.clone(), item.path.clone()))
+    }) else {
+        return;
+    };
+    REQUESTS.post(Request::Activate {
+        service,
+        path,
+        method,
+    });
+}
+
+/// The reply isn't waited for since the item may take its time.
+fn activate_on(connection: &mut dbus::Connection, service: &str, path: &str, method: &str) {
+    // Where to open a window or menu, leanbar doesn't know its position
+    let position = [dbus::Value::Int32(0), dbus::Value::Int32(0)];
+    if let Err(e) = connection.send(service, path, ITEM_INTERFACE, method, &position) {
+        eprintln!("[Tray] {} on {} failed: {}", method, service, e);
+    }
+}
@@ -0,0 +1,70 @@
+use std::os::fd::OwnedFd;
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    UPDATES_PENDING,
+    config::{self, Module},
+    health, ping_main_thread, signals,
+};
+
+/// Pending package updates, counted from the lines `updates_command` prints
+/// every `updates_interval` minutes or on its `updates_signal`.
+pub fn start(wake_fd: OwnedFd) {
+    let command = &config::get().updates_command;
+    if command.is_empty() {
+        health::disabled("updates", "updates_command not set");
+        return;
+    }
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Updates Thread] Started");
+            let interval = Duration::from_secs(config::get().updates_interval as u64 * 60);
+            loop {
+                match check(command) {
+                    Ok(pending) => {
+                        health::ok("updates");
+                        if UPDATES_PENDING.swap(pending, Ordering::AcqRel) != pending {
+                            ping_main_thread(&wake_fd);
+                        }
+                    }
+                    // The last count stays, a mirror being down changes nothing
+                    Err(e) => health::degraded("updates", e),
+                }
+                signals::sleep(Module::Updates, interval);
+            }
+        });
+}
+
+fn check(command: &str) -> Result<u16, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("updates_command: {}", e))?;
+    // checkupdates exits with 2 when nothing is pending, dnf check-update
+    // with 100 when something is
+    match output.status.code() {
+        Some(0 | 2 | 100) => {}
+        _ => return Err(format!("updates_command exited with {}", output.status)),
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(count_packages(&stdout).min(u16::MAX as usize - 1) as u16)
+}
+
+/// One package per line, leaving out the headers apt ("Listing...") and dnf
+/// ("Last metadata expiration check: ...") print before the list.
+fn count_packages(output: &str) -> usize {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            !line.is_empty() && !line.ends_with("...") && !line.starts_with("Last metadata")
+        })
+        .count()
+}
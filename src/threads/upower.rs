@@ -0,0 +1,136 @@
+use std::convert::Infallible;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::{
+    BATTERY_STATE,
+    config::{self, BatteryBackend},
+    dbus, health, ping_main_thread,
+    platform::BatteryReading,
+    threads::poll,
+};
+
+const UPOWER: &str = "org.freedesktop.UPower";
+/// UPower's combination of all batteries, what desktop shells show.
+const DISPLAY_DEVICE: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+/// Set while UPower provides the battery readings, the polling thread leaves
+/// sysfs alone meanwhile.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Acquire)
+}
+
+/// Battery readings from UPower's display device, read again whenever one of
+/// its properties changes and when UPower starts or stops. With the `auto`
+/// backend sysfs takes over while UPower isn't running.
+pub fn start(wake_fd: OwnedFd) {
+    let backend = config::get().battery_backend;
+    if backend == BatteryBackend::Sysfs {
+        return;
+    }
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[UPower Thread] Started");
+            let Err(e) = dbus::reconnecting(dbus::Connection::system, |connection| {
+                let Err(e) = watch(connection, backend, &wake_fd);
+                ACTIVE.store(false, Ordering::Release);
+                if backend == BatteryBackend::UPower {
+                    health::degraded("battery", format!("system bus connection lost: {}", e));
+                }
+            });
+            if backend == BatteryBackend::UPower {
+                health::disabled("battery", format!("no system bus: {}", e));
+            }
+        });
+}
+
+fn watch(
+    connection: &mut dbus::Connection,
+    backend: BatteryBackend,
+    wake_fd: &OwnedFd,
+) -> io::Result<Infallible> {
+    connection.add_match(&format!(
+        "type='signal',sender='{}',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='{}'",
+        UPOWER, DISPLAY_DEVICE
+    ))?;
+    connection.add_match(&format!(
+        "type='signal',interface='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'",
+        UPOWER
+    ))?;
+
+    loop {
+        match connection.get_all(UPOWER, DISPLAY_DEVICE, DEVICE_INTERFACE) {
+            Ok(properties) => store(&properties, backend, wake_fd),
+            // Not running (yet), it is picked up when it claims its name
+            Err(e) => {
+                ACTIVE.store(false, Ordering::Release);
+                if backend == BatteryBackend::UPower {
+                    health::disabled("battery", format!("UPower unavailable: {}", e));
+                }
+            }
+        }
+        // Both matches mean the display device has to be read again
+        connection.next_signal(None)?;
+    }
+}
+
+fn store(properties: &dbus::Value, backend: BatteryBackend, wake_fd: &OwnedFd) {
+    let present = properties
+        .get("IsPresent")
+        .and_then(dbus::Value::as_bool)
+        .unwrap_or(false);
+    if !present {
+        ACTIVE.store(false, Ordering::Release);
+        if backend == BatteryBackend::UPower {
+            BATTERY_STATE.store(255, Ordering::Release);
+            ping_main_thread(wake_fd);
+            health::disabled("battery", "UPower reports no battery");
+        }
+        return;
+    }
+
+    let number = |key: &str| properties.get(key).and_then(dbus::Value::as_i64);
+    let percent = properties
+        .get("Percentage")
+        .and_then(dbus::Value::as_f64)
+        .map_or(0, |p| p.round().clamp(0.0, 100.0) as u8);
+    // Charging, Discharging, Empty, FullyCharged, PendingCharge, PendingDischarge
+    let state = match number("State") {
+        Some(1) => 2,
+        Some(2 | 3) => 1,
+        Some(4) => 3,
+        _ => 0,
+    };
+    let seconds = match state {
+        1 => number("TimeToEmpty"),
+        2 => number("TimeToFull"),
+        _ => None,
+    };
+    let estimate_m = seconds.map_or(0, |s| (s.max(0) / 60).min(u16::MAX as i64) as u16);
+    // Watts flowing in or out, signed by some drivers
+    let rate_dw = match state {
+        1 | 2 => properties
+            .get("EnergyRate")
+            .and_then(dbus::Value::as_f64)
+            .map_or(0, |w| (w.abs() * 10.0).min(u16::MAX as f64) as u16),
+        _ => 0,
+    };
+
+    ACTIVE.store(true, Ordering::Release);
+    health::ok("battery");
+    let reading = BatteryReading {
+        percent,
+        state,
+        estimate_m,
+        rate_dw,
+    };
+    if poll::store_battery(reading) {
+        ping_main_thread(wake_fd);
+    }
+}
@@ -0,0 +1,423 @@
+use std::convert::Infallible;
+use std::io::{self, BufRead, BufReader};
+use std::os::fd::OwnedFd;
+use std::process::Child;
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    AUDIO_DEVICE, MIC_MUTED, VOLUME_MUTED, VOLUME_PERCENT,
+    config::{self, AudioBackend, Module},
+    health, ping_main_thread, pulse,
+};
+
+/// Used when `pactl subscribe` is unavailable and changes can't be waited for.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Changes come in bursts, a volume slider dragged reports dozens a second and
+/// every read starts `wpctl` a few times. The state is read once this long
+/// after the first of them.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+/// Between attempts to reconnect to a PulseAudio server that went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Longest sink name shown without a configured label.
+const DEVICE_NAME_MAX_CHARS: usize = 10;
+
+/// What the volume thread is woken for.
+struct Wakeups {
+    /// `pactl subscribe` reported a change.
+    changed: bool,
+    /// The audio device module was clicked.
+    cycle_sink: bool,
+}
+
+static WAKEUPS: Mutex<Wakeups> = Mutex::new(Wakeups {
+    changed: false,
+    cycle_sink: false,
+});
+static WOKEN: Condvar = Condvar::new();
+
+/// Volume, mute state and name of the default sink, and whether the default
+/// source (the microphone) is muted. With PipeWire this is a `wpctl` backend,
+/// not a PipeWire client: `wpctl` reads the state and `pactl subscribe`
+/// (pipewire-pulse) says when to read it again so changes show up instantly,
+/// without it the state is polled. Without PipeWire the PulseAudio server is
+/// asked directly over its native protocol.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[Volume Thread] Started");
+            let backend = config::get().audio_backend;
+            if backend != AudioBackend::Pulse && update(&wake_fd) {
+                let polling = match Command::new("pactl")
+                    .arg("subscribe")
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::null())
+                    .spawn()
+                {
+                    Ok(child) => {
+                        let _ = thread::Builder::new()
+                            .stack_size(64 * 1024)
+                            .spawn(move || follow_pactl_events(child));
+                        false
+                    }
+                    Err(_) => {
+                        println!("[Volume Thread] No pactl, polling wpctl");
+                        true
+                    }
+                };
+                serve(false, polling, &wake_fd);
+                return;
+            }
+            if backend == AudioBackend::Wpctl {
+                health::disabled("volume", "wpctl get-volume failed, is PipeWire running?");
+                return;
+            }
+            match pulse::Connection::connect() {
+                Ok(connection) => {
+                    let Ok(events_wake_fd) = wake_fd.try_clone() else {
+                        return;
+                    };
+                    let _ = thread::Builder::new()
+                        .stack_size(128 * 1024)
+                        .spawn(move || follow_pulse(connection, &events_wake_fd));
+                    serve(true, false, &wake_fd);
+                }
+                Err(e) => health::disabled(
+                    "volume",
+                    format!("no PipeWire and no PulseAudio server: {}", e),
+                ),
+            }
+        });
+}
+
+/// Reads the state through `wpctl` again when `pactl subscribe` reports a
+/// change (or every `POLL_INTERVAL` when `polling`) and switches sinks when the
+/// module is clicked. With `pulse` the PulseAudio thread reads the state and
+/// only switching is left.
+fn serve(pulse: bool, polling: bool, wake_fd: &OwnedFd) {
+    loop {
+        let Ok(wakeups) = WAKEUPS.lock() else {
+            return;
+        };
+        let asleep = |w: &mut Wakeups| !w.changed && !w.cycle_sink;
+        let wakeups = if polling {
+            WOKEN
+                .wait_timeout_while(wakeups, POLL_INTERVAL, asleep)
+                .ok()
+                .map(|(wakeups, _)| wakeups)
+        } else {
+            WOKEN.wait_while(wakeups, asleep).ok()
+        };
+        let Some(mut wakeups) = wakeups else {
+            return;
+        };
+        if wakeups.changed && !polling {
+            drop(wakeups);
+            thread::sleep(DEBOUNCE);
+            wakeups = match WAKEUPS.lock() {
+                Ok(wakeups) => wakeups,
+                Err(_) => return,
+            };
+        }
+        let changed = std::mem::take(&mut wakeups.changed) || polling;
+        let cycle_sink = std::mem::take(&mut wakeups.cycle_sink);
+        drop(wakeups);
+
+        if cycle_sink {
+            let result = if pulse {
+                cycle_pulse_sink()
+            } else {
+                cycle_wpctl_sink()
+            };
+            if let Err(e) = result {
+                eprintln!("[Volume] Switching the default sink failed: {}", e);
+            }
+        }
+        if changed && !pulse {
+            update(wake_fd);
+        }
+    }
+}
+
+fn wake(set: impl FnOnce(&mut Wakeups)) {
+    if let Ok(mut wakeups) = WAKEUPS.lock() {
+        set(&mut wakeups);
+        WOKEN.notify_one();
+    }
+}
+
+/// Reads the default sink over the PulseAudio protocol whenever the server
+/// reports a sink or server change, reconnecting if the server goes away.
+fn follow_pulse(mut connection: pulse::Connection, wake_fd: &OwnedFd) {
+    println!("[Volume Thread] Using the PulseAudio protocol");
+    loop {
+        let Err(e) = watch_pulse(&mut connection, wake_fd);
+        health::degraded("volume", format!("PulseAudio connection lost: {}", e));
+        connection = loop {
+            thread::sleep(RECONNECT_DELAY);
+            if let Ok(connection) = pulse::Connection::connect() {
+                break connection;
+            }
+        };
+    }
+}
+
+fn watch_pulse(connection: &mut pulse::Connection, wake_fd: &OwnedFd) -> io::Result<Infallible> {
+    connection.subscribe(
+        pulse::SUBSCRIPTION_SINK | pulse::SUBSCRIPTION_SOURCE | pulse::SUBSCRIPTION_SERVER,
+    )?;
+    loop {
+        let sink = connection.sink("@DEFAULT_SINK@")?;
+        health::ok("volume");
+        store(sink.percent, sink.muted, wake_fd);
+        store_device(&sink.description, wake_fd);
+        // Failing here only means there is no source, e.g. no microphone plugged in
+        let source = connection.source("@DEFAULT_SOURCE@").ok();
+        store_mic(source.map(|s| s.muted), wake_fd);
+        // The defaults may have changed too, so any sink, source or server event
+        // means reading them again
+        while !matches!(
+            connection.next_event()?,
+            pulse::FACILITY_SINK | pulse::FACILITY_SOURCE | pulse::FACILITY_SERVER
+        ) {}
+    }
+}
+
+/// Has the volume thread read the volumes again on every sink, source or
+/// server (defaults) change `pactl subscribe` reports, until it exits.
+fn follow_pactl_events(mut child: Child) {
+    let Some(stdout) = child.stdout.take() else {
+        return;
+    };
+    // "Event 'change' on sink #56"
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        if line.contains(" on sink #")
+            || line.contains(" on source #")
+            || line.contains(" on server")
+        {
+            wake(|w| w.changed = true);
+        }
+    }
+    let _ = child.wait();
+    health::degraded("volume", "pactl subscribe exited");
+}
+
+/// Reads the default sink and source through `wpctl`, returns false if the sink
+/// could not be read.
+fn update(wake_fd: &OwnedFd) -> bool {
+    let Ok(text) = wpctl_volume("@DEFAULT_AUDIO_SINK@") else {
+        return false;
+    };
+    let Some(volume) = parse_wpctl_volume(&text) else {
+        health::degraded(
+            "volume",
+            format!("unexpected wpctl output: {}", text.trim()),
+        );
+        return false;
+    };
+    health::ok("volume");
+    store(
+        (volume * 100.0).round() as u32,
+        text.contains("[MUTED]"),
+        wake_fd,
+    );
+
+    let source = wpctl_volume("@DEFAULT_AUDIO_SOURCE@").ok();
+    let mic_muted = source
+        .filter(|text| parse_wpctl_volume(text).is_some())
+        .map(|text| text.contains("[MUTED]"));
+    store_mic(mic_muted, wake_fd);
+
+    // Listing the sinks is another process per change, skip it when hidden
+    if config::get().module_widths.get(&Module::AudioDevice) != Some(&0) {
+        let sinks = wpctl_sinks().unwrap_or_default();
+        let default = sinks.iter().find(|s| s.default);
+        store_device(default.map_or("", |s| s.name.as_str()), wake_fd);
+    }
+    true
+}
+
+struct WpctlSink {
+    id: u32,
+    default: bool,
+    name: String,
+}
+
+/// The audio sinks from `wpctl status`:
+///
+/// ```text
+/// Audio
+///  ├─ Sinks:
+///  │  *   47. Built-in Audio Analog Stereo        [vol: 0.40]
+///  │      58. WH-1000XM4                          [vol: 0.50]
+///  │
+///  ├─ Sink endpoints:
+/// ```
+fn wpctl_sinks() -> io::Result<Vec<WpctlSink>> {
+    let output = Command::new("wpctl")
+        .arg("status")
+        .stderr(Stdio::null())
+        .output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut sinks = Vec::new();
+    let (mut in_audio, mut in_sinks) = (false, false);
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Section headers ("Audio", "Video") start at the first column
+        if !line.starts_with([' ', '│', '├', '└']) {
+            in_audio = line.trim() == "Audio";
+            in_sinks = false;
+            continue;
+        }
+        let entry = line.trim_start_matches(|c: char| c.is_whitespace() || "│├└─".contains(c));
+        if line.contains("─ ") {
+            in_sinks = in_audio && entry == "Sinks:";
+            continue;
+        }
+        if !in_sinks {
+            continue;
+        }
+        let (default, entry) = match entry.strip_prefix('*') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, entry),
+        };
+        let Some((id, rest)) = entry.split_once(". ") else {
+            continue;
+        };
+        let Ok(id) = id.parse() else {
+            continue;
+        };
+        let name = rest.split(" [vol:").next().unwrap_or(rest).trim();
+        sinks.push(WpctlSink {
+            id,
+            default,
+            name: name.to_string(),
+        });
+    }
+    Ok(sinks)
+}
+
+/// Makes the sink after the current default the default, wrapping around.
+/// Called from the main thread when the audio device module is clicked, the
+/// volume thread switches and the change comes back through the usual events.
+pub fn cycle_sink() {
+    wake(|w| w.cycle_sink = true);
+}
+
+fn cycle_wpctl_sink() -> io::Result<()> {
+    let sinks = wpctl_sinks()?;
+    let Some(next) = next_after(&sinks, |s| s.default) else {
+        return Ok(());
+    };
+    let status = Command::new("wpctl")
+        .args(["set-default", &next.id.to_string()])
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("wpctl set-default {}", next.id)));
+    }
+    Ok(())
+}
+
+/// A connection of its own, the PulseAudio thread's is blocked waiting for
+/// events.
+fn cycle_pulse_sink() -> io::Result<()> {
+    let mut connection = pulse::Connection::connect()?;
+    let current = connection.sink("@DEFAULT_SINK@")?.name;
+    let sinks = connection.sinks()?;
+    match next_after(&sinks, |s| s.name == current) {
+        Some(next) => connection.set_default_sink(&next.name),
+        None => Ok(()),
+    }
+}
+
+/// The item after the current one, wrapping around, or the first without a
+/// current one. None if there is nothing else to switch to.
+fn next_after<T>(items: &[T], is_current: impl Fn(&T) -> bool) -> Option<&T> {
+    let next = match items.iter().position(is_current) {
+        Some(_) if items.len() < 2 => return None,
+        Some(current) => (current + 1) % items.len(),
+        None => 0,
+    };
+    items.get(next)
+}
+
+/// Configured label for the sink, otherwise a short name made from its
+/// description, e.g. "HDMI" for "HDMI / DisplayPort 1 Output".
+fn device_label(description: &str) -> String {
+    let config = config::get();
+    let lowercase = description.to_lowercase();
+    if let Some((label, _)) = config
+        .audio_device_names
+        .iter()
+        .find(|(_, pattern)| lowercase.contains(&pattern.to_lowercase()))
+    {
+        return label.clone();
+    }
+    if lowercase.contains("hdmi") {
+        "HDMI".to_string()
+    } else if lowercase.contains("displayport") {
+        "DP".to_string()
+    } else if lowercase.contains("headphone") {
+        "Phones".to_string()
+    } else if lowercase.starts_with("built-in") || lowercase.contains("analog") {
+        "Speakers".to_string()
+    } else {
+        description
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(DEVICE_NAME_MAX_CHARS)
+            .collect()
+    }
+}
+
+fn wpctl_volume(target: &str) -> io::Result<String> {
+    let output = Command::new("wpctl")
+        .args(["get-volume", target])
+        .stderr(Stdio::null())
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// "Volume: 0.45" or "Volume: 0.45 [MUTED]"
+fn parse_wpctl_volume(text: &str) -> Option<f32> {
+    text.trim()
+        .strip_prefix("Volume: ")
+        .and_then(|v| v.split_whitespace().next())
+        .and_then(|v| v.parse().ok())
+}
+
+fn store(percent: u32, muted: bool, wake_fd: &OwnedFd) {
+    let percent = percent.min(254) as u8;
+    let changed = VOLUME_PERCENT.swap(percent, Ordering::AcqRel) != percent;
+    if VOLUME_MUTED.swap(muted, Ordering::AcqRel) != muted || changed {
+        ping_main_thread(wake_fd);
+    }
+}
+
+fn store_device(description: &str, wake_fd: &OwnedFd) {
+    let label = device_label(description);
+    if let Ok(mut device) = AUDIO_DEVICE.lock()
+        && *device != label
+    {
+        *device = label;
+        drop(device);
+        ping_main_thread(wake_fd);
+    }
+}
+
+/// None when there is no default source.
+fn store_mic(muted: Option<bool>, wake_fd: &OwnedFd) {
+    let state = muted.map_or(255, u8::from);
+    if MIC_MUTED.swap(state, Ordering::AcqRel) != state {
+        ping_main_thread(wake_fd);
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+
+use crate::netlink::{self, Family, NLM_F_DUMP, Socket};
+use crate::{WIFI_SIGNAL, WIFI_SSID, config, health, ping_main_thread};
+
+/// Signal strength has no event, it is re-read this often while connected.
+const SIGNAL_REFRESH: Duration = Duration::from_secs(30);
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+const NL80211_CMD_GET_INTERFACE: u8 = 5;
+const NL80211_CMD_GET_STATION: u8 = 17;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_IFNAME: u16 = 4;
+const NL80211_ATTR_STA_INFO: u16 = 21;
+const NL80211_ATTR_SSID: u16 = 52;
+const NL80211_STA_INFO_SIGNAL: u16 = 7;
+
+/// Connected SSID and signal strength from nl80211. Connects, disconnects and
+/// roams arrive as events on the "mlme" multicast group, without nl80211 the
+/// signal is polled from `/proc/net/wireless` and the SSID stays unknown.
+pub fn start(wake_fd: OwnedFd) {
+    let _ = thread::Builder::new()
+        .stack_size(128 * 1024)
+        .spawn(move || {
+            println!("[WiFi Thread] Started");
+            match Nl80211::connect() {
+                Ok(nl) => run_nl80211(nl, &wake_fd),
+                Err(e) => {
+                    eprintln!(
+                        "[WiFi Thread] nl80211 unavailable ({}), polling /proc/net/wireless",
+                        e
+                    );
+                    run_proc_wireless(&wake_fd);
+                }
+            }
+        });
+}
+
+struct Nl80211 {
+    requests: Socket,
+    /// Subscribed to "mlme", None when the group can't be joined with a bind mask.
+    events: Option<Socket>,
+    family: u16,
+}
+
+impl Nl80211 {
+    fn connect() -> io::Result<Self> {
+        let mut requests = Socket::generic(0)?;
+        let family = Family::resolve(&mut requests, "nl80211")?;
+        let events = family
+            .group("mlme")
+            .filter(|id| (1..=32).contains(id))
+            .and_then(|id| Socket::generic(1 << (id - 1)).ok());
+        Ok(Self {
+            requests,
+            events,
+            family: family.id,
+        })
+    }
+
+    /// Wireless interfaces as (ifindex, name, SSID when connected).
+    fn interfaces(&mut self) -> io::Result<Vec<(u32, String, Option<String>)>> {
+        let replies = self.requests.request(
+            self.family,
+            NLM_F_DUMP,
+            &netlink::genl_payload(NL80211_CMD_GET_INTERFACE, &[]),
+        )?;
+        let mut interfaces = Vec::new();
+        for reply in &replies {
+            let Some((_, attrs)) = netlink::genl_parts(&reply.payload) else {
+                continue;
+            };
+            let (mut index, mut name, mut ssid) = (None, String::new(), None);
+            for (kind, value) in netlink::attributes(attrs) {
+                match kind {
+                    NL80211_ATTR_IFINDEX => index = netlink::attr_u32(value),
+                    NL80211_ATTR_IFNAME => name = netlink::attr_string(value),
+                    NL80211_ATTR_SSID => ssid = Some(String::from_utf8_lossy(value).into_owned()),
+                    _ => {}
+                }
+            }
+            if let Some(index) = index {
+                interfaces.push((index, name, ssid));
+            }
+        }
+        Ok(interfaces)
+    }
+
+    /// Signal of the access point the interface is associated with, in dBm.
+    fn signal_dbm(&mut self, ifindex: u32) -> io::Result<Option<i8>> {
+        let mut attrs = Vec::new();
+        netlink::push_attribute(&mut attrs, NL80211_ATTR_IFINDEX, &ifindex.to_ne_bytes());
+        let replies = self.requests.request(
+            self.family,
+            NLM_F_DUMP,
+            &netlink::genl_payload(NL80211_CMD_GET_STATION, &attrs),
+        )?;
+        Ok(replies.iter().find_map(|reply| {
+            let (_, attrs) = netlink::genl_parts(&reply.payload)?;
+            let (_, info) =
+                netlink::attributes(attrs).find(|(k, _)| *k == NL80211_ATTR_STA_INFO)?;
+            let (_, signal) =
+                netlink::attributes(info).find(|(k, _)| *k == NL80211_STA_INFO_SIGNAL)?;
+            signal.first().map(|s| *s as i8)
+        }))
+    }
+
+    /// Reads the connection of the configured (or first connected) interface.
+    /// Returns None when there is no wireless interface at all.
+    fn read(&mut self) -> io::Result<Option<(String, u8)>> {
+        let wanted = &config::get().wifi_interface;
+        let interfaces = self.interfaces()?;
+        if interfaces.is_empty() {
+            return Ok(None);
+        }
+        let connected = interfaces
+            .iter()
+            .filter(|(_, name, _)| wanted.is_empty() || name == wanted)
+            .find_map(|(index, _, ssid)| Some((*index, ssid.clone()?)));
+        let Some((index, ssid)) = connected else {
+            return Ok(Some((String::new(), 255)));
+        };
+        let signal = self.signal_dbm(index)?.map_or(255, dbm_to_percent);
+        Ok(Some((ssid, signal)))
+    }
+}
+
+fn run_nl80211(mut nl: Nl80211, wake_fd: &OwnedFd) {
+    loop {
+        match nl.read() {
+            Ok(Some((ssid, signal))) => {
+                health::ok("wifi");
+                if store(&ssid, signal) {
+                    ping_main_thread(wake_fd);
+                }
+            }
+            Ok(None) => {
+                health::disabled("wifi", "no wireless interface");
+                return;
+            }
+            Err(e) => health::degraded("wifi", format!("nl80211: {}", e)),
+        }
+
+        // Wait for the next link event, or until the signal is due for a refresh
+        let Some(events) = nl.events.as_ref() else {
+            thread::sleep(SIGNAL_REFRESH);
+            continue;
+        };
+        let timeout = Timespec {
+            tv_sec: SIGNAL_REFRESH.as_secs() as i64,
+            tv_nsec: 0,
+        };
+        loop {
+            let mut fds = [PollFd::new(events, PollFlags::IN)];
+            if !poll(&mut fds, Some(&timeout)).is_ok_and(|n| n > 0) {
+                break;
+            }
+            // Which event it was doesn't matter, the whole state is re-read
+            match events.recv_events() {
+                Ok(messages) if messages.iter().any(|m| m.kind == nl.family) => break,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+fn run_proc_wireless(wake_fd: &OwnedFd) {
+    let wanted = &config::get().wifi_interface;
+    loop {
+        let Ok(table) = fs::read_to_string("/proc/net/wireless") else {
+            health::disabled("wifi", "no nl80211 and no /proc/net/wireless");
+            return;
+        };
+        // "wlan0: 0000   58.  -52.  -256 ...", the level column is in dBm
+        let signal = table.lines().skip(2).find_map(|line| {
+            let (name, stats) = line.split_once(':')?;
+            if !wanted.is_empty() && name.trim() != wanted {
+                return None;
+            }
+            let level: f32 = stats
+                .split_whitespace()
+                .nth(2)?
+                .trim_end_matches('.')
+                .parse()
+                .ok()?;
+            Some(dbm_to_percent(level as i8))
+        });
+        health::ok("wifi");
+        if store("", signal.unwrap_or(255)) {
+            ping_main_thread(wake_fd);
+        }
+        thread::sleep(FALLBACK_POLL_INTERVAL);
+    }
+}
+
+/// Linear from -100 dBm (0%) to -50 dBm (100%), the scale NetworkManager uses.
+fn dbm_to_percent(dbm: i8) -> u8 {
+    ((dbm as i32 + 100) * 2).clamp(0, 100) as u8
+}
+
+fn store(ssid: &str, signal: u8) -> bool {
+    let mut changed = WIFI_SIGNAL.swap(signal, Ordering::AcqRel) != signal;
+    if let Ok(mut guard) = WIFI_SSID.lock()
+        && *guard != ssid
+    {
+        guard.clear();
+        guard.push_str(ssid);
+        changed = true;
+    }
+    changed
+}
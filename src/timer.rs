@@ -0,0 +1,135 @@
+//! Stopwatch and countdown for the timer module, set from the control socket
+//! with `leanbar-ctl timer`. The polling thread stores the shown seconds every
+//! tick, they only change while the timer runs so a stopped one never wakes the
+//! main thread.
+
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::{TIMER_S, TIMER_STATE};
+
+/// `TIMER_STATE` values.
+pub const STOPPED: u8 = 0;
+pub const RUNNING: u8 = 1;
+/// A countdown reached zero, it stays shown until reset.
+pub const FINISHED: u8 = 2;
+
+struct Timer {
+    /// Counting down from this, None for a stopwatch.
+    countdown: Option<Duration>,
+    /// Time run before the last start.
+    elapsed: Duration,
+    /// Set while running.
+    started: Option<Instant>,
+}
+
+impl Timer {
+    fn elapsed(&self) -> Duration {
+        self.elapsed + self.started.map_or(Duration::ZERO, |s| s.elapsed())
+    }
+}
+
+static TIMER: Mutex<Option<Timer>> = Mutex::new(None);
+
+/// Starts a countdown of `duration`, or without one resumes the stopped timer
+/// or starts a stopwatch.
+pub fn start(duration: Option<Duration>) {
+    let Ok(mut timer) = TIMER.lock() else {
+        return;
+    };
+    match (duration, timer.as_mut()) {
+        (None, Some(timer)) => {
+            timer.started.get_or_insert_with(Instant::now);
+        }
+        (countdown, _) => {
+            *timer = Some(Timer {
+                countdown,
+                elapsed: Duration::ZERO,
+                started: Some(Instant::now()),
+            })
+        }
+    }
+}
+
+/// Stops the timer, it keeps showing where it stopped.
+pub fn stop() {
+    if let Ok(mut timer) = TIMER.lock()
+        && let Some(timer) = timer.as_mut()
+        && let Some(started) = timer.started.take()
+    {
+        timer.elapsed += started.elapsed();
+    }
+}
+
+/// Sets up a countdown of `duration` without starting it.
+pub fn set(duration: Duration) {
+    if let Ok(mut timer) = TIMER.lock() {
+        *timer = Some(Timer {
+            countdown: Some(duration),
+            elapsed: Duration::ZERO,
+            started: None,
+        });
+    }
+}
+
+/// Hides the timer.
+pub fn reset() {
+    if let Ok(mut timer) = TIMER.lock() {
+        *timer = None;
+    }
+}
+
+/// Stores the seconds the timer shows and its state, returns true if either
+/// changed.
+pub fn update() -> bool {
+    let (seconds, state) = match TIMER.lock().as_deref() {
+        Ok(Some(timer)) => {
+            let elapsed = timer.elapsed();
+            let state = if timer.started.is_some() {
+                RUNNING
+            } else {
+                STOPPED
+            };
+            let seconds = match timer.countdown {
+                Some(length) if elapsed >= length => return store(0, FINISHED),
+                // Rounded up, a countdown shows 0 only once it is over
+                Some(length) => (length - elapsed).as_secs_f64().ceil() as u64,
+                None => elapsed.as_secs(),
+            };
+            // u32::MAX is kept for no timer
+            (seconds.min(u32::MAX as u64 - 1) as u32, state)
+        }
+        _ => (u32::MAX, STOPPED),
+    };
+    store(seconds, state)
+}
+
+fn store(seconds: u32, state: u8) -> bool {
+    let seconds_changed = TIMER_S.swap(seconds, Ordering::AcqRel) != seconds;
+    TIMER_STATE.swap(state, Ordering::AcqRel) != state || seconds_changed
+}
+
+/// "10m", "1h30m", "90s" or a bare number of minutes.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    if let Ok(minutes) = value.parse::<u64>() {
+        return Some(Duration::from_secs(minutes * 60));
+    }
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: u64 = number.parse().ok()?;
+        number.clear();
+        seconds += n * match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+    }
+    (number.is_empty() && seconds > 0).then(|| Duration::from_secs(seconds))
+}
@@ -0,0 +1,218 @@
+//! Context menus of tray items. Right clicking an item with a
+//! com.canonical.dbusmenu reads its layout and lists the entries in a layer
+//! surface above the bar, clicking one sends it back to the item. Items without
+//! a menu are asked to show their own.
+
+use wayland_client::{
+    Connection, Dispatch, QueueHandle,
+    protocol::{wl_buffer::WlBuffer, wl_surface::WlSurface},
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1,
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+
+use crate::{
+    app_state::{self, AppState, MenuList},
+    config,
+    threads::tray::{self, Menu},
+};
+
+/// User data of the menu's layer surface, bars use their output's id.
+pub struct TrayMenuSurface;
+
+#[derive(Default)]
+pub struct TrayMenuState {
+    popup: Option<Popup>,
+    /// Bar, tray item and position of a right click whose menu the tray
+    /// thread is still reading.
+    requested: Option<(u32, u8, f64)>,
+}
+
+struct Popup {
+    surface: WlSurface,
+    layer_surface: ZwlrLayerSurfaceV1,
+    /// Allocated on the first configure, the menu never changes size.
+    buffer: Option<(WlBuffer, *mut u32, usize)>,
+    list: MenuList,
+    /// Its index is the tray item's, right clicking that again closes it.
+    menu: Menu,
+    hovered: Option<usize>,
+}
+
+impl Popup {
+    fn destroy(self) {
+        if let Some((buffer, pixels, len)) = self.buffer {
+            app_state::destroy_buffer(buffer, pixels, len);
+        }
+        self.layer_surface.destroy();
+        self.surface.destroy();
+    }
+}
+
+impl AppState {
+    /// Asks for the menu of the tray item at `index` to open above the given
+    /// bar, `x` from its left edge, or closes it when it is already open.
+    pub(crate) fn toggle_tray_menu(&mut self, bar: u32, index: u8, x: f64) {
+        let open = self.tray_menu.popup.as_ref().map(|p| p.menu.index);
+        self.close_tray_menu();
+        if open == Some(index) {
+            return;
+        }
+        self.tray_menu.requested = Some((bar, index, x));
+        tray::request_menu(index);
+    }
+
+    /// Opens the menu the tray thread read, unless it was closed since.
+    pub(crate) fn open_read_tray_menu(&mut self) {
+        let Some(menu) = tray::take_menu() else {
+            return;
+        };
+        let Some((bar, _, x)) = self
+            .tray_menu
+            .requested
+            .take_if(|(_, index, _)| *index == menu.index)
+        else {
+            return;
+        };
+        self.close_calendar();
+        let (Some(compositor), Some(layer_shell), Some(glyphs), Some(qh)) =
+            (&self.compositor, &self.layer_shell, &self.glyphs, &self.qh)
+        else {
+            return;
+        };
+        let surface = compositor.create_surface(qh, ());
+        let layer_surface = layer_shell.get_layer_surface(
+            &surface,
+            self.bar_output(bar).as_ref(),
+            zwlr_layer_shell_v1::Layer::Top,
+            format!("{}-menu", config::get().namespace),
+            qh,
+            TrayMenuSurface,
+        );
+        let list = MenuList::new(glyphs, &menu.entries);
+        let (width, height) = list.size();
+        let (anchor, [top, right, bottom, left]) = self.tray_menu_placement(bar, x);
+        layer_surface.set_anchor(anchor);
+        layer_surface.set_size(width, height);
+        layer_surface.set_margin(top, right, bottom, left);
+        surface.commit();
+
+        self.tray_menu.popup = Some(Popup {
+            surface,
+            layer_surface,
+            buffer: None,
+            list,
+            menu,
+            hovered: None,
+        });
+    }
+
+    /// Returns true if the menu was open.
+    pub(crate) fn close_tray_menu(&mut self) -> bool {
+        self.tray_menu.requested = None;
+        match self.tray_menu.popup.take() {
+            Some(popup) => {
+                popup.destroy();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_tray_menu_surface(&self, surface: &WlSurface) -> bool {
+        self.tray_menu
+            .popup
+            .as_ref()
+            .is_some_and(|p| p.surface == *surface)
+    }
+
+    /// Highlights the entry under the pointer, None when it left the menu.
+    pub(crate) fn hover_tray_menu(&mut self, y: Option<f64>) {
+        let Some(popup) = &mut self.tray_menu.popup else {
+            return;
+        };
+        let hovered = y
+            .and_then(|y| popup.list.row_at(y))
+            .filter(|row| clickable(&popup.menu, *row));
+        if hovered != popup.hovered {
+            popup.hovered = hovered;
+            self.draw_tray_menu();
+        }
+    }
+
+    /// Sends the clicked entry to its item and closes the menu, returns true if
+    /// an entry was hit.
+    pub(crate) fn click_tray_menu(&mut self, y: f64) -> bool {
+        let Some(popup) = &self.tray_menu.popup else {
+            return false;
+        };
+        let Some(row) = popup
+            .list
+            .row_at(y)
+            .filter(|row| clickable(&popup.menu, *row))
+        else {
+            return false;
+        };
+        tray::menu_clicked(&popup.menu, popup.menu.entries[row].id);
+        self.close_tray_menu()
+    }
+
+    fn draw_tray_menu(&mut self) {
+        let (Some(popup), Some(glyphs)) = (&self.tray_menu.popup, &self.glyphs) else {
+            return;
+        };
+        let Some((buffer, pixels, len)) = &popup.buffer else {
+            return;
+        };
+        let slice = unsafe { std::slice::from_raw_parts_mut(*pixels, len / 4) };
+        popup
+            .list
+            .draw(slice, glyphs, &popup.menu.entries, popup.hovered);
+        let (width, height) = popup.list.size();
+        popup
+            .surface
+            .damage_buffer(0, 0, width as i32, height as i32);
+        popup.surface.attach(Some(buffer), 0, 0);
+        popup.surface.commit();
+    }
+}
+
+/// Separators and disabled entries can't be clicked.
+fn clickable(menu: &Menu, row: usize) -> bool {
+    menu.entries
+        .get(row)
+        .is_some_and(|entry| entry.enabled && !entry.separator)
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, TrayMenuSurface> for AppState {
+    fn event(
+        state: &mut Self,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        _: &TrayMenuSurface,
+        _: &Connection,
+        qhandle: &QueueHandle<Self>,
+    ) {
+        state
+            .event_log
+            .record(layer_surface, event.opcode(), &event);
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => {
+                layer_surface.ack_configure(serial);
+                let (Some(popup), Some(shm)) = (&mut state.tray_menu.popup, &state.shm) else {
+                    return;
+                };
+                if popup.buffer.is_none() {
+                    let (width, height) = popup.list.size();
+                    popup.buffer = Some(app_state::create_buffer(shm, width, height, qhandle));
+                }
+                state.draw_tray_menu();
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                state.close_tray_menu();
+            }
+            _ => {}
+        }
+    }
+}
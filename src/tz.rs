@@ -0,0 +1,230 @@
+//! Time zone abbreviations (CET, CEST, AEST) from the system's compiled tz data.
+//! The UTC offset itself comes from `time`, the abbreviation is picked by that
+//! offset so DST rules never have to be evaluated here.
+
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+
+/// Abbreviations of the local zone with the UTC offset (in seconds) they belong
+/// to, the ones in current use first.
+pub struct Zone {
+    names: Vec<(i32, String)>,
+}
+
+static LOCAL: OnceLock<Option<Zone>> = OnceLock::new();
+
+/// The zone from `$TZ` or `/etc/localtime`, loaded once.
+pub fn local() -> Option<&'static Zone> {
+    LOCAL.get_or_init(Zone::load).as_ref()
+}
+
+/// Abbreviation for a UTC offset of the local zone, "+0530" style when the tz
+/// data has no name for it.
+pub fn abbreviation(offset: i32) -> String {
+    local()
+        .and_then(|zone| zone.abbreviation(offset))
+        .map_or_else(|| numeric(offset), str::to_string)
+}
+
+/// Every abbreviation the local zone may show, for sizing the clock slot.
+pub fn abbreviations() -> Vec<String> {
+    match local() {
+        Some(zone) => zone.names.iter().map(|(_, name)| name.clone()).collect(),
+        None => vec![numeric(-12 * 3600)],
+    }
+}
+
+pub fn numeric(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.unsigned_abs();
+    format!("{}{:02}{:02}", sign, offset / 3600, offset % 3600 / 60)
+}
+
+impl Zone {
+    fn load() -> Option<Self> {
+        let path = match env::var("TZ") {
+            Ok(tz) if !tz.is_empty() => {
+                let tz = tz.trim_start_matches(':');
+                if tz.starts_with('/') {
+                    tz.to_string()
+                } else {
+                    format!("/usr/share/zoneinfo/{}", tz)
+                }
+            }
+            _ => "/etc/localtime".to_string(),
+        };
+        let zone = Self::parse(&fs::read(path).ok()?);
+        if zone.is_none() {
+            eprintln!("[Time Zone] unreadable tz data, showing numeric offsets");
+        }
+        zone
+    }
+
+    pub fn abbreviation(&self, offset: i32) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(o, _)| *o == offset)
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Parses a TZif file. Version 2+ files repeat the data with 64 bit times
+    /// and end in a POSIX TZ string describing the rules after the last
+    /// transition, which is what decides the names in use today.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let (header, mut rest) = Header::parse(data)?;
+        let mut block = header.block(rest, 4)?;
+        let mut footer = None;
+        if header.version >= b'2' {
+            rest = rest.get(header.len(4)..)?;
+            let (header, rest) = Header::parse(rest)?;
+            block = header.block(rest, 8)?;
+            let tail = rest.get(header.len(8)..)?;
+            footer = std::str::from_utf8(tail)
+                .ok()
+                .and_then(|t| t.lines().nth(1));
+        }
+
+        let mut names = footer.map(parse_posix).unwrap_or_default();
+        // Then the types the most recent transitions switched to, then the rest
+        let recent = block.indices.iter().rev().map(|&i| i as usize);
+        for index in recent.chain(0..block.types.len()) {
+            let Some(&(offset, abbr_index)) = block.types.get(index) else {
+                continue;
+            };
+            let Some(name) = block
+                .abbreviations
+                .get(abbr_index as usize..)
+                .and_then(|a| a.split(|&b| b == 0).next())
+                .and_then(|a| std::str::from_utf8(a).ok())
+            else {
+                continue;
+            };
+            if !names.iter().any(|(o, n)| *o == offset && n == name) {
+                names.push((offset, name.to_string()));
+            }
+        }
+        (!names.is_empty()).then_some(Self { names })
+    }
+}
+
+struct Header {
+    version: u8,
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+/// The parts of a TZif data block the abbreviations are read from.
+struct Block<'a> {
+    /// Type index of every transition, oldest first.
+    indices: &'a [u8],
+    /// UTC offset and abbreviation index of every local time type.
+    types: Vec<(i32, u8)>,
+    abbreviations: &'a [u8],
+}
+
+impl Header {
+    const LEN: usize = 44;
+
+    fn parse(data: &[u8]) -> Option<(Self, &[u8])> {
+        if data.get(..4)? != b"TZif" {
+            return None;
+        }
+        let count = |i: usize| {
+            let at = 20 + i * 4;
+            Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?) as usize)
+        };
+        let header = Header {
+            version: *data.get(4)?,
+            isutcnt: count(0)?,
+            isstdcnt: count(1)?,
+            leapcnt: count(2)?,
+            timecnt: count(3)?,
+            typecnt: count(4)?,
+            charcnt: count(5)?,
+        };
+        Some((header, data.get(Self::LEN..)?))
+    }
+
+    /// Length of the data block following the header, times are `time_size` bytes.
+    fn len(&self, time_size: usize) -> usize {
+        self.timecnt * (time_size + 1)
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_size + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+
+    fn block<'a>(&self, data: &'a [u8], time_size: usize) -> Option<Block<'a>> {
+        let indices_at = self.timecnt * time_size;
+        let types_at = indices_at + self.timecnt;
+        let chars_at = types_at + self.typecnt * 6;
+        let types = data
+            .get(types_at..chars_at)?
+            .chunks_exact(6)
+            .map(|t| (i32::from_be_bytes(t[..4].try_into().unwrap()), t[5]))
+            .collect();
+        Some(Block {
+            indices: data.get(indices_at..types_at)?,
+            types,
+            abbreviations: data.get(chars_at..chars_at + self.charcnt)?,
+        })
+    }
+}
+
+/// Standard and DST names with their offsets from a POSIX TZ string like
+/// "CET-1CEST,M3.5.0,M10.5.0/3" or "<+0530>-5:30". POSIX offsets count west
+/// of Greenwich, the opposite of UTC offsets.
+fn parse_posix(tz: &str) -> Vec<(i32, String)> {
+    let mut rest = tz;
+    let mut names = Vec::new();
+    let Some(std_name) = take_name(&mut rest) else {
+        return names;
+    };
+    let Some(std_offset) = take_offset(&mut rest) else {
+        return names;
+    };
+    names.push((-std_offset, std_name.to_string()));
+    if let Some(dst_name) = take_name(&mut rest) {
+        let dst_offset = take_offset(&mut rest).unwrap_or(std_offset - 3600);
+        names.insert(0, (-dst_offset, dst_name.to_string()));
+    }
+    names
+}
+
+fn take_name<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let (name, tail) = if let Some(quoted) = rest.strip_prefix('<') {
+        let (name, tail) = quoted.split_once('>')?;
+        (name, tail)
+    } else {
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        rest.split_at(end)
+    };
+    *rest = tail;
+    (name.len() >= 3).then_some(name)
+}
+
+/// "[+-]hh[:mm[:ss]]" in seconds.
+fn take_offset(rest: &mut &str) -> Option<i32> {
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '-' | ':')))
+        .unwrap_or(rest.len());
+    let (text, tail) = rest.split_at(end);
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, text.trim_start_matches('+')),
+    };
+    let mut seconds = 0;
+    for (part, scale) in digits.split(':').zip([3600, 60, 1]) {
+        seconds += part.parse::<i32>().ok()? * scale;
+    }
+    *rest = tail;
+    Some(sign * seconds)
+}
@@ -0,0 +1,467 @@
+//! Runs the leanbar binary against the mock compositor in `mock_compositor`.
+//!
+//! Nothing is drawn without the bar's font, so the tests that look at frames
+//! need `font_renderer::FONT_PATH` installed and are ignored by default. Run
+//! them with `cargo test -- --ignored` where it is.
+
+mod mock_compositor;
+
+use std::fs;
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use mock_compositor::{MockCompositor, Request};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const BAR_HEIGHT: u32 = 28;
+/// Longest the first frame may take after the first configure.
+const FIRST_FRAME_BUDGET: Duration = Duration::from_millis(20);
+// Bottom | Left | Right
+const ANCHOR_BOTTOM_LEFT_RIGHT: u32 = 2 | 4 | 8;
+
+/// A leanbar process with its own runtime, config and cache directories.
+struct Leanbar {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Drop for Leanbar {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Starts a mock compositor with one output and a leanbar connected to it.
+fn start(test_name: &str) -> (MockCompositor, Leanbar) {
+    start_with(test_name, "", &[])
+}
+
+/// Like `start`, with a config file and extra environment variables for leanbar.
+fn start_with(test_name: &str, config: &str, env: &[(&str, String)]) -> (MockCompositor, Leanbar) {
+    let dir = std::env::temp_dir().join(format!(
+        "leanbar-headless-{}-{}",
+        test_name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("config/leanbar")).unwrap();
+    fs::write(dir.join("config/leanbar/config"), config).unwrap();
+
+    let socket = dir.join("wayland-test");
+    let mut compositor = MockCompositor::new(socket.clone());
+    compositor.add_output("HEADLESS-1");
+
+    let child = Command::new(env!("CARGO_BIN_EXE_leanbar"))
+        .env("WAYLAND_DISPLAY", &socket)
+        .env("XDG_RUNTIME_DIR", &dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .env("HOME", &dir)
+        .env_remove("HYPRLAND_INSTANCE_SIGNATURE")
+        .env_remove("WAYLAND_SOCKET")
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to spawn leanbar");
+
+    (compositor, Leanbar { child, dir })
+}
+
+/// Runs `leanbar ctl` against the leanbar's control socket, returns its output.
+fn ctl(leanbar: &Leanbar, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_leanbar"))
+        .arg("ctl")
+        .args(args)
+        .env("XDG_RUNTIME_DIR", &leanbar.dir)
+        .output()
+        .expect("failed to run leanbar ctl");
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn count(requests: &[Request], f: impl Fn(&Request) -> bool) -> usize {
+    requests.iter().filter(|r| f(r)).count()
+}
+
+/// `(x, y, width, height)` of every damage request.
+fn damage(requests: &[Request]) -> Vec<(i32, i32, i32, i32)> {
+    requests
+        .iter()
+        .filter_map(|r| match r {
+            Request::Damage {
+                x,
+                y,
+                width,
+                height,
+            } => Some((*x, *y, *width, *height)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Waits for the initial layer surface commit and answers it with a configure.
+fn configure_initial(compositor: &mut MockCompositor, width: u32) -> u32 {
+    assert!(
+        compositor.run_until(TIMEOUT, |s| {
+            !s.layer_surfaces.is_empty() && s.requests.contains(&Request::Commit)
+        }),
+        "leanbar never created and committed a layer surface"
+    );
+    compositor.configure(0, width, BAR_HEIGHT)
+}
+
+/// Waits for a frame (attach followed by commit) after the given request index.
+fn wait_for_frame(compositor: &mut MockCompositor, after: usize) -> Vec<Request> {
+    assert!(
+        compositor.run_until(TIMEOUT, |s| {
+            let tail = &s.requests[after..];
+            tail.iter()
+                .position(|r| *r == Request::Attach)
+                .is_some_and(|i| tail[i..].contains(&Request::Commit))
+        }),
+        "leanbar never attached and committed a buffer"
+    );
+    compositor.state.requests[after..].to_vec()
+}
+
+#[test]
+fn layer_surface_is_anchored_to_the_bottom_edge() {
+    let (mut compositor, _leanbar) = start("anchor");
+    configure_initial(&mut compositor, 800);
+
+    let requests = &compositor.state.requests;
+    assert_eq!(
+        requests[0],
+        Request::GetLayerSurface {
+            namespace: "leanbar".into(),
+            output: Some("HEADLESS-1".into()),
+        }
+    );
+    assert!(requests.contains(&Request::SetSize(0, BAR_HEIGHT)));
+    assert!(requests.contains(&Request::SetAnchor(ANCHOR_BOTTOM_LEFT_RIGHT)));
+    assert!(requests.contains(&Request::SetExclusiveZone(BAR_HEIGHT as i32)));
+    // Nothing may be attached before the first configure
+    assert!(!requests.contains(&Request::Attach));
+}
+
+#[test]
+#[ignore = "draws text, needs the bar's font"]
+fn configure_is_acked_and_followed_by_a_damaged_frame() {
+    let (mut compositor, _leanbar) = start("configure");
+    let serial = configure_initial(&mut compositor, 800);
+    let start = compositor.state.requests.len();
+    let frame = wait_for_frame(&mut compositor, start);
+
+    assert_eq!(frame[0], Request::AckConfigure(serial));
+    assert_eq!(
+        count(&frame, |r| matches!(r, Request::CreateBuffer { .. })),
+        1
+    );
+    assert!(frame.contains(&Request::CreateBuffer {
+        width: 800,
+        height: BAR_HEIGHT as i32,
+        stride: 800 * 4,
+    }));
+
+    let damage = damage(&frame);
+    assert!(!damage.is_empty(), "first frame carried no damage");
+    for (x, y, width, height) in damage {
+        assert!(x >= 0 && y == 0 && width > 0, "bad damage rect at {}", x);
+        assert!(x + width <= 800, "damage past the buffer edge at {}", x);
+        assert_eq!(height, BAR_HEIGHT as i32);
+    }
+
+    // Without state changes the bar must stay idle; a minute rollover may redraw once
+    let settled = compositor.state.requests.len();
+    compositor.run_for(Duration::from_millis(300));
+    let redraws = count(&compositor.state.requests[settled..], |r| {
+        *r == Request::Attach
+    });
+    assert!(redraws <= 1, "{} redraws without state changes", redraws);
+}
+
+#[test]
+#[ignore = "draws text, needs the bar's font"]
+fn first_frame_is_committed_soon_after_configure() {
+    let (mut compositor, _leanbar) = start("first-frame");
+    assert!(
+        compositor.run_until(TIMEOUT, |s| {
+            !s.layer_surfaces.is_empty() && s.requests.contains(&Request::Commit)
+        }),
+        "leanbar never created and committed a layer surface"
+    );
+    // Slow modules start on their own threads and must not hold up the first
+    // paint, the measurement includes the mock's few milliseconds of polling
+    let start = compositor.state.requests.len();
+    let configured = Instant::now();
+    compositor.configure(0, 1920, BAR_HEIGHT);
+    wait_for_frame(&mut compositor, start);
+    let elapsed = configured.elapsed();
+    assert!(
+        elapsed < FIRST_FRAME_BUDGET,
+        "first frame took {:?} after configure",
+        elapsed
+    );
+}
+
+#[test]
+#[ignore = "draws text, needs the bar's font"]
+fn reconfigure_with_new_width_reallocates_the_buffer() {
+    let (mut compositor, _leanbar) = start("resize");
+    configure_initial(&mut compositor, 800);
+    let start = compositor.state.requests.len();
+    wait_for_frame(&mut compositor, start);
+
+    let start = compositor.state.requests.len();
+    let serial = compositor.configure(0, 1280, BAR_HEIGHT);
+    let frame = wait_for_frame(&mut compositor, start);
+
+    assert_eq!(frame[0], Request::AckConfigure(serial));
+    assert!(frame.contains(&Request::CreateBuffer {
+        width: 1280,
+        height: BAR_HEIGHT as i32,
+        stride: 1280 * 4,
+    }));
+}
+
+#[test]
+#[ignore = "draws text, needs the bar's font"]
+fn a_state_change_only_damages_its_slot() {
+    let (mut compositor, leanbar) = start("damage");
+    // Wide enough for every module to get a slot of its own
+    let width = 4000;
+    configure_initial(&mut compositor, width);
+    wait_for_frame(&mut compositor, 0);
+
+    let socket = leanbar.dir.join("leanbar/control.sock");
+    assert!(
+        compositor.run_until(TIMEOUT, |_| socket.exists()),
+        "the control socket was never created"
+    );
+    // Both redraw on their own when the minute rolls over
+    let requests = compositor.state.requests.len();
+    assert_eq!(ctl(&leanbar, &["toggle", "clock"]), "hidden\n");
+    assert_eq!(ctl(&leanbar, &["toggle", "date"]), "hidden\n");
+    wait_for_frame(&mut compositor, requests);
+    compositor.run_for(Duration::from_millis(300));
+
+    let requests = compositor.state.requests.len();
+    assert_eq!(ctl(&leanbar, &["set", "custom.text", "first"]), "ok\n");
+    let first = damage(&wait_for_frame(&mut compositor, requests));
+    assert!(!first.is_empty(), "the new text carried no damage");
+    let left = first.iter().map(|d| d.0).min().unwrap();
+    let right = first.iter().map(|d| d.0 + d.2).max().unwrap();
+    assert!(
+        right - left < width as i32 / 8,
+        "changing one module damaged {}..{}",
+        left,
+        right
+    );
+
+    let requests = compositor.state.requests.len();
+    assert_eq!(ctl(&leanbar, &["set", "custom.text", "other"]), "ok\n");
+    let second = damage(&wait_for_frame(&mut compositor, requests));
+    assert!(!second.is_empty(), "the new text carried no damage");
+    for (x, _, width, _) in second {
+        assert!(
+            x >= left && x + width <= right,
+            "damage at {}..{} outside the custom slot {}..{}",
+            x,
+            x + width,
+            left,
+            right
+        );
+    }
+}
+
+#[test]
+#[ignore = "draws text, needs the bar's font"]
+fn highlight_fades_out_without_frame_callbacks() {
+    let (mut compositor, leanbar) = start_with("highlight", "highlight_changes = true\n", &[]);
+    configure_initial(&mut compositor, 4000);
+    wait_for_frame(&mut compositor, 0);
+
+    let socket = leanbar.dir.join("leanbar/control.sock");
+    assert!(
+        compositor.run_until(TIMEOUT, |_| socket.exists()),
+        "the control socket was never created"
+    );
+    // Both redraw on their own when the minute rolls over
+    assert_eq!(ctl(&leanbar, &["toggle", "clock"]), "hidden\n");
+    assert_eq!(ctl(&leanbar, &["toggle", "date"]), "hidden\n");
+    compositor.run_for(Duration::from_millis(1500));
+
+    // The mock never fires frame callbacks, the fade has to run on a timer
+    let requests = compositor.state.requests.len();
+    assert_eq!(ctl(&leanbar, &["set", "custom.text", "changed"]), "ok\n");
+    compositor.run_for(Duration::from_millis(1500));
+    let frames = count(&compositor.state.requests[requests..], |r| {
+        *r == Request::Attach
+    });
+    // Modules alone redraw about once a second
+    assert!(frames > 5, "the underline faded in {} frames", frames);
+}
+
+#[test]
+fn output_hotplug_creates_and_destroys_bars() {
+    let (mut compositor, _leanbar) = start("hotplug");
+    configure_initial(&mut compositor, 800);
+
+    let second = compositor.add_output("HEADLESS-2");
+    assert!(
+        compositor.run_until(TIMEOUT, |s| s.layer_surfaces.len() == 2),
+        "no layer surface was created for the new output"
+    );
+    assert!(
+        compositor
+            .state
+            .requests
+            .contains(&Request::GetLayerSurface {
+                namespace: "leanbar".into(),
+                output: Some("HEADLESS-2".into()),
+            })
+    );
+
+    compositor.remove_output(second);
+    assert!(
+        compositor.run_until(TIMEOUT, |s| s
+            .requests
+            .contains(&Request::DestroyLayerSurface)),
+        "the bar of the removed output was not destroyed"
+    );
+}
+
+#[test]
+fn control_socket_reports_module_health() {
+    let (mut compositor, leanbar) = start("control");
+    configure_initial(&mut compositor, 800);
+
+    let socket = leanbar.dir.join("leanbar/control.sock");
+    assert!(
+        compositor.run_until(TIMEOUT, |_| socket.exists()),
+        "the control socket was never created"
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_leanbar-ctl"))
+        .arg("modules")
+        .env("XDG_RUNTIME_DIR", &leanbar.dir)
+        .output()
+        .expect("failed to run leanbar-ctl");
+    assert!(output.status.success());
+
+    let report = String::from_utf8(output.stdout).unwrap();
+    let state_of = |module: &str| {
+        report
+            .lines()
+            .find(|l| l.split_whitespace().next() == Some(module))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .map(str::to_string)
+    };
+    assert_eq!(state_of("clock").as_deref(), Some("ok"));
+    // The test environment has no Hyprland instance
+    assert_eq!(state_of("workspaces").as_deref(), Some("disabled"));
+    assert_eq!(state_of("control").as_deref(), Some("ok"));
+}
+
+#[test]
+#[ignore = "draws text, needs the bar's font"]
+fn ctl_mode_toggles_modules_and_quits() {
+    let (mut compositor, mut leanbar) = start("ctl");
+    configure_initial(&mut compositor, 800);
+    wait_for_frame(&mut compositor, 0);
+
+    let socket = leanbar.dir.join("leanbar/control.sock");
+    assert!(
+        compositor.run_until(TIMEOUT, |_| socket.exists()),
+        "the control socket was never created"
+    );
+    let ctl = |args: &[&str]| ctl(&leanbar, args);
+
+    // A client that never finishes its command only holds the others up briefly
+    let _idle = UnixStream::connect(&socket).unwrap();
+
+    let requests = compositor.state.requests.len();
+    assert_eq!(ctl(&["toggle", "clock"]), "hidden\n");
+    // Hiding a module lays the bar out again
+    wait_for_frame(&mut compositor, requests);
+    assert_eq!(ctl(&["toggle", "clock"]), "shown\n");
+    assert_eq!(ctl(&["toggle", "nonsense"]), "unknown module: nonsense\n");
+
+    assert_eq!(ctl(&["quit"]), "ok\n");
+    assert!(
+        compositor.run_until(TIMEOUT, |_| leanbar
+            .child
+            .try_wait()
+            .is_ok_and(|s| s.is_some())),
+        "leanbar kept running after quit"
+    );
+}
+
+#[test]
+fn sigterm_destroys_the_layer_surface_before_exiting() {
+    let (mut compositor, mut leanbar) = start("sigterm");
+    configure_initial(&mut compositor, 800);
+
+    let killed = Command::new("kill")
+        .arg("-TERM")
+        .arg(leanbar.child.id().to_string())
+        .status()
+        .expect("failed to run kill");
+    assert!(killed.success());
+    assert!(
+        compositor.run_until(TIMEOUT, |s| s
+            .requests
+            .contains(&Request::DestroyLayerSurface)),
+        "the layer surface was not destroyed on SIGTERM"
+    );
+    assert!(
+        compositor.run_until(TIMEOUT, |_| leanbar
+            .child
+            .try_wait()
+            .is_ok_and(|s| s.is_some_and(|s| s.success()))),
+        "leanbar did not exit cleanly on SIGTERM"
+    );
+}
+
+#[test]
+#[ignore = "draws text, needs the bar's font"]
+fn systemd_is_notified_when_ready_and_the_watchdog_is_fed() {
+    let notify_dir = std::env::temp_dir().join(format!("leanbar-notify-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&notify_dir);
+    fs::create_dir_all(&notify_dir).unwrap();
+    let notify_path = notify_dir.join("notify.sock");
+    let socket = UnixDatagram::bind(&notify_path).unwrap();
+    socket.set_nonblocking(true).unwrap();
+
+    let env = [
+        ("NOTIFY_SOCKET", notify_path.display().to_string()),
+        ("WATCHDOG_USEC", "200000".to_string()),
+    ];
+    let (mut compositor, _leanbar) = start_with("notify", "", &env);
+    configure_initial(&mut compositor, 800);
+
+    let mut received = Vec::new();
+    let mut buf = [0u8; 64];
+    compositor.run_until(TIMEOUT, |_| {
+        while let Ok(n) = socket.recv(&mut buf) {
+            received.push(String::from_utf8_lossy(&buf[..n]).into_owned());
+        }
+        received.iter().any(|m| m == "READY=1") && received.iter().any(|m| m == "WATCHDOG=1")
+    });
+    let _ = fs::remove_dir_all(&notify_dir);
+    assert!(
+        received.iter().any(|m| m == "READY=1"),
+        "got {:?}",
+        received
+    );
+    assert!(
+        received.iter().any(|m| m == "WATCHDOG=1"),
+        "got {:?}",
+        received
+    );
+}
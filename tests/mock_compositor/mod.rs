@@ -0,0 +1,415 @@
+//! A minimal in-process Wayland compositor for driving leanbar headlessly.
+//!
+//! It advertises just enough globals for the bar to start (compositor, shm,
+//! layer shell and outputs), records every request the bar makes and lets the
+//! test send configure events and hotplug outputs.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use wayland_protocols_wlr::layer_shell::v1::server::{
+    zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1},
+};
+use wayland_server::{
+    Client, DataInit, Dispatch, Display, DisplayHandle, GlobalDispatch, ListeningSocket, New,
+    Resource,
+    backend::{ClientData, GlobalId},
+    protocol::{
+        wl_buffer::{self, WlBuffer},
+        wl_callback::WlCallback,
+        wl_compositor::{self, WlCompositor},
+        wl_output::{self, WlOutput},
+        wl_region::{self, WlRegion},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::{self, WlShmPool},
+        wl_surface::{self, WlSurface},
+    },
+};
+
+/// A request made by the bar, in the order the compositor received it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    GetLayerSurface {
+        namespace: String,
+        output: Option<String>,
+    },
+    SetSize(u32, u32),
+    SetAnchor(u32),
+    SetExclusiveZone(i32),
+    AckConfigure(u32),
+    CreateBuffer {
+        width: i32,
+        height: i32,
+        stride: i32,
+    },
+    Attach,
+    Damage {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    Commit,
+    DestroyLayerSurface,
+}
+
+#[derive(Default)]
+pub struct State {
+    pub requests: Vec<Request>,
+    pub layer_surfaces: Vec<ZwlrLayerSurfaceV1>,
+    next_serial: u32,
+}
+
+pub struct MockCompositor {
+    display: Display<State>,
+    handle: DisplayHandle,
+    socket: ListeningSocket,
+    clients: Vec<Client>,
+    pub state: State,
+}
+
+impl MockCompositor {
+    pub fn new(socket_path: PathBuf) -> Self {
+        let display = Display::new().expect("failed to create display");
+        let handle = display.handle();
+        handle.create_global::<State, WlCompositor, ()>(4, ());
+        handle.create_global::<State, WlShm, ()>(1, ());
+        handle.create_global::<State, ZwlrLayerShellV1, ()>(4, ());
+        let socket = ListeningSocket::bind_absolute(socket_path).expect("failed to bind socket");
+        Self {
+            display,
+            handle,
+            socket,
+            clients: Vec::new(),
+            state: State::default(),
+        }
+    }
+
+    pub fn add_output(&mut self, name: &str) -> GlobalId {
+        self.handle
+            .create_global::<State, WlOutput, String>(4, name.to_string())
+    }
+
+    pub fn remove_output(&mut self, global: GlobalId) {
+        self.handle.remove_global::<State>(global);
+    }
+
+    /// Sends a configure to the n-th layer surface and returns its serial.
+    pub fn configure(&mut self, surface: usize, width: u32, height: u32) -> u32 {
+        self.state.next_serial += 1;
+        let serial = self.state.next_serial;
+        self.state.layer_surfaces[surface].configure(serial, width, height);
+        serial
+    }
+
+    /// Accepts new clients and processes pending requests once.
+    pub fn dispatch(&mut self) {
+        while let Ok(Some(stream)) = self.socket.accept() {
+            let client = self
+                .handle
+                .insert_client(stream, Arc::new(ClientState))
+                .expect("failed to insert client");
+            self.clients.push(client);
+        }
+        self.display
+            .dispatch_clients(&mut self.state)
+            .expect("dispatch failed");
+        self.display.flush_clients().expect("flush failed");
+    }
+
+    /// Dispatches until `done` holds or the timeout expires. Returns whether `done` held.
+    pub fn run_until(&mut self, timeout: Duration, mut done: impl FnMut(&State) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            self.dispatch();
+            if done(&self.state) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    /// Dispatches for a fixed amount of time, to catch requests that should not happen.
+    pub fn run_for(&mut self, duration: Duration) {
+        self.run_until(duration, |_| false);
+    }
+}
+
+struct ClientState;
+
+impl ClientData for ClientState {}
+
+impl GlobalDispatch<WlCompositor, ()> for State {
+    fn bind(
+        _: &mut Self,
+        _: &DisplayHandle,
+        _: &Client,
+        resource: New<WlCompositor>,
+        _: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<WlCompositor, ()> for State {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &WlCompositor,
+        request: wl_compositor::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_compositor::Request::CreateSurface { id } => {
+                data_init.init(id, ());
+            }
+            wl_compositor::Request::CreateRegion { id } => {
+                data_init.init(id, ());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlRegion, ()> for State {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &WlRegion,
+        _: wl_region::Request,
+        _: &(),
+        _: &DisplayHandle,
+        _: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlSurface, ()> for State {
+    fn request(
+        state: &mut Self,
+        _: &Client,
+        _: &WlSurface,
+        request: wl_surface::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            // Never fired, like a compositor holding back frames of a hidden surface
+            wl_surface::Request::Frame { callback } => {
+                data_init.init(callback, ());
+            }
+            wl_surface::Request::Attach {
+                buffer: Some(_), ..
+            } => state.requests.push(Request::Attach),
+            wl_surface::Request::DamageBuffer {
+                x,
+                y,
+                width,
+                height,
+            } => state.requests.push(Request::Damage {
+                x,
+                y,
+                width,
+                height,
+            }),
+            wl_surface::Request::Commit => state.requests.push(Request::Commit),
+            _ => {}
+        }
+    }
+}
+
+impl GlobalDispatch<WlShm, ()> for State {
+    fn bind(
+        _: &mut Self,
+        _: &DisplayHandle,
+        _: &Client,
+        resource: New<WlShm>,
+        _: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let shm = data_init.init(resource, ());
+        shm.format(wl_shm::Format::Argb8888);
+        shm.format(wl_shm::Format::Xrgb8888);
+    }
+}
+
+impl Dispatch<WlShm, ()> for State {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &WlShm,
+        request: wl_shm::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_shm::Request::CreatePool { id, .. } = request {
+            data_init.init(id, ());
+        }
+    }
+}
+
+impl Dispatch<WlShmPool, ()> for State {
+    fn request(
+        state: &mut Self,
+        _: &Client,
+        _: &WlShmPool,
+        request: wl_shm_pool::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_shm_pool::Request::CreateBuffer {
+            id,
+            width,
+            height,
+            stride,
+            ..
+        } = request
+        {
+            data_init.init(id, ());
+            state.requests.push(Request::CreateBuffer {
+                width,
+                height,
+                stride,
+            });
+        }
+    }
+}
+
+impl Dispatch<WlBuffer, ()> for State {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &WlBuffer,
+        _: wl_buffer::Request,
+        _: &(),
+        _: &DisplayHandle,
+        _: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlCallback, ()> for State {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &WlCallback,
+        _: wayland_server::protocol::wl_callback::Request,
+        _: &(),
+        _: &DisplayHandle,
+        _: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<WlOutput, String> for State {
+    fn bind(
+        _: &mut Self,
+        _: &DisplayHandle,
+        _: &Client,
+        resource: New<WlOutput>,
+        name: &String,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let output = data_init.init(resource, name.clone());
+        if output.version() >= 4 {
+            output.name(name.clone());
+        }
+        if output.version() >= 2 {
+            output.done();
+        }
+    }
+}
+
+impl Dispatch<WlOutput, String> for State {
+    fn request(
+        _: &mut Self,
+        _: &Client,
+        _: &WlOutput,
+        _: wl_output::Request,
+        _: &String,
+        _: &DisplayHandle,
+        _: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<ZwlrLayerShellV1, ()> for State {
+    fn bind(
+        _: &mut Self,
+        _: &DisplayHandle,
+        _: &Client,
+        resource: New<ZwlrLayerShellV1>,
+        _: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwlrLayerShellV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _: &Client,
+        _: &ZwlrLayerShellV1,
+        request: zwlr_layer_shell_v1::Request,
+        _: &(),
+        _: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_layer_shell_v1::Request::GetLayerSurface {
+            id,
+            output,
+            namespace,
+            ..
+        } = request
+        {
+            let layer_surface = data_init.init(id, ());
+            state.layer_surfaces.push(layer_surface);
+            state.requests.push(Request::GetLayerSurface {
+                namespace,
+                output: output.and_then(|o| o.data::<String>().cloned()),
+            });
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for State {
+    fn request(
+        state: &mut Self,
+        _: &Client,
+        _: &ZwlrLayerSurfaceV1,
+        request: zwlr_layer_surface_v1::Request,
+        _: &(),
+        _: &DisplayHandle,
+        _: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_layer_surface_v1::Request::SetSize { width, height } => {
+                state.requests.push(Request::SetSize(width, height))
+            }
+            zwlr_layer_surface_v1::Request::SetAnchor { anchor } => {
+                state.requests.push(Request::SetAnchor(anchor.into()))
+            }
+            zwlr_layer_surface_v1::Request::SetExclusiveZone { zone } => {
+                state.requests.push(Request::SetExclusiveZone(zone))
+            }
+            zwlr_layer_surface_v1::Request::AckConfigure { serial } => {
+                state.requests.push(Request::AckConfigure(serial))
+            }
+            zwlr_layer_surface_v1::Request::Destroy => {
+                state.requests.push(Request::DestroyLayerSurface)
+            }
+            _ => {}
+        }
+    }
+}